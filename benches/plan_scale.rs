@@ -0,0 +1,107 @@
+//! Planning scale benchmark on a synthetic repository.
+//!
+//! Builds a repo with `XD_BENCH_LINKS` (default 20 000) `[links]`
+//! entries spread over nested source directories, then times
+//! `xd deploy --dry-run` and `xd status` against an empty home. Both
+//! commands run full discovery and planning over every entry, which is
+//! where link-nesting detection and the per-run stat cache matter.
+//!
+//! Set `XD_BENCH_BASELINE` to another `xd` binary, e.g. one built from
+//! a release that still compared every pair of link paths, to time it
+//! side by side on the same repository.
+//!
+//! Run with `cargo bench --bench plan_scale`.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 3;
+
+fn main() {
+    let n: usize = std::env::var("XD_BENCH_LINKS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(20_000);
+    let root = std::env::temp_dir().join(format!("xd_bench_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let repo = root.join("repo");
+    let home = root.join("home");
+    fs::create_dir_all(&home).unwrap();
+    build_repo(&repo, n);
+
+    let baseline = std::env::var_os("XD_BENCH_BASELINE").map(PathBuf::from);
+
+    println!("synthetic repo: {} links", n);
+    for args in [&["deploy", "--dry-run"][..], &["status"][..]] {
+        let current = Path::new(env!("CARGO_BIN_EXE_xd"));
+        let best = best_of(current, &repo, &home, args);
+        print!(
+            "  xd {:<18} best of {}: {:>8.1} ms",
+            args.join(" "),
+            RUNS,
+            ms(best)
+        );
+        if let Some(bin) = &baseline {
+            let base = best_of(bin, &repo, &home, args);
+            print!(
+                "   baseline: {:>8.1} ms ({:.0}x)",
+                ms(base),
+                base.as_secs_f64() / best.as_secs_f64()
+            );
+        }
+        println!();
+    }
+
+    let _ = fs::remove_dir_all(&root);
+}
+
+/// 100 links per directory, two directory levels, so that many entries
+/// share ancestors both on the source side and on the link side.
+fn build_repo(repo: &Path, n: usize) {
+    let mut toml = String::from("[links]\n");
+    for i in 0..n {
+        let rel = PathBuf::from(format!("g{}/d{}/f{}", i / 2_000, i / 100, i));
+        let src = repo.join(&rel);
+        fs::create_dir_all(src.parent().unwrap()).unwrap();
+        fs::write(&src, "x").unwrap();
+        writeln!(
+            toml,
+            "\"{}\" = \"~/.bench/{}\"",
+            rel.display(),
+            rel.display()
+        )
+        .unwrap();
+    }
+    fs::write(repo.join("xdotter.toml"), toml).unwrap();
+}
+
+fn best_of(bin: &Path, repo: &Path, home: &Path, args: &[&str]) -> Duration {
+    (0..RUNS)
+        .map(|_| time(bin, repo, home, args))
+        .min()
+        .unwrap()
+}
+
+fn time(bin: &Path, repo: &Path, home: &Path, args: &[&str]) -> Duration {
+    let start = Instant::now();
+    let status = Command::new(bin)
+        .args(args)
+        .current_dir(repo)
+        .env("HOME", home)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to spawn xd binary");
+    let elapsed = start.elapsed();
+    // `status` exits non-zero because nothing is deployed; only a crash
+    // (signal) would invalidate the timing.
+    assert!(status.code().is_some(), "xd {:?} was killed", args);
+    elapsed
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1_000.0
+}
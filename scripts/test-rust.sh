@@ -911,7 +911,7 @@ rm -rf "$tmpdir"
 
 # Test: Completion Invalid Shell
 tmpdir=$(mktemp -d)
-output=$(run_xd "$tmpdir" completion powershell 2>&1)
+output=$(run_xd "$tmpdir" completion tcsh 2>&1)
 if echo "$output" | grep -qi "unsupported\|invalid\|error\|不支持\|CLI 参数错误"; then
     log_test "Completion Invalid Shell" "PASS"
 else
@@ -0,0 +1,233 @@
+//! Scripted answers to interactive prompts.
+//!
+//! Interactive apply asks one question per destructive operation, and a
+//! non-TTY stdin declines every one of them. [`Answers`] lets a caller
+//! settle whole [`PromptKind`]s up front — "replace broken symlinks, but
+//! never regular files" — so the same run works unattended. Kinds
+//! without a scripted answer are still asked; the dry-run simulation
+//! honors scripted answers and declines the rest.
+//!
+//! Entries are written `kind=decision` on the command line and
+//! `kind = "decision"` in an answers file, with `decision` one of `yes`
+//! or `no`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+use crate::error::XdError;
+use crate::plan::{ExistingKind, UndeployActionKind};
+
+/// Kind of destructive operation an interactive prompt asks about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PromptKind {
+    /// Deploy: replace a regular file at the link path.
+    ReplaceFile,
+    /// Deploy: replace an empty real directory at the link path.
+    ReplaceEmptyDir,
+    /// Deploy: replace a symlink to something other than the source.
+    ReplaceWrongSymlink,
+    /// Deploy: replace a symlink whose target does not exist.
+    ReplaceBrokenSymlink,
+    /// Deploy: fix the permission bits of a sensitive source.
+    FixPermission,
+    /// Undeploy: delete a symlink to the configured source.
+    DeleteCorrectSymlink,
+    /// Undeploy: delete a symlink whose target does not exist.
+    DeleteBrokenSymlink,
+    /// Undeploy: delete a symlink to something other than the source.
+    DeleteWrongSymlink,
+}
+
+impl PromptKind {
+    /// Every kind, in declaration order.
+    pub const ALL: [PromptKind; 8] = [
+        PromptKind::ReplaceFile,
+        PromptKind::ReplaceEmptyDir,
+        PromptKind::ReplaceWrongSymlink,
+        PromptKind::ReplaceBrokenSymlink,
+        PromptKind::FixPermission,
+        PromptKind::DeleteCorrectSymlink,
+        PromptKind::DeleteBrokenSymlink,
+        PromptKind::DeleteWrongSymlink,
+    ];
+
+    /// Kind of the prompt before replacing `existing`.
+    pub fn replace(existing: &ExistingKind) -> Self {
+        match existing {
+            ExistingKind::RegularFile => Self::ReplaceFile,
+            ExistingKind::EmptyRealDir => Self::ReplaceEmptyDir,
+            ExistingKind::WrongSymlink => Self::ReplaceWrongSymlink,
+            ExistingKind::BrokenSymlink => Self::ReplaceBrokenSymlink,
+        }
+    }
+
+    /// Kind of the prompt before an undeploy delete; `None` for actions
+    /// that never prompt.
+    pub fn delete(kind: &UndeployActionKind) -> Option<Self> {
+        match kind {
+            UndeployActionKind::DeleteCorrect => Some(Self::DeleteCorrectSymlink),
+            UndeployActionKind::DeleteBroken => Some(Self::DeleteBrokenSymlink),
+            UndeployActionKind::DeleteWrong => Some(Self::DeleteWrongSymlink),
+            _ => None,
+        }
+    }
+
+    /// Name used in `kind=decision` entries.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ReplaceFile => "replace-file",
+            Self::ReplaceEmptyDir => "replace-empty-dir",
+            Self::ReplaceWrongSymlink => "replace-wrong-symlink",
+            Self::ReplaceBrokenSymlink => "replace-broken-symlink",
+            Self::FixPermission => "fix-permission",
+            Self::DeleteCorrectSymlink => "delete-correct-symlink",
+            Self::DeleteBrokenSymlink => "delete-broken-symlink",
+            Self::DeleteWrongSymlink => "delete-wrong-symlink",
+        }
+    }
+
+    /// Kind named `name`, as returned by [`PromptKind::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+
+    /// Chinese description for prompts and summaries.
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::ReplaceFile => "替换普通文件",
+            Self::ReplaceEmptyDir => "替换空目录",
+            Self::ReplaceWrongSymlink => "替换错误符号链接",
+            Self::ReplaceBrokenSymlink => "替换损坏符号链接",
+            Self::FixPermission => "修复权限",
+            Self::DeleteCorrectSymlink => "删除正确符号链接",
+            Self::DeleteBrokenSymlink => "删除损坏符号链接",
+            Self::DeleteWrongSymlink => "删除错误符号链接",
+        }
+    }
+}
+
+impl fmt::Display for PromptKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Scripted yes/no answers by [`PromptKind`]. Later entries for the same
+/// kind override earlier ones.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Answers {
+    by_kind: BTreeMap<PromptKind, bool>,
+}
+
+impl Answers {
+    /// No scripted answers: every prompt is asked.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True when no kind has a scripted answer.
+    pub fn is_empty(&self) -> bool {
+        self.by_kind.is_empty()
+    }
+
+    /// Answer every prompt of `kind` with yes (`true`) or no.
+    pub fn set(&mut self, kind: PromptKind, yes: bool) {
+        self.by_kind.insert(kind, yes);
+    }
+
+    /// Scripted answer for `kind`, if any.
+    pub fn get(&self, kind: PromptKind) -> Option<bool> {
+        self.by_kind.get(&kind).copied()
+    }
+
+    /// Add one `kind=decision` entry, as given to `--answer`.
+    pub fn add_entry(&mut self, entry: &str) -> Result<(), XdError> {
+        let Some((kind, decision)) = entry.split_once('=') else {
+            return Err(XdError::cli(format!(
+                "--answer 需要 kind=decision 形式: {}",
+                entry
+            )));
+        };
+        let (kind, yes) = parse(kind.trim(), decision.trim())?;
+        self.set(kind, yes);
+        Ok(())
+    }
+
+    /// Parse an answers file: a TOML table of `kind = "decision"`.
+    pub fn from_toml(content: &str, source: &Path) -> Result<Self, XdError> {
+        let raw: BTreeMap<String, String> = basic_toml::from_str(content)
+            .map_err(|e| XdError::cli(format!("{}: 回答文件解析失败: {}", source.display(), e)))?;
+        let mut answers = Self::new();
+        for (kind, decision) in &raw {
+            let (kind, yes) = parse(kind, decision)
+                .map_err(|e| XdError::cli(format!("{}: {}", source.display(), e.body())))?;
+            answers.set(kind, yes);
+        }
+        Ok(answers)
+    }
+}
+
+fn parse(kind: &str, decision: &str) -> Result<(PromptKind, bool), XdError> {
+    let Some(k) = PromptKind::from_name(kind) else {
+        let known: Vec<_> = PromptKind::ALL.iter().map(|k| k.name()).collect();
+        return Err(XdError::cli(format!(
+            "未知的回答类型 {}，可用类型: {}",
+            kind,
+            known.join(", ")
+        )));
+    };
+    let yes = match decision.to_ascii_lowercase().as_str() {
+        "yes" => true,
+        "no" => false,
+        _ => {
+            return Err(XdError::cli(format!(
+                "{} 的回答 {} 无效，只能是 yes 或 no",
+                kind, decision
+            )))
+        }
+    };
+    Ok((k, yes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_and_files_parse_by_kind_name() {
+        let mut a = Answers::new();
+        a.add_entry("replace-broken-symlink=yes").unwrap();
+        a.add_entry("replace-file = No").unwrap();
+        assert_eq!(a.get(PromptKind::ReplaceBrokenSymlink), Some(true));
+        assert_eq!(a.get(PromptKind::ReplaceFile), Some(false));
+        assert_eq!(a.get(PromptKind::FixPermission), None);
+
+        let f = Answers::from_toml(
+            "delete-wrong-symlink = \"yes\"\nfix-permission = \"no\"\n",
+            Path::new("answers.toml"),
+        )
+        .unwrap();
+        assert_eq!(f.get(PromptKind::DeleteWrongSymlink), Some(true));
+        assert_eq!(f.get(PromptKind::FixPermission), Some(false));
+    }
+
+    #[test]
+    fn malformed_entries_are_cli_errors() {
+        let mut a = Answers::new();
+        for bad in ["replace-file", "replace-files=yes", "replace-file=maybe"] {
+            let e = a.add_entry(bad).unwrap_err();
+            assert!(e.is_cli(), "{bad}: {e}");
+        }
+        let e = Answers::from_toml("replace-file = true\n", Path::new("a.toml")).unwrap_err();
+        assert!(e.to_string().contains("a.toml"), "{e}");
+        assert!(a.is_empty());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for k in PromptKind::ALL {
+            assert_eq!(PromptKind::from_name(k.name()), Some(k));
+        }
+    }
+}
@@ -2,40 +2,225 @@
 //!
 //! Executes the actions produced by `plan`. Before each destructive
 //! operation, re-checks the filesystem state observed at planning time;
-//! if it has changed, fails closed instead of proceeding. Apply-stage
-//! re-checks run only in apply mode (not in --dry-run) per
-//! `dry_run_no_apply_recheck`.
+//! if it has changed, fails closed instead of proceeding.
+//!
+//! Re-checks and changes at a link path act on its parent directory
+//! opened once through [`Fs::open_dir`], by entry name. With the
+//! descriptor-backed [`Dir`] of [`RealFs`] on Linux, swapping the parent
+//! or one of its ancestors for a symlink after the re-check cannot
+//! redirect the change; a permission fix walks down to the source the
+//! same way, never following a symlink.
+//!
+//! `--dry-run` runs the same steps against an [`OverlayFs`] seeded from
+//! the live filesystem, with every prompt declined.
+//! Each step therefore sees the effects of the steps before it, and the
+//! predicted counters and hard-failure point match a real apply of the
+//! same state. Re-checks in a simulation never observe concurrent
+//! changes.
 //!
 //! Per-step result is three-state per SPEC:
 //!   - Success — link processed.
 //!   - SkippedFailure — recoverable conflict, user reject, or
-//!     not-a-symlink warning. Counts as a failure in the totals
+//!     not-a-symlink object. Counts as a failure in the totals
 //!     but the loop continues with the next link.
 //!   - HardFailure — apply-stage system error (OS error,
 //!     re-check mismatch, OS-level permission fix failure).
 //!     The loop stops immediately per §"应用阶段错误".
+//!
+//! Interactive prompts accept more than yes/no (SPEC §"交互模式"):
+//! "all" and "none" answer every later prompt of the same kind or of
+//! any kind, "quit" and "skip" leave the rest of the run or of the
+//! current dependency untouched, counted as skipped rather than
+//! failed. Prompts whose kind has a scripted [`Answers`] entry are
+//! settled without asking, in apply and simulation alike. The answers
+//! are tallied in [`ApplyOutcome::prompts`].
 
-use std::fs;
+use std::cell::RefCell;
+use std::ffi::{OsStr, OsString};
 use std::io::{self, BufRead, IsTerminal, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+use crate::answers::{Answers, PromptKind};
+use crate::codes;
+use crate::diff;
 use crate::error::{ErrorBag, XdError};
-use crate::permissions;
+use crate::fs::{Change, Dir, Fs, OverlayFs, RealFs};
+use crate::logging::{self, Level};
 use crate::plan::{
     any_symlink_component, describe_existing, DeployAction, DeployActionKind, DeployPlan,
     ExistingKind, PermissionAction, UndeployAction, UndeployActionKind, UndeployPlan,
 };
 
-#[cfg(unix)]
-use std::os::unix::fs as unix_fs;
-
 /// Result of applying a plan: per-action outcome plus aggregate errors.
 #[derive(Debug, Default)]
 pub struct ApplyOutcome {
+    /// Links processed successfully (including already-correct ones).
     pub successes: usize,
+    /// Links intentionally left untouched without counting as failures.
     pub skipped: usize,
+    /// Links skipped as failures plus the hard failure, if any.
     pub failures: usize,
+    /// One error per failure, in plan order.
     pub errors: ErrorBag,
+    /// Index of the action whose hard failure stopped the run.
+    pub hard_failure: Option<usize>,
+    /// Answers given to interactive prompts.
+    pub prompts: PromptTally,
+}
+
+/// How the user answered the interactive prompts of one run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PromptTally {
+    /// Operations confirmed, including those covered by an "all" answer.
+    pub confirmed: usize,
+    /// Of `confirmed`, operations answered by an earlier "all".
+    pub confirmed_by_all: usize,
+    /// Operations declined, including those covered by a "none" answer.
+    pub declined: usize,
+    /// Of `declined`, operations answered by an earlier "none".
+    pub declined_by_none: usize,
+    /// Operations settled by a scripted [`Answers`] entry without asking.
+    pub scripted: usize,
+    /// Index of the action at which the user quit.
+    pub quit_at: Option<usize>,
+    /// Config files whose remaining links the user chose to skip.
+    pub skipped_dependencies: Vec<PathBuf>,
+}
+
+/// Predicted result of applying a plan, from [`simulate_deploy`] or
+/// [`simulate_undeploy`].
+#[derive(Debug, Default)]
+pub struct Simulation {
+    /// What a real apply would report.
+    pub outcome: ApplyOutcome,
+    /// Filesystem changes per action, in plan order. Shorter than the
+    /// plan when a hard failure stops the run.
+    pub changes: Vec<Vec<Change>>,
+}
+
+/// How one apply run reaches its surroundings.
+struct Ctx<'a> {
+    fs: &'a dyn Fs,
+    /// Answers an interactive prompt.
+    ask: &'a dyn Fn(&Prompt) -> Answer,
+    /// Settle prompts of these kinds without asking.
+    answers: &'a Answers,
+    /// A dry-run simulation rather than a live apply.
+    simulated: bool,
+    session: RefCell<Session>,
+}
+
+/// Answers that outlive the prompt they were given at.
+#[derive(Default)]
+struct Session {
+    yes_to: Vec<PromptKind>,
+    no_to_all: bool,
+    quit: bool,
+    tally: PromptTally,
+}
+
+impl<'a> Ctx<'a> {
+    fn live(fs: &'a dyn Fs, answers: &'a Answers) -> Self {
+        Self::new(fs, &ask_terminal, answers, false)
+    }
+
+    /// SPEC §"执行模式": a dry-run answers "no" to every prompt that
+    /// has no scripted answer and never asks the user.
+    fn simulated(fs: &'a dyn Fs, answers: &'a Answers) -> Self {
+        Self::new(fs, &|_| Answer::No, answers, true)
+    }
+
+    fn new(
+        fs: &'a dyn Fs,
+        ask: &'a dyn Fn(&Prompt) -> Answer,
+        answers: &'a Answers,
+        simulated: bool,
+    ) -> Self {
+        Self {
+            fs,
+            ask,
+            answers,
+            simulated,
+            session: RefCell::default(),
+        }
+    }
+
+    /// Resolve `p` against scripted answers and earlier sticky answers,
+    /// asking only when none applies.
+    fn decide(&self, p: &Prompt) -> Decision {
+        let mut s = self.session.borrow_mut();
+        if let Some(yes) = self.answers.get(p.kind) {
+            s.tally.scripted += 1;
+            return if yes {
+                s.tally.confirmed += 1;
+                Decision::Accept
+            } else {
+                s.tally.declined += 1;
+                Decision::Decline
+            };
+        }
+        if s.no_to_all {
+            s.tally.declined += 1;
+            s.tally.declined_by_none += 1;
+            return Decision::Decline;
+        }
+        if s.yes_to.contains(&p.kind) {
+            s.tally.confirmed += 1;
+            s.tally.confirmed_by_all += 1;
+            return Decision::Accept;
+        }
+        match (self.ask)(p) {
+            Answer::Yes => {
+                s.tally.confirmed += 1;
+                Decision::Accept
+            }
+            Answer::No => {
+                s.tally.declined += 1;
+                Decision::Decline
+            }
+            Answer::YesToAll => {
+                s.yes_to.push(p.kind);
+                s.tally.confirmed += 1;
+                Decision::Accept
+            }
+            Answer::NoToAll => {
+                s.no_to_all = true;
+                s.tally.declined += 1;
+                Decision::Decline
+            }
+            Answer::Quit => {
+                s.quit = true;
+                Decision::Leave
+            }
+            Answer::SkipDependency => {
+                s.tally
+                    .skipped_dependencies
+                    .push(p.config_file.to_path_buf());
+                Decision::Leave
+            }
+        }
+    }
+
+    /// True when an earlier answer leaves actions of `config_file`
+    /// untouched.
+    fn leaves(&self, config_file: &Path) -> bool {
+        let s = self.session.borrow();
+        s.quit
+            || s.tally
+                .skipped_dependencies
+                .iter()
+                .any(|c| c == config_file)
+    }
+
+    /// Move the tally into `out`, noting where the user quit.
+    fn finish(&self, out: &mut ApplyOutcome, quit_at: Option<usize>) {
+        out.prompts = std::mem::take(&mut self.session.borrow_mut().tally);
+        out.prompts.quit_at = quit_at;
+    }
+
+    fn quit(&self) -> bool {
+        self.session.borrow().quit
+    }
 }
 
 /// Generic per-step result. Used by both deploy and undeploy.
@@ -47,49 +232,127 @@ enum StepResult {
     /// Apply-stage system failure — counted as a failure and the loop
     /// stops on the spot.
     HardFailure(XdError),
+    /// Left untouched on the user's "quit" or "skip" answer — counted
+    /// as skipped, not as a failure.
+    Left,
 }
 
 // -----------------------------------------------------------------------------
 // Deploy
 // -----------------------------------------------------------------------------
 
+/// Execute a deploy plan. Stops at the first hard failure; recoverable
+/// conflicts are skipped and counted. Interactive plans prompt on the
+/// terminal; non-TTY stdin declines every prompt.
 pub fn apply_deploy(plan: &DeployPlan) -> ApplyOutcome {
-    let mut out = ApplyOutcome::default();
-    for act in &plan.actions {
-        // Sensitive-target warning per SPEC §"权限和敏感文件语义":
-        // independent of permission state — emitted whenever the link
-        // hits a built-in permission target.
-        emit_sensitive_warning(act);
+    apply_deploy_with(&RealFs, plan)
+}
 
-        match apply_one_deploy(act, plan.mode.interactive) {
-            StepResult::Success => out.successes += 1,
-            StepResult::SkippedFailure(e) => {
-                out.failures += 1;
-                out.errors.push(e);
-                // continue with next link
-            }
-            StepResult::HardFailure(e) => {
-                out.failures += 1;
-                out.errors.push(e);
-                break;
-            }
+/// [`apply_deploy`] against `fs` instead of the live filesystem.
+pub fn apply_deploy_with(fs: &dyn Fs, plan: &DeployPlan) -> ApplyOutcome {
+    apply_deploy_answering(fs, plan, &Answers::new())
+}
+
+/// [`apply_deploy_with`], settling interactive prompts covered by
+/// `answers` without asking.
+pub fn apply_deploy_answering(fs: &dyn Fs, plan: &DeployPlan, answers: &Answers) -> ApplyOutcome {
+    apply_deploy_observed(fs, plan, answers, |_| {})
+}
+
+/// [`apply_deploy_answering`], calling `after_step` with the index of
+/// each action as soon as it is done — for example to record what a
+/// wrapping `fs` saw it change.
+pub fn apply_deploy_observed(
+    fs: &dyn Fs,
+    plan: &DeployPlan,
+    answers: &Answers,
+    after_step: impl FnMut(usize),
+) -> ApplyOutcome {
+    run_deploy(&Ctx::live(fs, answers), plan, after_step)
+}
+
+/// Predict [`apply_deploy`] without modifying the filesystem.
+pub fn simulate_deploy(plan: &DeployPlan) -> Simulation {
+    simulate_deploy_with(&RealFs, plan)
+}
+
+/// [`simulate_deploy`] on top of `base` instead of the live filesystem.
+pub fn simulate_deploy_with(base: &dyn Fs, plan: &DeployPlan) -> Simulation {
+    simulate_deploy_answering(base, plan, &Answers::new())
+}
+
+/// [`simulate_deploy_with`], predicting [`apply_deploy_answering`].
+pub fn simulate_deploy_answering(
+    base: &dyn Fs,
+    plan: &DeployPlan,
+    answers: &Answers,
+) -> Simulation {
+    let overlay = OverlayFs::new(base);
+    let mut changes = Vec::new();
+    let outcome = run_deploy(&Ctx::simulated(&overlay, answers), plan, |_| {
+        changes.push(overlay.take_changes())
+    });
+    Simulation { outcome, changes }
+}
+
+fn run_deploy(ctx: &Ctx, plan: &DeployPlan, mut after_step: impl FnMut(usize)) -> ApplyOutcome {
+    let mut out = ApplyOutcome::default();
+    let mut quit_at = None;
+    for (i, act) in plan.actions.iter().enumerate() {
+        if ctx.leaves(&act.config_file) {
+            out.skipped += 1;
+            after_step(i);
+            continue;
+        }
+        let step = apply_one_deploy(ctx, act, plan.mode.interactive);
+        log_step(ctx, "deploy", &act.link_expanded, &step);
+        after_step(i);
+        if quit_at.is_none() && ctx.quit() {
+            quit_at = Some(i);
+        }
+        if record_step(&mut out, i, step) {
+            break;
         }
     }
+    ctx.finish(&mut out, quit_at);
     out
 }
 
-fn emit_sensitive_warning(act: &DeployAction) {
-    if let Some((mode, label)) = act.permission_required {
-        eprintln!(
-            "[警告] 链接 {} 命中敏感目标 ({}, 期望权限 {:o})；请确认该路径由 xdotter 管理",
-            act.link_expanded.display(),
-            label,
-            mode
-        );
+fn log_step(ctx: &Ctx, command: &str, link: &Path, step: &StepResult) {
+    logging::log("apply", Level::Debug, || {
+        let result = match step {
+            StepResult::Success => "成功".to_string(),
+            StepResult::SkippedFailure(e) => format!("跳过并计为失败: {}", e),
+            StepResult::HardFailure(e) => format!("失败并停止: {}", e),
+            StepResult::Left => "按用户回答保留".to_string(),
+        };
+        let sim = if ctx.simulated { " (预演)" } else { "" };
+        format!("{}{} {}: {}", command, sim, link.display(), result)
+    });
+}
+
+/// Fold one step into `out`; true when the run must stop.
+fn record_step(out: &mut ApplyOutcome, i: usize, step: StepResult) -> bool {
+    match step {
+        StepResult::Success => out.successes += 1,
+        StepResult::SkippedFailure(e) => {
+            out.failures += 1;
+            out.errors.push(e);
+            // continue with next link
+        }
+        StepResult::HardFailure(e) => {
+            out.failures += 1;
+            out.errors.push(e);
+            out.hard_failure = Some(i);
+            return true;
+        }
+        StepResult::Left => out.skipped += 1,
     }
+    false
 }
 
-fn apply_one_deploy(act: &DeployAction, interactive: bool) -> StepResult {
+fn apply_one_deploy(ctx: &Ctx, act: &DeployAction, interactive: bool) -> StepResult {
+    let fs = ctx.fs;
     let link = &act.link_expanded;
     let source = &act.source_canonical;
 
@@ -97,7 +360,7 @@ fn apply_one_deploy(act: &DeployAction, interactive: bool) -> StepResult {
     match &act.kind {
         DeployActionKind::AlreadyCorrect => {
             // Permission step still applies.
-            handle_permission(act, interactive)
+            handle_permission(ctx, act, interactive)
         }
         DeployActionKind::SkipFailure(reason) => StepResult::SkippedFailure(XdError::planning(
             format!("链接 {} 因可恢复冲突跳过: {}", link.display(), reason),
@@ -105,83 +368,264 @@ fn apply_one_deploy(act: &DeployAction, interactive: bool) -> StepResult {
         DeployActionKind::Create => {
             // Apply-stage re-check: source path must not have had symlink
             // components injected between planning and apply (TOCTOU defence).
-            if any_symlink_component(&act.source_canonical, &act.config_dir) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "应用阶段重新校验失败: 源路径 {} 出现了符号链接组件",
-                    act.source_canonical.display()
-                )));
+            if any_symlink_component(fs, &act.source_canonical, &act.config_dir) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "应用阶段重新校验失败: 源路径 {} 出现了符号链接组件",
+                        act.source_canonical.display()
+                    ))
+                    .with_code(codes::RECHECK_SOURCE_SYMLINK),
+                );
             }
-            if let Err(e) = ensure_parent_dir(link) {
+            if let Err(e) = ensure_parent_dir(fs, link) {
                 return StepResult::HardFailure(e);
             }
-            if let Err(e) = recheck_link_missing(link) {
+            let (dir, name) = match open_parent(fs, link) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    return StepResult::HardFailure(
+                        XdError::apply(format!("打开父目录失败 {}: {}", link.display(), e))
+                            .with_code(codes::FS_OPERATION_FAILED),
+                    )
+                }
+            };
+            if let Err(e) = recheck_link_missing(&*dir, name, link) {
                 return StepResult::HardFailure(e);
             }
-            if let Err(e) = create_symlink(link, source) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "创建符号链接失败 {} -> {}: {}",
-                    link.display(),
-                    source.display(),
-                    e
-                )));
+            if let Err(e) = dir.symlink(&link_target(fs, act), name) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "创建符号链接失败 {} -> {}: {}",
+                        link.display(),
+                        source.display(),
+                        e
+                    ))
+                    .with_code(codes::FS_OPERATION_FAILED),
+                );
             }
-            handle_permission(act, interactive)
+            handle_permission(ctx, act, interactive)
         }
         DeployActionKind::Replace(existing) => {
             // Interactive: prompt for the destructive replace.
             if interactive {
-                let prompt = format!(
-                    "替换 {} ({})? [y/N] ",
-                    link.display(),
-                    describe_existing(existing)
-                );
-                if !confirm(&prompt) {
+                let prompt = Prompt {
+                    kind: PromptKind::replace(existing),
+                    fs,
+                    link,
+                    source: Some(source),
+                    mode: None,
+                    config_file: &act.config_file,
+                };
+                match ctx.decide(&prompt) {
+                    Decision::Accept => {}
                     // User reject = SkippedFailure (continue with next link).
-                    return StepResult::SkippedFailure(XdError::planning(format!(
-                        "链接 {} 在交互确认时被拒绝",
-                        link.display()
-                    )));
+                    Decision::Decline => {
+                        return StepResult::SkippedFailure(XdError::planning(format!(
+                            "链接 {} 在交互确认时被拒绝",
+                            link.display()
+                        )))
+                    }
+                    Decision::Leave => return StepResult::Left,
                 }
             }
-            if let Err(e) = recheck_existing_kind(link, existing) {
+            let (dir, name) = match open_parent(fs, link) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    return StepResult::HardFailure(
+                        XdError::apply(format!(
+                            "应用阶段重新校验失败: 无法读取 {}: {}",
+                            link.display(),
+                            e
+                        ))
+                        .with_code(codes::RECHECK_TARGET_CHANGED),
+                    )
+                }
+            };
+            if let Err(e) = recheck_existing_kind(fs, &*dir, name, link, existing) {
                 if matches!(existing, ExistingKind::EmptyRealDir) {
                     // Empty dir became non-empty between plan and apply:
                     // treat as recoverable skip rather than hard failure.
-                    return StepResult::SkippedFailure(XdError::planning(format!(
-                        "{} 在规划后变为非空目录，跳过",
-                        link.display()
-                    )));
+                    return became_non_empty(link);
                 }
                 return StepResult::HardFailure(e);
             }
             // Apply-stage re-check: source path must not have had symlink
             // components injected between planning and apply.
-            if any_symlink_component(&act.source_canonical, &act.config_dir) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "应用阶段重新校验失败: 源路径 {} 出现了符号链接组件",
-                    act.source_canonical.display()
-                )));
+            if any_symlink_component(fs, &act.source_canonical, &act.config_dir) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "应用阶段重新校验失败: 源路径 {} 出现了符号链接组件",
+                        act.source_canonical.display()
+                    ))
+                    .with_code(codes::RECHECK_SOURCE_SYMLINK),
+                );
             }
-            if let Err(e) = remove_existing(link, existing) {
-                return StepResult::HardFailure(e);
+            // The new link is made under a temporary name and moved over
+            // the existing object, so the link path is never left empty.
+            remove_stale_temps(fs, &*dir, name);
+            let temp = temp_name(name);
+            if let Err(e) = dir.symlink(&link_target(fs, act), &temp) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "创建符号链接失败 {} -> {}: {}",
+                        dir.path().join(&temp).display(),
+                        source.display(),
+                        e
+                    ))
+                    .with_code(codes::FS_OPERATION_FAILED),
+                );
             }
-            if let Err(e) = ensure_parent_dir(link) {
-                return StepResult::HardFailure(e);
+            let swapped = match existing {
+                ExistingKind::EmptyRealDir => swap_empty_dir(&*dir, &temp, name, link),
+                _ => dir.rename(&temp, name).map_err(|e| {
+                    let _ = dir.remove_file(&temp);
+                    StepResult::HardFailure(replace_failed(link, e))
+                }),
+            };
+            if let Err(step) = swapped {
+                return step;
+            }
+            handle_permission(ctx, act, interactive)
+        }
+    }
+}
+
+/// Longest file name most filesystems accept, in bytes.
+const NAME_MAX: usize = 255;
+
+/// Start of the temporary names used for replacing `name`:
+/// `.<name>.xd-`, or just `.xd-` where the full temporary name could
+/// exceed [`NAME_MAX`] with the longest PID.
+fn temp_prefix(name: &OsStr) -> OsString {
+    let longest = ".".len() + name.len() + ".xd-".len() + u32::MAX.to_string().len() + ".new".len();
+    let mut prefix = OsString::from(".");
+    if longest <= NAME_MAX {
+        prefix.push(name);
+        prefix.push(".");
+    }
+    prefix.push("xd-");
+    prefix
+}
+
+/// Sibling name a replacing symlink is created under.
+fn temp_name(name: &OsStr) -> OsString {
+    let mut temp = temp_prefix(name);
+    temp.push(format!("{}.new", std::process::id()));
+    temp
+}
+
+/// Remove symlinks left under a temporary name for `name` by a run that
+/// stopped between creating and renaming one, whatever its PID. The run
+/// lock keeps any other run from using these names meanwhile. Entries
+/// that are not symlinks are not ours and are left alone; failures only
+/// mean the debris stays.
+fn remove_stale_temps(fs: &dyn Fs, dir: &dyn Dir, name: &OsStr) {
+    let prefix = temp_prefix(name);
+    let prefix = prefix.as_encoded_bytes();
+    let Ok(entries) = fs.read_dir(dir.path()) else {
+        return;
+    };
+    for entry in entries {
+        let Some(entry) = entry.file_name() else {
+            continue;
+        };
+        let is_temp = entry
+            .as_encoded_bytes()
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(b".new"))
+            .is_some_and(|pid| !pid.is_empty() && pid.iter().all(u8::is_ascii_digit));
+        if is_temp
+            && dir
+                .symlink_metadata(entry)
+                .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            let _ = dir.remove_file(entry);
+        }
+    }
+}
+
+/// Put the symlink `temp` in place of the empty directory `name`.
+///
+/// Where the two can be exchanged atomically, the directory ends up under
+/// `temp` and is removed there; should it have gained entries meanwhile,
+/// it is exchanged back and the step skipped. Otherwise the directory is
+/// removed first, which fails if it is no longer empty, and `temp`
+/// renamed into its place.
+fn swap_empty_dir(
+    dir: &dyn Dir,
+    temp: &OsStr,
+    name: &OsStr,
+    link: &Path,
+) -> Result<(), StepResult> {
+    let discard_temp = || {
+        let _ = dir.remove_file(temp);
+    };
+    match dir.exchange(temp, name) {
+        Ok(()) => match dir.remove_dir(temp) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::DirectoryNotEmpty => {
+                match dir.exchange(temp, name) {
+                    Ok(()) => {
+                        discard_temp();
+                        Err(became_non_empty(link))
+                    }
+                    Err(e) => Err(StepResult::HardFailure(
+                        XdError::apply(format!(
+                            "{} 在规划后变为非空目录，已被移至 {} 且无法移回: {}",
+                            link.display(),
+                            dir.path().join(temp).display(),
+                            e
+                        ))
+                        .with_code(codes::RECHECK_TARGET_CHANGED),
+                    )),
+                }
             }
-            if let Err(e) = create_symlink(link, source) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "创建符号链接失败 {} -> {}: {}",
-                    link.display(),
-                    source.display(),
+            Err(e) => Err(StepResult::HardFailure(
+                XdError::apply(format!(
+                    "删除空目录 {} 失败: {}",
+                    dir.path().join(temp).display(),
                     e
-                )));
+                ))
+                .with_code(codes::FS_OPERATION_FAILED),
+            )),
+        },
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+            if let Err(e) = dir.remove_dir(name) {
+                discard_temp();
+                if e.kind() == io::ErrorKind::DirectoryNotEmpty {
+                    return Err(became_non_empty(link));
+                }
+                return Err(StepResult::HardFailure(
+                    XdError::apply(format!("删除空目录 {} 失败: {}", link.display(), e))
+                        .with_code(codes::FS_OPERATION_FAILED),
+                ));
             }
-            handle_permission(act, interactive)
+            dir.rename(temp, name).map_err(|e| {
+                discard_temp();
+                StepResult::HardFailure(replace_failed(link, e))
+            })
+        }
+        Err(e) => {
+            discard_temp();
+            Err(StepResult::HardFailure(replace_failed(link, e)))
         }
     }
 }
 
-fn handle_permission(act: &DeployAction, interactive: bool) -> StepResult {
+fn became_non_empty(link: &Path) -> StepResult {
+    StepResult::SkippedFailure(
+        XdError::planning(format!("{} 在规划后变为非空目录，跳过", link.display()))
+            .with_code(codes::RECHECK_TARGET_CHANGED),
+    )
+}
+
+fn replace_failed(link: &Path, e: io::Error) -> XdError {
+    XdError::apply(format!("替换 {} 失败: {}", link.display(), e))
+        .with_code(codes::FS_OPERATION_FAILED)
+}
+
+fn handle_permission(ctx: &Ctx, act: &DeployAction, interactive: bool) -> StepResult {
+    let fs = ctx.fs;
     match (&act.permission_action, &act.permission_required) {
         (PermissionAction::None, _) | (PermissionAction::AlreadyOk, _) => StepResult::Success,
         (PermissionAction::SkipFailure(reason), _) => {
@@ -193,44 +637,61 @@ fn handle_permission(act: &DeployAction, interactive: bool) -> StepResult {
         }
         (PermissionAction::Fix, Some((mode, label))) => {
             if interactive {
-                let prompt = format!(
-                    "修复 {} 权限为 {:o}? [y/N] ",
-                    act.link_expanded.display(),
-                    mode
-                );
-                if !confirm(&prompt) {
+                let prompt = Prompt {
+                    kind: PromptKind::FixPermission,
+                    fs,
+                    link: &act.link_expanded,
+                    source: Some(&act.source_canonical),
+                    mode: Some(*mode),
+                    config_file: &act.config_file,
+                };
+                match ctx.decide(&prompt) {
+                    Decision::Accept => {}
                     // User reject = SkippedFailure: per SPEC interactive
                     // granularity, rejecting any required destructive
                     // operation skips the entire link, but we move on
                     // to the next link rather than abort.
-                    return StepResult::SkippedFailure(XdError::planning(format!(
-                        "链接 {} 的权限修复在交互确认时被拒绝",
-                        act.link_expanded.display()
-                    )));
+                    Decision::Decline => {
+                        return StepResult::SkippedFailure(XdError::planning(format!(
+                            "链接 {} 的权限修复在交互确认时被拒绝",
+                            act.link_expanded.display()
+                        )))
+                    }
+                    Decision::Leave => return StepResult::Left,
                 }
             }
             // Apply-stage re-check: target must still resolve to the configured source.
             // Also re-verify source path has no symlink component injection.
-            if !target_matches_source(&act.link_expanded, &act.source_canonical) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "权限修复前重新校验失败: {} 不再指向 {}",
-                    act.link_expanded.display(),
-                    act.source_canonical.display()
-                )));
-            }
-            if any_symlink_component(&act.source_canonical, &act.config_dir) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "权限修复前重新校验失败: 源路径 {} 出现了符号链接组件",
-                    act.source_canonical.display()
-                )));
-            }
-            if !permissions::fix_permission(&act.source_canonical, *mode) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "修复 {} 权限失败 ({} 要求 {:o})",
-                    act.source_canonical.display(),
-                    label,
-                    mode
-                )));
+            if !target_matches_source(fs, &act.link_expanded, &act.source_canonical) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "权限修复前重新校验失败: {} 不再指向 {}",
+                        act.link_expanded.display(),
+                        act.source_canonical.display()
+                    ))
+                    .with_code(codes::RECHECK_PERMISSION_TARGET),
+                );
+            }
+            if any_symlink_component(fs, &act.source_canonical, &act.config_dir) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "权限修复前重新校验失败: 源路径 {} 出现了符号链接组件",
+                        act.source_canonical.display()
+                    ))
+                    .with_code(codes::RECHECK_SOURCE_SYMLINK),
+                );
+            }
+            if let Err(e) = fix_source_permission(fs, act, *mode) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!(
+                        "修复 {} 权限失败 ({} 要求 {:o}): {}",
+                        act.source_canonical.display(),
+                        label,
+                        mode,
+                        e
+                    ))
+                    .with_code(codes::FS_OPERATION_FAILED),
+                );
             }
             StepResult::Success
         }
@@ -238,45 +699,84 @@ fn handle_permission(act: &DeployAction, interactive: bool) -> StepResult {
     }
 }
 
-fn ensure_parent_dir(link: &Path) -> Result<(), XdError> {
+/// Target to write into the symlink: the canonical source, or for
+/// relative entries the path to it from the link's (existing) parent.
+/// The parent is canonicalized so the `..` steps match how the kernel
+/// resolves them through symlinked ancestors.
+fn link_target(fs: &dyn Fs, act: &DeployAction) -> PathBuf {
+    let source = &act.source_canonical;
+    if !act.relative {
+        return source.clone();
+    }
+    act.link_expanded
+        .parent()
+        .and_then(|p| fs.canonicalize(p).ok())
+        .and_then(|base| crate::path::relative_to(source, &base))
+        .unwrap_or_else(|| source.clone())
+}
+
+fn ensure_parent_dir(fs: &dyn Fs, link: &Path) -> Result<(), XdError> {
     if let Some(parent) = link.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent).map_err(|e| {
+        if !fs.exists(parent) {
+            fs.create_dir_all(parent).map_err(|e| {
                 XdError::apply(format!("创建父目录失败 {}: {}", parent.display(), e))
+                    .with_code(codes::FS_OPERATION_FAILED)
             })?;
         }
     }
     Ok(())
 }
 
-fn recheck_link_missing(link: &Path) -> Result<(), XdError> {
-    if fs::symlink_metadata(link).is_ok() {
-        return Err(XdError::apply(format!(
-            "应用阶段重新校验失败: {} 已存在",
-            link.display()
-        )));
+/// The parent directory of `link`, opened, and the entry name of `link`
+/// in it.
+fn open_parent<'f>(fs: &'f dyn Fs, link: &'f Path) -> io::Result<(Box<dyn Dir + 'f>, &'f OsStr)> {
+    match (link.parent(), link.file_name()) {
+        (Some(parent), Some(name)) => Ok((fs.open_dir(parent)?, name)),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "链接路径没有父目录",
+        )),
+    }
+}
+
+fn recheck_link_missing(dir: &dyn Dir, name: &OsStr, link: &Path) -> Result<(), XdError> {
+    if dir.symlink_metadata(name).is_ok() {
+        return Err(
+            XdError::apply(format!("应用阶段重新校验失败: {} 已存在", link.display()))
+                .with_code(codes::RECHECK_LINK_EXISTS),
+        );
     }
     Ok(())
 }
 
-fn recheck_existing_kind(link: &Path, expected: &ExistingKind) -> Result<(), XdError> {
-    let meta = fs::symlink_metadata(link).map_err(|e| {
+/// Re-check the kind of `name` in `dir` (at `link`). Whether a symlink
+/// resolves and whether a directory is empty are only looked up by path:
+/// they classify the entry but are not acted on.
+fn recheck_existing_kind(
+    fs: &dyn Fs,
+    dir: &dyn Dir,
+    name: &OsStr,
+    link: &Path,
+    expected: &ExistingKind,
+) -> Result<(), XdError> {
+    let meta = dir.symlink_metadata(name).map_err(|e| {
         XdError::apply(format!(
             "应用阶段重新校验失败: 无法读取 {}: {}",
             link.display(),
             e
         ))
+        .with_code(codes::RECHECK_TARGET_CHANGED)
     })?;
     let ft = meta.file_type();
     let actual = if ft.is_symlink() {
-        match fs::read_link(link) {
+        match dir.read_link(name) {
             Ok(t) => {
                 let abs = if t.is_absolute() {
                     t
                 } else {
                     link.parent().map(|p| p.join(&t)).unwrap_or(t)
                 };
-                if abs.canonicalize().is_ok() {
+                if fs.canonicalize(&abs).is_ok() {
                     ExistingKind::WrongSymlink
                 } else {
                     ExistingKind::BrokenSymlink
@@ -287,22 +787,21 @@ fn recheck_existing_kind(link: &Path, expected: &ExistingKind) -> Result<(), XdE
     } else if ft.is_file() {
         ExistingKind::RegularFile
     } else if ft.is_dir() {
-        if fs::read_dir(link)
-            .map(|mut it| it.next().is_none())
-            .unwrap_or(false)
-        {
+        if fs.read_dir(link).map(|es| es.is_empty()).unwrap_or(false) {
             ExistingKind::EmptyRealDir
         } else {
             return Err(XdError::apply(format!(
                 "应用阶段重新校验失败: {} 已变成非空目录",
                 link.display()
-            )));
+            ))
+            .with_code(codes::RECHECK_TARGET_CHANGED));
         }
     } else {
         return Err(XdError::apply(format!(
             "应用阶段重新校验失败: {} 类型不再可处理",
             link.display()
-        )));
+        ))
+        .with_code(codes::RECHECK_TARGET_CHANGED));
     };
     if &actual != expected {
         return Err(XdError::apply(format!(
@@ -310,31 +809,49 @@ fn recheck_existing_kind(link: &Path, expected: &ExistingKind) -> Result<(), XdE
             link.display(),
             expected,
             actual
-        )));
+        ))
+        .with_code(codes::RECHECK_TARGET_CHANGED));
     }
     Ok(())
 }
 
-fn remove_existing(link: &Path, kind: &ExistingKind) -> Result<(), XdError> {
-    match kind {
-        ExistingKind::RegularFile | ExistingKind::WrongSymlink | ExistingKind::BrokenSymlink => {
-            fs::remove_file(link)
-                .map_err(|e| XdError::apply(format!("删除 {} 失败: {}", link.display(), e)))
-        }
-        ExistingKind::EmptyRealDir => fs::remove_dir(link)
-            .map_err(|e| XdError::apply(format!("删除空目录 {} 失败: {}", link.display(), e))),
+/// Set `mode` on the source, reached from its configuration directory
+/// one directory handle at a time. No component is followed if it is a
+/// symlink, so one swapped in after the re-check fails the walk instead
+/// of redirecting the change.
+fn fix_source_permission(fs: &dyn Fs, act: &DeployAction, mode: u32) -> io::Result<()> {
+    let source = &act.source_canonical;
+    let boundary = fs
+        .canonicalize(&act.config_dir)
+        .unwrap_or_else(|_| act.config_dir.clone());
+    let Ok(rel) = source.strip_prefix(&boundary) else {
+        // Outside of the configuration directory: nothing to walk.
+        return fs.set_permissions(source, mode);
+    };
+    let names: Vec<&OsStr> = rel.iter().collect();
+    chmod_below(&*fs.open_dir(&boundary)?, &names, mode)
+}
+
+fn chmod_below(dir: &dyn Dir, names: &[&OsStr], mode: u32) -> io::Result<()> {
+    match names {
+        [] => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "源路径就是配置目录",
+        )),
+        [name] => dir.set_permissions(name, mode),
+        [first, rest @ ..] => chmod_below(&*dir.open_dir(first)?, rest, mode),
     }
 }
 
-fn target_matches_source(link: &Path, source_canon: &Path) -> bool {
-    match fs::read_link(link) {
+fn target_matches_source(fs: &dyn Fs, link: &Path, source_canon: &Path) -> bool {
+    match fs.read_link(link) {
         Ok(t) => {
             let abs = if t.is_absolute() {
                 t
             } else {
                 link.parent().map(|p| p.join(&t)).unwrap_or(t)
             };
-            abs.canonicalize()
+            fs.canonicalize(&abs)
                 .map(|c| c == source_canon)
                 .unwrap_or(false)
         }
@@ -342,56 +859,99 @@ fn target_matches_source(link: &Path, source_canon: &Path) -> bool {
     }
 }
 
-#[cfg(unix)]
-fn create_symlink(link: &Path, source: &Path) -> io::Result<()> {
-    unix_fs::symlink(source, link)
-}
-
-#[cfg(windows)]
-fn create_symlink(link: &Path, source: &Path) -> io::Result<()> {
-    if source.is_dir() {
-        std::os::windows::fs::symlink_dir(source, link)
-    } else {
-        std::os::windows::fs::symlink_file(source, link)
-    }
-}
-
 // -----------------------------------------------------------------------------
 // Undeploy
 // -----------------------------------------------------------------------------
 
+/// Execute an undeploy plan; same failure semantics as
+/// [`apply_deploy`].
 pub fn apply_undeploy(plan: &UndeployPlan) -> ApplyOutcome {
+    apply_undeploy_with(&RealFs, plan)
+}
+
+/// [`apply_undeploy`] against `fs` instead of the live filesystem.
+pub fn apply_undeploy_with(fs: &dyn Fs, plan: &UndeployPlan) -> ApplyOutcome {
+    apply_undeploy_answering(fs, plan, &Answers::new())
+}
+
+/// [`apply_undeploy_with`], settling interactive prompts covered by
+/// `answers` without asking.
+pub fn apply_undeploy_answering(
+    fs: &dyn Fs,
+    plan: &UndeployPlan,
+    answers: &Answers,
+) -> ApplyOutcome {
+    apply_undeploy_observed(fs, plan, answers, |_| {})
+}
+
+/// [`apply_undeploy_answering`], calling `after_step` with the index of
+/// each action as soon as it is done.
+pub fn apply_undeploy_observed(
+    fs: &dyn Fs,
+    plan: &UndeployPlan,
+    answers: &Answers,
+    after_step: impl FnMut(usize),
+) -> ApplyOutcome {
+    run_undeploy(&Ctx::live(fs, answers), plan, after_step)
+}
+
+/// Predict [`apply_undeploy`] without modifying the filesystem.
+pub fn simulate_undeploy(plan: &UndeployPlan) -> Simulation {
+    simulate_undeploy_with(&RealFs, plan)
+}
+
+/// [`simulate_undeploy`] on top of `base` instead of the live filesystem.
+pub fn simulate_undeploy_with(base: &dyn Fs, plan: &UndeployPlan) -> Simulation {
+    simulate_undeploy_answering(base, plan, &Answers::new())
+}
+
+/// [`simulate_undeploy_with`], predicting [`apply_undeploy_answering`].
+pub fn simulate_undeploy_answering(
+    base: &dyn Fs,
+    plan: &UndeployPlan,
+    answers: &Answers,
+) -> Simulation {
+    let overlay = OverlayFs::new(base);
+    let mut changes = Vec::new();
+    let outcome = run_undeploy(&Ctx::simulated(&overlay, answers), plan, |_| {
+        changes.push(overlay.take_changes())
+    });
+    Simulation { outcome, changes }
+}
+
+fn run_undeploy(ctx: &Ctx, plan: &UndeployPlan, mut after_step: impl FnMut(usize)) -> ApplyOutcome {
     let mut out = ApplyOutcome::default();
-    for act in &plan.actions {
-        match apply_one_undeploy(act, plan.mode.interactive) {
-            StepResult::Success => out.successes += 1,
-            StepResult::SkippedFailure(e) => {
-                out.failures += 1;
-                out.errors.push(e);
-                // continue with next link
-            }
-            StepResult::HardFailure(e) => {
-                out.failures += 1;
-                out.errors.push(e);
-                break;
-            }
+    let mut quit_at = None;
+    for (i, act) in plan.actions.iter().enumerate() {
+        if ctx.leaves(&act.config_file) {
+            out.skipped += 1;
+            after_step(i);
+            continue;
+        }
+        let step = apply_one_undeploy(ctx, act, plan.mode.interactive);
+        log_step(ctx, "undeploy", &act.link_expanded, &step);
+        after_step(i);
+        if quit_at.is_none() && ctx.quit() {
+            quit_at = Some(i);
+        }
+        if record_step(&mut out, i, step) {
+            break;
         }
     }
+    ctx.finish(&mut out, quit_at);
     out
 }
 
-fn apply_one_undeploy(act: &UndeployAction, interactive: bool) -> StepResult {
+fn apply_one_undeploy(ctx: &Ctx, act: &UndeployAction, interactive: bool) -> StepResult {
+    let fs = ctx.fs;
     let link = &act.link_expanded;
 
     match &act.kind {
         UndeployActionKind::NotPresent => StepResult::Success,
         UndeployActionKind::NotASymlinkWarning => {
             // SPEC §undeploy table: "存在但不是符号链接 → 警告，计为失败，不删除"
-            // — count as a failure but continue to the next link.
-            eprintln!(
-                "[警告] 链接路径 {} 是非符号链接对象，未删除",
-                link.display()
-            );
+            // — count as a failure but continue to the next link. The
+            // warning itself comes from planning.
             StepResult::SkippedFailure(XdError::planning(format!(
                 "链接路径 {} 不是符号链接，未删除",
                 link.display()
@@ -404,35 +964,53 @@ fn apply_one_undeploy(act: &UndeployAction, interactive: bool) -> StepResult {
         | UndeployActionKind::DeleteBroken
         | UndeployActionKind::DeleteWrong => {
             if interactive {
-                let prompt = format!("删除 {}? [y/N] ", link.display());
-                if !confirm(&prompt) {
-                    return StepResult::SkippedFailure(XdError::planning(format!(
-                        "链接 {} 在交互确认时被拒绝",
-                        link.display()
-                    )));
+                let prompt = Prompt {
+                    // Only the three delete kinds reach this arm.
+                    kind: PromptKind::delete(&act.kind).unwrap_or(PromptKind::DeleteCorrectSymlink),
+                    fs,
+                    link,
+                    source: act.source_canonical.as_deref(),
+                    mode: None,
+                    config_file: &act.config_file,
+                };
+                match ctx.decide(&prompt) {
+                    Decision::Accept => {}
+                    Decision::Decline => {
+                        return StepResult::SkippedFailure(XdError::planning(format!(
+                            "链接 {} 在交互确认时被拒绝",
+                            link.display()
+                        )))
+                    }
+                    Decision::Leave => return StepResult::Left,
                 }
             }
             // Apply-stage re-check: link must still be a symlink.
-            match fs::symlink_metadata(link) {
+            let Ok((dir, name)) = open_parent(fs, link) else {
+                // Its directory vanished, and the link with it.
+                return StepResult::Success;
+            };
+            match dir.symlink_metadata(name) {
                 Err(_) => {
                     // It vanished — count as success per "link missing -> silent success".
                     return StepResult::Success;
                 }
                 Ok(m) => {
                     if !m.file_type().is_symlink() {
-                        return StepResult::HardFailure(XdError::apply(format!(
-                            "应用阶段重新校验失败: {} 不再是符号链接",
-                            link.display()
-                        )));
+                        return StepResult::HardFailure(
+                            XdError::apply(format!(
+                                "应用阶段重新校验失败: {} 不再是符号链接",
+                                link.display()
+                            ))
+                            .with_code(codes::RECHECK_NOT_SYMLINK),
+                        );
                     }
                 }
             }
-            if let Err(e) = fs::remove_file(link) {
-                return StepResult::HardFailure(XdError::apply(format!(
-                    "删除符号链接失败 {}: {}",
-                    link.display(),
-                    e
-                )));
+            if let Err(e) = dir.remove_file(name) {
+                return StepResult::HardFailure(
+                    XdError::apply(format!("删除符号链接失败 {}: {}", link.display(), e))
+                        .with_code(codes::FS_OPERATION_FAILED),
+                );
             }
             StepResult::Success
         }
@@ -443,20 +1021,1005 @@ fn apply_one_undeploy(act: &UndeployAction, interactive: bool) -> StepResult {
 // Confirmation helper
 // -----------------------------------------------------------------------------
 
-/// Read a yes/no answer from stdin. Per SPEC: only `y` / `yes`
-/// (case-insensitive) confirm; empty / EOF / non-TTY all reject.
-pub fn confirm(prompt: &str) -> bool {
+/// One question put to the user.
+struct Prompt<'a> {
+    kind: PromptKind,
+    fs: &'a dyn Fs,
+    link: &'a Path,
+    source: Option<&'a Path>,
+    /// Required mode, for [`PromptKind::FixPermission`].
+    mode: Option<u32>,
+    config_file: &'a Path,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    /// Yes to this and every later prompt of the same kind.
+    YesToAll,
+    /// No to this and every later prompt.
+    NoToAll,
+    /// Leave this and every later action untouched.
+    Quit,
+    /// Leave the rest of this prompt's config file untouched.
+    SkipDependency,
+}
+
+/// What the current step does after [`Ctx::decide`].
+enum Decision {
+    Accept,
+    Decline,
+    Leave,
+}
+
+enum Reply {
+    Answer(Answer),
+    Diff,
+    Help,
+}
+
+const CHOICES: &str = "[y/N/a/x/q/d/s/?]";
+
+const HELP: &str = "\
+y - 是
+n - 否（默认）
+a - 是，并对其余同类操作都回答是
+x - 否，并对其余所有操作都回答否
+q - 退出：本条及其余链接保持不变，计为跳过
+d - 显示差异后重新询问
+s - 跳过当前依赖配置中的其余链接，计为跳过
+? - 显示本帮助";
+
+impl Prompt<'_> {
+    fn question(&self) -> String {
+        match self.kind {
+            PromptKind::FixPermission => format!(
+                "修复 {} 权限为 {:o}?",
+                self.link.display(),
+                self.mode.unwrap_or_default()
+            ),
+            PromptKind::DeleteCorrectSymlink
+            | PromptKind::DeleteBrokenSymlink
+            | PromptKind::DeleteWrongSymlink => {
+                format!("删除 {}?", self.link.display())
+            }
+            PromptKind::ReplaceFile => self.replace_question(ExistingKind::RegularFile),
+            PromptKind::ReplaceEmptyDir => self.replace_question(ExistingKind::EmptyRealDir),
+            PromptKind::ReplaceWrongSymlink => self.replace_question(ExistingKind::WrongSymlink),
+            PromptKind::ReplaceBrokenSymlink => self.replace_question(ExistingKind::BrokenSymlink),
+        }
+    }
+
+    fn replace_question(&self, existing: ExistingKind) -> String {
+        format!(
+            "替换 {} ({})?",
+            self.link.display(),
+            describe_existing(&existing)
+        )
+    }
+
+    /// What accepting would change, for the `d` answer.
+    fn diff(&self) -> String {
+        let link = self.link.display();
+        let source = self.source.map(|s| s.display().to_string());
+        let source = source.as_deref().unwrap_or("?");
+        let target = || {
+            self.fs
+                .read_link(self.link)
+                .map(|t| t.display().to_string())
+                .unwrap_or_else(|_| "?".to_string())
+        };
+        match self.kind {
+            PromptKind::ReplaceFile => {
+                let src_is_file = self
+                    .source
+                    .and_then(|s| self.fs.metadata(s).ok())
+                    .is_some_and(|m| m.is_file());
+                if !src_is_file {
+                    return format!("{} 是目录；{} 将被替换为指向它的符号链接\n", source, link);
+                }
+                // Contents are read from the live filesystem; `Fs` only
+                // models metadata.
+                let old = std::fs::read_to_string(self.link);
+                let new = self.source.map(std::fs::read_to_string);
+                match (old, new) {
+                    (Ok(old), Some(Ok(new))) => {
+                        diff::unified(&old, &new, &link.to_string(), source)
+                    }
+                    _ => "二进制文件或无法读取，不显示差异\n".to_string(),
+                }
+            }
+            PromptKind::ReplaceEmptyDir => {
+                format!("- {}/ (空目录)\n+ {} -> {}\n", link, link, source)
+            }
+            PromptKind::ReplaceWrongSymlink | PromptKind::ReplaceBrokenSymlink => {
+                format!("- {} -> {}\n+ {} -> {}\n", link, target(), link, source)
+            }
+            PromptKind::FixPermission => {
+                let current = self
+                    .source
+                    .and_then(|s| self.fs.metadata(s).ok())
+                    .map(|m| format!("{:o}", m.mode() & 0o7777))
+                    .unwrap_or_else(|| "?".to_string());
+                format!(
+                    "  {}\n- 权限 {}\n+ 权限 {:o}\n",
+                    source,
+                    current,
+                    self.mode.unwrap_or_default()
+                )
+            }
+            PromptKind::DeleteCorrectSymlink
+            | PromptKind::DeleteBrokenSymlink
+            | PromptKind::DeleteWrongSymlink => {
+                format!("- {} -> {}\n", link, target())
+            }
+        }
+    }
+}
+
+/// SPEC §"交互模式": only `y`/`yes` confirm; the extra answers are
+/// spelled out in [`HELP`]; anything else, including empty input,
+/// declines.
+fn parse_reply(line: &str) -> Reply {
+    match line.trim().to_ascii_lowercase().as_str() {
+        "y" | "yes" => Reply::Answer(Answer::Yes),
+        "a" | "all" => Reply::Answer(Answer::YesToAll),
+        "x" | "none" => Reply::Answer(Answer::NoToAll),
+        "q" | "quit" => Reply::Answer(Answer::Quit),
+        "s" | "skip" => Reply::Answer(Answer::SkipDependency),
+        "d" | "diff" => Reply::Diff,
+        "?" | "help" => Reply::Help,
+        _ => Reply::Answer(Answer::No),
+    }
+}
+
+/// Ask on the terminal; EOF and a non-TTY stdin decline.
+fn ask_terminal(p: &Prompt) -> Answer {
     if !io::stdin().is_terminal() {
-        return false;
-    }
-    print!("{}", prompt);
-    let _ = io::stdout().flush();
-    let stdin = io::stdin();
-    let mut line = String::new();
-    let n = stdin.lock().read_line(&mut line).unwrap_or(0);
-    if n == 0 {
-        return false; // EOF
-    }
-    let trimmed = line.trim().to_ascii_lowercase();
-    matches!(trimmed.as_str(), "y" | "yes")
+        return Answer::No;
+    }
+    ask_on(&mut io::stdin().lock(), &mut io::stdout(), p)
+}
+
+/// Ask `p` on `output` until `input` gives an answer; EOF declines.
+/// Everything the prompt prints, help and diffs included, goes to
+/// `output`.
+fn ask_on(input: &mut dyn BufRead, output: &mut dyn Write, p: &Prompt) -> Answer {
+    loop {
+        let _ = write!(output, "{} {} ", p.question(), CHOICES);
+        let _ = output.flush();
+        let mut line = String::new();
+        let n = input.read_line(&mut line).unwrap_or(0);
+        if n == 0 {
+            return Answer::No; // EOF
+        }
+        let _ = match parse_reply(&line) {
+            Reply::Answer(Answer::YesToAll) => {
+                let _ = writeln!(output, "其余「{}」操作将自动确认", p.kind.describe());
+                return Answer::YesToAll;
+            }
+            Reply::Answer(a) => return a,
+            Reply::Diff => write!(output, "{}", p.diff()),
+            Reply::Help => writeln!(output, "{}", HELP),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::{MemFs, Op};
+    use crate::plan::ConflictModeRecord;
+    use std::path::PathBuf;
+
+    const AFTER: &str = "/home/after";
+
+    fn repo() -> MemFs {
+        let fs = MemFs::new();
+        fs.add_file("/repo/f", 0o644);
+        fs.add_file("/repo/after", 0o644);
+        fs.add_dir("/home");
+        fs
+    }
+
+    fn deploy(link: &str, source: &str, kind: DeployActionKind) -> DeployAction {
+        DeployAction {
+            config_file: PathBuf::from("/repo/xdotter.toml"),
+            config_dir: PathBuf::from("/repo"),
+            source_raw: source.trim_start_matches("/repo/").to_string(),
+            source_canonical: PathBuf::from(source),
+            link_raw: link.to_string(),
+            link_expanded: PathBuf::from(link),
+            relative: false,
+            kind,
+            permission_required: None,
+            permission_action: PermissionAction::None,
+        }
+    }
+
+    fn perm_fix(link: &str, source: &str) -> DeployAction {
+        DeployAction {
+            permission_required: Some((0o600, "test target")),
+            permission_action: PermissionAction::Fix,
+            ..deploy(link, source, DeployActionKind::AlreadyCorrect)
+        }
+    }
+
+    /// Apply `act` followed by a harmless create; the hard failure must
+    /// stop the loop before the second action runs.
+    fn assert_hard_failure(fs: &MemFs, act: DeployAction, needle: &str) {
+        let plan = DeployPlan {
+            actions: vec![act, deploy(AFTER, "/repo/after", DeployActionKind::Create)],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(fs, &plan);
+        assert_eq!((out.successes, out.failures), (0, 1), "{:?}", out);
+        let msg = out.errors.into_error().to_string();
+        assert!(msg.starts_with("[应用阶段错误]"), "{msg}");
+        assert!(msg.contains(needle), "expected {needle:?} in {msg}");
+        assert!(!fs.is_symlink(Path::new(AFTER)), "loop must stop");
+    }
+
+    fn assert_undeploy_hard_failure(fs: &MemFs, link: &str, needle: &str) {
+        let act = |link: &str| UndeployAction {
+            config_file: PathBuf::from("/repo/xdotter.toml"),
+            source_raw: "f".to_string(),
+            source_canonical: Some(PathBuf::from("/repo/f")),
+            link_raw: link.to_string(),
+            link_expanded: PathBuf::from(link),
+            kind: UndeployActionKind::DeleteCorrect,
+        };
+        fs.add_symlink(AFTER, "/repo/after");
+        let plan = UndeployPlan {
+            actions: vec![act(link), act(AFTER)],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_undeploy_with(fs, &plan);
+        assert_eq!((out.successes, out.failures), (0, 1), "{:?}", out);
+        let msg = out.errors.into_error().to_string();
+        assert!(msg.contains(needle), "expected {needle:?} in {msg}");
+        assert!(fs.is_symlink(Path::new(AFTER)), "loop must stop");
+    }
+
+    #[test]
+    fn create_and_permission_fix_succeed() {
+        let fs = repo();
+        let plan = DeployPlan {
+            actions: vec![
+                deploy("/home/.config/a", "/repo/f", DeployActionKind::Create),
+                deploy(AFTER, "/repo/after", DeployActionKind::Create),
+            ],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (2, 0));
+        assert_eq!(
+            fs.canonicalize(Path::new("/home/.config/a")).unwrap(),
+            Path::new("/repo/f")
+        );
+    }
+
+    #[test]
+    fn relative_entries_link_from_the_resolved_parent() {
+        let fs = repo();
+        fs.add_dir("/data/u");
+        fs.add_symlink("/home/u", "/data/u");
+        let relative = |link| DeployAction {
+            relative: true,
+            ..deploy(link, "/repo/f", DeployActionKind::Create)
+        };
+        let plan = DeployPlan {
+            actions: vec![relative("/home/.config/a"), relative("/home/u/a")],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (2, 0));
+        for (link, target) in [
+            ("/home/.config/a", "../../repo/f"),
+            ("/home/u/a", "../../repo/f"),
+        ] {
+            assert_eq!(fs.read_link(Path::new(link)).unwrap(), Path::new(target));
+            assert!(target_matches_source(
+                &fs,
+                Path::new(link),
+                Path::new("/repo/f")
+            ));
+        }
+    }
+
+    #[test]
+    fn create_fails_when_source_gained_symlink_component() {
+        let fs = repo();
+        fs.add_file("/elsewhere/f", 0o644);
+        fs.add_symlink("/repo/sub", "/elsewhere");
+        let act = deploy("/home/a", "/repo/sub/f", DeployActionKind::Create);
+        assert_hard_failure(&fs, act, "出现了符号链接组件");
+    }
+
+    #[test]
+    fn create_fails_when_parent_cannot_be_created() {
+        let fs = repo();
+        fs.fail(
+            Op::CreateDirAll,
+            "/home/.config",
+            io::ErrorKind::PermissionDenied,
+        );
+        let act = deploy("/home/.config/a", "/repo/f", DeployActionKind::Create);
+        assert_hard_failure(&fs, act, "创建父目录失败");
+    }
+
+    #[test]
+    fn create_fails_when_link_appeared_after_planning() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        let act = deploy("/home/a", "/repo/f", DeployActionKind::Create);
+        assert_hard_failure(&fs, act, "已存在");
+    }
+
+    #[test]
+    fn create_fails_when_symlink_creation_fails() {
+        let fs = repo();
+        fs.fail(Op::Symlink, "/home/a", io::ErrorKind::PermissionDenied);
+        let act = deploy("/home/a", "/repo/f", DeployActionKind::Create);
+        assert_hard_failure(&fs, act, "创建符号链接失败");
+    }
+
+    #[test]
+    fn replace_fails_when_existing_kind_changed() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        let act = deploy(
+            "/home/a",
+            "/repo/f",
+            DeployActionKind::Replace(ExistingKind::WrongSymlink),
+        );
+        assert_hard_failure(&fs, act, "类型变化");
+    }
+
+    #[test]
+    fn replace_of_dir_that_became_non_empty_is_skipped_not_fatal() {
+        let fs = repo();
+        fs.add_file("/home/a/new", 0o644);
+        let plan = DeployPlan {
+            actions: vec![
+                deploy(
+                    "/home/a",
+                    "/repo/f",
+                    DeployActionKind::Replace(ExistingKind::EmptyRealDir),
+                ),
+                deploy(AFTER, "/repo/after", DeployActionKind::Create),
+            ],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 1));
+        assert!(fs.is_symlink(Path::new(AFTER)));
+    }
+
+    #[test]
+    fn replace_fails_when_source_gained_symlink_component() {
+        let fs = repo();
+        fs.add_file("/elsewhere/f", 0o644);
+        fs.add_symlink("/repo/sub", "/elsewhere");
+        fs.add_file("/home/a", 0o644);
+        let act = deploy(
+            "/home/a",
+            "/repo/sub/f",
+            DeployActionKind::Replace(ExistingKind::RegularFile),
+        );
+        assert_hard_failure(&fs, act, "出现了符号链接组件");
+    }
+
+    fn temp_path(link: &str) -> PathBuf {
+        let link = Path::new(link);
+        link.parent()
+            .unwrap()
+            .join(temp_name(link.file_name().unwrap()))
+    }
+
+    #[test]
+    fn replace_moves_the_new_link_over_the_existing_object() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        let plan = DeployPlan {
+            actions: vec![deploy(
+                "/home/a",
+                "/repo/f",
+                DeployActionKind::Replace(ExistingKind::RegularFile),
+            )],
+            mode: ConflictModeRecord::default(),
+        };
+        let sim = simulate_deploy_with(&fs, &plan);
+        assert_eq!(
+            sim.changes,
+            vec![vec![
+                Change::Symlink {
+                    link: temp_path("/home/a"),
+                    target: PathBuf::from("/repo/f"),
+                },
+                Change::Rename {
+                    from: temp_path("/home/a"),
+                    to: PathBuf::from("/home/a"),
+                },
+            ]]
+        );
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 0));
+        assert_eq!(
+            fs.read_link(Path::new("/home/a")).unwrap(),
+            Path::new("/repo/f")
+        );
+    }
+
+    #[test]
+    fn replace_fails_when_existing_object_cannot_be_replaced() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        fs.fail(Op::Rename, "/home/a", io::ErrorKind::PermissionDenied);
+        let act = deploy(
+            "/home/a",
+            "/repo/f",
+            DeployActionKind::Replace(ExistingKind::RegularFile),
+        );
+        assert_hard_failure(&fs, act, "替换 /home/a 失败");
+        // The original stays, and the temporary link is cleaned up.
+        assert!(fs.symlink_metadata(Path::new("/home/a")).unwrap().is_file());
+        assert!(fs.symlink_metadata(&temp_path("/home/a")).is_err());
+    }
+
+    #[test]
+    fn replace_removes_temporary_links_a_crashed_run_left() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        fs.add_symlink("/home/.a.xd-1.new", "/repo/f");
+        fs.add_symlink(temp_path("/home/a"), "/repo/f");
+        // Not ours: a regular file, another entry's, not a PID.
+        fs.add_file("/home/.a.xd-2.new", 0o644);
+        fs.add_symlink("/home/.b.xd-1.new", "/repo/f");
+        fs.add_symlink("/home/.a.xd-x.new", "/repo/f");
+        let plan = DeployPlan {
+            actions: vec![deploy(
+                "/home/a",
+                "/repo/f",
+                DeployActionKind::Replace(ExistingKind::RegularFile),
+            )],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 0));
+        assert!(fs.is_symlink(Path::new("/home/a")));
+        assert!(fs.symlink_metadata(Path::new("/home/.a.xd-1.new")).is_err());
+        assert!(fs.symlink_metadata(&temp_path("/home/a")).is_err());
+        for kept in [
+            "/home/.a.xd-2.new",
+            "/home/.b.xd-1.new",
+            "/home/.a.xd-x.new",
+        ] {
+            assert!(fs.symlink_metadata(Path::new(kept)).is_ok(), "{}", kept);
+        }
+    }
+
+    #[test]
+    fn replace_uses_a_short_temporary_name_for_long_names() {
+        let name = "n".repeat(NAME_MAX - 5);
+        let link = format!("/home/{}", name);
+        assert_eq!(
+            temp_name(OsStr::new(&name)),
+            OsString::from(format!(".xd-{}.new", std::process::id()))
+        );
+        let fs = repo();
+        fs.add_file(&link, 0o644);
+        fs.add_symlink("/home/.xd-1.new", "/repo/f");
+        let plan = DeployPlan {
+            actions: vec![deploy(
+                &link,
+                "/repo/f",
+                DeployActionKind::Replace(ExistingKind::RegularFile),
+            )],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 0));
+        assert!(fs.is_symlink(Path::new(&link)));
+        assert!(fs.symlink_metadata(Path::new("/home/.xd-1.new")).is_err());
+    }
+
+    #[test]
+    fn replace_exchanges_an_empty_dir_for_the_link() {
+        let fs = repo();
+        fs.add_dir("/home/a");
+        let act = deploy(
+            "/home/a",
+            "/repo/f",
+            DeployActionKind::Replace(ExistingKind::EmptyRealDir),
+        );
+        let plan = DeployPlan {
+            actions: vec![act],
+            mode: ConflictModeRecord::default(),
+        };
+        let sim = simulate_deploy_with(&fs, &plan);
+        assert_eq!(
+            sim.changes[0][1..],
+            [
+                Change::Exchange {
+                    from: temp_path("/home/a"),
+                    to: PathBuf::from("/home/a"),
+                },
+                Change::RemoveDir(temp_path("/home/a")),
+            ]
+        );
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 0));
+        assert!(fs.is_symlink(Path::new("/home/a")));
+        assert!(!fs.exists(&temp_path("/home/a")));
+    }
+
+    #[test]
+    fn replace_of_empty_dir_without_exchange_removes_it_first() {
+        let fs = repo();
+        fs.add_dir("/home/a");
+        fs.fail(Op::Exchange, "/home/a", io::ErrorKind::Unsupported);
+        let act = deploy(
+            "/home/a",
+            "/repo/f",
+            DeployActionKind::Replace(ExistingKind::EmptyRealDir),
+        );
+        let plan = DeployPlan {
+            actions: vec![act],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 0));
+        assert!(fs.is_symlink(Path::new("/home/a")));
+        assert!(fs.symlink_metadata(&temp_path("/home/a")).is_err());
+    }
+
+    #[test]
+    fn dir_filled_after_the_exchange_is_put_back_and_skipped() {
+        let fs = repo();
+        fs.add_dir("/home/a");
+        // Someone writes into the directory right after it was swapped
+        // out of the way.
+        fs.before(Op::RemoveDir, temp_path("/home/a"), |fs| {
+            fs.add_file(temp_path("/home/a").join("new"), 0o644);
+        });
+        let plan = DeployPlan {
+            actions: vec![
+                deploy(
+                    "/home/a",
+                    "/repo/f",
+                    DeployActionKind::Replace(ExistingKind::EmptyRealDir),
+                ),
+                deploy(AFTER, "/repo/after", DeployActionKind::Create),
+            ],
+            mode: ConflictModeRecord::default(),
+        };
+        let out = apply_deploy_with(&fs, &plan);
+        assert_eq!((out.successes, out.failures), (1, 1));
+        assert!(fs.exists(Path::new("/home/a/new")));
+        assert!(fs.symlink_metadata(&temp_path("/home/a")).is_err());
+    }
+
+    #[test]
+    fn replace_fails_when_parent_cannot_be_opened() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        // Parent vanishes from view before the re-check.
+        fs.fail(Op::Metadata, "/home", io::ErrorKind::NotFound);
+        let act = deploy(
+            "/home/a",
+            "/repo/f",
+            DeployActionKind::Replace(ExistingKind::RegularFile),
+        );
+        assert_hard_failure(&fs, act, "无法读取 /home/a");
+        assert!(fs.symlink_metadata(Path::new("/home/a")).unwrap().is_file());
+    }
+
+    #[test]
+    fn replace_fails_when_symlink_creation_fails() {
+        let fs = repo();
+        fs.add_symlink("/home/a", "/nowhere");
+        fs.fail(
+            Op::Symlink,
+            temp_path("/home/a"),
+            io::ErrorKind::PermissionDenied,
+        );
+        let act = deploy(
+            "/home/a",
+            "/repo/f",
+            DeployActionKind::Replace(ExistingKind::BrokenSymlink),
+        );
+        assert_hard_failure(&fs, act, "创建符号链接失败");
+    }
+
+    #[test]
+    fn permission_fix_fails_when_link_was_retargeted() {
+        let fs = repo();
+        fs.add_symlink("/home/a", "/repo/after");
+        assert_hard_failure(&fs, perm_fix("/home/a", "/repo/f"), "不再指向");
+    }
+
+    #[test]
+    fn permission_fix_fails_when_source_gained_symlink_component() {
+        let fs = repo();
+        fs.add_file("/repo/sub/f", 0o644);
+        fs.add_file("/elsewhere/f", 0o644);
+        fs.add_symlink("/home/a", "/repo/sub/f");
+        // A concurrent writer swaps `sub` for a symlink after the link
+        // target was verified but before the component walk.
+        fs.before(Op::SymlinkMetadata, "/repo/sub", |fs| {
+            fs.remove_all("/repo/sub");
+            fs.add_symlink("/repo/sub", "/elsewhere");
+        });
+        assert_hard_failure(
+            &fs,
+            perm_fix("/home/a", "/repo/sub/f"),
+            "出现了符号链接组件",
+        );
+    }
+
+    #[test]
+    fn permission_fix_does_not_follow_a_component_swapped_after_the_recheck() {
+        let fs = repo();
+        fs.add_file("/repo/sub/f", 0o644);
+        fs.add_file("/elsewhere/f", 0o644);
+        fs.add_symlink("/home/a", "/repo/sub/f");
+        // The swap lands after every re-check, as the walk to the source
+        // opens the configuration directory.
+        fs.before(Op::Metadata, "/repo", |fs| {
+            fs.remove_all("/repo/sub");
+            fs.add_symlink("/repo/sub", "/elsewhere");
+        });
+        assert_hard_failure(&fs, perm_fix("/home/a", "/repo/sub/f"), "权限失败");
+        let m = fs.metadata(Path::new("/elsewhere/f")).unwrap();
+        assert_eq!(m.mode(), 0o644);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn permission_fix_fails_when_chmod_fails() {
+        let fs = repo();
+        fs.add_symlink("/home/a", "/repo/f");
+        fs.fail(
+            Op::SetPermissions,
+            "/repo/f",
+            io::ErrorKind::PermissionDenied,
+        );
+        assert_hard_failure(&fs, perm_fix("/home/a", "/repo/f"), "权限失败");
+    }
+
+    #[test]
+    fn undeploy_fails_when_link_is_no_longer_a_symlink() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        assert_undeploy_hard_failure(&fs, "/home/a", "不再是符号链接");
+    }
+
+    #[test]
+    fn undeploy_fails_when_symlink_cannot_be_removed() {
+        let fs = repo();
+        fs.add_symlink("/home/a", "/repo/f");
+        fs.fail(Op::RemoveFile, "/home/a", io::ErrorKind::PermissionDenied);
+        assert_undeploy_hard_failure(&fs, "/home/a", "删除符号链接失败");
+    }
+
+    #[test]
+    fn simulation_sees_effects_of_earlier_steps() {
+        let fs = repo();
+        let plan = DeployPlan {
+            actions: vec![
+                deploy("/home/.config/a", "/repo/f", DeployActionKind::Create),
+                deploy("/home/.config/b", "/repo/f", DeployActionKind::Create),
+            ],
+            mode: ConflictModeRecord::default(),
+        };
+        let sim = simulate_deploy_with(&fs, &plan);
+        assert_eq!((sim.outcome.successes, sim.outcome.failures), (2, 0));
+        // The parent created by the first step already exists for the second.
+        assert_eq!(
+            sim.changes,
+            vec![
+                vec![
+                    Change::CreateDir(PathBuf::from("/home/.config")),
+                    Change::Symlink {
+                        link: PathBuf::from("/home/.config/a"),
+                        target: PathBuf::from("/repo/f"),
+                    },
+                ],
+                vec![Change::Symlink {
+                    link: PathBuf::from("/home/.config/b"),
+                    target: PathBuf::from("/repo/f"),
+                }],
+            ]
+        );
+        assert!(!fs.exists(Path::new("/home/.config")), "base is untouched");
+    }
+
+    #[test]
+    fn simulation_predicts_the_same_hard_failure_as_apply() {
+        let plan = DeployPlan {
+            actions: vec![
+                deploy(
+                    "/home/a",
+                    "/repo/f",
+                    DeployActionKind::Replace(ExistingKind::WrongSymlink),
+                ),
+                deploy("/home/a", "/repo/after", DeployActionKind::Create),
+                deploy(AFTER, "/repo/after", DeployActionKind::Create),
+            ],
+            mode: ConflictModeRecord::default(),
+        };
+        let with_wrong_link = || {
+            let fs = repo();
+            fs.add_symlink("/home/a", "/repo/after");
+            fs
+        };
+        let sim = simulate_deploy_with(&with_wrong_link(), &plan);
+        let out = apply_deploy_with(&with_wrong_link(), &plan);
+        assert_eq!(sim.outcome.hard_failure, Some(1));
+        assert_eq!(out.hard_failure, Some(1));
+        assert_eq!(
+            (sim.outcome.successes, sim.outcome.failures),
+            (out.successes, out.failures)
+        );
+        assert_eq!(sim.changes.len(), 2, "no changes recorded past the stop");
+    }
+
+    #[test]
+    fn simulation_declines_interactive_prompts() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        let plan = DeployPlan {
+            actions: vec![deploy(
+                "/home/a",
+                "/repo/f",
+                DeployActionKind::Replace(ExistingKind::RegularFile),
+            )],
+            mode: ConflictModeRecord {
+                force: false,
+                interactive: true,
+            },
+        };
+        let sim = simulate_deploy_with(&fs, &plan);
+        assert_eq!((sim.outcome.failures, sim.outcome.hard_failure), (1, None));
+        assert_eq!(sim.changes, vec![Vec::<Change>::new()]);
+    }
+
+    #[test]
+    fn undeploy_simulation_records_removals() {
+        let fs = repo();
+        fs.add_symlink("/home/a", "/repo/f");
+        let plan = UndeployPlan {
+            actions: vec![UndeployAction {
+                config_file: PathBuf::from("/repo/xdotter.toml"),
+                source_raw: "f".to_string(),
+                source_canonical: Some(PathBuf::from("/repo/f")),
+                link_raw: "/home/a".to_string(),
+                link_expanded: PathBuf::from("/home/a"),
+                kind: UndeployActionKind::DeleteCorrect,
+            }],
+            mode: ConflictModeRecord::default(),
+        };
+        let sim = simulate_undeploy_with(&fs, &plan);
+        assert_eq!(sim.outcome.successes, 1);
+        assert_eq!(
+            sim.changes,
+            vec![vec![Change::RemoveFile(PathBuf::from("/home/a"))]]
+        );
+        assert!(fs.is_symlink(Path::new("/home/a")));
+    }
+
+    fn interactive(actions: Vec<DeployAction>) -> DeployPlan {
+        DeployPlan {
+            actions,
+            mode: ConflictModeRecord {
+                force: false,
+                interactive: true,
+            },
+        }
+    }
+
+    fn replace(link: &str, existing: ExistingKind) -> DeployAction {
+        deploy(link, "/repo/f", DeployActionKind::Replace(existing))
+    }
+
+    /// Apply `plan` answering prompts from `answers` in order; also
+    /// returns how many prompts were actually put to the user.
+    fn deploy_answering(
+        fs: &MemFs,
+        plan: &DeployPlan,
+        answers: &[Answer],
+    ) -> (ApplyOutcome, usize) {
+        let queue = RefCell::new(answers.iter().copied());
+        let asked = std::cell::Cell::new(0);
+        let ask = |_: &Prompt| {
+            asked.set(asked.get() + 1);
+            queue.borrow_mut().next().expect("unexpected prompt")
+        };
+        let answers = Answers::new();
+        let out = run_deploy(&Ctx::new(fs, &ask, &answers, false), plan, |_| {});
+        (out, asked.get())
+    }
+
+    fn conflicts() -> MemFs {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        fs.add_file("/home/b", 0o644);
+        fs.add_symlink("/home/w", "/repo/after");
+        fs
+    }
+
+    fn three_replaces() -> DeployPlan {
+        interactive(vec![
+            replace("/home/a", ExistingKind::RegularFile),
+            replace("/home/b", ExistingKind::RegularFile),
+            replace("/home/w", ExistingKind::WrongSymlink),
+        ])
+    }
+
+    #[test]
+    fn all_answer_covers_later_prompts_of_the_same_kind() {
+        let fs = conflicts();
+        let (out, asked) =
+            deploy_answering(&fs, &three_replaces(), &[Answer::YesToAll, Answer::No]);
+        assert_eq!(asked, 2, "the second file replace is not asked");
+        assert_eq!((out.successes, out.failures), (2, 1));
+        assert_eq!(
+            (
+                out.prompts.confirmed,
+                out.prompts.confirmed_by_all,
+                out.prompts.declined
+            ),
+            (2, 1, 1)
+        );
+        assert!(fs.is_symlink(Path::new("/home/b")));
+    }
+
+    #[test]
+    fn none_answer_declines_every_later_prompt() {
+        let fs = conflicts();
+        let (out, asked) = deploy_answering(&fs, &three_replaces(), &[Answer::NoToAll]);
+        assert_eq!(asked, 1);
+        assert_eq!((out.successes, out.failures), (0, 3));
+        assert_eq!((out.prompts.declined, out.prompts.declined_by_none), (3, 2));
+    }
+
+    #[test]
+    fn quit_leaves_the_rest_untouched_as_skipped() {
+        let fs = conflicts();
+        let plan = interactive(vec![
+            replace("/home/a", ExistingKind::RegularFile),
+            replace("/home/b", ExistingKind::RegularFile),
+            deploy(AFTER, "/repo/after", DeployActionKind::Create),
+        ]);
+        let (out, asked) = deploy_answering(&fs, &plan, &[Answer::Yes, Answer::Quit]);
+        assert_eq!(asked, 2);
+        assert_eq!((out.successes, out.skipped, out.failures), (1, 2, 0));
+        assert_eq!(out.prompts.quit_at, Some(1));
+        assert!(!fs.is_symlink(Path::new("/home/b")));
+        assert!(!fs.exists(Path::new(AFTER)));
+    }
+
+    #[test]
+    fn skip_dependency_leaves_only_that_config() {
+        let fs = conflicts();
+        let dep = |act: DeployAction| DeployAction {
+            config_file: PathBuf::from("/repo/dep/xdotter.toml"),
+            ..act
+        };
+        let plan = interactive(vec![
+            dep(replace("/home/a", ExistingKind::RegularFile)),
+            dep(deploy("/home/c", "/repo/f", DeployActionKind::Create)),
+            deploy(AFTER, "/repo/after", DeployActionKind::Create),
+        ]);
+        let (out, _) = deploy_answering(&fs, &plan, &[Answer::SkipDependency]);
+        assert_eq!((out.successes, out.skipped, out.failures), (1, 2, 0));
+        assert_eq!(
+            out.prompts.skipped_dependencies,
+            vec![PathBuf::from("/repo/dep/xdotter.toml")]
+        );
+        assert_eq!(out.prompts.quit_at, None);
+        assert!(!fs.exists(Path::new("/home/c")));
+        assert!(fs.is_symlink(Path::new(AFTER)));
+    }
+
+    #[test]
+    fn replies_parse_case_insensitively_and_default_to_no() {
+        let answer = |s: &str| match parse_reply(s) {
+            Reply::Answer(a) => Some(a),
+            _ => None,
+        };
+        assert_eq!(answer("Y\n"), Some(Answer::Yes));
+        assert_eq!(answer("all"), Some(Answer::YesToAll));
+        assert_eq!(answer("x"), Some(Answer::NoToAll));
+        assert_eq!(answer("Q"), Some(Answer::Quit));
+        assert_eq!(answer("s"), Some(Answer::SkipDependency));
+        assert_eq!(answer(""), Some(Answer::No));
+        assert_eq!(answer("yep"), Some(Answer::No));
+        assert!(matches!(parse_reply("d"), Reply::Diff));
+        assert!(matches!(parse_reply("?"), Reply::Help));
+    }
+
+    #[test]
+    fn prompt_text_goes_to_the_prompt_output() {
+        let fs = conflicts();
+        let p = Prompt {
+            kind: PromptKind::ReplaceWrongSymlink,
+            fs: &fs,
+            link: Path::new("/home/w"),
+            source: Some(Path::new("/repo/f")),
+            mode: None,
+            config_file: Path::new("/repo/xdotter.toml"),
+        };
+        let mut output = Vec::new();
+        let answer = ask_on(&mut "?\nd\na\n".as_bytes(), &mut output, &p);
+        assert_eq!(answer, Answer::YesToAll);
+        let q = format!("替换 /home/w (错误符号链接)? {} ", CHOICES);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "{q}{HELP}\n{q}- /home/w -> /repo/after\n+ /home/w -> /repo/f\n{q}\
+                 其余「{}」操作将自动确认\n",
+                PromptKind::ReplaceWrongSymlink.describe()
+            )
+        );
+
+        let mut output = Vec::new();
+        assert_eq!(ask_on(&mut "".as_bytes(), &mut output, &p), Answer::No);
+        assert_eq!(String::from_utf8(output).unwrap(), q);
+    }
+
+    #[test]
+    fn diff_shows_symlink_retarget() {
+        let fs = conflicts();
+        let p = Prompt {
+            kind: PromptKind::ReplaceWrongSymlink,
+            fs: &fs,
+            link: Path::new("/home/w"),
+            source: Some(Path::new("/repo/f")),
+            mode: None,
+            config_file: Path::new("/repo/xdotter.toml"),
+        };
+        assert_eq!(p.diff(), "- /home/w -> /repo/after\n+ /home/w -> /repo/f\n");
+    }
+
+    #[test]
+    fn scripted_answers_settle_their_kinds_in_apply_and_simulation() {
+        let fs = repo();
+        fs.add_file("/home/a", 0o644);
+        fs.add_symlink("/home/b", "/repo/missing");
+        fs.add_symlink("/home/w", "/repo/after");
+        let plan = interactive(vec![
+            replace("/home/a", ExistingKind::RegularFile),
+            replace("/home/b", ExistingKind::BrokenSymlink),
+            replace("/home/w", ExistingKind::WrongSymlink),
+        ]);
+        let mut answers = Answers::new();
+        answers.set(PromptKind::ReplaceBrokenSymlink, true);
+        answers.set(PromptKind::ReplaceFile, false);
+
+        // Unscripted kinds are still declined without a terminal.
+        let sim = simulate_deploy_answering(&fs, &plan, &answers);
+        assert_eq!((sim.outcome.successes, sim.outcome.failures), (1, 2));
+        assert_eq!(sim.changes[1].len(), 2, "{:?}", sim.changes);
+
+        let queue = RefCell::new(vec![Answer::Yes].into_iter());
+        let ask = |p: &Prompt| {
+            assert_eq!(p.kind, PromptKind::ReplaceWrongSymlink);
+            queue.borrow_mut().next().expect("unexpected prompt")
+        };
+        let out = run_deploy(&Ctx::new(&fs, &ask, &answers, false), &plan, |_| {});
+        assert_eq!((out.successes, out.failures), (2, 1));
+        assert_eq!(
+            (
+                out.prompts.scripted,
+                out.prompts.confirmed,
+                out.prompts.declined
+            ),
+            (2, 2, 1)
+        );
+        assert!(!fs.is_symlink(Path::new("/home/a")));
+        assert!(fs.is_symlink(Path::new("/home/b")));
+    }
 }
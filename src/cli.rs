@@ -1,4 +1,6 @@
-use clap::{ArgAction, Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -14,6 +16,30 @@ pub struct Cli {
     /// May be repeated; more than three is treated as -vvv.
     #[arg(short = 'v', long = "verbose", global = true, action = ArgAction::Count)]
     pub verbose: u8,
+
+    /// When to style human-readable output: auto (default; only when the
+    /// stream is a terminal and NO_COLOR is unset), always, or never.
+    #[arg(long = "color", global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Treat warnings as planning errors: report them as errors and stop
+    /// before modifying anything.
+    #[arg(long = "deny-warnings", global = true)]
+    pub deny_warnings: bool,
+
+    /// Append timestamped records of discovery, planning decisions,
+    /// apply steps and filesystem calls to PATH. XD_LOG (for example
+    /// plan=trace,apply=debug) limits what is recorded.
+    #[arg(long = "log-file", global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
 }
 
 #[derive(Subcommand, Debug)]
@@ -24,13 +50,37 @@ pub enum Command {
     Undeploy(UndeployArgs),
     /// Show deployment status
     Status,
+    /// Watch the repository and deploy newly added links
+    Watch(WatchArgs),
+    /// Report repository hygiene warnings
+    Lint(LintArgs),
+    /// List repository files no link entry references
+    Orphans(OrphansArgs),
     /// Create a new xdotter.toml template
     New(NewArgs),
+    /// Pin git dependencies to their latest revisions and check them out
+    UpdateDeps(UpdateDepsArgs),
+    /// Manage the registry of dotfile repositories deployed together
+    Repo {
+        #[command(subcommand)]
+        command: RepoCommand,
+    },
+    /// Show the audit log of changes deploy, undeploy and watch made
+    History(HistoryArgs),
+    /// Explain an error code, or list every code
+    Explain {
+        /// Error code, such as XD0203
+        #[arg(value_name = "CODE")]
+        code: Option<String>,
+    },
     /// Generate shell completion scripts
     Completion {
         /// Shell to generate completion for
         shell: String,
     },
+    /// Print completion candidates for the shell scripts
+    #[command(name = "__complete", hide = true)]
+    Complete(CompleteArgs),
     /// Print version
     Version,
 }
@@ -42,12 +92,41 @@ pub struct DeployArgs {
     pub dry_run: bool,
 
     /// Automatically handle recoverable conflicts (cannot bypass safety checks).
-    #[arg(short = 'f', long = "force", conflicts_with = "interactive")]
-    pub force: bool,
+    /// Optionally limited to categories: file, empty-dir, wrong-symlink, broken, perms.
+    #[arg(
+        short = 'f',
+        long = "force",
+        value_name = "KINDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "all",
+        conflicts_with = "interactive"
+    )]
+    pub force: Option<String>,
 
     /// Ask for confirmation before each destructive operation.
     #[arg(short = 'i', long = "interactive", conflicts_with = "force")]
     pub interactive: bool,
+
+    /// Review the plan in $VISUAL / $EDITOR and apply only the kept actions.
+    #[arg(long = "edit-plan", conflicts_with_all = ["force", "interactive"])]
+    pub edit_plan: bool,
+
+    /// Answer interactive prompts of one kind without asking (repeatable).
+    #[arg(long = "answer", value_name = "KIND=yes|no", requires = "interactive")]
+    pub answer: Vec<String>,
+
+    /// Read scripted answers from a TOML file of `KIND = "yes"|"no"`.
+    #[arg(long = "answers", value_name = "FILE", requires = "interactive")]
+    pub answers: Option<PathBuf>,
+
+    /// Wait for another xd run holding the run lock to finish (default).
+    #[arg(long = "wait", overrides_with = "no_wait")]
+    pub wait: bool,
+
+    /// Fail at once when another xd run holds the run lock.
+    #[arg(long = "no-wait", overrides_with = "wait")]
+    pub no_wait: bool,
 }
 
 #[derive(clap::Args, Debug, Default)]
@@ -57,48 +136,140 @@ pub struct UndeployArgs {
     pub dry_run: bool,
 
     /// Automatically handle recoverable conflicts (cannot bypass safety checks).
-    #[arg(short = 'f', long = "force", conflicts_with = "interactive")]
-    pub force: bool,
+    /// Optionally limited to categories: file, empty-dir, wrong-symlink, broken, perms.
+    #[arg(
+        short = 'f',
+        long = "force",
+        value_name = "KINDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "all",
+        conflicts_with = "interactive"
+    )]
+    pub force: Option<String>,
 
     /// Ask for confirmation before each destructive operation.
     #[arg(short = 'i', long = "interactive", conflicts_with = "force")]
     pub interactive: bool,
+
+    /// Answer interactive prompts of one kind without asking (repeatable).
+    #[arg(long = "answer", value_name = "KIND=yes|no", requires = "interactive")]
+    pub answer: Vec<String>,
+
+    /// Read scripted answers from a TOML file of `KIND = "yes"|"no"`.
+    #[arg(long = "answers", value_name = "FILE", requires = "interactive")]
+    pub answers: Option<PathBuf>,
+
+    /// Wait for another xd run holding the run lock to finish (default).
+    #[arg(long = "wait", overrides_with = "no_wait")]
+    pub wait: bool,
+
+    /// Fail at once when another xd run holds the run lock.
+    #[arg(long = "no-wait", overrides_with = "wait")]
+    pub no_wait: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct WatchArgs {
+    /// Polling interval in milliseconds.
+    #[arg(long = "interval", value_name = "MS", default_value_t = 500)]
+    pub interval: u64,
+
+    /// Quiet period in milliseconds a change must settle for before
+    /// re-planning.
+    #[arg(long = "debounce", value_name = "MS", default_value_t = 300)]
+    pub debounce: u64,
 }
 
 #[derive(clap::Args, Debug, Default)]
-pub struct NewArgs {
-    /// Report what would be created without writing the file.
-    #[arg(short = 'n', long = "dry-run")]
-    pub dry_run: bool,
+pub struct LintArgs {
+    /// Do not report warnings of this rule (repeatable).
+    #[arg(long = "allow", value_name = "RULE")]
+    pub allow: Vec<String>,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum ConflictMode {
-    Default,
-    Force,
-    Interactive,
+#[derive(clap::Args, Debug, Default)]
+pub struct HistoryArgs {
+    /// Only show changes made for the link at PATH (`~/` is expanded).
+    #[arg(long = "link", value_name = "PATH")]
+    pub link: Option<String>,
+}
+
+#[derive(clap::Args, Debug, Default)]
+pub struct OrphansArgs {
+    /// Print a suggested [links] snippet for the orphans instead of a list.
+    #[arg(long = "suggest")]
+    pub suggest: bool,
+}
+
+#[derive(clap::Args, Debug, Default)]
+pub struct UpdateDepsArgs {
+    /// Keep the pins in xdotter.lock; only check out the pinned commits.
+    #[arg(long = "locked")]
+    pub locked: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RepoCommand {
+    /// Register a repository (default: the current directory)
+    Add {
+        /// Directory holding the repository's root xdotter.toml.
+        path: Option<PathBuf>,
+        /// Name to register it under (default: the directory name).
+        #[arg(long = "name")]
+        name: Option<String>,
+    },
+    /// Unregister a repository
+    Remove {
+        /// Registered name.
+        #[arg(value_name = "REPO")]
+        name: String,
+    },
+    /// List registered repositories
+    List,
 }
 
-impl DeployArgs {
-    pub fn conflict_mode(&self) -> ConflictMode {
-        if self.force {
-            ConflictMode::Force
-        } else if self.interactive {
-            ConflictMode::Interactive
-        } else {
-            ConflictMode::Default
-        }
-    }
+/// Arguments of `xd __complete`, the endpoint the scripts printed by
+/// `xd completion` call on every TAB.
+#[derive(clap::Args, Debug, Default)]
+pub struct CompleteArgs {
+    /// Word being completed, passed as `--cur=WORD` so it may be empty.
+    #[arg(long = "cur", default_value = "", allow_hyphen_values = true)]
+    pub cur: String,
+
+    /// List every candidate of one kind instead of completing a command line.
+    #[arg(long = "values", value_enum)]
+    pub values: Option<CompleteKind>,
+
+    /// Words before the one being completed, without the program name.
+    #[arg(last = true)]
+    pub words: Vec<String>,
 }
 
-impl UndeployArgs {
-    pub fn conflict_mode(&self) -> ConflictMode {
-        if self.force {
-            ConflictMode::Force
-        } else if self.interactive {
-            ConflictMode::Interactive
-        } else {
-            ConflictMode::Default
-        }
-    }
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CompleteKind {
+    /// Dependency names of the discovered configurations.
+    Deps,
+    /// Link paths as written in the configurations.
+    Links,
+    /// Source paths, relative to the current directory.
+    Sources,
+    /// Names of registered repositories.
+    Repos,
+}
+
+#[derive(clap::Args, Debug, Default)]
+pub struct NewArgs {
+    /// Report what would be created without writing the file.
+    #[arg(short = 'n', long = "dry-run")]
+    pub dry_run: bool,
+
+    /// Scan DIR (default: the home directory) for existing dotfiles, move
+    /// the chosen ones into this repository and link them in the config.
+    #[arg(long = "scan", value_name = "DIR", num_args = 0..=1)]
+    pub scan: Option<Option<PathBuf>>,
+
+    /// With --scan, take every entry found instead of asking.
+    #[arg(long = "all", requires = "scan")]
+    pub all: bool,
 }
@@ -0,0 +1,642 @@
+//! Stable error codes and the explanations `xd explain` prints.
+//!
+//! Every distinct failure of the path rules, dependency discovery,
+//! planning checks and apply-stage re-checks carries a [`Code`] such as
+//! `XD0203`, printed after the classification label:
+//!
+//! ```text
+//! [配置错误][XD0203] /home/me/dotfiles/xdotter.toml:3:1: 源路径不得包含父目录跳转 `..`: "../b"
+//! ```
+//!
+//! Codes are grouped by hundreds: `XD01xx` configuration files,
+//! `XD02xx` path rules, `XD03xx` dependency discovery, `XD04xx` planning
+//! and `XD05xx` apply. A code, once published, keeps its meaning; a
+//! failure that goes away retires its code instead of handing it on.
+
+use std::fmt;
+
+/// A stable error code, `XD` and four digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Code(&'static str);
+
+impl Code {
+    /// The code as printed, e.g. `"XD0203"`.
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    /// The code's entry in [`ALL`].
+    pub fn explanation(&self) -> &'static Explanation {
+        ALL.iter()
+            .find(|e| e.code == *self)
+            .expect("every code has an explanation")
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// What a code means and how to fix it.
+#[derive(Debug)]
+pub struct Explanation {
+    /// The code.
+    pub code: Code,
+    /// Classification label of errors with this code.
+    pub label: &'static str,
+    /// One-line summary.
+    pub title: &'static str,
+    /// SPEC section stating the rule.
+    pub spec: &'static str,
+    /// Why the rule exists and how to fix the failure.
+    pub text: &'static str,
+}
+
+/// The entry for `code`, ignoring ASCII case (`xd0203` finds `XD0203`).
+pub fn explain(code: &str) -> Option<&'static Explanation> {
+    ALL.iter()
+        .find(|e| e.code.as_str().eq_ignore_ascii_case(code.trim()))
+}
+
+const CONFIG: &str = "[配置错误]";
+const PLANNING: &str = "[规划阻塞错误]";
+const APPLY: &str = "[应用阶段错误]";
+
+/// `xdotter.toml` is not valid TOML or does not match the format.
+pub const TOML_PARSE: Code = Code("XD0101");
+/// `[warnings] allow` names an unknown warning.
+pub const WARNING_UNKNOWN: Code = Code("XD0102");
+
+/// A source path is empty, `.` or a bare `~`.
+pub const SOURCE_MALFORMED: Code = Code("XD0201");
+/// A source path is absolute or home-relative.
+pub const SOURCE_NOT_RELATIVE: Code = Code("XD0202");
+/// A source path contains `..`.
+pub const SOURCE_PARENT_TRAVERSAL: Code = Code("XD0203");
+/// A link path is empty or of an unsupported form.
+pub const LINK_MALFORMED: Code = Code("XD0204");
+/// A link path names the home directory itself.
+pub const LINK_IS_HOME: Code = Code("XD0205");
+/// A link path names the filesystem root.
+pub const LINK_IS_ROOT: Code = Code("XD0206");
+/// A link path is a plain relative path.
+pub const LINK_RELATIVE: Code = Code("XD0207");
+/// A link path contains `..`.
+pub const LINK_PARENT_TRAVERSAL: Code = Code("XD0208");
+/// A dependency path is empty, `.` or a bare `~`.
+pub const DEPENDENCY_MALFORMED: Code = Code("XD0209");
+/// A dependency path is absolute or home-relative.
+pub const DEPENDENCY_NOT_RELATIVE: Code = Code("XD0210");
+/// A dependency path contains `..`.
+pub const DEPENDENCY_PARENT_TRAVERSAL: Code = Code("XD0211");
+/// `~/` cannot be expanded because the home directory is unknown.
+pub const HOME_UNKNOWN: Code = Code("XD0212");
+
+/// A root configuration directory cannot be accessed.
+pub const ROOT_INACCESSIBLE: Code = Code("XD0301");
+/// The dependency graph has a cycle.
+pub const DEPENDENCY_CYCLE: Code = Code("XD0302");
+/// An `xdotter.toml` cannot be read.
+pub const CONFIG_UNREADABLE: Code = Code("XD0303");
+/// A dependency directory does not exist or cannot be accessed.
+pub const DEPENDENCY_MISSING: Code = Code("XD0304");
+/// A dependency resolves outside its configuration directory tree.
+pub const DEPENDENCY_ESCAPES: Code = Code("XD0305");
+/// Two dependencies of one table resolve to the same directory.
+pub const DEPENDENCY_DUPLICATE: Code = Code("XD0306");
+/// A dependency directory has no `xdotter.toml`.
+pub const DEPENDENCY_NO_CONFIG: Code = Code("XD0307");
+/// A git dependency names a repository that is not local.
+pub const GIT_NOT_LOCAL: Code = Code("XD0308");
+/// A git dependency's location or revision starts with `-`.
+pub const GIT_OPTION_LIKE: Code = Code("XD0309");
+/// `xdotter.lock` cannot be read or parsed.
+pub const LOCK_INVALID: Code = Code("XD0310");
+/// A git dependency has no pin, or a pin for another location or revision.
+pub const GIT_NOT_LOCKED: Code = Code("XD0311");
+/// A git dependency's checkout is missing or not at its pinned commit.
+pub const GIT_NOT_CHECKED_OUT: Code = Code("XD0312");
+/// A revision or pinned commit does not exist in the repository.
+pub const GIT_REVISION_MISSING: Code = Code("XD0313");
+/// A git command run by `xd update-deps` failed.
+pub const GIT_FAILED: Code = Code("XD0314");
+/// `xdotter.lock` cannot be written.
+pub const LOCK_UNWRITABLE: Code = Code("XD0315");
+
+/// Several entries expand to the same link path.
+pub const LINK_COLLISION: Code = Code("XD0401");
+/// A link path lies inside another link path.
+pub const LINK_NESTED: Code = Code("XD0402");
+/// A source does not exist.
+pub const SOURCE_MISSING: Code = Code("XD0403");
+/// A source path goes through a symlink.
+pub const SOURCE_SYMLINK_COMPONENT: Code = Code("XD0404");
+/// A source cannot be accessed.
+pub const SOURCE_INACCESSIBLE: Code = Code("XD0405");
+/// A source is neither a regular file nor a directory.
+pub const SOURCE_NOT_FILE_OR_DIR: Code = Code("XD0406");
+/// A source resolves outside its configuration directory tree.
+pub const SOURCE_ESCAPES: Code = Code("XD0407");
+/// A link path and its source are the same object.
+pub const LINK_IS_SOURCE: Code = Code("XD0408");
+/// A link path lies inside its source.
+pub const LINK_INSIDE_SOURCE: Code = Code("XD0409");
+/// An ancestor of a link path is a symlink into the source.
+pub const ANCESTOR_UNSAFE_SYMLINK: Code = Code("XD0410");
+/// An ancestor of a link path is not a directory.
+pub const ANCESTOR_NOT_DIR: Code = Code("XD0411");
+/// Creating the link would make a symlink loop.
+pub const SYMLINK_LOOP: Code = Code("XD0412");
+/// The empty directory to replace contains the source.
+pub const TARGET_CONTAINS_SOURCE: Code = Code("XD0413");
+/// A warning under `--deny-warnings`.
+pub const WARNING_DENIED: Code = Code("XD0414");
+/// Another run holds a lock and `--no-wait` was given.
+pub const LOCK_HELD: Code = Code("XD0415");
+/// A run lock cannot be created or taken.
+pub const LOCK_UNAVAILABLE: Code = Code("XD0416");
+
+/// The source gained a symlink component after planning.
+pub const RECHECK_SOURCE_SYMLINK: Code = Code("XD0501");
+/// The link path appeared after planning.
+pub const RECHECK_LINK_EXISTS: Code = Code("XD0502");
+/// The object at the link path changed after planning.
+pub const RECHECK_TARGET_CHANGED: Code = Code("XD0503");
+/// The link no longer points at the source whose permissions to fix.
+pub const RECHECK_PERMISSION_TARGET: Code = Code("XD0504");
+/// The link to remove is no longer a symlink.
+pub const RECHECK_NOT_SYMLINK: Code = Code("XD0505");
+/// A filesystem operation of the apply stage failed.
+pub const FS_OPERATION_FAILED: Code = Code("XD0506");
+/// The operation history cannot be written.
+pub const HISTORY_UNWRITABLE: Code = Code("XD0507");
+
+/// Every code, in code order.
+pub const ALL: &[Explanation] = &[
+    Explanation {
+        code: TOML_PARSE,
+        label: CONFIG,
+        title: "xdotter.toml 无法解析",
+        spec: "配置格式",
+        text: "文件不是合法的 TOML，或含有未知的表、键，或值的类型不对。\
+               xdotter 拒绝整个文件，而不是猜测其含义。\n\
+               修复：按消息给出的行列修正语法；只使用 [options]、[links]、[dependencies]、[lint] 和 [warnings]。",
+    },
+    Explanation {
+        code: WARNING_UNKNOWN,
+        label: CONFIG,
+        title: "[warnings] allow 中有未知的警告名称",
+        spec: "[warnings]",
+        text: "拼错的名称会让本应关闭的警告继续出现，或让人误以为某个警告已被关闭，\
+               因此未知名称是配置错误而不是被忽略。\n\
+               修复：使用消息中列出的名称之一，也就是警告行末尾方括号中的名称。",
+    },
+    Explanation {
+        code: SOURCE_MALFORMED,
+        label: CONFIG,
+        title: "源路径为空、为 \".\" 或为单独的 \"~\"",
+        spec: "源路径",
+        text: "源路径必须指向配置目录内的某个文件或目录；空路径和 \".\" 会把整个配置目录链接出去。\n\
+               修复：写出要链接的文件或目录相对于配置目录的路径。",
+    },
+    Explanation {
+        code: SOURCE_NOT_RELATIVE,
+        label: CONFIG,
+        title: "源路径是绝对路径或 home 相对路径",
+        spec: "源路径",
+        text: "源路径是 [links] 的键，必须是相对于声明它的配置目录的普通相对路径，\
+               这样仓库在任何位置克隆都能部署出同样的结果。\n\
+               修复：把文件移入仓库，用相对配置目录的路径作为键。",
+    },
+    Explanation {
+        code: SOURCE_PARENT_TRAVERSAL,
+        label: CONFIG,
+        title: "源路径包含 `..`",
+        spec: "源路径",
+        text: "源路径不得逃出声明它的配置目录树；依赖配置不能借此链接上层仓库的文件。\n\
+               修复：把条目移到包含该文件的上层配置中，或把文件移入本配置目录。",
+    },
+    Explanation {
+        code: LINK_MALFORMED,
+        label: CONFIG,
+        title: "链接路径为空或形式不受支持",
+        spec: "链接路径",
+        text: "链接路径必须是绝对路径或以 `~/` 开头。单独的 `~`、空字符串，以及 Windows 上的 \
+               POSIX 根路径、UNC 和盘符相对路径都不受支持。\n\
+               修复：写成 `~/...` 或（Windows 上带盘符的）绝对路径。",
+    },
+    Explanation {
+        code: LINK_IS_HOME,
+        label: CONFIG,
+        title: "链接路径解析为 home 目录本身",
+        spec: "链接路径",
+        text: "部署会把链接路径上的对象替换为符号链接；替换 home 目录本身不可能是想要的结果。\n\
+               修复：指向 home 下的具体文件或目录，例如 `~/.zshrc`。",
+    },
+    Explanation {
+        code: LINK_IS_ROOT,
+        label: CONFIG,
+        title: "链接路径解析为文件系统根目录",
+        spec: "链接路径",
+        text: "与 XD0205 相同，根目录不能被替换为符号链接。\n\
+               修复：写出完整的目标路径。",
+    },
+    Explanation {
+        code: LINK_RELATIVE,
+        label: CONFIG,
+        title: "链接路径是普通相对路径",
+        spec: "链接路径",
+        text: "普通相对路径的含义取决于运行 xd 时的当前目录，部署结果不稳定。\n\
+               修复：改为 `~/...` 或绝对路径。",
+    },
+    Explanation {
+        code: LINK_PARENT_TRAVERSAL,
+        label: CONFIG,
+        title: "链接路径包含 `..`",
+        spec: "链接路径",
+        text: "展开 `~/` 后链接路径不得含有 `..`：静态校验无法确定它最终指向哪里。\n\
+               修复：写出规范化后的路径。",
+    },
+    Explanation {
+        code: DEPENDENCY_MALFORMED,
+        label: CONFIG,
+        title: "依赖路径为空、为 \".\" 或为单独的 \"~\"",
+        spec: "依赖路径",
+        text: "依赖路径必须指向配置目录下的某个子目录；\".\" 会让配置依赖自身。\n\
+               修复：写出包含依赖 xdotter.toml 的子目录。",
+    },
+    Explanation {
+        code: DEPENDENCY_NOT_RELATIVE,
+        label: CONFIG,
+        title: "依赖路径是绝对路径或 home 相对路径",
+        spec: "依赖路径",
+        text: "路径依赖必须位于声明它的配置目录树内，仓库才能自包含。\n\
+               修复：把依赖移入仓库；其他仓库用 git 依赖（`{ git = \"...\" }`）或 `xd repo add`。",
+    },
+    Explanation {
+        code: DEPENDENCY_PARENT_TRAVERSAL,
+        label: CONFIG,
+        title: "依赖路径包含 `..`",
+        spec: "依赖路径",
+        text: "依赖遍历必须保持在当前配置目录树内。\n\
+               修复：从上层配置声明该依赖，或改用 git 依赖。",
+    },
+    Explanation {
+        code: HOME_UNKNOWN,
+        label: PLANNING,
+        title: "无法确定 HOME 目录",
+        spec: "`~` 展开",
+        text: "`~/` 展开为当前用户的 home 目录；既没有非空的 HOME，系统也无法给出 home 目录时无法规划。\n\
+               修复：设置 HOME 环境变量。",
+    },
+    Explanation {
+        code: ROOT_INACCESSIBLE,
+        label: PLANNING,
+        title: "根配置目录无法访问",
+        spec: "多仓库",
+        text: "当前目录或已注册仓库的根目录不存在或无法访问。\n\
+               修复：检查路径和权限；已移动或删除的仓库用 `xd repo remove` 注销。",
+    },
+    Explanation {
+        code: DEPENDENCY_CYCLE,
+        label: PLANNING,
+        title: "依赖图存在循环",
+        spec: "依赖语义",
+        text: "某个配置经由 [dependencies] 最终又依赖到自身，发现过程无法终止。共享依赖不是循环。\n\
+               修复：删除形成循环的依赖条目。",
+    },
+    Explanation {
+        code: CONFIG_UNREADABLE,
+        label: PLANNING,
+        title: "xdotter.toml 无法读取",
+        spec: "配置目录",
+        text: "文件存在性或权限在发现时出了问题。\n\
+               修复：检查该文件的权限和编码（必须是 UTF-8）。",
+    },
+    Explanation {
+        code: DEPENDENCY_MISSING,
+        label: PLANNING,
+        title: "依赖目录不存在或无法访问",
+        spec: "依赖路径",
+        text: "依赖路径相对于声明它的配置目录解析，解析结果必须是存在的目录。\n\
+               修复：修正路径，或创建该目录并放入 xdotter.toml。",
+    },
+    Explanation {
+        code: DEPENDENCY_ESCAPES,
+        label: PLANNING,
+        title: "依赖解析后逃出配置目录树",
+        spec: "依赖路径",
+        text: "依赖路径的某个组件是指向目录树外的符号链接。依赖遍历必须保持在当前配置目录树内。\n\
+               修复：用真实目录替换该符号链接，或改用 git 依赖。",
+    },
+    Explanation {
+        code: DEPENDENCY_DUPLICATE,
+        label: CONFIG,
+        title: "同一 [dependencies] 表中多个依赖指向同一目录",
+        spec: "依赖语义",
+        text: "同一个表中的依赖必须解析到不同的真实目录，否则同一配置会以两个名字出现。\n\
+               修复：删除重复的依赖条目。",
+    },
+    Explanation {
+        code: DEPENDENCY_NO_CONFIG,
+        label: PLANNING,
+        title: "依赖目录缺少 xdotter.toml",
+        spec: "依赖语义",
+        text: "每个依赖目录（包括 git 依赖的检出）必须有自己的 xdotter.toml。\n\
+               修复：在依赖目录中创建 xdotter.toml，或修正依赖路径；git 依赖检查 `rev` 指向的提交。",
+    },
+    Explanation {
+        code: GIT_NOT_LOCAL,
+        label: CONFIG,
+        title: "git 依赖不是本地仓库",
+        spec: "Git 依赖",
+        text: "`git` 只接受 `file://` URL 或相对配置目录的路径；网络地址会让部署依赖网络和远端的可信度。\n\
+               修复：把仓库克隆到本地，再用路径或 `file://` URL 引用它。",
+    },
+    Explanation {
+        code: GIT_OPTION_LIKE,
+        label: CONFIG,
+        title: "git 依赖的位置或修订以 `-` 开头",
+        spec: "Git 依赖",
+        text: "位置和修订会作为参数传给 git；以 `-` 开头的值会被 git 当作选项，可能执行任意命令，\
+               因此在 xdotter.toml 和 xdotter.lock 中都会被拒绝。\n\
+               修复：改用 `./-name` 形式的路径，或换一个不以 `-` 开头的分支、标签名。",
+    },
+    Explanation {
+        code: LOCK_INVALID,
+        label: CONFIG,
+        title: "xdotter.lock 无法读取或解析",
+        spec: "Git 依赖",
+        text: "锁文件由 `xd update-deps` 生成，每个条目必须记录完整的提交 ID。\n\
+               修复：检查文件权限；若文件被手工改坏，删除它后运行 xd update-deps 重新生成。",
+    },
+    Explanation {
+        code: GIT_NOT_LOCKED,
+        label: PLANNING,
+        title: "git 依赖没有锁定",
+        spec: "Git 依赖",
+        text: "git 依赖只在 xdotter.lock 锁定的提交上部署；没有锁定条目，或条目记录的位置、修订与配置不同，\
+               都不能部署。通过 git 依赖引入的仓库必须提交自己的锁文件。\n\
+               修复：运行 xd update-deps 写入锁定；在 git 依赖的仓库中运行并提交 xdotter.lock。",
+    },
+    Explanation {
+        code: GIT_NOT_CHECKED_OUT,
+        label: PLANNING,
+        title: "git 依赖没有检出锁定的提交",
+        spec: "Git 依赖",
+        text: "部署只读取缓存中的检出，不会自己运行 git；检出缺失、被移动或不在锁定的提交上时无法规划。\n\
+               修复：运行 xd update-deps --locked 按锁文件检出。",
+    },
+    Explanation {
+        code: GIT_REVISION_MISSING,
+        label: APPLY,
+        title: "修订或锁定的提交在仓库中不存在",
+        spec: "Git 依赖",
+        text: "`rev` 指向的分支、标签或提交，或 xdotter.lock 锁定的提交，在获取仓库后仍然找不到。\n\
+               修复：修正 `rev`；锁定的提交被改写历史删除时，运行 xd update-deps 重新锁定。",
+    },
+    Explanation {
+        code: GIT_FAILED,
+        label: APPLY,
+        title: "git 命令失败",
+        spec: "Git 依赖",
+        text: "xd update-deps 通过 git 获取仓库并检出提交；git 无法运行或返回失败时停止，消息中带有 git 的输出。\n\
+               修复：确认 git 在 PATH 中、仓库可访问；缓存损坏时删除 ~/.cache/xdotter/git 中对应的目录后重试。",
+    },
+    Explanation {
+        code: LOCK_UNWRITABLE,
+        label: APPLY,
+        title: "xdotter.lock 无法写入",
+        spec: "Git 依赖",
+        text: "xd update-deps 在锁定变化后重写配置目录中的 xdotter.lock。\n\
+               修复：检查配置目录和锁文件的权限。",
+    },
+    Explanation {
+        code: LINK_COLLISION,
+        label: CONFIG,
+        title: "多个链接条目指向同一链接路径",
+        spec: "依赖语义",
+        text: "所有可达配置的链接条目组成全局链接集合，展开和规范化后的链接路径必须唯一，\
+               否则部署结果取决于处理顺序。消息列出了所有冲突条目。\n\
+               修复：只保留一个条目，或修改其余条目的链接路径。",
+    },
+    Explanation {
+        code: LINK_NESTED,
+        label: PLANNING,
+        title: "链接路径位于另一链接路径内部",
+        spec: "符号链接安全语义",
+        text: "外层链接部署后是符号链接，内层链接会被创建到源仓库里，而不是 home 下。\n\
+               修复：删除内层条目，把它的文件放进外层链接的源目录。",
+    },
+    Explanation {
+        code: SOURCE_MISSING,
+        label: PLANNING,
+        title: "源路径不存在",
+        spec: "源路径",
+        text: "源必须是存在的普通文件或目录。\n\
+               修复：创建或恢复该文件，或删除这个链接条目。",
+    },
+    Explanation {
+        code: SOURCE_SYMLINK_COMPONENT,
+        label: PLANNING,
+        title: "源路径的某个组件是符号链接",
+        spec: "源路径",
+        text: "源路径上不得有符号链接：否则链接的实际目标可能在仓库之外，而且会随该符号链接改变。\n\
+               修复：用真实文件或目录替换仓库中的符号链接。",
+    },
+    Explanation {
+        code: SOURCE_INACCESSIBLE,
+        label: PLANNING,
+        title: "源路径无法访问",
+        spec: "源路径",
+        text: "解析源路径时出现 I/O 错误，通常是权限问题。\n\
+               修复：检查源路径各级目录的权限。",
+    },
+    Explanation {
+        code: SOURCE_NOT_FILE_OR_DIR,
+        label: PLANNING,
+        title: "源路径不是普通文件或目录",
+        spec: "源路径",
+        text: "FIFO、套接字和设备文件不能作为源。\n\
+               修复：删除该条目，或把源换成普通文件。",
+    },
+    Explanation {
+        code: SOURCE_ESCAPES,
+        label: PLANNING,
+        title: "源路径解析后逃出配置目录树",
+        spec: "源路径",
+        text: "源路径解析后必须仍在声明它的配置目录树内。\n\
+               修复：把文件移入配置目录。",
+    },
+    Explanation {
+        code: LINK_IS_SOURCE,
+        label: PLANNING,
+        title: "链接路径与源路径是同一对象",
+        spec: "符号链接安全语义",
+        text: "部署会用指向源的符号链接替换链接路径上的对象，也就是删掉源本身。\n\
+               修复：修正链接路径；不要在仓库目录本身上部署。",
+    },
+    Explanation {
+        code: LINK_INSIDE_SOURCE,
+        label: PLANNING,
+        title: "链接路径位于源路径内部",
+        spec: "符号链接安全语义",
+        text: "在源目录里创建指向源的链接会修改仓库并形成自引用。\n\
+               修复：修正链接路径，使其位于源之外。",
+    },
+    Explanation {
+        code: ANCESTOR_UNSAFE_SYMLINK,
+        label: PLANNING,
+        title: "链接路径的祖先是指向源的符号链接",
+        spec: "符号链接安全语义",
+        text: "经过该祖先创建链接，链接实际会落在源内部或替换源。\n\
+               修复：检查并删除该祖先符号链接（通常是旧部署的残留）。",
+    },
+    Explanation {
+        code: ANCESTOR_NOT_DIR,
+        label: PLANNING,
+        title: "链接路径的祖先不是目录",
+        spec: "符号链接安全语义",
+        text: "链接路径的上级路径被普通文件占用，无法在其中创建链接，xdotter 也不会替换祖先。\n\
+               修复：移走该文件，或修正链接路径。",
+    },
+    Explanation {
+        code: SYMLINK_LOOP,
+        label: PLANNING,
+        title: "创建链接会产生符号链接循环",
+        spec: "符号链接安全语义",
+        text: "某个祖先符号链接指回链接创建位置之下，新链接会使路径解析无法终止。\n\
+               修复：删除形成循环的祖先符号链接。",
+    },
+    Explanation {
+        code: TARGET_CONTAINS_SOURCE,
+        label: PLANNING,
+        title: "要替换的目录包含源路径",
+        spec: "冲突处理模式",
+        text: "替换空目录会删除它；若源位于其中，部署会删除或包含源。\n\
+               修复：修正链接路径。",
+    },
+    Explanation {
+        code: WARNING_DENIED,
+        label: PLANNING,
+        title: "警告被 --deny-warnings 视为错误",
+        spec: "输出语义",
+        text: "--deny-warnings 让任何未被关闭的警告阻止本次运行，适合要求零警告的 CI；\
+               规划阶段即停止，不修改文件系统。\n\
+               修复：处理警告所指的问题；确认无需处理时，在该条目所在配置的 \
+               [warnings] allow 中加入行末方括号里的警告名称。",
+    },
+    Explanation {
+        code: LOCK_HELD,
+        label: PLANNING,
+        title: "另一个 xd 进程正在运行",
+        spec: "并发运行",
+        text: "修改链接的运行在规划和应用期间持有主目录和仓库的运行锁，避免两次运行交错、\
+               使应用阶段的重新校验中途失败。--no-wait 指定不等待，本次运行没有做任何修改。\n\
+               修复：等待消息中给出的进程结束后重新运行，或去掉 --no-wait 让 xd 自动等待。",
+    },
+    Explanation {
+        code: LOCK_UNAVAILABLE,
+        label: PLANNING,
+        title: "无法获取运行锁",
+        spec: "并发运行",
+        text: "运行锁文件 ~/.local/state/xdotter/run.lock 或仓库目录无法打开或加锁，\
+               消息中给出了原因；本次运行没有做任何修改。\n\
+               修复：检查该路径的权限，或文件系统是否支持文件锁，然后重新运行。",
+    },
+    Explanation {
+        code: RECHECK_SOURCE_SYMLINK,
+        label: APPLY,
+        title: "规划后源路径出现了符号链接组件",
+        spec: "竞态安全和失败关闭行为",
+        text: "应用每个操作前都会重新校验；规划之后有进程把源路径的某级换成了符号链接。\
+               xdotter 失败关闭，停止后续操作。\n\
+               修复：查明是谁修改了仓库，恢复后重新运行。",
+    },
+    Explanation {
+        code: RECHECK_LINK_EXISTS,
+        label: APPLY,
+        title: "规划后链接路径上出现了对象",
+        spec: "竞态安全和失败关闭行为",
+        text: "规划时链接路径不存在，应用时已经存在；xdotter 不会覆盖未经规划的对象。\n\
+               修复：重新运行以按当前状态规划。",
+    },
+    Explanation {
+        code: RECHECK_TARGET_CHANGED,
+        label: APPLY,
+        title: "规划后链接路径上的对象发生了变化",
+        spec: "竞态安全和失败关闭行为",
+        text: "要替换的对象类型与规划时不同，或已无法读取；空目录在规划后变为非空时跳过该链接。\n\
+               修复：重新运行以按当前状态规划。",
+    },
+    Explanation {
+        code: RECHECK_PERMISSION_TARGET,
+        label: APPLY,
+        title: "修复权限前链接不再指向源",
+        spec: "权限和敏感文件语义",
+        text: "只有能验证为预期源路径的对象才会被修改权限。\n\
+               修复：重新运行以按当前状态规划。",
+    },
+    Explanation {
+        code: RECHECK_NOT_SYMLINK,
+        label: APPLY,
+        title: "要删除的链接不再是符号链接",
+        spec: "竞态安全和失败关闭行为",
+        text: "卸载只删除符号链接；规划后该路径被替换成了真实文件或目录。\n\
+               修复：检查该路径上的对象，必要时手动处理后重新运行。",
+    },
+    Explanation {
+        code: FS_OPERATION_FAILED,
+        label: APPLY,
+        title: "应用阶段的文件系统操作失败",
+        spec: "应用阶段错误",
+        text: "创建父目录、创建或删除符号链接、删除被替换对象或修改权限时操作系统返回了错误，\
+               消息中给出了原因。\n\
+               修复：按消息处理（常见为权限不足或磁盘已满）后重新运行。",
+    },
+    Explanation {
+        code: HISTORY_UNWRITABLE,
+        label: APPLY,
+        title: "操作历史无法写入",
+        spec: "操作历史",
+        text: "每次实际修改都要记入 ~/.local/state/xdotter/history.toml；运行开始前无法打开该文件时\
+               不做任何修改，运行中写入失败时该运行以失败结束。\n\
+               修复：检查该文件及其目录的权限和磁盘空间后重新运行。",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_unique_sorted_and_well_formed() {
+        for pair in ALL.windows(2) {
+            assert!(pair[0].code.as_str() < pair[1].code.as_str());
+        }
+        for e in ALL {
+            let s = e.code.as_str();
+            assert!(s.len() == 6 && s.starts_with("XD"));
+            assert!(s[2..].chars().all(|c| c.is_ascii_digit()), "{s}");
+            assert!(crate::error::LABELS.contains(&e.label));
+            assert!(e.text.contains("修复："), "{s}");
+        }
+        assert_eq!(explain("xd0203").unwrap().code, SOURCE_PARENT_TRAVERSAL);
+        assert!(explain("XD9999").is_none());
+        for code in [
+            GIT_NOT_LOCAL,
+            GIT_OPTION_LIKE,
+            LOCK_INVALID,
+            GIT_NOT_LOCKED,
+            GIT_NOT_CHECKED_OUT,
+            GIT_REVISION_MISSING,
+            GIT_FAILED,
+            LOCK_UNWRITABLE,
+        ] {
+            assert!(explain(code.as_str()).is_some(), "{code}");
+        }
+        assert_eq!(LINK_RELATIVE.explanation().spec, "链接路径");
+    }
+}
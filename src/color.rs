@@ -0,0 +1,190 @@
+//! Terminal styling for human-readable output.
+//!
+//! Color is a presentation detail only: it never changes the text of a
+//! message, only wraps parts of it in SGR escape sequences. Whether a
+//! stream is styled is decided once per stream from `--color`, the
+//! `NO_COLOR` convention (<https://no-color.org>), `TERM=dumb` and
+//! whether the stream is a terminal. `--color=always` wins over
+//! `NO_COLOR`; `--color=never` wins over everything.
+
+use std::io::IsTerminal;
+
+use crate::cli::{Cli, ColorChoice};
+use crate::error::LABELS;
+use crate::warning::{self, Warning};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Style {
+    Green,
+    Yellow,
+    Red,
+    Magenta,
+    Dim,
+    /// Error classification labels.
+    ErrorLabel,
+    /// The warning label.
+    WarningLabel,
+}
+
+impl Style {
+    fn sgr(self) -> &'static str {
+        match self {
+            Style::Green => "32",
+            Style::Yellow => "33",
+            Style::Red => "31",
+            Style::Magenta => "35",
+            Style::Dim => "2",
+            Style::ErrorLabel => "1;31",
+            Style::WarningLabel => "1;33",
+        }
+    }
+}
+
+/// Styling decision for one output stream.
+#[derive(Copy, Clone, Debug)]
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    pub fn new(choice: ColorChoice, stream: Stream) -> Self {
+        let is_tty = match stream {
+            Stream::Stdout => std::io::stdout().is_terminal(),
+            Stream::Stderr => std::io::stderr().is_terminal(),
+        };
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let dumb = std::env::var_os("TERM").is_some_and(|v| v == "dumb");
+        Self {
+            enabled: resolve(choice, no_color, dumb, is_tty),
+        }
+    }
+
+    pub fn stdout(cli: &Cli) -> Self {
+        Self::new(cli.color, Stream::Stdout)
+    }
+
+    pub fn stderr(cli: &Cli) -> Self {
+        Self::new(cli.color, Stream::Stderr)
+    }
+
+    #[cfg(test)]
+    fn with_enabled(enabled: bool) -> Self {
+        Self { enabled }
+    }
+
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", style.sgr(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Style the classification label at the start of every line of an
+    /// error message. Lines without a label are left untouched.
+    pub fn paint_error(&self, text: &str) -> String {
+        if !self.enabled {
+            return text.to_string();
+        }
+        text.lines()
+            .map(|line| match LABELS.iter().find(|l| line.starts_with(*l)) {
+                Some(label) => format!(
+                    "{}{}",
+                    self.paint(Style::ErrorLabel, label),
+                    &line[label.len()..]
+                ),
+                None => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Painter {
+    /// Style the label at the start of a warning.
+    pub fn paint_warning(&self, w: &Warning) -> String {
+        let text = w.to_string();
+        match text.strip_prefix(warning::LABEL) {
+            Some(rest) => format!(
+                "{}{}",
+                self.paint(Style::WarningLabel, warning::LABEL),
+                rest
+            ),
+            None => text,
+        }
+    }
+}
+
+fn resolve(choice: ColorChoice, no_color: bool, dumb: bool, is_tty: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color && !dumb && is_tty,
+    }
+}
+
+/// Style for the one-character action markers used by dry-run plans.
+pub fn marker_style(marker: &str) -> Style {
+    match marker {
+        "+" => Style::Green,
+        "~" | "-" => Style::Yellow,
+        "!" => Style::Red,
+        _ => Style::Dim,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_requires_tty_and_no_opt_out() {
+        assert!(resolve(ColorChoice::Auto, false, false, true));
+        assert!(!resolve(ColorChoice::Auto, false, false, false));
+        assert!(!resolve(ColorChoice::Auto, true, false, true));
+        assert!(!resolve(ColorChoice::Auto, false, true, true));
+    }
+
+    #[test]
+    fn explicit_choice_overrides_environment() {
+        assert!(resolve(ColorChoice::Always, true, true, false));
+        assert!(!resolve(ColorChoice::Never, false, false, true));
+    }
+
+    #[test]
+    fn disabled_painter_returns_text_verbatim() {
+        let p = Painter::with_enabled(false);
+        assert_eq!(p.paint(Style::Red, "x"), "x");
+        assert_eq!(p.paint_error("[配置错误] x"), "[配置错误] x");
+    }
+
+    #[test]
+    fn paint_warning_styles_only_the_label() {
+        let w = Warning {
+            kind: crate::warning::WarningKind::NotASymlink,
+            config_file: None,
+            path: "/h/x".into(),
+            message: "m".to_string(),
+        };
+        assert_eq!(
+            Painter::with_enabled(true).paint_warning(&w),
+            "\x1b[1;33m[警告]\x1b[0m m [not-a-symlink]"
+        );
+    }
+
+    #[test]
+    fn paint_error_styles_each_label_line() {
+        let p = Painter::with_enabled(true);
+        let s = p.paint_error("[配置错误] a\n  detail\n[应用阶段错误] b");
+        let lines: Vec<_> = s.lines().collect();
+        assert_eq!(lines[0], "\x1b[1;31m[配置错误]\x1b[0m a");
+        assert_eq!(lines[1], "  detail");
+        assert_eq!(lines[2], "\x1b[1;31m[应用阶段错误]\x1b[0m b");
+    }
+}
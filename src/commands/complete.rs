@@ -0,0 +1,303 @@
+//! Hidden `xd __complete` endpoint behind the scripts `xd completion`
+//! prints.
+//!
+//! A script passes the words before the cursor (without `xd`) after `--`
+//! and the word being completed as `--cur=WORD`. Candidates are printed
+//! one per line as `value<TAB>description`, the description optional,
+//! and the shell filters nothing further. Subcommands, flags and
+//! enumerated values come from the clap definition; repository names,
+//! dependency names, link paths and source paths are looked up when
+//! asked for, so completion follows the repository without regenerating
+//! anything.
+//!
+//! Completion never fails: an unreadable registry or a broken
+//! configuration only means fewer candidates.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::answers::PromptKind;
+use crate::codes;
+use crate::lint;
+use crate::plan::ForceSet;
+use crate::registry::Registry;
+use crate::XdError;
+use clap::{Arg, Command as ClapCommand, CommandFactory};
+
+use crate::cli::{Cli, CompleteArgs, CompleteKind};
+
+struct Candidate {
+    value: String,
+    help: String,
+}
+
+impl Candidate {
+    fn new(value: impl Into<String>, help: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            help: help.into(),
+        }
+    }
+}
+
+pub fn run(cli: &Cli, args: &CompleteArgs) -> Result<(), XdError> {
+    let candidates = match args.values {
+        Some(kind) => lookup(cli, kind),
+        None => complete(cli, &args.words, &args.cur),
+    };
+    let mut seen = HashSet::new();
+    for c in candidates {
+        if !c.value.starts_with(&args.cur) {
+            continue;
+        }
+        if !seen.insert(c.value.clone()) {
+            continue;
+        }
+        if c.help.is_empty() {
+            println!("{}", c.value);
+        } else {
+            println!("{}\t{}", c.value, c.help);
+        }
+    }
+    Ok(())
+}
+
+/// Candidates for `cur` after `words`, unfiltered.
+fn complete(cli: &Cli, words: &[String], cur: &str) -> Vec<Candidate> {
+    let mut cmd = Cli::command();
+    cmd.build();
+
+    let mut positional = 0;
+    let mut pending: Option<Arg> = None;
+    let mut after_dashes = false;
+    for w in words {
+        if pending.take().is_some() {
+            continue;
+        }
+        if after_dashes {
+            positional += 1;
+        } else if w == "--" {
+            after_dashes = true;
+        } else if let Some(long) = w.strip_prefix("--") {
+            if !long.contains('=') {
+                pending = find_long(&cmd, long).filter(takes_separate_value);
+            }
+        } else if let Some(shorts) = w.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // In a cluster such as `-nf`, only the last flag can take the
+            // next word as its value.
+            if let Some(c) = shorts.chars().last() {
+                pending = find_short(&cmd, c).filter(takes_separate_value);
+            }
+        } else if let Some(sub) = (positional == 0)
+            .then(|| cmd.find_subcommand(w).cloned())
+            .flatten()
+        {
+            cmd = sub;
+        } else {
+            positional += 1;
+        }
+    }
+
+    if let Some(arg) = pending {
+        return values(cli, &arg, cur, "");
+    }
+    if !after_dashes {
+        if let Some((flag, value)) = cur.strip_prefix("--").and_then(|c| c.split_once('=')) {
+            return match find_long(&cmd, flag) {
+                Some(arg) => values(cli, &arg, value, &format!("--{}=", flag)),
+                None => Vec::new(),
+            };
+        }
+        if cur.starts_with('-') {
+            return flags(&cmd, cur);
+        }
+    }
+
+    let mut out = Vec::new();
+    if positional == 0 && !after_dashes {
+        out.extend(
+            cmd.get_subcommands()
+                .filter(|s| !s.is_hide_set())
+                .map(|s| Candidate::new(s.get_name(), about(s))),
+        );
+    }
+    let positionals: Vec<&Arg> = cmd.get_positionals().collect();
+    let arg = positionals.get(positional).or_else(|| {
+        positionals
+            .last()
+            .filter(|a| a.get_num_args().is_some_and(|n| n.max_values() > 1))
+    });
+    if let Some(arg) = arg {
+        out.extend(values(cli, arg, cur, ""));
+    }
+    out
+}
+
+fn find_long(cmd: &ClapCommand, long: &str) -> Option<Arg> {
+    cmd.get_arguments()
+        .find(|a| a.get_long() == Some(long))
+        .cloned()
+}
+
+fn find_short(cmd: &ClapCommand, short: char) -> Option<Arg> {
+    cmd.get_arguments()
+        .find(|a| a.get_short() == Some(short))
+        .cloned()
+}
+
+/// True when the option consumes the following word, as opposed to a
+/// flag or an option whose value must be attached with `=`.
+fn takes_separate_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values() && !arg.is_require_equals_set()
+}
+
+fn flags(cmd: &ClapCommand, cur: &str) -> Vec<Candidate> {
+    let mut out = Vec::new();
+    for arg in cmd.get_arguments() {
+        if arg.is_positional() || arg.is_hide_set() {
+            continue;
+        }
+        let help = first_line(arg.get_help().map(ToString::to_string));
+        if let Some(long) = arg.get_long() {
+            out.push(Candidate::new(format!("--{}", long), help.clone()));
+        }
+        if let Some(short) = arg.get_short().filter(|_| !cur.starts_with("--")) {
+            out.push(Candidate::new(format!("-{}", short), help));
+        }
+    }
+    out
+}
+
+/// Candidates for the value of `arg`, each prefixed with `prefix` (the
+/// `--flag=` part when the value is attached to its flag).
+fn values(cli: &Cli, arg: &Arg, cur: &str, prefix: &str) -> Vec<Candidate> {
+    let mut out: Vec<Candidate> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| {
+            let help = first_line(v.get_help().map(ToString::to_string));
+            Candidate::new(v.get_name(), help)
+        })
+        .collect();
+    match arg.get_id().as_str() {
+        "force" => {
+            // A comma-separated set: complete the last category and keep
+            // the ones already typed.
+            let head = cur.rfind(',').map_or("", |i| &cur[..=i]);
+            let typed: Vec<&str> = head.split(',').collect();
+            out.extend(
+                ForceSet::names()
+                    .chain(["all"])
+                    .filter(|n| !typed.contains(n))
+                    .map(|n| Candidate::new(format!("{}{}", head, n), "")),
+            );
+        }
+        "answer" => {
+            for kind in PromptKind::ALL {
+                for decision in ["yes", "no"] {
+                    out.push(Candidate::new(
+                        format!("{}={}", kind.name(), decision),
+                        kind.describe(),
+                    ));
+                }
+            }
+        }
+        "code" => out.extend(
+            codes::ALL
+                .iter()
+                .map(|e| Candidate::new(e.code.as_str(), e.title)),
+        ),
+        "allow" => out.extend(lint::Rule::ALL.iter().map(|r| Candidate::new(r.name(), ""))),
+        "shell" => out.extend(
+            super::completion::SHELLS
+                .iter()
+                .map(|s| Candidate::new(*s, "")),
+        ),
+        _ => {}
+    }
+    let kind = match arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|n| n.as_str())
+    {
+        Some("REPO") => Some(CompleteKind::Repos),
+        Some("DEP") => Some(CompleteKind::Deps),
+        Some("LINK") => Some(CompleteKind::Links),
+        Some("SOURCE") => Some(CompleteKind::Sources),
+        _ => None,
+    };
+    if let Some(kind) = kind {
+        out.extend(lookup(cli, kind));
+    }
+    for c in &mut out {
+        c.value.insert_str(0, prefix);
+    }
+    out
+}
+
+/// Candidates that depend on the registry or the repository contents.
+fn lookup(cli: &Cli, kind: CompleteKind) -> Vec<Candidate> {
+    if kind == CompleteKind::Repos {
+        let registry = Registry::default_path()
+            .and_then(|p| Registry::load(&p).ok())
+            .unwrap_or_default();
+        return registry
+            .repos()
+            .map(|(name, root)| Candidate::new(name, root.display().to_string()))
+            .collect();
+    }
+
+    let configs = super::discover_configs(cli)
+        .map(|d| d.configs)
+        .unwrap_or_default();
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let mut out = Vec::new();
+    for dc in &configs {
+        let cfg = &dc.config;
+        match kind {
+            CompleteKind::Deps => {
+                out.extend(cfg.dependencies.iter().map(|(n, p)| Candidate::new(n, p)));
+                out.extend(
+                    cfg.git_dependencies
+                        .iter()
+                        .map(|(n, g)| Candidate::new(n, &g.url)),
+                );
+            }
+            CompleteKind::Links => out.extend(
+                cfg.links
+                    .iter()
+                    .map(|(source, link)| Candidate::new(link, source)),
+            ),
+            CompleteKind::Sources => out.extend(cfg.links.iter().map(|(source, link)| {
+                Candidate::new(display_source(&dc.config_dir, source, &cwd), link)
+            })),
+            CompleteKind::Repos => unreachable!("handled above"),
+        }
+    }
+    out
+}
+
+/// `source` of a configuration in `config_dir`, relative to `cwd` when
+/// it lies below it.
+fn display_source(config_dir: &Path, source: &str, cwd: &Path) -> String {
+    let path = config_dir.join(source);
+    let cwd = cwd.canonicalize().unwrap_or_else(|_| cwd.to_path_buf());
+    path.strip_prefix(&cwd)
+        .unwrap_or(&path)
+        .display()
+        .to_string()
+}
+
+fn about(cmd: &ClapCommand) -> String {
+    first_line(cmd.get_about().map(ToString::to_string))
+}
+
+/// First sentence of a help text, which is all a completion menu has
+/// room for.
+fn first_line(text: Option<String>) -> String {
+    let text = text.unwrap_or_default();
+    let line = text.lines().next().unwrap_or("");
+    let sentence = line.split(". ").next().unwrap_or(line);
+    sentence.trim_end_matches('.').to_string()
+}
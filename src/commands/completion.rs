@@ -1,18 +1,28 @@
-use crate::error::XdError;
+use crate::XdError;
 
-const BASH: &str = include_str!(concat!(env!("OUT_DIR"), "/xd.bash"));
-const ZSH: &str = include_str!(concat!(env!("OUT_DIR"), "/_xd"));
-const FISH: &str = include_str!(concat!(env!("OUT_DIR"), "/xd.fish"));
+/// Shells `xd completion` prints a script for.
+pub const SHELLS: [&str; 6] = ["bash", "zsh", "fish", "powershell", "elvish", "nushell"];
+
+const BASH: &str = include_str!("completion/xd.bash");
+const ZSH: &str = include_str!("completion/_xd");
+const FISH: &str = include_str!("completion/xd.fish");
+const POWERSHELL: &str = include_str!("completion/xd.ps1");
+const ELVISH: &str = include_str!("completion/xd.elv");
+const NUSHELL: &str = include_str!("completion/xd.nu");
 
 pub fn run(shell: &str) -> Result<(), XdError> {
     let s = match shell.to_ascii_lowercase().as_str() {
         "bash" => BASH,
         "zsh" => ZSH,
         "fish" => FISH,
+        "powershell" | "pwsh" => POWERSHELL,
+        "elvish" => ELVISH,
+        "nushell" | "nu" => NUSHELL,
         other => {
             return Err(XdError::cli(format!(
-                "不支持的 shell: {}. 支持: bash, zsh, fish",
-                other
+                "不支持的 shell: {}. 支持: {}",
+                other,
+                SHELLS.join(", ")
             )));
         }
     };
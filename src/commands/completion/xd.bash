@@ -0,0 +1,25 @@
+# bash completion for xd
+#
+# Candidates come from `xd __complete`, so dependency names, link paths
+# and registered repositories follow the repository being completed in.
+
+_xd() {
+    local line=${COMP_LINE:0:COMP_POINT}
+    local -a words
+    read -ra words <<< "$line"
+    [[ $line == *[[:space:]] || ${#words[@]} -eq 0 ]] && words+=("")
+
+    local partial=${words[${#words[@]}-1]}
+    local -a prev=("${words[@]:1:${#words[@]}-2}")
+    # Bash splits `--force=br` at `=`; replace only its last piece.
+    local strip=${partial%"${COMP_WORDS[COMP_CWORD]}"}
+
+    local IFS=$'\n' c
+    COMPREPLY=()
+    for c in $(command xd __complete --cur="$partial" -- "${prev[@]}" 2>/dev/null); do
+        c=${c%%$'\t'*}
+        COMPREPLY+=("${c#"$strip"}")
+    done
+}
+
+complete -o default -F _xd xd
@@ -0,0 +1,19 @@
+# fish completion for xd
+#
+# Candidates come from `xd __complete`, so dependency names, link paths
+# and registered repositories follow the repository being completed in.
+
+function __xd_complete
+    set -l words (commandline -opc)
+    set -l cur (commandline -ct)
+    command xd __complete "--cur=$cur" -- $words[2..-1] 2>/dev/null
+end
+
+function __xd_has_candidates
+    set -l candidates (__xd_complete)
+    test (count $candidates) -gt 0
+end
+
+complete -c xd -e
+complete -c xd -f -n __xd_has_candidates -a '(__xd_complete)'
+complete -c xd -F -n 'not __xd_has_candidates'
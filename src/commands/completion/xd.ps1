@@ -0,0 +1,18 @@
+# PowerShell completion for xd
+#
+# Candidates come from `xd __complete`, so dependency names, link paths
+# and registered repositories follow the repository being completed in.
+
+Register-ArgumentCompleter -Native -CommandName xd -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $prev = @($commandAst.CommandElements |
+        Select-Object -Skip 1 |
+        Where-Object { $_.Extent.EndOffset -lt $cursorPosition -or
+            ($_.Extent.EndOffset -eq $cursorPosition -and $wordToComplete -eq '') } |
+        ForEach-Object { $_.Extent.Text })
+    & xd __complete "--cur=$wordToComplete" -- @prev 2>$null | ForEach-Object {
+        $value, $help = $_ -split "`t", 2
+        if (-not $help) { $help = $value }
+        [System.Management.Automation.CompletionResult]::new($value, $value, 'ParameterValue', $help)
+    }
+}
@@ -1,35 +1,51 @@
 use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
 
+use crate::answers::{Answers, PromptKind};
 use crate::apply;
-use crate::cli::{Cli, ConflictMode, DeployArgs};
 use crate::discover;
-use crate::error::XdError;
-use crate::log;
+use crate::edit;
+use crate::fs::RealFs;
+use crate::history::AuditFs;
 use crate::plan::{
-    self, DeployAction, DeployActionKind, DeployPlan, ExistingKind, PermissionAction,
+    self, ConflictMode, DeployAction, DeployActionKind, DeployPlan, ExistingKind, ForceSet,
+    PermissionAction,
 };
+use crate::XdError;
 
-pub fn run(cli: &Cli, args: &DeployArgs) -> Result<(), XdError> {
-    let cwd = std::env::current_dir()
-        .map_err(|e| XdError::cli(format!("无法获取当前工作目录: {}", e)))?;
-    if !cwd.join("xdotter.toml").exists() {
-        return Err(XdError::cli(format!(
-            "当前目录 {} 中没有 xdotter.toml",
-            cwd.display()
-        )));
-    }
+use crate::cli::{Cli, DeployArgs};
+use crate::color::{self, Painter};
+use crate::log;
 
-    let mode = args.conflict_mode();
+pub fn run(cli: &Cli, args: &DeployArgs) -> Result<(), XdError> {
+    // `--edit-plan` plans like `--force` so every recoverable conflict
+    // becomes an editable line; the editor replaces per-operation prompts.
+    let mode = if args.edit_plan {
+        ConflictMode::Force(ForceSet::ALL)
+    } else {
+        ConflictMode::from_flags(super::force_set(args.force.as_deref())?, args.interactive)
+    };
     log::info(
         cli,
         format!("deploy: 模式={:?}, dry_run={}", mode, args.dry_run),
     );
-    let disc = discover::discover(&cwd);
+    let answers = super::load_answers(&args.answer, args.answers.as_deref())?;
+    let roots = super::config_roots(cli)?;
+    // Held until apply is done; dry-runs change nothing and take no lock.
+    let _lock = if args.dry_run {
+        None
+    } else {
+        Some(super::lock_run(&roots, args.no_wait)?)
+    };
+    let disc = discover::discover_repos(&roots);
     log::debug(
         cli,
         format!("deploy: 发现 {} 个配置文件", disc.configs.len()),
     );
-    let res = plan::build_deploy_plan(disc, mode);
+    let mut res = plan::build_deploy_plan(disc, mode);
+    super::report_warnings(cli, std::mem::take(&mut res.warnings), &mut res.errors);
 
     if !res.errors.is_empty() {
         return Err(res.errors.into_error());
@@ -45,9 +61,23 @@ pub fn run(cli: &Cli, args: &DeployArgs) -> Result<(), XdError> {
         }
     }
 
+    let planned = res.plan.actions.len();
+    let plan = if args.edit_plan {
+        edit_plan(&res.plan)?
+    } else {
+        res.plan
+    };
+    let left_out = planned - plan.actions.len();
+    if left_out > 0 {
+        log::info(cli, format!("deploy: 编辑计划时跳过 {} 条动作", left_out));
+    }
+
     if args.dry_run {
-        print_deploy_plan(&res.plan, mode);
-        if dry_run_plan_has_failures(&res.plan, mode) {
+        let mut sim = apply::simulate_deploy_answering(&RealFs, &plan, &answers);
+        // Predict the same totals the live run reports.
+        sim.outcome.skipped += left_out;
+        print_deploy_plan(&plan, mode, &answers, &sim, planned, &Painter::stdout(cli));
+        if sim.outcome.failures > 0 {
             return Err(XdError::planning(
                 "dry-run 计划包含会跳过并计为失败的链接".to_string(),
             ));
@@ -55,8 +85,17 @@ pub fn run(cli: &Cli, args: &DeployArgs) -> Result<(), XdError> {
         return Ok(());
     }
 
-    let outcome = apply::apply_deploy(&res.plan);
-    print_deploy_outcome(&outcome, &res.plan);
+    let mut history = super::open_history("deploy", plan.mode)?;
+    let audit = AuditFs::new(&RealFs);
+    let mut unrecorded = None;
+    let mut outcome = apply::apply_deploy_observed(&audit, &plan, &answers, |i| {
+        if let Err(e) = history.deploy(&plan.actions[i], audit.take()) {
+            unrecorded.get_or_insert(e);
+        }
+    });
+    outcome.skipped += left_out;
+    outcome.errors.extend(unrecorded);
+    print_deploy_outcome(&outcome, planned);
 
     if outcome.failures > 0 || !outcome.errors.is_empty() {
         return Err(outcome.errors.into_error());
@@ -87,39 +126,29 @@ fn log_action(cli: &Cli, a: &DeployAction) {
     );
 }
 
-fn dry_run_plan_has_failures(plan: &DeployPlan, mode: ConflictMode) -> bool {
-    let interactive_dry_run = matches!(mode, ConflictMode::Interactive);
-    plan.actions.iter().any(|a| {
-        matches!(a.kind, DeployActionKind::SkipFailure(_))
-            || (interactive_dry_run && matches!(a.kind, DeployActionKind::Replace(_)))
-            || (interactive_dry_run && matches!(a.permission_action, PermissionAction::Fix))
-            || matches!(a.permission_action, PermissionAction::SkipFailure(_))
-    })
-}
-
-fn print_deploy_plan(plan: &DeployPlan, mode: ConflictMode) {
+fn print_deploy_plan(
+    plan: &DeployPlan,
+    mode: ConflictMode,
+    answers: &Answers,
+    sim: &apply::Simulation,
+    planned: usize,
+    painter: &Painter,
+) {
     // SPEC §"执行模式": `--interactive --dry-run` is treated as "no" for
-    // every recoverable conflict; `--force --dry-run` is treated as
-    // "yes". This affects how Replace and PermissionAction::Fix are
-    // rendered in the dry-run output — `apply` is never called here.
+    // every recoverable conflict without a scripted answer; `--force
+    // --dry-run` is treated as "yes". This affects how Replace and
+    // PermissionAction::Fix are rendered in the dry-run output. The
+    // per-action effects come from `sim`, which ran the same apply steps
+    // against an in-memory overlay.
     let interactive_dry_run = matches!(mode, ConflictMode::Interactive);
+    let declined = |k| interactive_dry_run && answers.get(k) != Some(true);
 
     println!("# Deploy plan ({} 条目)", plan.actions.len());
-    for a in &plan.actions {
-        // Sensitive-target advisory belongs on stderr (warnings / diagnostics).
-        if let Some((m, label)) = a.permission_required {
-            eprintln!(
-                "[警告] 链接 {} 命中敏感目标 ({}, 期望权限 {:o})；请确认该路径由 xdotter 管理",
-                a.link_expanded.display(),
-                label,
-                m
-            );
-        }
-
-        let (marker, desc) = describe_action_for_dry_run(&a.kind, interactive_dry_run);
+    for (i, a) in plan.actions.iter().enumerate() {
+        let (marker, desc) = describe_action_for_dry_run(&a.kind, &declined);
         println!(
             "{} {} -> {} [{}]",
-            marker,
+            painter.paint(color::marker_style(marker), marker),
             display_link(&a.link_expanded),
             a.source_canonical.display(),
             desc
@@ -133,7 +162,7 @@ fn print_deploy_plan(plan: &DeployPlan, mode: ConflictMode) {
                     println!("    perm: ok ({}, {:o})", label, mode_required);
                 }
                 PermissionAction::Fix => {
-                    if interactive_dry_run {
+                    if declined(PromptKind::FixPermission) {
                         println!("    perm: would skip permission fix (interactive declined)");
                     } else {
                         println!("    perm: would fix to {:o} ({})", mode_required, label);
@@ -144,20 +173,23 @@ fn print_deploy_plan(plan: &DeployPlan, mode: ConflictMode) {
                 }
             }
         }
+        super::print_simulated_step(sim, i, painter);
     }
+    super::print_prediction("deploy", sim, planned);
 }
 
 /// Render a deploy action for dry-run, accounting for interactive mode
-/// (every recoverable conflict is treated as "no" per SPEC).
+/// (every recoverable conflict without a scripted "yes" is treated as
+/// "no" per SPEC).
 fn describe_action_for_dry_run(
     k: &DeployActionKind,
-    interactive_dry_run: bool,
+    declined: &dyn Fn(PromptKind) -> bool,
 ) -> (&'static str, String) {
     match k {
         DeployActionKind::Create => ("+", "create".to_string()),
         DeployActionKind::AlreadyCorrect => ("=", "already correct".to_string()),
         DeployActionKind::Replace(existing) => {
-            if interactive_dry_run {
+            if declined(PromptKind::replace(existing)) {
                 (
                     "!",
                     format!(
@@ -173,14 +205,12 @@ fn describe_action_for_dry_run(
     }
 }
 
-fn print_deploy_outcome(outcome: &apply::ApplyOutcome, plan: &DeployPlan) {
+fn print_deploy_outcome(outcome: &apply::ApplyOutcome, planned: usize) {
     eprintln!(
         "Deploy: {} succeeded, {} skipped, {} failed (planned {})",
-        outcome.successes,
-        outcome.skipped,
-        outcome.failures,
-        plan.actions.len()
+        outcome.successes, outcome.skipped, outcome.failures, planned
     );
+    super::print_prompt_tally(&outcome.prompts);
     // Errors are printed by main.rs via the returned Err result.
 }
 
@@ -196,3 +226,61 @@ fn describe_existing(e: &ExistingKind) -> &'static str {
 fn display_link(p: &Path) -> String {
     p.display().to_string()
 }
+
+/// Let the user edit `plan` in `$VISUAL` / `$EDITOR` and read back the
+/// actions they kept.
+fn edit_plan(plan: &DeployPlan) -> Result<DeployPlan, XdError> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let path = env::temp_dir().join(format!("xd-plan-{}-{}.txt", std::process::id(), nanos));
+    // create_new refuses to follow a symlink planted at the path.
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut f| std::io::Write::write_all(&mut f, edit::render(plan).as_bytes()))
+        .map_err(|e| XdError::cli(format!("无法写入计划文件 {}: {}", path.display(), e)))?;
+
+    let (editor, mut cmd) = editor_command(&path);
+    let status = cmd.status();
+    let text = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(s) => {
+            return Err(XdError::cli(format!(
+                "编辑器 {} 以 {} 退出，已取消部署",
+                editor, s
+            )))
+        }
+        Err(e) => return Err(XdError::cli(format!("无法启动编辑器 {}: {}", editor, e))),
+    }
+    let text =
+        text.map_err(|e| XdError::cli(format!("无法读取计划文件 {}: {}", path.display(), e)))?;
+    edit::parse(plan, &text)
+}
+
+/// The user's editor, run through the shell so values such as
+/// `code --wait` work as they do for git.
+fn editor_command(path: &Path) -> (String, Command) {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|v| env::var(v).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    let mut cmd;
+    if cfg!(windows) {
+        cmd = Command::new("cmd");
+        cmd.arg("/C")
+            .arg(format!("{} \"{}\"", editor, path.display()));
+    } else {
+        cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("{} \"$@\"", editor))
+            .arg(&editor)
+            .arg(path);
+    }
+    (editor, cmd)
+}
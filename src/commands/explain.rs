@@ -0,0 +1,29 @@
+use crate::codes;
+use crate::XdError;
+
+pub fn run(code: Option<&str>) -> Result<(), XdError> {
+    let Some(code) = code else {
+        for e in codes::ALL {
+            // Labels are CJK, two columns per character.
+            let width: usize = e
+                .label
+                .chars()
+                .map(|c| if c.is_ascii() { 1 } else { 2 })
+                .sum();
+            let pad = " ".repeat(14usize.saturating_sub(width));
+            println!("{}  {}{}  {}", e.code, e.label, pad, e.title);
+        }
+        return Ok(());
+    };
+    let e = codes::explain(code).ok_or_else(|| {
+        XdError::cli(format!(
+            "未知的错误代码 \"{}\"；运行 xd explain 列出全部代码",
+            code
+        ))
+    })?;
+    println!("{} {} {}", e.code, e.label, e.title);
+    println!("SPEC: §\"{}\"", e.spec);
+    println!();
+    println!("{}", e.text);
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use crate::history::{self, Entry};
+use crate::XdError;
+
+use crate::cli::HistoryArgs;
+
+pub fn run(args: &HistoryArgs) -> Result<(), XdError> {
+    let path = history::default_path()
+        .ok_or_else(|| XdError::cli("无法确定主目录，找不到操作历史".to_string()))?;
+    let link = args.link.as_deref().map(link_path).transpose()?;
+    for e in history::load(&path)? {
+        if link.as_ref().is_none_or(|l| e.link == *l) {
+            println!("{}", describe(&e));
+        }
+    }
+    Ok(())
+}
+
+/// `--link` as an absolute path comparable with [`Entry::link`].
+fn link_path(raw: &str) -> Result<PathBuf, XdError> {
+    let p = match raw.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .ok_or_else(|| XdError::cli(format!("无法确定主目录，无法展开 {}", raw)))?
+            .join(rest),
+        None => PathBuf::from(raw),
+    };
+    if p.is_absolute() {
+        return Ok(p);
+    }
+    let cwd = std::env::current_dir()
+        .map_err(|e| XdError::cli(format!("无法获取当前工作目录: {}", e)))?;
+    Ok(cwd.join(p))
+}
+
+/// `TIME  deploy --force  symlink LINK -> TARGET  (was missing)  @REV`,
+/// or `rename FROM -> LINK` for a rename.
+fn describe(e: &Entry) -> String {
+    let mut line = format!("{}  {}", e.time, e.command);
+    if e.mode != "default" {
+        line.push_str(&format!(" --{}", e.mode));
+    }
+    line.push_str(&format!("  {}", e.op));
+    if let Some(mode) = &e.permissions {
+        line.push_str(&format!(" {}", mode));
+    }
+    if let Some(from) = &e.from {
+        let arrow = if e.op == "exchange" { "<->" } else { "->" };
+        line.push_str(&format!(" {} {}", from.display(), arrow));
+    }
+    line.push_str(&format!(" {}", e.path.display()));
+    if let Some(target) = &e.target {
+        line.push_str(&format!(" -> {}", target.display()));
+    }
+    line.push_str(&format!("  (was {})", e.previous));
+    if e.path != e.link {
+        line.push_str(&format!("  [link {}]", e.link.display()));
+    }
+    if let Some(rev) = &e.revision {
+        line.push_str(&format!("  @{}", rev.chars().take(12).collect::<String>()));
+    }
+    line
+}
@@ -0,0 +1,37 @@
+use crate::lint::{self, Rule};
+use crate::XdError;
+
+use crate::cli::{Cli, LintArgs};
+use crate::color::{Painter, Style};
+
+pub fn run(cli: &Cli, args: &LintArgs) -> Result<(), XdError> {
+    let allow = args
+        .allow
+        .iter()
+        .map(|name| Rule::parse(name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let disc = super::discover_configs(cli)?;
+    let report = lint::lint(disc, &allow);
+
+    let painter = Painter::stdout(cli);
+    for f in &report.findings {
+        let label = format!("[{}]", f.rule);
+        println!(
+            "{} {}: {}",
+            painter.paint(Style::Yellow, &label),
+            f.config_file.display(),
+            f.message
+        );
+    }
+    println!(
+        "Lint: {} warnings, {} suppressed",
+        report.findings.len(),
+        report.suppressed
+    );
+
+    // Warnings alone leave the exit status at 0; hard errors do not.
+    if !report.errors.is_empty() {
+        return Err(report.errors.into_error());
+    }
+    Ok(())
+}
@@ -3,25 +3,290 @@
 //! - Command results -> stdout
 //! - Warnings / errors / diagnostics -> stderr
 
+mod complete;
 mod completion;
 mod deploy;
+mod explain;
+mod history;
+mod lint;
 mod new;
+mod orphans;
+mod repo;
 mod status;
 mod undeploy;
+mod update_deps;
 mod version;
+mod watch;
 
-use crate::cli::{Cli, Command};
-use crate::error::XdError;
+use std::path::{Path, PathBuf};
 
-pub fn dispatch(cli: &Cli) -> Result<(), XdError> {
+use clap::error::ErrorKind;
+use clap::Parser;
+
+use crate::answers::Answers;
+use crate::apply::{PromptTally, Simulation};
+use crate::discover::{self, Discovered};
+use crate::fs::Change;
+use crate::history::Recorder;
+use crate::lock::{self, RunLock};
+use crate::plan::{ConflictModeRecord, ForceSet};
+use crate::registry::Registry;
+use crate::warning::WarningBag;
+use crate::{ErrorBag, XdError};
+
+use crate::cli::{Cli, ColorChoice, Command};
+use crate::color::{Painter, Stream, Style};
+use crate::log;
+
+/// Run `xd` with the process arguments and exit.
+pub fn main() -> ! {
+    // Manually parse so we can wrap CLI argument errors with the SPEC
+    // [CLI 参数错误] classification label. clap's --help/--version exits
+    // are not errors and are passed through.
+    let cli = match Cli::try_parse() {
+        Ok(c) => c,
+        Err(e) => match e.kind() {
+            ErrorKind::DisplayHelp | ErrorKind::DisplayVersion => {
+                // clap prints the help/version to stdout and exits 0.
+                e.exit();
+            }
+            _ => {
+                // `--color` has not been parsed; fall back to auto-detection.
+                let painter = Painter::new(ColorChoice::Auto, Stream::Stderr);
+                let body = e.to_string();
+                eprintln!("{}", painter.paint_error(&XdError::cli(body).to_string()));
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let result = dispatch(&cli);
+    log::finish(&result);
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("{}", Painter::stderr(&cli).paint_error(&e.to_string()));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn dispatch(cli: &Cli) -> Result<(), XdError> {
     let cmd = cli.command.as_ref();
+    // `__complete` runs on every TAB and never prints diagnostics.
+    if !matches!(cmd, Some(Command::Complete(_))) {
+        log::init(cli)?;
+    }
     match cmd {
         None => deploy::run(cli, &Default::default()),
         Some(Command::Deploy(args)) => deploy::run(cli, args),
         Some(Command::Undeploy(args)) => undeploy::run(cli, args),
         Some(Command::Status) => status::run(cli),
+        Some(Command::Watch(args)) => watch::run(cli, args),
+        Some(Command::Lint(args)) => lint::run(cli, args),
+        Some(Command::Orphans(args)) => orphans::run(cli, args),
         Some(Command::New(args)) => new::run(cli, args),
+        Some(Command::UpdateDeps(args)) => update_deps::run(cli, args),
+        Some(Command::Repo { command }) => repo::run(command),
+        Some(Command::History(args)) => history::run(args),
+        Some(Command::Explain { code }) => explain::run(code.as_deref()),
         Some(Command::Completion { shell }) => completion::run(shell),
+        Some(Command::Complete(args)) => complete::run(cli, args),
         Some(Command::Version) => version::run(),
     }
 }
+
+/// The current directory, which must hold the root `xdotter.toml`.
+fn config_root() -> Result<PathBuf, XdError> {
+    let cwd = std::env::current_dir()
+        .map_err(|e| XdError::cli(format!("无法获取当前工作目录: {}", e)))?;
+    if !cwd.join("xdotter.toml").exists() {
+        return Err(XdError::cli(format!(
+            "当前目录 {} 中没有 xdotter.toml",
+            cwd.display()
+        )));
+    }
+    Ok(cwd)
+}
+
+/// Path of the user-level repository registry.
+fn registry_path() -> Result<PathBuf, XdError> {
+    Registry::default_path()
+        .ok_or_else(|| XdError::cli("无法确定主目录，找不到仓库注册表".to_string()))
+}
+
+/// Open the operation history for a live run of `command`.
+fn open_history(command: &'static str, mode: ConflictModeRecord) -> Result<Recorder, XdError> {
+    let path = crate::history::default_path()
+        .ok_or_else(|| XdError::cli("无法确定主目录，找不到操作历史".to_string()))?;
+    Recorder::open(&path, command, mode)
+}
+
+/// Root configurations deploy, undeploy, status and update-deps operate
+/// on. With registered repositories that is all of them, plus the
+/// current directory when it holds an unregistered `xdotter.toml`;
+/// otherwise the current directory alone.
+fn config_roots(cli: &Cli) -> Result<Vec<(Option<String>, PathBuf)>, XdError> {
+    let registry = match Registry::default_path() {
+        Some(path) => Registry::load(&path)?,
+        None => Registry::default(),
+    };
+    if registry.is_empty() {
+        return Ok(vec![(None, config_root()?)]);
+    }
+    let mut roots: Vec<(Option<String>, PathBuf)> = registry
+        .repos()
+        .map(|(name, root)| (Some(name.to_string()), root.to_path_buf()))
+        .collect();
+    if let Ok(cwd) = config_root() {
+        roots.push((None, cwd));
+    }
+    log::debug(
+        cli,
+        format!("发现: {} 个根配置目录 (含已注册仓库)", roots.len()),
+    );
+    Ok(roots)
+}
+
+/// Take the run locks of a live run over `roots` (SPEC §"并发运行"),
+/// waiting for another run to finish unless `no_wait`.
+fn lock_run(roots: &[(Option<String>, PathBuf)], no_wait: bool) -> Result<RunLock, XdError> {
+    let path = lock::home_lock_path()
+        .ok_or_else(|| XdError::cli("无法确定主目录，找不到运行锁".to_string()))?;
+    let dirs: Vec<PathBuf> = roots.iter().map(|(_, root)| root.clone()).collect();
+    lock::acquire(&path, &dirs, !no_wait, |holder| {
+        eprintln!("{}，等待其结束…", holder)
+    })
+}
+
+/// Discover every configuration reachable from [`config_roots`].
+fn discover_configs(cli: &Cli) -> Result<Discovered, XdError> {
+    Ok(discover::discover_repos(&config_roots(cli)?))
+}
+
+/// Print `warnings` to stderr or, under `--deny-warnings`, add them to
+/// `errors`.
+fn report_warnings(cli: &Cli, warnings: WarningBag, errors: &mut ErrorBag) {
+    if warnings.suppressed() > 0 {
+        log::info(
+            cli,
+            format!("[warnings] allow 关闭了 {} 条警告", warnings.suppressed()),
+        );
+    }
+    if cli.deny_warnings {
+        errors.extend(warnings.deny().into_vec());
+        return;
+    }
+    let painter = Painter::stderr(cli);
+    for w in warnings.iter() {
+        eprintln!("{}", painter.paint_warning(w));
+    }
+}
+
+/// The `--force[=KINDS]` value, if given.
+fn force_set(value: Option<&str>) -> Result<Option<ForceSet>, XdError> {
+    value.map(ForceSet::parse).transpose()
+}
+
+/// Scripted answers from `--answers FILE`, overridden by each
+/// `--answer KIND=yes|no` in order.
+fn load_answers(entries: &[String], file: Option<&Path>) -> Result<Answers, XdError> {
+    let mut answers = match file {
+        Some(path) => {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| XdError::cli(format!("无法读取回答文件 {}: {}", path.display(), e)))?;
+            Answers::from_toml(&content, path)?
+        }
+        None => Answers::new(),
+    };
+    for e in entries {
+        answers.add_entry(e)?;
+    }
+    Ok(answers)
+}
+
+/// Print the predicted filesystem effects of dry-run action `i`, or why
+/// it would not run.
+fn print_simulated_step(sim: &Simulation, i: usize, painter: &Painter) {
+    match sim.outcome.hard_failure {
+        Some(stop) if i == stop => {
+            let err = sim.outcome.errors.iter().next_back().map(|e| e.to_string());
+            println!(
+                "    {} {}",
+                painter.paint(Style::Red, "would fail:"),
+                err.unwrap_or_default()
+            );
+            return;
+        }
+        Some(stop) if i > stop => {
+            println!(
+                "    {}",
+                painter.paint(
+                    Style::Dim,
+                    "not reached (apply stops at the first hard failure)"
+                )
+            );
+            return;
+        }
+        _ => {}
+    }
+    for c in sim.changes.get(i).into_iter().flatten() {
+        println!("    {}", painter.paint(Style::Dim, &describe_change(c)));
+    }
+}
+
+fn describe_change(c: &Change) -> String {
+    match c {
+        Change::CreateDir(p) => format!("fs: mkdir {}", p.display()),
+        Change::Symlink { link, target } => {
+            format!("fs: symlink {} -> {}", link.display(), target.display())
+        }
+        Change::Rename { from, to } => {
+            format!("fs: rename {} -> {}", from.display(), to.display())
+        }
+        Change::Exchange { from, to } => {
+            format!("fs: exchange {} <-> {}", from.display(), to.display())
+        }
+        Change::RemoveFile(p) => format!("fs: remove {}", p.display()),
+        Change::RemoveDir(p) => format!("fs: rmdir {}", p.display()),
+        Change::SetPermissions { path, mode } => {
+            format!("fs: chmod {:o} {}", mode, path.display())
+        }
+    }
+}
+
+/// Dry-run counterpart of the apply summary line.
+fn print_prediction(command: &str, sim: &Simulation, planned: usize) {
+    println!(
+        "# Predicted {}: {} succeeded, {} skipped, {} failed (planned {})",
+        command, sim.outcome.successes, sim.outcome.skipped, sim.outcome.failures, planned
+    );
+}
+
+/// Summarize interactive answers after the apply summary line.
+fn print_prompt_tally(t: &PromptTally) {
+    if *t == PromptTally::default() {
+        return;
+    }
+    let mut line = format!("Interactive: {} confirmed", t.confirmed);
+    if t.confirmed_by_all > 0 {
+        line.push_str(&format!(" ({} by 'all')", t.confirmed_by_all));
+    }
+    line.push_str(&format!(", {} declined", t.declined));
+    if t.declined_by_none > 0 {
+        line.push_str(&format!(" ({} by 'none')", t.declined_by_none));
+    }
+    if t.scripted > 0 {
+        line.push_str(&format!("; {} answered by script", t.scripted));
+    }
+    eprintln!("{}", line);
+    if let Some(i) = t.quit_at {
+        eprintln!(
+            "Interactive: quit at action {}; remaining links left untouched",
+            i + 1
+        );
+    }
+    for c in &t.skipped_dependencies {
+        eprintln!("Interactive: skipped remaining links of {}", c.display());
+    }
+}
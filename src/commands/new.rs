@@ -1,9 +1,13 @@
 use std::fs;
-use std::path::Path;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
 use crate::cli::{Cli, NewArgs};
-use crate::error::XdError;
+use crate::color::{Painter, Style};
 use crate::log;
+use crate::scan::{self, ScanEntry};
+use crate::warning::{Warning, WarningBag, WarningKind};
+use crate::{ErrorBag, XdError};
 
 const TEMPLATE: &str = r#"# xdotter configuration file
 
@@ -31,6 +35,9 @@ pub fn run(cli: &Cli, args: &NewArgs) -> Result<(), XdError> {
             path.display()
         )));
     }
+    if let Some(dir) = &args.scan {
+        return run_scan(cli, args, dir.as_deref(), path);
+    }
     if args.dry_run {
         println!("Would create xdotter.toml");
         return Ok(());
@@ -41,3 +48,148 @@ pub fn run(cli: &Cli, args: &NewArgs) -> Result<(), XdError> {
     println!("Created xdotter.toml");
     Ok(())
 }
+
+/// `xd new --scan`: list what the scan found, take the chosen entries
+/// into the repository and write a config linking them.
+fn run_scan(cli: &Cli, args: &NewArgs, dir: Option<&Path>, config: &Path) -> Result<(), XdError> {
+    let repo = std::env::current_dir()
+        .map_err(|e| XdError::cli(format!("无法获取当前工作目录: {}", e)))?;
+    let home = dirs::home_dir();
+    let root: PathBuf = match dir {
+        Some(d) => d
+            .canonicalize()
+            .map_err(|e| XdError::cli(format!("无法访问扫描目录 {}: {}", d.display(), e)))?,
+        None => home
+            .clone()
+            .ok_or_else(|| XdError::cli("无法确定主目录，请指定扫描目录".to_string()))?,
+    };
+    log::debug(cli, format!("new: 扫描 {}", root.display()));
+    let found = scan::scan(&root, home.as_deref(), &repo);
+
+    let painter = Painter::stdout(cli);
+    for s in &found.skipped {
+        let line = format!("跳过 {}: {}", s.path.display(), s.reason);
+        println!("{}", painter.paint(Style::Dim, &line));
+    }
+    if found.entries.is_empty() {
+        println!("No dotfiles found in {}", root.display());
+        return Ok(());
+    }
+    for (i, e) in found.entries.iter().enumerate() {
+        let slash = if e.is_dir { "/" } else { "" };
+        let mut line = format!(
+            "{:>3}. {}{} ({})",
+            i + 1,
+            e.link,
+            slash,
+            human_size(e.bytes)
+        );
+        if let Some((mode, what)) = e.permission {
+            let flag = format!("[敏感: {}，部署时要求权限 {:o}]", what, mode);
+            line = format!("{} {}", line, painter.paint(Style::Yellow, &flag));
+        }
+        println!("{}", line);
+    }
+
+    let chosen: Vec<&ScanEntry> = if args.all {
+        found.entries.iter().collect()
+    } else {
+        let picked = scan::select(&ask_selection()?, found.entries.len())?;
+        picked.into_iter().map(|i| &found.entries[i]).collect()
+    };
+    if chosen.is_empty() {
+        println!("No entries selected; nothing changed");
+        return Ok(());
+    }
+
+    // Refuse before moving anything when a destination is taken.
+    let mut errors = ErrorBag::new();
+    for e in &chosen {
+        let dest = repo.join(&e.source);
+        if fs::symlink_metadata(&dest).is_ok() {
+            errors.push(XdError::cli(format!(
+                "仓库中已存在 {}，不会移动 {}",
+                dest.display(),
+                e.path.display()
+            )));
+        }
+    }
+    let mut warnings = WarningBag::new();
+    for e in &chosen {
+        if let Some((mode, what)) = e.permission {
+            warnings.push(Warning {
+                kind: WarningKind::SensitiveFile,
+                config_file: None,
+                path: e.path.clone(),
+                message: format!(
+                    "{} 是敏感文件 ({})，部署时要求权限 {:o}；请勿将其推送到公开仓库",
+                    e.link, what, mode
+                ),
+            });
+        }
+    }
+    super::report_warnings(cli, warnings, &mut errors);
+    if !errors.is_empty() {
+        return Err(errors.into_error());
+    }
+
+    if args.dry_run {
+        for e in &chosen {
+            println!("Would move {} -> {}", e.link, e.source);
+        }
+        println!("Would create xdotter.toml with {} links", chosen.len());
+        return Ok(());
+    }
+    let mut moved = Vec::new();
+    for e in chosen {
+        match scan::adopt(e, &repo) {
+            Ok(()) => {
+                println!("Moved {} -> {}", e.link, e.source);
+                moved.push(e);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+    if !moved.is_empty() {
+        fs::write(config, scan::render_config(&moved))
+            .map_err(|e| XdError::apply(format!("写入 {} 失败: {}", config.display(), e)))?;
+        println!("Created xdotter.toml with {} links", moved.len());
+        println!("运行 xd deploy 在原位置创建指向仓库的链接");
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.into_error())
+    }
+}
+
+/// Read the selection from the terminal; a non-TTY stdin needs `--all`.
+fn ask_selection() -> Result<String, XdError> {
+    if !io::stdin().is_terminal() {
+        return Err(XdError::cli(
+            "标准输入不是终端，无法选择条目；请使用 --all".to_string(),
+        ));
+    }
+    print!("选择要移入仓库的条目 (例如 1,3-5；all 为全部；留空取消): ");
+    let _ = io::stdout().flush();
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| XdError::cli(format!("读取选择失败: {}", e)))?;
+    Ok(line)
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
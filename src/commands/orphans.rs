@@ -0,0 +1,32 @@
+use crate::orphans;
+use crate::XdError;
+
+use crate::cli::{Cli, OrphansArgs};
+use crate::color::{Painter, Style};
+
+pub fn run(cli: &Cli, args: &OrphansArgs) -> Result<(), XdError> {
+    let disc = super::discover_configs(cli)?;
+    let found = orphans::find(&disc.configs);
+
+    if args.suggest {
+        print!("{}", orphans::suggest(&found));
+        println!("# Orphans: {} files", found.len());
+    } else {
+        let painter = Painter::stdout(cli);
+        for o in &found {
+            println!(
+                "{}: {}",
+                painter.paint(Style::Dim, &o.config_file.display().to_string()),
+                o.relative().display()
+            );
+        }
+        println!("Orphans: {} files", found.len());
+    }
+
+    // Configurations that failed to load were not walked; say so rather
+    // than report a partial list as complete.
+    if !disc.errors.is_empty() {
+        return Err(disc.errors.into_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use crate::registry::Registry;
+use crate::XdError;
+
+use crate::cli::RepoCommand;
+
+pub fn run(command: &RepoCommand) -> Result<(), XdError> {
+    let path = super::registry_path()?;
+    let mut registry = Registry::load(&path)?;
+    match command {
+        RepoCommand::Add { path: root, name } => {
+            let root = root.clone().unwrap_or_else(|| PathBuf::from("."));
+            let root = root
+                .canonicalize()
+                .map_err(|e| XdError::cli(format!("无法访问仓库目录 {}: {}", root.display(), e)))?;
+            if !root.join("xdotter.toml").is_file() {
+                return Err(XdError::cli(format!(
+                    "{} 中没有 xdotter.toml",
+                    root.display()
+                )));
+            }
+            let name = match name {
+                Some(n) => n.clone(),
+                None => root
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .ok_or_else(|| {
+                        XdError::cli(format!(
+                            "无法从 {} 推断仓库名，请使用 --name",
+                            root.display()
+                        ))
+                    })?,
+            };
+            registry.add(&name, &root)?;
+            registry.save(&path)?;
+            println!("Registered {}: {}", name, root.display());
+        }
+        RepoCommand::Remove { name } => {
+            let Some(root) = registry.remove(name) else {
+                return Err(XdError::cli(format!("未注册的仓库: {}", name)));
+            };
+            registry.save(&path)?;
+            println!("Removed {}: {}", name, root.display());
+        }
+        RepoCommand::List => {
+            for (name, root) in registry.repos() {
+                let note = if root.join("xdotter.toml").is_file() {
+                    ""
+                } else {
+                    "  (缺少 xdotter.toml)"
+                };
+                println!("{}\t{}{}", name, root.display(), note);
+            }
+        }
+    }
+    Ok(())
+}
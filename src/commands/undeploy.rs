@@ -1,31 +1,36 @@
+use crate::answers::{Answers, PromptKind};
 use crate::apply;
-use crate::cli::{Cli, ConflictMode, UndeployArgs};
 use crate::discover;
-use crate::error::XdError;
+use crate::fs::RealFs;
+use crate::history::AuditFs;
+use crate::plan::{self, ConflictMode, UndeployAction, UndeployActionKind, UndeployPlan};
+use crate::XdError;
+
+use crate::cli::{Cli, UndeployArgs};
+use crate::color::{self, Painter};
 use crate::log;
-use crate::plan::{self, UndeployAction, UndeployActionKind, UndeployPlan};
 
 pub fn run(cli: &Cli, args: &UndeployArgs) -> Result<(), XdError> {
-    let cwd = std::env::current_dir()
-        .map_err(|e| XdError::cli(format!("无法获取当前工作目录: {}", e)))?;
-    if !cwd.join("xdotter.toml").exists() {
-        return Err(XdError::cli(format!(
-            "当前目录 {} 中没有 xdotter.toml",
-            cwd.display()
-        )));
-    }
-
-    let mode = args.conflict_mode();
+    let mode = ConflictMode::from_flags(super::force_set(args.force.as_deref())?, args.interactive);
     log::info(
         cli,
         format!("undeploy: 模式={:?}, dry_run={}", mode, args.dry_run),
     );
-    let disc = discover::discover(&cwd);
+    let answers = super::load_answers(&args.answer, args.answers.as_deref())?;
+    let roots = super::config_roots(cli)?;
+    // Held until apply is done; dry-runs change nothing and take no lock.
+    let _lock = if args.dry_run {
+        None
+    } else {
+        Some(super::lock_run(&roots, args.no_wait)?)
+    };
+    let disc = discover::discover_repos(&roots);
     log::debug(
         cli,
         format!("undeploy: 发现 {} 个配置文件", disc.configs.len()),
     );
-    let res = plan::build_undeploy_plan(disc, mode);
+    let mut res = plan::build_undeploy_plan(disc, mode);
+    super::report_warnings(cli, std::mem::take(&mut res.warnings), &mut res.errors);
 
     if !res.errors.is_empty() {
         return Err(res.errors.into_error());
@@ -42,8 +47,9 @@ pub fn run(cli: &Cli, args: &UndeployArgs) -> Result<(), XdError> {
     }
 
     if args.dry_run {
-        print_undeploy_plan(&res.plan, mode);
-        if dry_run_plan_has_failures(&res.plan, mode) {
+        let sim = apply::simulate_undeploy_answering(&RealFs, &res.plan, &answers);
+        print_undeploy_plan(&res.plan, mode, &answers, &sim, &Painter::stdout(cli));
+        if sim.outcome.failures > 0 {
             return Err(XdError::planning(
                 "dry-run 计划包含会跳过并计为失败的链接".to_string(),
             ));
@@ -51,7 +57,15 @@ pub fn run(cli: &Cli, args: &UndeployArgs) -> Result<(), XdError> {
         return Ok(());
     }
 
-    let outcome = apply::apply_undeploy(&res.plan);
+    let mut history = super::open_history("undeploy", res.plan.mode)?;
+    let audit = AuditFs::new(&RealFs);
+    let mut unrecorded = None;
+    let mut outcome = apply::apply_undeploy_observed(&audit, &res.plan, &answers, |i| {
+        if let Err(e) = history.undeploy(&res.plan.actions[i], audit.take()) {
+            unrecorded.get_or_insert(e);
+        }
+    });
+    outcome.errors.extend(unrecorded);
     print_undeploy_outcome(&outcome, &res.plan);
 
     if outcome.failures > 0 || !outcome.errors.is_empty() {
@@ -80,48 +94,52 @@ fn log_action(cli: &Cli, a: &UndeployAction) {
     log::info(cli, format!("  {} {}", summary, a.link_expanded.display()));
 }
 
-fn dry_run_plan_has_failures(plan: &UndeployPlan, mode: ConflictMode) -> bool {
-    let interactive_dry_run = matches!(mode, ConflictMode::Interactive);
-    plan.actions.iter().any(|a| {
-        matches!(
-            a.kind,
-            UndeployActionKind::SkipFailure(_) | UndeployActionKind::NotASymlinkWarning
-        ) || (interactive_dry_run
-            && matches!(
-                a.kind,
-                UndeployActionKind::DeleteCorrect
-                    | UndeployActionKind::DeleteBroken
-                    | UndeployActionKind::DeleteWrong
-            ))
-    })
-}
-
-fn print_undeploy_plan(plan: &UndeployPlan, mode: ConflictMode) {
+fn print_undeploy_plan(
+    plan: &UndeployPlan,
+    mode: ConflictMode,
+    answers: &Answers,
+    sim: &apply::Simulation,
+    painter: &Painter,
+) {
     // SPEC §"执行模式": `--interactive --dry-run` is treated as "no" for
-    // every recoverable conflict; `--force --dry-run` is treated as
-    // "yes". Rendering reflects that — `apply` is never called here.
+    // every recoverable conflict without a scripted answer; `--force
+    // --dry-run` is treated as "yes". Rendering reflects that; `sim`
+    // carries the effects the apply steps produced against an in-memory
+    // overlay.
     let interactive_dry_run = matches!(mode, ConflictMode::Interactive);
+    let declined = |k| interactive_dry_run && answers.get(k) != Some(true);
 
     println!("# Undeploy plan ({} 条目)", plan.actions.len());
-    for a in &plan.actions {
-        let (marker, desc) = render_action(&a.kind, interactive_dry_run);
-        println!("{} {} [{}]", marker, a.link_expanded.display(), desc);
+    for (i, a) in plan.actions.iter().enumerate() {
+        let (marker, desc) = render_action(&a.kind, &declined);
+        println!(
+            "{} {} [{}]",
+            painter.paint(color::marker_style(marker), marker),
+            a.link_expanded.display(),
+            desc
+        );
+        super::print_simulated_step(sim, i, painter);
     }
+    super::print_prediction("undeploy", sim, plan.actions.len());
 }
 
-fn render_action(k: &UndeployActionKind, interactive_dry_run: bool) -> (&'static str, String) {
+fn render_action(
+    k: &UndeployActionKind,
+    declined: &dyn Fn(PromptKind) -> bool,
+) -> (&'static str, String) {
+    let delete = |what| render_delete(what, PromptKind::delete(k).is_some_and(declined));
     match k {
         UndeployActionKind::NotPresent => ("·", "absent (silent success)".to_string()),
-        UndeployActionKind::DeleteCorrect => render_delete("correct symlink", interactive_dry_run),
-        UndeployActionKind::DeleteBroken => render_delete("broken symlink", interactive_dry_run),
-        UndeployActionKind::DeleteWrong => render_delete("wrong symlink", interactive_dry_run),
+        UndeployActionKind::DeleteCorrect => delete("correct symlink"),
+        UndeployActionKind::DeleteBroken => delete("broken symlink"),
+        UndeployActionKind::DeleteWrong => delete("wrong symlink"),
         UndeployActionKind::SkipFailure(r) => ("!", format!("skip: {}", r)),
         UndeployActionKind::NotASymlinkWarning => ("!", "warning: not a symlink".to_string()),
     }
 }
 
-fn render_delete(kind: &str, interactive_dry_run: bool) -> (&'static str, String) {
-    if interactive_dry_run {
+fn render_delete(kind: &str, declined: bool) -> (&'static str, String) {
+    if declined {
         (
             "!",
             format!("would skip delete {} (interactive declined)", kind),
@@ -139,5 +157,6 @@ fn print_undeploy_outcome(outcome: &apply::ApplyOutcome, plan: &UndeployPlan) {
         outcome.failures,
         plan.actions.len()
     );
+    super::print_prompt_tally(&outcome.prompts);
     // Errors are printed by main.rs via the returned Err result.
 }
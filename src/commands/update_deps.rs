@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use crate::gitdeps::{self, UpdateMode};
+use crate::XdError;
+
+use crate::cli::{Cli, UpdateDepsArgs};
+use crate::log;
+
+pub fn run(cli: &Cli, args: &UpdateDepsArgs) -> Result<(), XdError> {
+    let roots: Vec<PathBuf> = super::config_roots(cli)?
+        .into_iter()
+        .map(|(_, root)| root)
+        .collect();
+    let mode = if args.locked {
+        UpdateMode::Locked
+    } else {
+        UpdateMode::Bump
+    };
+    log::info(cli, format!("update-deps: 模式={:?}", mode));
+    let report = gitdeps::update(&roots, mode)?;
+    for c in &report.changes {
+        match &c.old {
+            Some(old) => println!(
+                "Updated {} {} -> {} ({})",
+                c.name,
+                short(old),
+                short(&c.new),
+                c.config_file.display()
+            ),
+            None => println!(
+                "Locked {} at {} ({})",
+                c.name,
+                short(&c.new),
+                c.config_file.display()
+            ),
+        }
+    }
+    println!(
+        "Git dependencies: {} checked out, {} pins changed",
+        report.checked_out,
+        report.changes.len()
+    );
+    Ok(())
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(12)]
+}
@@ -1,4 +1,4 @@
-use crate::error::XdError;
+use crate::XdError;
 
 pub fn run() -> Result<(), XdError> {
     println!("xdotter {}", env!("CARGO_PKG_VERSION"));
@@ -0,0 +1,241 @@
+//! `xd watch`: redeploy new links while the repository is being edited.
+//!
+//! Watching is polling-based to keep the binary dependency-free. Each
+//! poll takes a metadata snapshot of every `xdotter.toml` reachable via
+//! `discover` plus every configured source tree; a change starts a
+//! debounce window that ends once the snapshot stops changing. The repo
+//! is then re-discovered and re-planned in default mode, and only
+//! `Create` actions are applied. Recoverable conflicts are reported but
+//! never acted upon.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::answers::Answers;
+use crate::apply;
+use crate::discover::{self, DiscoveredConfig};
+use crate::fs::RealFs;
+use crate::history::AuditFs;
+use crate::plan::{self, ConflictMode, ConflictModeRecord, DeployActionKind, DeployPlan};
+use crate::XdError;
+
+use crate::cli::{Cli, WatchArgs};
+use crate::color::Painter;
+use crate::log;
+
+pub fn run(cli: &Cli, args: &WatchArgs) -> Result<(), XdError> {
+    let cwd = super::config_root()?;
+
+    let interval = Duration::from_millis(args.interval.max(10));
+    let debounce = Duration::from_millis(args.debounce);
+    log::info(
+        cli,
+        format!(
+            "watch: 轮询间隔 {}ms, 去抖 {}ms",
+            interval.as_millis(),
+            debounce.as_millis()
+        ),
+    );
+
+    let (mut watched, mut last) = deploy_cycle(cli, &cwd, vec![cwd.join("xdotter.toml")]);
+    loop {
+        thread::sleep(interval);
+        let mut current = snapshot(&watched);
+        if current == last {
+            continue;
+        }
+        // Debounce: wait until the tree has been quiet for a full window
+        // so editors that write in several steps trigger a single cycle.
+        loop {
+            thread::sleep(debounce);
+            let next = snapshot(&watched);
+            if next == current {
+                break;
+            }
+            current = next;
+        }
+        log::info(cli, "watch: 检测到变化，重新规划");
+        (watched, last) = deploy_cycle(cli, &cwd, watched);
+    }
+}
+
+/// Take the run lock, then discover, plan in default mode and apply the
+/// `Create` actions. Returns the set of paths to watch for the next
+/// cycle, and their snapshot taken right after discovery so edits made
+/// while the cycle runs trigger the next one. When the lock cannot be
+/// taken, `previous` stays the watched set.
+fn deploy_cycle(cli: &Cli, root: &Path, previous: Vec<PathBuf>) -> (Vec<PathBuf>, Snapshot) {
+    let err_painter = Painter::stderr(cli);
+    // Lock before reading any configuration, so the plan is built from
+    // the state no other run is changing.
+    let _lock = match super::lock_run(&[(None, root.to_path_buf())], false) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", err_painter.paint_error(&e.to_string()));
+            let seen = snapshot(&previous);
+            return (previous, seen);
+        }
+    };
+    let disc = discover::discover(root);
+    let watched = watched_paths(root, &disc.configs);
+    let seen = snapshot(&watched);
+    log::debug(
+        cli,
+        format!(
+            "watch: 发现 {} 个配置文件, 监视 {} 个路径",
+            disc.configs.len(),
+            watched.len()
+        ),
+    );
+
+    let mut res = plan::build_deploy_plan(disc, ConflictMode::Default);
+    // Only links this cycle creates are news; the others were warned
+    // about when they were created.
+    let creating: BTreeSet<PathBuf> = res
+        .plan
+        .actions
+        .iter()
+        .filter(|a| a.kind == DeployActionKind::Create)
+        .map(|a| a.link_expanded.clone())
+        .collect();
+    res.warnings.retain(|w| creating.contains(&w.path));
+    super::report_warnings(cli, std::mem::take(&mut res.warnings), &mut res.errors);
+    if !res.errors.is_empty() {
+        // Keep watching: the user is most likely in the middle of an edit.
+        eprintln!(
+            "{}",
+            err_painter.paint_error(&res.errors.into_error().to_string())
+        );
+        return (watched, seen);
+    }
+
+    let mut creates = Vec::new();
+    let mut conflicts = 0usize;
+    for a in res.plan.actions {
+        let reason = match &a.kind {
+            DeployActionKind::Create => {
+                creates.push(a);
+                continue;
+            }
+            DeployActionKind::AlreadyCorrect => continue,
+            DeployActionKind::Replace(existing) => {
+                format!("已有{}", plan::describe_existing(existing))
+            }
+            DeployActionKind::SkipFailure(r) => r.clone(),
+        };
+        conflicts += 1;
+        eprintln!(
+            "{}",
+            err_painter.paint_error(
+                &XdError::planning(format!(
+                    "链接 {} 存在冲突，watch 不处理: {}",
+                    a.link_expanded.display(),
+                    reason
+                ))
+                .to_string()
+            )
+        );
+    }
+
+    if creates.is_empty() && conflicts == 0 {
+        log::info(cli, "watch: 无需创建的链接");
+        return (watched, seen);
+    }
+
+    let planned = creates.len();
+    let plan = DeployPlan {
+        actions: creates,
+        mode: ConflictModeRecord::from(ConflictMode::Default),
+    };
+    let mut history = match super::open_history("watch", plan.mode) {
+        Ok(h) => h,
+        Err(e) => {
+            eprintln!("{}", err_painter.paint_error(&e.to_string()));
+            return (watched, seen);
+        }
+    };
+    let audit = AuditFs::new(&RealFs);
+    let mut unrecorded = None;
+    let mut outcome = apply::apply_deploy_observed(&audit, &plan, &Answers::new(), |i| {
+        if let Err(e) = history.deploy(&plan.actions[i], audit.take()) {
+            unrecorded.get_or_insert(e);
+        }
+    });
+    outcome.errors.extend(unrecorded);
+    eprintln!(
+        "Watch: {} created, {} conflicts, {} failed (planned {})",
+        outcome.successes, conflicts, outcome.failures, planned
+    );
+    if !outcome.errors.is_empty() {
+        eprintln!(
+            "{}",
+            err_painter.paint_error(&outcome.errors.into_error().to_string())
+        );
+    }
+    (watched, seen)
+}
+
+/// Every toml reachable from `root` (including ones that failed to parse,
+/// which are not in `configs`) plus every configured source path.
+fn watched_paths(root: &Path, configs: &[DiscoveredConfig]) -> Vec<PathBuf> {
+    let mut out = vec![root.join("xdotter.toml")];
+    for c in configs {
+        out.push(c.config_file.clone());
+        for dep in c.config.dependencies.values() {
+            out.push(c.config_dir.join(dep).join("xdotter.toml"));
+        }
+        for src in c.config.links.keys() {
+            out.push(c.config_dir.join(src));
+        }
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    is_dir: bool,
+    is_symlink: bool,
+}
+
+/// Metadata snapshot of `roots`, recursing into real directories.
+/// Missing paths are recorded as `None` so their creation is noticed.
+type Snapshot = BTreeMap<PathBuf, Option<Stamp>>;
+
+fn snapshot(roots: &[PathBuf]) -> Snapshot {
+    let mut out = BTreeMap::new();
+    for r in roots {
+        stamp_tree(r, &mut out);
+    }
+    out
+}
+
+fn stamp_tree(p: &Path, out: &mut BTreeMap<PathBuf, Option<Stamp>>) {
+    let Ok(meta) = fs::symlink_metadata(p) else {
+        out.insert(p.to_path_buf(), None);
+        return;
+    };
+    let ft = meta.file_type();
+    out.insert(
+        p.to_path_buf(),
+        Some(Stamp {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+            is_dir: ft.is_dir(),
+            is_symlink: ft.is_symlink(),
+        }),
+    );
+    if ft.is_dir() {
+        if let Ok(entries) = fs::read_dir(p) {
+            for e in entries.flatten() {
+                stamp_tree(&e.path(), out);
+            }
+        }
+    }
+}
@@ -1,40 +1,215 @@
+//! `xdotter.toml` parsing per SPEC §"配置文件".
+
 use serde::Deserialize;
 use std::collections::BTreeMap;
 use std::path::Path;
 
+use crate::codes;
 use crate::error::XdError;
+use crate::span::{self, EntrySpan};
+use crate::warning::WarningKind;
 
-/// Parsed `xdotter.toml`. Both `[links]` and `[dependencies]` may be absent;
-/// an empty config is legal per SPEC.
+/// Parsed `xdotter.toml`. `[options]`, `[links]` and `[dependencies]` may
+/// all be absent; an empty config is legal per SPEC.
 #[derive(Debug, Clone, Default)]
 pub struct Config {
     /// Source path -> link path. Source paths are TOML keys; thus 1 source -> 1 link.
     pub links: BTreeMap<String, String>,
     /// Dependency name -> relative subdirectory containing its own `xdotter.toml`.
     pub dependencies: BTreeMap<String, String>,
+    /// Dependency name -> local git repository checked out at a pinned
+    /// commit, pinned in `xdotter.lock` by `xd update-deps`.
+    pub git_dependencies: BTreeMap<String, GitDependency>,
+    /// `[options] relative_links`: create this config's symlinks with
+    /// targets relative to the link's parent directory.
+    pub relative_links: bool,
+    /// Per-entry `relative` overrides of [`Config::relative_links`], keyed
+    /// by source path.
+    pub relative: BTreeMap<String, bool>,
+    /// `[lint] allow`: names of `xd lint` rules not reported for this
+    /// config.
+    pub lint_allow: Vec<String>,
+    /// `[warnings] allow`: warnings about this config's entries that are
+    /// not reported (see [`crate::warning`]).
+    pub warnings_allow: Vec<WarningKind>,
+    /// Where each `[links]` entry is written, keyed by source path.
+    /// Entries the scanner in [`crate::span`] cannot place are missing.
+    pub link_spans: BTreeMap<String, EntrySpan>,
+    /// Where each `[dependencies]` entry, path or git, is written, keyed
+    /// by name.
+    pub dependency_spans: BTreeMap<String, EntrySpan>,
 }
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 struct RawConfig {
     #[serde(default)]
-    links: Option<BTreeMap<String, String>>,
+    options: Option<RawOptions>,
+    #[serde(default)]
+    links: Option<BTreeMap<String, RawLink>>,
+    #[serde(default)]
+    dependencies: Option<BTreeMap<String, RawDependency>>,
+    #[serde(default)]
+    lint: Option<RawLint>,
+    #[serde(default)]
+    warnings: Option<RawWarnings>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLint {
+    #[serde(default)]
+    allow: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawWarnings {
     #[serde(default)]
-    dependencies: Option<BTreeMap<String, String>>,
+    allow: Vec<String>,
+}
+
+/// A `[dependencies]` entry that lives in another git repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitDependency {
+    /// Repository location as written: a `file://` URL or a local path,
+    /// relative paths being resolved against the config directory.
+    pub url: String,
+    /// Branch, tag or commit to pin; `None` follows the remote `HEAD`.
+    pub rev: Option<String>,
+}
+
+/// A `[dependencies]` value: a subdirectory, or an inline git table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDependency {
+    Path(String),
+    Git(RawGitDependency),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawGitDependency {
+    git: String,
+    #[serde(default)]
+    rev: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOptions {
+    #[serde(default)]
+    relative_links: bool,
+}
+
+/// A `[links]` value: the link path, or an inline table carrying it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawLink {
+    Path(String),
+    Entry(RawLinkEntry),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLinkEntry {
+    link: String,
+    #[serde(default)]
+    relative: Option<bool>,
 }
 
 impl Config {
     /// Parse a TOML string. Unknown top-level keys/tables and malformed types
-    /// are reported as configuration errors per SPEC.
+    /// are reported as configuration errors per SPEC, with a snippet of the
+    /// offending line when basic-toml reports one.
     pub fn from_toml(content: &str, source: &Path) -> Result<Self, XdError> {
-        let raw: RawConfig = basic_toml::from_str(content)
-            .map_err(|e| XdError::config(format!("{}: TOML 解析失败: {}", source.display(), e)))?;
+        let raw: RawConfig = basic_toml::from_str(content).map_err(|e| {
+            let message = e.to_string();
+            let err = match span::parse_error(&message, content) {
+                Some((message, at)) => XdError::config(format!(
+                    "{}:{}:{}: TOML 解析失败: {}\n{}",
+                    source.display(),
+                    at.line,
+                    at.column,
+                    message,
+                    at.snippet()
+                )),
+                None => {
+                    XdError::config(format!("{}: TOML 解析失败: {}", source.display(), message))
+                }
+            };
+            err.with_code(codes::TOML_PARSE)
+        })?;
+        let located = span::locate(content);
 
+        let mut links = BTreeMap::new();
+        let mut relative = BTreeMap::new();
+        for (src, value) in raw.links.unwrap_or_default() {
+            let link = match value {
+                RawLink::Path(link) => link,
+                RawLink::Entry(e) => {
+                    if let Some(r) = e.relative {
+                        relative.insert(src.clone(), r);
+                    }
+                    e.link
+                }
+            };
+            links.insert(src, link);
+        }
+        let mut dependencies = BTreeMap::new();
+        let mut git_dependencies = BTreeMap::new();
+        for (name, value) in raw.dependencies.unwrap_or_default() {
+            match value {
+                RawDependency::Path(path) => {
+                    dependencies.insert(name, path);
+                }
+                RawDependency::Git(g) => {
+                    git_dependencies.insert(
+                        name,
+                        GitDependency {
+                            url: g.git,
+                            rev: g.rev,
+                        },
+                    );
+                }
+            }
+        }
+        let mut warnings_allow = Vec::new();
+        for name in raw.warnings.map(|w| w.allow).unwrap_or_default() {
+            match WarningKind::from_name(&name) {
+                Some(k) => warnings_allow.push(k),
+                None => {
+                    let known: Vec<_> = WarningKind::ALL.iter().map(|k| k.name()).collect();
+                    return Err(XdError::config(format!(
+                        "{}: [warnings] allow 中未知的警告 {}，可用警告: {}",
+                        source.display(),
+                        name,
+                        known.join(", ")
+                    ))
+                    .with_code(codes::WARNING_UNKNOWN));
+                }
+            }
+        }
         Ok(Config {
-            links: raw.links.unwrap_or_default(),
-            dependencies: raw.dependencies.unwrap_or_default(),
+            links,
+            dependencies,
+            git_dependencies,
+            relative_links: raw.options.is_some_and(|o| o.relative_links),
+            relative,
+            lint_allow: raw.lint.map(|l| l.allow).unwrap_or_default(),
+            warnings_allow,
+            link_spans: located.links,
+            dependency_spans: located.dependencies,
         })
     }
+
+    /// Whether the link for `source` gets a relative target.
+    pub fn is_relative(&self, source: &str) -> bool {
+        self.relative
+            .get(source)
+            .copied()
+            .unwrap_or(self.relative_links)
+    }
 }
 
 #[cfg(test)]
@@ -70,6 +245,87 @@ mod tests {
         assert_eq!(c.dependencies.get("nvim").unwrap(), "config/nvim");
     }
 
+    #[test]
+    fn git_dependencies_parse_as_inline_tables() {
+        let c = Config::from_toml(
+            r#"
+[dependencies]
+"local" = "sub"
+"team" = { git = "file:///srv/team.git", rev = "v2" }
+"base" = { git = "../baseline" }
+"#,
+            &p(),
+        )
+        .unwrap();
+        assert_eq!(c.dependencies.len(), 1);
+        assert_eq!(
+            c.git_dependencies.get("team"),
+            Some(&GitDependency {
+                url: "file:///srv/team.git".to_string(),
+                rev: Some("v2".to_string()),
+            })
+        );
+        assert_eq!(c.git_dependencies.get("base").unwrap().rev, None);
+        let err = Config::from_toml(
+            "[dependencies]\n\"x\" = { git = \"a\", branch = \"b\" }\n",
+            &p(),
+        )
+        .unwrap_err();
+        assert!(err.is_config());
+    }
+
+    #[test]
+    fn relative_links_per_repo_and_per_entry() {
+        let c = Config::from_toml(
+            r#"
+[options]
+relative_links = true
+
+[links]
+"a" = "~/a"
+"b" = { link = "~/b", relative = false }
+"c" = { link = "~/c" }
+"#,
+            &p(),
+        )
+        .unwrap();
+        assert_eq!(c.links.get("b").unwrap(), "~/b");
+        assert!(c.is_relative("a"));
+        assert!(!c.is_relative("b"));
+        assert!(c.is_relative("c"));
+
+        let c = Config::from_toml(
+            "[links]\n\"a\" = \"~/a\"\n\"b\" = { link = \"~/b\", relative = true }\n",
+            &p(),
+        )
+        .unwrap();
+        assert!(!c.is_relative("a"));
+        assert!(c.is_relative("b"));
+    }
+
+    #[test]
+    fn malformed_link_entries_are_config_errors() {
+        for bad in [
+            "[links]\n\"a\" = { relative = true }\n",
+            "[links]\n\"a\" = { link = \"~/a\", mode = 1 }\n",
+            "[links]\n\"a\" = 1\n",
+            "[options]\nrelative = true\n",
+        ] {
+            let err = Config::from_toml(bad, &p()).unwrap_err();
+            assert!(err.is_config(), "{bad}: {err:?}");
+        }
+    }
+
+    #[test]
+    fn warnings_allow_names_known_kinds() {
+        let c = Config::from_toml("[warnings]\nallow = [\"not-a-symlink\"]\n", &p()).unwrap();
+        assert_eq!(c.warnings_allow, [WarningKind::NotASymlink]);
+        let err = Config::from_toml("[warnings]\nallow = [\"sensitive\"]\n", &p()).unwrap_err();
+        assert!(err.is_config());
+        assert_eq!(err.code(), Some(codes::WARNING_UNKNOWN));
+        assert!(err.body().contains("sensitive-target"), "{err}");
+    }
+
     #[test]
     fn unknown_top_level_key_is_config_error() {
         let err = Config::from_toml(r#"unknown = "x""#, &p()).unwrap_err();
@@ -93,6 +349,21 @@ x = 1
     fn malformed_toml_is_config_error() {
         let err = Config::from_toml("[links", &p()).unwrap_err();
         assert!(err.is_config());
+        assert_eq!(
+            err.body(),
+            "xdotter.toml:1:7: TOML 解析失败: expected a right bracket, found eof\n  |\n1 | [links\n  |       ^"
+        );
+    }
+
+    #[test]
+    fn entry_spans_are_recorded() {
+        let c = Config::from_toml(
+            "[links]\n\"a\" = \"~/a\"\n[dependencies]\nsub = { git = \"x\" }\n",
+            &p(),
+        )
+        .unwrap();
+        assert_eq!(c.link_spans["a"].value.line, 2);
+        assert_eq!(c.dependency_spans["sub"].value.column, 15);
     }
 
     #[test]
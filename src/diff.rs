@@ -0,0 +1,157 @@
+//! Minimal unified line diff, shown when an interactive prompt is
+//! answered with `d`.
+//!
+//! The longest-common-subsequence table is quadratic, so inputs whose
+//! line counts multiply past [`MAX_CELLS`] are reported as differing
+//! without a hunk listing. Dotfiles are small; that bound is never hit
+//! in practice.
+
+const CONTEXT: usize = 3;
+const MAX_CELLS: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Del,
+    Add,
+}
+
+/// Diff `old` against `new` in unified format, `---`/`+++` headers
+/// included.
+pub fn unified(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    if a == b {
+        out.push_str("内容相同\n");
+        return out;
+    }
+    if a.len().saturating_mul(b.len()) > MAX_CELLS {
+        out.push_str(&format!(
+            "内容不同 ({} 行 / {} 行)，文件过大，不显示差异\n",
+            a.len(),
+            b.len()
+        ));
+        return out;
+    }
+
+    let ops = script(&a, &b);
+    // Line number (0-based) on each side before ops[k].
+    let mut pos = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for (op, _) in &ops {
+        pos.push((i, j));
+        match op {
+            Op::Same => (i, j) = (i + 1, j + 1),
+            Op::Del => i += 1,
+            Op::Add => j += 1,
+        }
+    }
+    pos.push((i, j));
+
+    let mut k = 0;
+    while k < ops.len() {
+        if ops[k].0 == Op::Same {
+            k += 1;
+            continue;
+        }
+        // Extend the hunk while the next change is within 2*CONTEXT
+        // unchanged lines of the last one.
+        let mut last = k;
+        loop {
+            let next = (last + 1..ops.len()).find(|&n| ops[n].0 != Op::Same);
+            match next {
+                Some(n) if n - last - 1 <= 2 * CONTEXT => last = n,
+                _ => break,
+            }
+        }
+        let start = k.saturating_sub(CONTEXT);
+        let stop = (last + 1 + CONTEXT).min(ops.len());
+        let (os, ns) = pos[start];
+        let (oe, ne) = pos[stop];
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(os, oe - os),
+            range(ns, ne - ns)
+        ));
+        for (op, line) in &ops[start..stop] {
+            let sign = match op {
+                Op::Same => ' ',
+                Op::Del => '-',
+                Op::Add => '+',
+            };
+            out.push(sign);
+            out.push_str(line);
+            out.push('\n');
+        }
+        k = stop;
+    }
+    out
+}
+
+fn range(start: usize, len: usize) -> String {
+    // An empty range names the line before it, per the unified format.
+    let first = if len == 0 { start } else { start + 1 };
+    format!("{},{}", first, len)
+}
+
+fn script<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let (n, m) = (a.len(), b.len());
+    let w = m + 1;
+    // lcs[i * w + j]: LCS length of a[i..] and b[j..].
+    let mut lcs = vec![0u32; (n + 1) * w];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * w + j] = if a[i] == b[j] {
+                lcs[(i + 1) * w + j + 1] + 1
+            } else {
+                lcs[(i + 1) * w + j].max(lcs[i * w + j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            ops.push((Op::Same, a[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[(i + 1) * w + j] >= lcs[i * w + j + 1] {
+            ops.push((Op::Del, a[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Add, b[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[i..].iter().map(|l| (Op::Del, *l)));
+    ops.extend(b[j..].iter().map(|l| (Op::Add, *l)));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunks_carry_context_and_line_ranges() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\nsixteen\n";
+        let d = unified(old, new, "a", "b");
+        assert_eq!(
+            d,
+            "--- a\n+++ b\n\
+             @@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n\
+             @@ -13,3 +13,4 @@\n 13\n 14\n 15\n+sixteen\n"
+        );
+    }
+
+    #[test]
+    fn identical_and_empty_inputs() {
+        assert!(unified("x\n", "x\n", "a", "b").ends_with("内容相同\n"));
+        assert_eq!(
+            unified("", "new\n", "a", "b"),
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+new\n"
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! [`Dir`] on a Linux directory file descriptor, for [`RealFs`].
+//!
+//! The descriptor is opened `O_PATH`, so it grants no access of its own,
+//! and every entry operation goes through the `*at` system calls with the
+//! final component never followed. Once open, the descriptor names the
+//! directory itself: renaming it, or replacing a path component above it
+//! with a symlink, leaves later operations where they were.
+//!
+//! [`RealFs`]: crate::fs::RealFs
+
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use crate::fs::{traced, Dir, FileType, Metadata};
+
+/// An open directory of the live filesystem.
+pub(crate) struct FdDir {
+    fd: OwnedFd,
+    path: PathBuf,
+}
+
+impl FdDir {
+    /// Open directory `p`, following symlinks on the way.
+    pub(crate) fn open(p: &Path) -> io::Result<Self> {
+        let c = CString::new(p.as_os_str().as_bytes())?;
+        // SAFETY: `c` is a valid NUL-terminated string.
+        let fd = cvt(unsafe {
+            libc::open(
+                c.as_ptr(),
+                libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        })?;
+        Ok(Self {
+            // SAFETY: `fd` was just opened and is owned by nobody else.
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            path: p.to_path_buf(),
+        })
+    }
+
+    fn raw(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// `openat(self, name, flags | O_NOFOLLOW | O_CLOEXEC)`.
+    fn open_entry(&self, name: &OsStr, flags: libc::c_int) -> io::Result<OwnedFd> {
+        let c = entry_name(name)?;
+        // SAFETY: `self.fd` is open and `c` is NUL-terminated.
+        let fd = cvt(unsafe {
+            libc::openat(
+                self.raw(),
+                c.as_ptr(),
+                flags | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        })?;
+        // SAFETY: `fd` was just opened and is owned by nobody else.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn stat(&self, name: &OsStr) -> io::Result<Metadata> {
+        let c = entry_name(name)?;
+        // SAFETY: all-zero is a valid `stat`; it is filled in below.
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        // SAFETY: `self.fd` is open, `c` is NUL-terminated and `st` is
+        // writable.
+        cvt(unsafe { libc::fstatat(self.raw(), c.as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW) })?;
+        Ok(metadata(&st))
+    }
+
+    fn readlink(&self, name: &OsStr) -> io::Result<PathBuf> {
+        let c = entry_name(name)?;
+        let mut buf = vec![0u8; 256];
+        loop {
+            // SAFETY: `self.fd` is open, `c` is NUL-terminated and `buf`
+            // has `buf.len()` writable bytes.
+            let n = unsafe {
+                libc::readlinkat(self.raw(), c.as_ptr(), buf.as_mut_ptr().cast(), buf.len())
+            };
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let n = n as usize;
+            // A full buffer may have truncated the target.
+            if n < buf.len() {
+                buf.truncate(n);
+                return Ok(PathBuf::from(OsString::from_vec(buf)));
+            }
+            buf.resize(buf.len() * 2, 0);
+        }
+    }
+
+    fn unlink(&self, name: &OsStr, flags: libc::c_int) -> io::Result<()> {
+        let c = entry_name(name)?;
+        // SAFETY: `self.fd` is open and `c` is NUL-terminated.
+        cvt(unsafe { libc::unlinkat(self.raw(), c.as_ptr(), flags) }).map(drop)
+    }
+
+    fn chmod(&self, name: &OsStr, mode: u32) -> io::Result<()> {
+        let entry = self.open_entry(name, libc::O_PATH)?;
+        // SAFETY: all-zero is a valid `stat`; it is filled in below.
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        // SAFETY: `entry` is open and `st` is writable.
+        cvt(unsafe { libc::fstat(entry.as_raw_fd(), &mut st) })?;
+        // With O_NOFOLLOW, O_PATH opens a symlink itself.
+        if metadata(&st).file_type().is_symlink() {
+            return Err(io::Error::from_raw_os_error(libc::ELOOP));
+        }
+        // fchmod does not take O_PATH descriptors; their /proc entry
+        // reaches the inode that was opened.
+        use std::os::unix::fs::PermissionsExt;
+        let proc = format!("/proc/self/fd/{}", entry.as_raw_fd());
+        std::fs::set_permissions(proc, std::fs::Permissions::from_mode(mode))
+    }
+}
+
+impl Dir for FdDir {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn symlink_metadata(&self, name: &OsStr) -> io::Result<Metadata> {
+        traced("fstatat", &self.path.join(name), self.stat(name))
+    }
+
+    fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        traced("readlinkat", &self.path.join(name), self.readlink(name))
+    }
+
+    fn open_dir(&self, name: &OsStr) -> io::Result<Box<dyn Dir + '_>> {
+        let path = self.path.join(name);
+        let r = self.open_entry(name, libc::O_PATH | libc::O_DIRECTORY);
+        let fd = traced("openat", &path, r)?;
+        Ok(Box::new(FdDir { fd, path }))
+    }
+
+    fn symlink(&self, target: &Path, name: &OsStr) -> io::Result<()> {
+        let r = entry_name(name).and_then(|c| {
+            let t = CString::new(target.as_os_str().as_bytes())?;
+            // SAFETY: `self.fd` is open and both strings are
+            // NUL-terminated.
+            cvt(unsafe { libc::symlinkat(t.as_ptr(), self.raw(), c.as_ptr()) }).map(drop)
+        });
+        let call = format!("symlinkat {} ->", target.display());
+        traced(&call, &self.path.join(name), r)
+    }
+
+    fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        traced("unlinkat", &self.path.join(name), self.unlink(name, 0))
+    }
+
+    fn remove_dir(&self, name: &OsStr) -> io::Result<()> {
+        let r = self.unlink(name, libc::AT_REMOVEDIR);
+        traced("unlinkat AT_REMOVEDIR", &self.path.join(name), r)
+    }
+
+    fn rename(&self, from: &OsStr, to: &OsStr) -> io::Result<()> {
+        let r = entry_name(from).and_then(|f| {
+            let t = entry_name(to)?;
+            renameat2(self.raw(), &f, self.raw(), &t, 0)
+        });
+        let call = format!("renameat2 {} ->", self.path.join(from).display());
+        traced(&call, &self.path.join(to), r)
+    }
+
+    fn exchange(&self, a: &OsStr, b: &OsStr) -> io::Result<()> {
+        let r = entry_name(a).and_then(|x| {
+            let y = entry_name(b)?;
+            renameat2(self.raw(), &x, self.raw(), &y, libc::RENAME_EXCHANGE)
+        });
+        let call = format!(
+            "renameat2 RENAME_EXCHANGE {} <->",
+            self.path.join(a).display()
+        );
+        traced(&call, &self.path.join(b), r)
+    }
+
+    fn set_permissions(&self, name: &OsStr, mode: u32) -> io::Result<()> {
+        let call = format!("chmod {:o}", mode);
+        traced(&call, &self.path.join(name), self.chmod(name, mode))
+    }
+}
+
+/// Swap the objects at paths `a` and `b`, for [`RealFs`].
+///
+/// [`RealFs`]: crate::fs::RealFs
+pub(crate) fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    renameat2(
+        libc::AT_FDCWD,
+        &a,
+        libc::AT_FDCWD,
+        &b,
+        libc::RENAME_EXCHANGE,
+    )
+}
+
+/// `renameat2(2)`, through `syscall` as musl has no wrapper. A flag the
+/// kernel or filesystem does not know is reported as
+/// [`io::ErrorKind::Unsupported`].
+fn renameat2(
+    old_dir: RawFd,
+    old: &CStr,
+    new_dir: RawFd,
+    new: &CStr,
+    flags: libc::c_uint,
+) -> io::Result<()> {
+    // SAFETY: both strings are NUL-terminated; the descriptors are open
+    // or AT_FDCWD.
+    let r = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            old_dir,
+            old.as_ptr(),
+            new_dir,
+            new.as_ptr(),
+            flags,
+        )
+    };
+    if r == 0 {
+        return Ok(());
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EINVAL) if flags != 0 => Err(io::Error::new(io::ErrorKind::Unsupported, e)),
+        _ => Err(e),
+    }
+}
+
+/// `name` as a C string; it must be a single component.
+fn entry_name(name: &OsStr) -> io::Result<CString> {
+    let b = name.as_bytes();
+    if b.is_empty() || b == b"." || b == b".." || b.contains(&b'/') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("不是单个路径组件: {}", name.to_string_lossy()),
+        ));
+    }
+    Ok(CString::new(b)?)
+}
+
+fn metadata(st: &libc::stat) -> Metadata {
+    let file_type = match st.st_mode & libc::S_IFMT {
+        libc::S_IFLNK => FileType::Symlink,
+        libc::S_IFREG => FileType::File,
+        libc::S_IFDIR => FileType::Dir,
+        _ => FileType::Other,
+    };
+    Metadata::new(file_type, st.st_mode & 0o7777)
+}
+
+fn cvt(r: libc::c_int) -> io::Result<libc::c_int> {
+    if r < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn scratch(tag: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("xd-dirfd-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&d);
+        fs::create_dir_all(&d).unwrap();
+        d
+    }
+
+    #[test]
+    fn a_parent_swapped_for_a_symlink_does_not_redirect_changes() {
+        let d = scratch("swap");
+        fs::create_dir_all(d.join("home")).unwrap();
+        fs::create_dir_all(d.join("victim")).unwrap();
+        fs::write(d.join("home").join("old"), "x").unwrap();
+        let dir = FdDir::open(&d.join("home")).unwrap();
+
+        // After the parent was opened, it moves away and a symlink to
+        // another directory takes its place.
+        fs::rename(d.join("home"), d.join("moved")).unwrap();
+        std::os::unix::fs::symlink(d.join("victim"), d.join("home")).unwrap();
+
+        dir.symlink(Path::new("/repo/f"), OsStr::new("link"))
+            .unwrap();
+        dir.remove_file(OsStr::new("old")).unwrap();
+        assert_eq!(
+            fs::read_link(d.join("moved").join("link")).unwrap(),
+            Path::new("/repo/f")
+        );
+        assert!(!d.join("moved").join("old").exists());
+        assert_eq!(fs::read_dir(d.join("victim")).unwrap().count(), 0);
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[test]
+    fn symlinked_entries_are_neither_entered_nor_chmodded() {
+        use std::os::unix::fs::PermissionsExt;
+        let d = scratch("nofollow");
+        fs::create_dir_all(d.join("real")).unwrap();
+        fs::write(d.join("real").join("f"), "x").unwrap();
+        std::os::unix::fs::symlink(d.join("real"), d.join("sub")).unwrap();
+        std::os::unix::fs::symlink(d.join("real").join("f"), d.join("f")).unwrap();
+        let dir = FdDir::open(&d).unwrap();
+
+        assert!(dir.open_dir(OsStr::new("sub")).is_err());
+        assert!(dir.set_permissions(OsStr::new("f"), 0o600).is_err());
+        assert!(dir
+            .symlink_metadata(OsStr::new("sub"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            dir.read_link(OsStr::new("f")).unwrap(),
+            d.join("real").join("f")
+        );
+        assert!(dir.open_dir(OsStr::new("..")).is_err());
+
+        let real = dir.open_dir(OsStr::new("real")).unwrap();
+        real.set_permissions(OsStr::new("f"), 0o600).unwrap();
+        let mode = fs::metadata(d.join("real").join("f"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o600);
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[test]
+    fn renames_and_exchanges_stay_in_the_directory() {
+        let d = scratch("rename");
+        fs::write(d.join("file"), "x").unwrap();
+        fs::create_dir(d.join("empty")).unwrap();
+        let dir = FdDir::open(&d).unwrap();
+
+        dir.symlink(Path::new("/repo/f"), OsStr::new("new"))
+            .unwrap();
+        dir.rename(OsStr::new("new"), OsStr::new("file")).unwrap();
+        assert_eq!(fs::read_link(d.join("file")).unwrap(), Path::new("/repo/f"));
+        assert!(!d.join("new").exists());
+
+        dir.symlink(Path::new("/repo/g"), OsStr::new("new"))
+            .unwrap();
+        match dir.exchange(OsStr::new("new"), OsStr::new("empty")) {
+            Ok(()) => {
+                assert_eq!(
+                    fs::read_link(d.join("empty")).unwrap(),
+                    Path::new("/repo/g")
+                );
+                assert!(fs::symlink_metadata(d.join("new")).unwrap().is_dir());
+            }
+            // Filesystems without RENAME_EXCHANGE leave both in place.
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::Unsupported),
+        }
+        let _ = fs::remove_dir_all(&d);
+    }
+}
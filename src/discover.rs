@@ -5,7 +5,8 @@
 //! its own *configuration directory tree* (its directory + descendants);
 //! source paths and dependency paths declared in that toml must remain
 //! inside that tree after resolution. Shared dependencies (same canonical
-//! directory) are processed once.
+//! directory) are processed once. Git dependencies are read from their
+//! pinned checkouts under `~/.cache/xdotter/git`, each a tree of its own.
 //!
 //! This stage emits configuration errors and planning-block errors only;
 //! it does not modify the filesystem.
@@ -14,8 +15,11 @@ use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::codes;
 use crate::config::Config;
 use crate::error::{decorate, ErrorBag, XdError};
+use crate::gitdeps;
+use crate::logging::{self, Level};
 use crate::path as p;
 
 /// A configuration that has been discovered, parsed, and had its
@@ -32,39 +36,64 @@ pub struct DiscoveredConfig {
     pub config_dir: PathBuf,
     /// Parsed config (links + dependencies, both possibly empty).
     pub config: Config,
+    /// Name of the registered repository this config was reached from;
+    /// `None` when discovery started from a plain root directory.
+    pub repo: Option<String>,
 }
 
 /// Discovery result. Configs are returned in a stable order: root first,
 /// then dependencies in deterministic (sorted) traversal order.
 #[derive(Debug, Default)]
 pub struct Discovered {
+    /// Every configuration that parsed and validated.
     pub configs: Vec<DiscoveredConfig>,
+    /// Configuration and planning-block errors found while walking.
     pub errors: ErrorBag,
 }
 
 /// Entry point. `root` must be a directory containing `xdotter.toml`.
 pub fn discover(root: &Path) -> Discovered {
+    discover_repos(&[(None, root.to_path_buf())])
+}
+
+/// Discover several roots into one set, each paired with the registered
+/// repository name it is reported under. Roots are walked in order; a
+/// configuration reachable from more than one root is processed once,
+/// under the first.
+pub fn discover_repos(roots: &[(Option<String>, PathBuf)]) -> Discovered {
     let mut out = Discovered::default();
     let mut seen: BTreeSet<PathBuf> = BTreeSet::new();
-    let mut stack: BTreeSet<PathBuf> = BTreeSet::new();
 
-    let root_canon = match canonicalize_dir(root) {
-        Ok(p) => p,
-        Err(e) => {
-            out.errors.push(XdError::planning(format!(
-                "无法访问根配置目录 {}: {}",
-                root.display(),
-                e
-            )));
-            return out;
-        }
-    };
-    visit(&root_canon, &mut seen, &mut stack, &mut out);
+    for (repo, root) in roots {
+        let root_canon = match canonicalize_dir(root) {
+            Ok(p) => p,
+            Err(e) => {
+                let what = match repo {
+                    Some(name) => format!("已注册仓库 \"{}\" 的根配置目录", name),
+                    None => "根配置目录".to_string(),
+                };
+                out.errors.push(
+                    XdError::planning(format!("无法访问{} {}: {}", what, root.display(), e))
+                        .with_code(codes::ROOT_INACCESSIBLE),
+                );
+                continue;
+            }
+        };
+        let mut stack: BTreeSet<PathBuf> = BTreeSet::new();
+        visit(
+            &root_canon,
+            repo.as_deref(),
+            &mut seen,
+            &mut stack,
+            &mut out,
+        );
+    }
     out
 }
 
 fn visit(
     dir: &Path,
+    repo: Option<&str>,
     seen: &mut BTreeSet<PathBuf>,
     stack: &mut BTreeSet<PathBuf>,
     out: &mut Discovered,
@@ -75,10 +104,13 @@ fn visit(
     // is "directory still on the active path", which must be reported
     // even if we've also added it to `seen`.
     if stack.contains(dir) {
-        out.errors.push(XdError::planning(format!(
-            "依赖图存在真实循环: {} 在遍历中再次出现",
-            dir.display()
-        )));
+        out.errors.push(
+            XdError::planning(format!(
+                "依赖图存在真实循环: {} 在遍历中再次出现",
+                dir.display()
+            ))
+            .with_code(codes::DEPENDENCY_CYCLE),
+        );
         return;
     }
     if seen.contains(dir) {
@@ -88,14 +120,16 @@ fn visit(
     stack.insert(dir.to_path_buf());
 
     let toml_path = dir.join("xdotter.toml");
+    logging::log("discover", Level::Debug, || {
+        format!("读取配置 {}", toml_path.display())
+    });
     let content = match fs::read_to_string(&toml_path) {
         Ok(s) => s,
         Err(e) => {
-            out.errors.push(XdError::planning(format!(
-                "无法读取配置文件 {}: {}",
-                toml_path.display(),
-                e
-            )));
+            out.errors.push(
+                XdError::planning(format!("无法读取配置文件 {}: {}", toml_path.display(), e))
+                    .with_code(codes::CONFIG_UNREADABLE),
+            );
             stack.remove(dir);
             return;
         }
@@ -104,6 +138,9 @@ fn visit(
     let cfg = match Config::from_toml(&content, &toml_path) {
         Ok(c) => c,
         Err(e) => {
+            logging::log("discover", Level::Debug, || {
+                format!("{} 无法解析: {}", toml_path.display(), e.body())
+            });
             out.errors.push(e);
             seen.insert(dir.to_path_buf());
             stack.remove(dir);
@@ -116,68 +153,132 @@ fn visit(
     let mut to_recurse: Vec<PathBuf> = Vec::new();
 
     for (name, raw) in &cfg.dependencies {
+        let span = cfg.dependency_spans.get(name);
+        let ctx = format!("依赖 \"{name}\"");
+        let at_value = |e: XdError| decorate(&e, &toml_path, Some(&ctx), span.map(|s| &s.value));
         if let Err(e) = p::validate_dependency_path(raw) {
-            out.errors
-                .push(decorate(&e, &toml_path, Some(&format!("依赖 \"{name}\""))));
+            out.errors.push(at_value(e));
             continue;
         }
         let dep_dir = dir.join(raw);
         let dep_canon = match canonicalize_dir(&dep_dir) {
             Ok(c) => c,
             Err(e) => {
-                out.errors.push(XdError::planning(format!(
-                    "{}: 依赖 \"{}\" 路径不存在或无法访问 ({}): {}",
-                    toml_path.display(),
-                    name,
-                    raw,
-                    e
-                )));
+                out.errors.push(at_value(
+                    XdError::planning(format!("路径不存在或无法访问 ({}): {}", raw, e))
+                        .with_code(codes::DEPENDENCY_MISSING),
+                ));
                 continue;
             }
         };
         if !is_inside(&dep_canon, dir) {
-            out.errors.push(XdError::planning(format!(
-                "{}: 依赖 \"{}\" 解析后逃出当前配置目录树: {}",
-                toml_path.display(),
-                name,
-                raw
-            )));
+            out.errors.push(at_value(
+                XdError::planning(format!("解析后逃出当前配置目录树: {}", raw))
+                    .with_code(codes::DEPENDENCY_ESCAPES),
+            ));
             continue;
         }
         // Per-table uniqueness.
         if let Some(prev) = resolved_in_table.get(&dep_canon) {
-            out.errors.push(XdError::config(format!(
-                "{}: 同一 [dependencies] 表中多个依赖解析到同一真实目录: \"{}\" 与 \"{}\" 都指向 {}",
-                toml_path.display(),
-                prev,
-                name,
-                dep_canon.display()
-            )));
+            out.errors.push(decorate(
+                &XdError::config(format!(
+                    "同一 [dependencies] 表中多个依赖解析到同一真实目录: \"{}\" 与 \"{}\" 都指向 {}",
+                    prev,
+                    name,
+                    dep_canon.display()
+                ))
+                .with_code(codes::DEPENDENCY_DUPLICATE),
+                &toml_path,
+                None,
+                span.map(|s| &s.key),
+            ));
             continue;
         }
         resolved_in_table.insert(dep_canon.clone(), name.clone());
+        logging::log("discover", Level::Trace, || {
+            format!("依赖 \"{}\" = \"{}\" -> {}", name, raw, dep_canon.display())
+        });
         // Must contain its own xdotter.toml.
         if !dep_canon.join("xdotter.toml").exists() {
-            out.errors.push(XdError::planning(format!(
-                "{}: 依赖 \"{}\" 目录缺少 xdotter.toml: {}",
-                toml_path.display(),
-                name,
-                dep_canon.display()
-            )));
+            out.errors.push(at_value(
+                XdError::planning(format!("目录缺少 xdotter.toml: {}", dep_canon.display()))
+                    .with_code(codes::DEPENDENCY_NO_CONFIG),
+            ));
             continue;
         }
         to_recurse.push(dep_canon);
     }
 
+    // Git dependencies: pinned checkouts outside this tree.
+    if !cfg.git_dependencies.is_empty() {
+        match gitdeps::Lock::load(dir) {
+            Ok(lock) => {
+                for (name, dep) in &cfg.git_dependencies {
+                    let span = cfg.dependency_spans.get(name);
+                    let ctx = format!("依赖 \"{name}\"");
+                    let at_value =
+                        |e: XdError| decorate(&e, &toml_path, Some(&ctx), span.map(|s| &s.value));
+                    let checkout = match gitdeps::locked_checkout(dir, &lock, name, dep) {
+                        Ok(c) => c,
+                        Err(e) => {
+                            out.errors.push(at_value(e));
+                            continue;
+                        }
+                    };
+                    if let Some(prev) = resolved_in_table.get(&checkout) {
+                        out.errors.push(decorate(
+                            &XdError::config(format!(
+                                "同一 [dependencies] 表中多个依赖解析到同一真实目录: \"{}\" 与 \"{}\" 都指向 {}",
+                                prev,
+                                name,
+                                checkout.display()
+                            ))
+                            .with_code(codes::DEPENDENCY_DUPLICATE),
+                            &toml_path,
+                            None,
+                            span.map(|s| &s.key),
+                        ));
+                        continue;
+                    }
+                    resolved_in_table.insert(checkout.clone(), name.clone());
+                    if !checkout.join("xdotter.toml").exists() {
+                        out.errors.push(at_value(
+                            XdError::planning(format!(
+                                "检出缺少 xdotter.toml: {}",
+                                checkout.display()
+                            ))
+                            .with_code(codes::DEPENDENCY_NO_CONFIG),
+                        ));
+                        continue;
+                    }
+                    logging::log("discover", Level::Trace, || {
+                        format!("git 依赖 \"{}\" -> {}", name, checkout.display())
+                    });
+                    to_recurse.push(checkout);
+                }
+            }
+            Err(e) => out.errors.push(e),
+        }
+    }
+
+    logging::log("discover", Level::Info, || {
+        format!(
+            "{}: {} 条链接, {} 个依赖",
+            toml_path.display(),
+            cfg.links.len(),
+            to_recurse.len()
+        )
+    });
     out.configs.push(DiscoveredConfig {
         config_file: toml_path,
         config_dir: dir.to_path_buf(),
         config: cfg,
+        repo: repo.map(str::to_string),
     });
     seen.insert(dir.to_path_buf());
 
     for d in to_recurse {
-        visit(&d, seen, stack, out);
+        visit(&d, repo, seen, stack, out);
     }
 
     stack.remove(dir);
@@ -198,7 +299,7 @@ fn canonicalize_dir(p: &Path) -> std::io::Result<PathBuf> {
 
 /// True iff `child` is `parent` or a descendant of `parent`. Both must
 /// be already-canonicalized absolute paths.
-pub fn is_inside(child: &Path, parent: &Path) -> bool {
+pub(crate) fn is_inside(child: &Path, parent: &Path) -> bool {
     child.starts_with(parent)
 }
 
@@ -357,6 +458,32 @@ mod tests {
         assert_eq!(r.configs.len(), 3);
     }
 
+    #[test]
+    fn repos_are_discovered_together_and_labelled() {
+        let team = tmpdir("team");
+        let mine = tmpdir("mine");
+        fs::create_dir_all(team.join("sub")).unwrap();
+        fs::write(team.join("sub/xdotter.toml"), "").unwrap();
+        fs::write(
+            team.join("xdotter.toml"),
+            "[dependencies]\n\"s\" = \"sub\"\n",
+        )
+        .unwrap();
+        fs::write(mine.join("xdotter.toml"), "").unwrap();
+        let r = discover_repos(&[
+            (Some("team".to_string()), team.clone()),
+            (Some("mine".to_string()), mine),
+            // Already reached through `team`: not processed twice.
+            (None, team.join("sub")),
+            (Some("gone".to_string()), team.join("missing")),
+        ]);
+        let repos: Vec<_> = r.configs.iter().map(|c| c.repo.as_deref()).collect();
+        assert_eq!(repos, [Some("team"), Some("team"), Some("mine")]);
+        let errs: Vec<_> = r.errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(errs.len(), 1);
+        assert!(errs[0].contains("已注册仓库 \"gone\""), "{errs:?}");
+    }
+
     #[test]
     #[cfg(unix)]
     fn real_cycle_detected() {
@@ -0,0 +1,236 @@
+//! Editable text form of a [`DeployPlan`], for `xd deploy --edit-plan`.
+//!
+//! [`render`] writes one line per action the user can veto, in the style
+//! of a `git rebase` todo list:
+//!
+//! ```text
+//! create /home/u/.zshrc -> /repo/zsh/zshrc
+//! replace /home/u/.vimrc -> /repo/vimrc [普通文件]
+//! fix /home/u/.ssh/config -> /repo/ssh/config [chmod 600]
+//! ```
+//!
+//! [`parse`] reads the edited text back against the original plan. A
+//! line may keep its verb or change it to `skip`; deleting a line also
+//! skips it. Anything else — a new line, a changed path, a different
+//! verb, a duplicate — is rejected, so the result is always a subset of
+//! the original plan and still goes through the apply re-checks. Kept
+//! actions run in plan order regardless of line order.
+//!
+//! Actions with nothing to veto (already-correct links, links skipped as
+//! failures) are listed as comments and always kept.
+
+use std::collections::HashMap;
+
+use crate::error::{ErrorBag, XdError};
+use crate::plan::{
+    describe_existing, DeployAction, DeployActionKind, DeployPlan, PermissionAction,
+};
+
+const HEADER: &str = "\
+# 每行一个操作：<动作> <链接路径> -> <源路径> [说明]
+#   create  创建符号链接
+#   replace 替换链接路径上的已有对象
+#   fix     修复源文件权限
+#   skip    保持该链接不变（删除该行效果相同）
+#
+# 只能把动作改为 skip 或删除整行；不能新增操作或修改路径和说明。
+# 保留的操作按原计划顺序执行，并且仍会经过应用阶段重新校验。
+# 以非零状态退出编辑器可取消部署。
+";
+
+/// The verb and the text after it for an editable action, `None` for
+/// actions the user cannot veto.
+fn entry(a: &DeployAction) -> Option<(&'static str, String)> {
+    let fix = matches!(a.permission_action, PermissionAction::Fix);
+    let chmod = match (fix, a.permission_required) {
+        (true, Some((mode, _))) => format!(" [chmod {:o}]", mode),
+        _ => String::new(),
+    };
+    let target = format!(
+        "{} -> {}",
+        a.link_expanded.display(),
+        a.source_canonical.display()
+    );
+    match &a.kind {
+        DeployActionKind::Create => Some(("create", format!("{}{}", target, chmod))),
+        DeployActionKind::Replace(existing) => Some((
+            "replace",
+            format!("{} [{}]{}", target, describe_existing(existing), chmod),
+        )),
+        DeployActionKind::AlreadyCorrect if fix => Some(("fix", format!("{}{}", target, chmod))),
+        DeployActionKind::AlreadyCorrect | DeployActionKind::SkipFailure(_) => None,
+    }
+}
+
+/// Render `plan` for editing.
+pub fn render(plan: &DeployPlan) -> String {
+    let mut out = format!(
+        "# xd deploy 计划 ({} 条目)\n#\n{}\n",
+        plan.actions.len(),
+        HEADER
+    );
+    for a in &plan.actions {
+        match entry(a) {
+            Some((verb, rest)) => out.push_str(&format!("{} {}\n", verb, rest)),
+            None => {
+                let note = match &a.kind {
+                    DeployActionKind::SkipFailure(r) => format!("将跳过并计为失败: {}", r),
+                    _ => "已正确".to_string(),
+                };
+                out.push_str(&format!(
+                    "# {} -> {} ({})\n",
+                    a.link_expanded.display(),
+                    a.source_canonical.display(),
+                    note
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// Read edited `text` back against `plan`, returning the plan with the
+/// skipped actions removed. Every invalid line is reported.
+pub fn parse(plan: &DeployPlan, text: &str) -> Result<DeployPlan, XdError> {
+    let entries: Vec<_> = plan.actions.iter().map(entry).collect();
+    let index: HashMap<&str, usize> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, e)| e.as_ref().map(|(_, rest)| (rest.as_str(), i)))
+        .collect();
+
+    let mut seen = vec![false; entries.len()];
+    let mut keep = vec![false; entries.len()];
+    let mut errors = ErrorBag::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (verb, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let Some(&i) = index.get(rest.trim_start()) else {
+            errors.push(XdError::cli(format!(
+                "编辑后的计划第 {} 行不是原计划中的操作: {}",
+                n + 1,
+                line
+            )));
+            continue;
+        };
+        if std::mem::replace(&mut seen[i], true) {
+            errors.push(XdError::cli(format!(
+                "编辑后的计划第 {} 行重复了同一操作: {}",
+                n + 1,
+                line
+            )));
+            continue;
+        }
+        let planned = entries[i].as_ref().map_or("", |(v, _)| *v);
+        if verb == planned {
+            keep[i] = true;
+        } else if verb != "skip" {
+            errors.push(XdError::cli(format!(
+                "编辑后的计划第 {} 行的动作 {} 无效: 只能保留 {} 或改为 skip",
+                n + 1,
+                verb,
+                planned
+            )));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors.into_error());
+    }
+
+    let actions = plan
+        .actions
+        .iter()
+        .zip(entries.iter().zip(keep))
+        .filter(|(_, (e, kept))| e.is_none() || *kept)
+        .map(|(a, _)| a.clone())
+        .collect();
+    Ok(DeployPlan {
+        actions,
+        mode: plan.mode,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{ConflictModeRecord, ExistingKind};
+    use std::path::PathBuf;
+
+    fn action(link: &str, kind: DeployActionKind) -> DeployAction {
+        DeployAction {
+            config_file: PathBuf::from("/repo/xdotter.toml"),
+            config_dir: PathBuf::from("/repo"),
+            source_raw: "f".to_string(),
+            source_canonical: PathBuf::from("/repo/f"),
+            link_raw: link.to_string(),
+            link_expanded: PathBuf::from(link),
+            relative: false,
+            kind,
+            permission_required: None,
+            permission_action: PermissionAction::None,
+        }
+    }
+
+    fn plan() -> DeployPlan {
+        DeployPlan {
+            actions: vec![
+                action("/h/a", DeployActionKind::Create),
+                action("/h/b", DeployActionKind::Replace(ExistingKind::RegularFile)),
+                action("/h/c", DeployActionKind::AlreadyCorrect),
+                action("/h/d", DeployActionKind::SkipFailure("冲突".to_string())),
+            ],
+            mode: ConflictModeRecord {
+                force: true,
+                interactive: false,
+            },
+        }
+    }
+
+    fn links(p: &DeployPlan) -> Vec<&str> {
+        p.actions.iter().map(|a| a.link_raw.as_str()).collect()
+    }
+
+    #[test]
+    fn unedited_text_keeps_the_whole_plan() {
+        let p = plan();
+        let text = render(&p);
+        assert!(text.contains("\ncreate /h/a -> /repo/f\n"), "{text}");
+        assert!(
+            text.contains("\nreplace /h/b -> /repo/f [普通文件]\n"),
+            "{text}"
+        );
+        assert!(text.contains("\n# /h/d -> /repo/f (将跳过并计为失败: 冲突)\n"));
+        assert_eq!(
+            links(&parse(&p, &text).unwrap()),
+            ["/h/a", "/h/b", "/h/c", "/h/d"]
+        );
+    }
+
+    #[test]
+    fn skipped_and_deleted_lines_drop_only_editable_actions() {
+        let p = plan();
+        let edited = "skip /h/a -> /repo/f\n";
+        assert_eq!(links(&parse(&p, edited).unwrap()), ["/h/c", "/h/d"]);
+    }
+
+    #[test]
+    fn lines_outside_the_plan_are_rejected() {
+        let p = plan();
+        let edited = "\
+create /h/a -> /elsewhere
+replace /h/a -> /repo/f
+create /h/b -> /repo/f [普通文件]
+skip /h/b -> /repo/f [普通文件]
+skip /h/b -> /repo/f [普通文件]
+";
+        let msg = parse(&p, edited).unwrap_err().to_string();
+        assert!(msg.contains("第 1 行不是原计划中的操作"), "{msg}");
+        assert!(msg.contains("第 2 行的动作 replace 无效"), "{msg}");
+        assert!(msg.contains("第 3 行的动作 create 无效"), "{msg}");
+        assert!(msg.contains("第 5 行重复了同一操作"), "{msg}");
+        assert!(msg.starts_with("[CLI 参数错误]"), "{msg}");
+    }
+}
@@ -8,6 +8,9 @@
 use std::fmt;
 use std::path::Path;
 
+use crate::codes::Code;
+use crate::span::Span;
+
 /// SPEC error class. Each variant maps to a stable label prefix that
 /// appears at the start of `Display` output so users and scripts can
 /// classify failures without parsing free-form text.
@@ -15,48 +18,105 @@ use std::path::Path;
 pub enum XdError {
     /// CLI 参数错误 — invalid command-line arguments. Detected before
     /// any config is read.
-    Cli(String),
+    Cli(Message),
     /// 配置错误 — static configuration violation (TOML, types, path
     /// rules judgable from config text alone).
-    Config(String),
+    Config(Message),
     /// 规划阻塞错误 — planning-stage error that prevents safe planning
     /// or apply (filesystem-state-dependent).
-    Planning(String),
+    Planning(Message),
     /// 应用阶段错误 — error during apply-stage execution.
-    Apply(String),
+    Apply(Message),
+}
+
+/// Text of an [`XdError`], and the stable [`Code`] of the failure when
+/// it has one. Dereferences to the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    text: String,
+    code: Option<Code>,
+}
+
+impl Message {
+    /// The failure's code, if it has one.
+    pub fn code(&self) -> Option<Code> {
+        self.code
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self { text, code: None }
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl std::ops::Deref for Message {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
 }
 
 impl XdError {
+    /// Construct a [`XdError::Cli`].
     pub fn cli<S: Into<String>>(msg: S) -> Self {
-        XdError::Cli(msg.into())
+        XdError::Cli(msg.into().into())
     }
+    /// Construct a [`XdError::Config`].
     pub fn config<S: Into<String>>(msg: S) -> Self {
-        XdError::Config(msg.into())
+        XdError::Config(msg.into().into())
     }
+    /// Construct a [`XdError::Planning`].
     pub fn planning<S: Into<String>>(msg: S) -> Self {
-        XdError::Planning(msg.into())
+        XdError::Planning(msg.into().into())
     }
+    /// Construct a [`XdError::Apply`].
     pub fn apply<S: Into<String>>(msg: S) -> Self {
-        XdError::Apply(msg.into())
+        XdError::Apply(msg.into().into())
     }
 
-    #[allow(dead_code)]
+    /// The same error, identified by `code`.
+    pub fn with_code(mut self, code: Code) -> Self {
+        self.message_mut().code = Some(code);
+        self
+    }
+
+    /// Stable code of the failure, printed after the label and looked up
+    /// by `xd explain`.
+    pub fn code(&self) -> Option<Code> {
+        self.message().code
+    }
+
+    /// True for [`XdError::Cli`].
     pub fn is_cli(&self) -> bool {
         matches!(self, XdError::Cli(_))
     }
-    #[allow(dead_code)]
+    /// True for [`XdError::Config`].
     pub fn is_config(&self) -> bool {
         matches!(self, XdError::Config(_))
     }
-    #[allow(dead_code)]
+    /// True for [`XdError::Planning`].
     pub fn is_planning(&self) -> bool {
         matches!(self, XdError::Planning(_))
     }
-    #[allow(dead_code)]
+    /// True for [`XdError::Apply`].
     pub fn is_apply(&self) -> bool {
         matches!(self, XdError::Apply(_))
     }
 
+    /// Classification label printed before the message.
     pub fn label(&self) -> &'static str {
         match self {
             XdError::Cli(_) => "[CLI 参数错误]",
@@ -66,19 +126,49 @@ impl XdError {
         }
     }
 
+    /// Message without the classification label.
     pub fn body(&self) -> &str {
+        &self.message().text
+    }
+
+    fn message(&self) -> &Message {
+        match self {
+            XdError::Cli(m) | XdError::Config(m) | XdError::Planning(m) | XdError::Apply(m) => m,
+        }
+    }
+
+    fn message_mut(&mut self) -> &mut Message {
         match self {
-            XdError::Cli(s) | XdError::Config(s) | XdError::Planning(s) | XdError::Apply(s) => s,
+            XdError::Cli(m) | XdError::Config(m) | XdError::Planning(m) | XdError::Apply(m) => m,
+        }
+    }
+
+    /// The same class and code with `text` as the message.
+    fn retext(&self, text: String) -> Self {
+        let message = Message {
+            text,
+            code: self.code(),
+        };
+        match self {
+            XdError::Cli(_) => XdError::Cli(message),
+            XdError::Config(_) => XdError::Config(message),
+            XdError::Planning(_) => XdError::Planning(message),
+            XdError::Apply(_) => XdError::Apply(message),
         }
     }
 }
 
+/// The four SPEC classification labels, in `XdError` variant order.
+pub const LABELS: [&str; 4] = [
+    "[CLI 参数错误]",
+    "[配置错误]",
+    "[规划阻塞错误]",
+    "[应用阶段错误]",
+];
+
 /// True when `s` starts with one of the four SPEC classification labels.
 fn starts_with_label(s: &str) -> bool {
-    s.starts_with("[CLI 参数错误]")
-        || s.starts_with("[配置错误]")
-        || s.starts_with("[规划阻塞错误]")
-        || s.starts_with("[应用阶段错误]")
+    LABELS.iter().any(|l| s.starts_with(l))
 }
 
 impl fmt::Display for XdError {
@@ -88,6 +178,8 @@ impl fmt::Display for XdError {
             // Joined error bag: each line already carries its own
             // classification label per SPEC §"输出语义".
             write!(f, "{}", body)
+        } else if let Some(code) = self.code() {
+            write!(f, "{}[{}] {}", self.label(), code, body)
         } else {
             write!(f, "{} {}", self.label(), body)
         }
@@ -97,18 +189,23 @@ impl fmt::Display for XdError {
 impl std::error::Error for XdError {}
 
 /// Wrap an XdError's body with a config-file path prefix, preserving
-/// the error variant and classification label.
-pub(crate) fn decorate(e: &XdError, toml: &Path, ctx: Option<&str>) -> XdError {
-    let msg = match ctx {
-        Some(ctx) => format!("{}: {} {}", toml.display(), ctx, e.body()),
-        None => format!("{}: {}", toml.display(), e.body()),
+/// the error variant and classification label. When the offending entry
+/// was located, the prefix becomes `path:line:column` and a snippet of
+/// the line follows the message.
+pub(crate) fn decorate(e: &XdError, toml: &Path, ctx: Option<&str>, at: Option<&Span>) -> XdError {
+    let location = match at {
+        Some(at) => format!("{}:{}:{}", toml.display(), at.line, at.column),
+        None => toml.display().to_string(),
     };
-    match e {
-        XdError::Cli(_) => XdError::Cli(msg),
-        XdError::Config(_) => XdError::Config(msg),
-        XdError::Planning(_) => XdError::Planning(msg),
-        XdError::Apply(_) => XdError::Apply(msg),
+    let mut msg = match ctx {
+        Some(ctx) => format!("{}: {} {}", location, ctx, e.body()),
+        None => format!("{}: {}", location, e.body()),
+    };
+    if let Some(at) = at {
+        msg.push('\n');
+        msg.push_str(&at.snippet());
     }
+    e.retext(msg)
 }
 
 /// Convenience: collect multiple errors into one Display-able blob,
@@ -120,28 +217,31 @@ pub struct ErrorBag {
 }
 
 impl ErrorBag {
-    #[allow(dead_code)]
+    /// Empty bag.
     pub fn new() -> Self {
         Self::default()
     }
+    /// Add one error.
     pub fn push(&mut self, e: XdError) {
         self.items.push(e);
     }
-    #[allow(dead_code)]
+    /// Add every error of `it`.
     pub fn extend<I: IntoIterator<Item = XdError>>(&mut self, it: I) {
         self.items.extend(it);
     }
+    /// True when no error was collected.
     pub fn is_empty(&self) -> bool {
         self.items.is_empty()
     }
-    #[allow(dead_code)]
+    /// Number of collected errors.
     pub fn len(&self) -> usize {
         self.items.len()
     }
-    #[allow(dead_code)]
+    /// Collected errors in insertion order.
     pub fn into_vec(self) -> Vec<XdError> {
         self.items
     }
+    /// Iterate over collected errors in insertion order.
     pub fn iter(&self) -> std::slice::Iter<'_, XdError> {
         self.items.iter()
     }
@@ -164,7 +264,7 @@ impl ErrorBag {
             .join("\n");
         // Variant choice does not affect Display output for multi-line
         // content (see Display impl).
-        Some(XdError::Planning(joined))
+        Some(XdError::planning(joined))
     }
 
     /// Convert the bag into a single `XdError` suitable for `Err`
@@ -206,6 +306,17 @@ mod tests {
         assert!(s.contains("  - b"), "must contain line b: {s}");
     }
 
+    #[test]
+    fn codes_follow_the_label_and_survive_decoration() {
+        let e = XdError::config("x").with_code(crate::codes::LINK_RELATIVE);
+        assert_eq!(e.to_string(), "[配置错误][XD0207] x");
+        assert_eq!(e.body(), "x");
+        let d = decorate(&e, Path::new("a.toml"), None, None);
+        assert_eq!(d.code(), Some(crate::codes::LINK_RELATIVE));
+        assert_eq!(d.to_string(), "[配置错误][XD0207] a.toml: x");
+        assert!(matches!(&d, XdError::Config(m) if m.starts_with("a.toml")));
+    }
+
     #[test]
     fn into_single_preserves_per_line_labels() {
         let mut bag = ErrorBag::new();
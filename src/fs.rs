@@ -0,0 +1,1236 @@
+//! Filesystem access used by planning and apply.
+//!
+//! Planning and apply never call `std::fs` directly; every probe and
+//! modification goes through an [`Fs`] implementation. [`RealFs`] is the
+//! live filesystem. [`MemFs`] is an in-memory tree with Unix symlink
+//! semantics whose operations can be made to fail on chosen paths, so
+//! failure paths and plan/apply races can be exercised deterministically.
+//! [`OverlayFs`] layers in-memory modifications over another `Fs`; dry-run
+//! applies plans to it to predict their sequential effects.
+//!
+//! Apply acts on link paths through a [`Dir`]: the parent directory is
+//! opened once with [`Fs::open_dir`] and its entries are then re-checked
+//! and modified by name. On Linux [`RealFs`] holds a directory file
+//! descriptor, so a parent swapped for a symlink after it was opened
+//! cannot redirect the change; elsewhere, and for the in-memory trees,
+//! [`PathDir`] joins the name back onto the path.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::logging::{self, Level};
+
+/// Kind of filesystem object, without following a final symlink unless
+/// obtained from [`Fs::metadata`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// Regular file.
+    File,
+    /// Directory.
+    Dir,
+    /// Symbolic link.
+    Symlink,
+    /// Socket, fifo, device or anything else.
+    Other,
+}
+
+impl FileType {
+    /// True for [`FileType::File`].
+    pub fn is_file(self) -> bool {
+        self == FileType::File
+    }
+    /// True for [`FileType::Dir`].
+    pub fn is_dir(self) -> bool {
+        self == FileType::Dir
+    }
+    /// True for [`FileType::Symlink`].
+    pub fn is_symlink(self) -> bool {
+        self == FileType::Symlink
+    }
+}
+
+/// The subset of file metadata xdotter looks at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    file_type: FileType,
+    mode: u32,
+}
+
+impl Metadata {
+    /// Metadata for an object of type `file_type` with permission bits
+    /// `mode`.
+    pub fn new(file_type: FileType, mode: u32) -> Self {
+        Self { file_type, mode }
+    }
+    /// Object type.
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+    /// True for a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+    /// True for a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+    /// Unix permission bits (`0o7777` mask); `0` where not applicable.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+}
+
+impl From<std::fs::Metadata> for Metadata {
+    fn from(m: std::fs::Metadata) -> Self {
+        let ft = m.file_type();
+        let file_type = if ft.is_symlink() {
+            FileType::Symlink
+        } else if ft.is_file() {
+            FileType::File
+        } else if ft.is_dir() {
+            FileType::Dir
+        } else {
+            FileType::Other
+        };
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            m.permissions().mode() & 0o7777
+        };
+        #[cfg(not(unix))]
+        let mode = 0;
+        Self { file_type, mode }
+    }
+}
+
+/// Filesystem operations needed by planning and apply. Semantics follow
+/// the `std::fs` function of the same name.
+pub trait Fs {
+    /// Metadata of `p` itself; a final symlink is not followed.
+    fn symlink_metadata(&self, p: &Path) -> io::Result<Metadata>;
+    /// Metadata of what `p` resolves to.
+    fn metadata(&self, p: &Path) -> io::Result<Metadata>;
+    /// Target of the symlink at `p`, verbatim.
+    fn read_link(&self, p: &Path) -> io::Result<PathBuf>;
+    /// Absolute path of `p` with every symlink resolved; `p` must exist.
+    fn canonicalize(&self, p: &Path) -> io::Result<PathBuf>;
+    /// Paths of the entries of directory `p`.
+    fn read_dir(&self, p: &Path) -> io::Result<Vec<PathBuf>>;
+    /// Create `p` and any missing ancestors as directories.
+    fn create_dir_all(&self, p: &Path) -> io::Result<()>;
+    /// Create a symlink at `link` pointing to `target`.
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+    /// Remove the file or symlink at `p`.
+    fn remove_file(&self, p: &Path) -> io::Result<()>;
+    /// Remove the empty directory at `p`.
+    fn remove_dir(&self, p: &Path) -> io::Result<()>;
+    /// Rename `from` to `to`, atomically replacing a file or symlink at
+    /// `to`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Atomically swap the objects at `a` and `b`, both of which must
+    /// exist. Fails with [`io::ErrorKind::Unsupported`] where the
+    /// platform or filesystem cannot.
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()>;
+    /// Set the permission bits of what `p` resolves to. A no-op where
+    /// Unix modes do not apply.
+    fn set_permissions(&self, p: &Path, mode: u32) -> io::Result<()>;
+    /// Open directory `p`, following symlinks, to act on its entries by
+    /// name. The default is a [`PathDir`] over `self`.
+    fn open_dir(&self, p: &Path) -> io::Result<Box<dyn Dir + '_>> {
+        Ok(Box::new(PathDir::open(self, p)?))
+    }
+
+    /// `Path::exists`: `p` resolves.
+    fn exists(&self, p: &Path) -> bool {
+        self.metadata(p).is_ok()
+    }
+    /// `Path::is_symlink`: the final component of `p` is a symlink.
+    fn is_symlink(&self, p: &Path) -> bool {
+        self.symlink_metadata(p)
+            .is_ok_and(|m| m.file_type().is_symlink())
+    }
+}
+
+/// An open directory whose entries are probed and modified by name.
+///
+/// `name` is always a single path component. No operation follows a
+/// symlink at `name`; [`Dir::open_dir`] and [`Dir::set_permissions`]
+/// refuse one.
+pub trait Dir {
+    /// Path the directory was opened at.
+    fn path(&self) -> &Path;
+    /// Metadata of entry `name` itself.
+    fn symlink_metadata(&self, name: &OsStr) -> io::Result<Metadata>;
+    /// Target of the symlink `name`, verbatim.
+    fn read_link(&self, name: &OsStr) -> io::Result<PathBuf>;
+    /// Open the subdirectory `name`.
+    fn open_dir(&self, name: &OsStr) -> io::Result<Box<dyn Dir + '_>>;
+    /// Create a symlink `name` pointing to `target`.
+    fn symlink(&self, target: &Path, name: &OsStr) -> io::Result<()>;
+    /// Remove the file or symlink `name`.
+    fn remove_file(&self, name: &OsStr) -> io::Result<()>;
+    /// Remove the empty directory `name`.
+    fn remove_dir(&self, name: &OsStr) -> io::Result<()>;
+    /// [`Fs::rename`] within this directory.
+    fn rename(&self, from: &OsStr, to: &OsStr) -> io::Result<()>;
+    /// [`Fs::exchange`] within this directory.
+    fn exchange(&self, a: &OsStr, b: &OsStr) -> io::Result<()>;
+    /// Set the permission bits of the file or directory `name`.
+    fn set_permissions(&self, name: &OsStr, mode: u32) -> io::Result<()>;
+}
+
+/// [`Dir`] that acts on `path/name` through an [`Fs`]. Each operation
+/// resolves the path anew, so it only narrows the window between
+/// re-check and change; used where no directory handle is available.
+pub struct PathDir<'a, F: Fs + ?Sized> {
+    fs: &'a F,
+    path: PathBuf,
+}
+
+impl<'a, F: Fs + ?Sized> PathDir<'a, F> {
+    /// Directory `p` of `fs`; fails unless `p` resolves to a directory.
+    pub fn open(fs: &'a F, p: &Path) -> io::Result<Self> {
+        if !fs.metadata(p)?.is_dir() {
+            return Err(not_a_dir(p));
+        }
+        Ok(Self {
+            fs,
+            path: p.to_path_buf(),
+        })
+    }
+
+    fn entry(&self, name: &OsStr) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Metadata of `name`, failing when it is a symlink.
+    fn no_follow(&self, name: &OsStr) -> io::Result<Metadata> {
+        let p = self.entry(name);
+        let m = self.fs.symlink_metadata(&p)?;
+        if m.file_type().is_symlink() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} 是符号链接", p.display()),
+            ));
+        }
+        Ok(m)
+    }
+}
+
+impl<F: Fs + ?Sized> Dir for PathDir<'_, F> {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+    fn symlink_metadata(&self, name: &OsStr) -> io::Result<Metadata> {
+        self.fs.symlink_metadata(&self.entry(name))
+    }
+    fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        self.fs.read_link(&self.entry(name))
+    }
+    fn open_dir(&self, name: &OsStr) -> io::Result<Box<dyn Dir + '_>> {
+        if !self.no_follow(name)?.is_dir() {
+            return Err(not_a_dir(&self.entry(name)));
+        }
+        Ok(Box::new(PathDir {
+            fs: self.fs,
+            path: self.entry(name),
+        }))
+    }
+    fn symlink(&self, target: &Path, name: &OsStr) -> io::Result<()> {
+        self.fs.symlink(target, &self.entry(name))
+    }
+    fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        self.fs.remove_file(&self.entry(name))
+    }
+    fn remove_dir(&self, name: &OsStr) -> io::Result<()> {
+        self.fs.remove_dir(&self.entry(name))
+    }
+    fn rename(&self, from: &OsStr, to: &OsStr) -> io::Result<()> {
+        self.fs.rename(&self.entry(from), &self.entry(to))
+    }
+    fn exchange(&self, a: &OsStr, b: &OsStr) -> io::Result<()> {
+        self.fs.exchange(&self.entry(a), &self.entry(b))
+    }
+    fn set_permissions(&self, name: &OsStr, mode: u32) -> io::Result<()> {
+        self.no_follow(name)?;
+        self.fs.set_permissions(&self.entry(name), mode)
+    }
+}
+
+fn not_a_dir(p: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotADirectory,
+        format!("{} 不是目录", p.display()),
+    )
+}
+
+/// The live filesystem.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn symlink_metadata(&self, p: &Path) -> io::Result<Metadata> {
+        traced(
+            "symlink_metadata",
+            p,
+            std::fs::symlink_metadata(p).map(Metadata::from),
+        )
+    }
+
+    fn metadata(&self, p: &Path) -> io::Result<Metadata> {
+        traced("metadata", p, std::fs::metadata(p).map(Metadata::from))
+    }
+
+    fn read_link(&self, p: &Path) -> io::Result<PathBuf> {
+        traced("read_link", p, std::fs::read_link(p))
+    }
+
+    fn canonicalize(&self, p: &Path) -> io::Result<PathBuf> {
+        traced("canonicalize", p, p.canonicalize())
+    }
+
+    fn read_dir(&self, p: &Path) -> io::Result<Vec<PathBuf>> {
+        let entries = std::fs::read_dir(p).and_then(|d| d.map(|e| e.map(|e| e.path())).collect());
+        traced("read_dir", p, entries)
+    }
+
+    fn create_dir_all(&self, p: &Path) -> io::Result<()> {
+        traced("create_dir_all", p, std::fs::create_dir_all(p))
+    }
+
+    #[cfg(unix)]
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let r = std::os::unix::fs::symlink(target, link);
+        traced(&format!("symlink {} ->", target.display()), link, r)
+    }
+
+    #[cfg(windows)]
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let r = if target.is_dir() {
+            std::os::windows::fs::symlink_dir(target, link)
+        } else {
+            std::os::windows::fs::symlink_file(target, link)
+        };
+        traced(&format!("symlink {} ->", target.display()), link, r)
+    }
+
+    fn remove_file(&self, p: &Path) -> io::Result<()> {
+        traced("remove_file", p, std::fs::remove_file(p))
+    }
+
+    fn remove_dir(&self, p: &Path) -> io::Result<()> {
+        traced("remove_dir", p, std::fs::remove_dir(p))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let r = std::fs::rename(from, to);
+        traced(&format!("rename {} ->", from.display()), to, r)
+    }
+
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        let r = crate::dirfd::exchange(a, b);
+        #[cfg(not(target_os = "linux"))]
+        let r = Err(io::Error::from(io::ErrorKind::Unsupported));
+        traced(&format!("exchange {} <->", a.display()), b, r)
+    }
+
+    #[cfg(unix)]
+    fn set_permissions(&self, p: &Path, mode: u32) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let r = std::fs::set_permissions(p, std::fs::Permissions::from_mode(mode));
+        traced(&format!("set_permissions {:o}", mode), p, r)
+    }
+
+    #[cfg(not(unix))]
+    fn set_permissions(&self, _p: &Path, _mode: u32) -> io::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn open_dir(&self, p: &Path) -> io::Result<Box<dyn Dir + '_>> {
+        let r = crate::dirfd::FdDir::open(p);
+        Ok(Box::new(traced("open_dir", p, r)?))
+    }
+}
+
+/// Record a call on the live filesystem, and its error, at trace level.
+pub(crate) fn traced<T>(call: &str, p: &Path, r: io::Result<T>) -> io::Result<T> {
+    logging::log("fs", Level::Trace, || match &r {
+        Ok(_) => format!("{} {}", call, p.display()),
+        Err(e) => format!("{} {}: {}", call, p.display(), e),
+    });
+    r
+}
+
+/// An [`Fs`] operation, used to target injected faults.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// [`Fs::symlink_metadata`].
+    SymlinkMetadata,
+    /// [`Fs::metadata`].
+    Metadata,
+    /// [`Fs::read_link`].
+    ReadLink,
+    /// [`Fs::canonicalize`].
+    Canonicalize,
+    /// [`Fs::read_dir`].
+    ReadDir,
+    /// [`Fs::create_dir_all`].
+    CreateDirAll,
+    /// [`Fs::symlink`]; matched against the link path.
+    Symlink,
+    /// [`Fs::remove_file`].
+    RemoveFile,
+    /// [`Fs::remove_dir`].
+    RemoveDir,
+    /// [`Fs::rename`]; matched against the destination.
+    Rename,
+    /// [`Fs::exchange`]; matched against the second path.
+    Exchange,
+    /// [`Fs::set_permissions`].
+    SetPermissions,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    File { mode: u32 },
+    Dir { mode: u32 },
+    Symlink(PathBuf),
+    Other,
+}
+
+/// Symlink hops allowed during one resolution, as on Linux.
+const MAX_HOPS: usize = 40;
+
+/// A modification made through [`OverlayFs`] or a recording `Fs`
+/// wrapper. Paths are the ones the caller passed in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// A directory was created (once per missing ancestor).
+    CreateDir(PathBuf),
+    /// A symlink was created at `link` pointing to `target`.
+    Symlink {
+        /// Where the symlink was created.
+        link: PathBuf,
+        /// Its target, verbatim.
+        target: PathBuf,
+    },
+    /// A file or symlink was removed.
+    RemoveFile(PathBuf),
+    /// An empty directory was removed.
+    RemoveDir(PathBuf),
+    /// An object was renamed, replacing what was at `to`.
+    Rename {
+        /// Its old path.
+        from: PathBuf,
+        /// Its new path.
+        to: PathBuf,
+    },
+    /// The objects at two paths swapped places.
+    Exchange {
+        /// The first path.
+        from: PathBuf,
+        /// The second path.
+        to: PathBuf,
+    },
+    /// Permission bits were set.
+    SetPermissions {
+        /// The path passed in; the change applies to what it resolves to.
+        path: PathBuf,
+        /// New permission bits.
+        mode: u32,
+    },
+}
+
+/// Node-level storage shared by [`MemFs`] and [`OverlayFs`]; all path
+/// semantics live in the `tree_*` functions below. Keys are resolved
+/// absolute paths.
+trait Tree {
+    fn get(&self, at: &Path) -> io::Result<Option<Node>>;
+    /// Insert `node`, or remove the entry when `None`.
+    fn put(&self, at: PathBuf, node: Option<Node>);
+    /// Names of the entries directly below directory `at`.
+    fn children(&self, at: &Path) -> io::Result<Vec<OsString>>;
+    fn record(&self, _change: Change) {}
+}
+
+/// Resolve `p` to the key of the object it names. Every intermediate
+/// symlink is followed; the final one only if `follow_last`. A missing
+/// final component resolves to where it would be created.
+fn resolve(t: &dyn Tree, p: &Path, follow_last: bool) -> io::Result<PathBuf> {
+    if !p.is_absolute() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("只接受绝对路径: {}", p.display()),
+        ));
+    }
+    let mut queue: VecDeque<OsString> = components(p);
+    let mut cur = PathBuf::from("/");
+    let mut hops = 0;
+    while let Some(c) = queue.pop_front() {
+        if c == ".." {
+            cur.pop();
+            continue;
+        }
+        let next = cur.join(&c);
+        let last = queue.is_empty();
+        match t.get(&next)? {
+            None if last => return Ok(next),
+            None => return Err(not_found(&next)),
+            Some(Node::Symlink(target)) if !last || follow_last => {
+                hops += 1;
+                if hops > MAX_HOPS {
+                    return Err(io::Error::other(format!(
+                        "符号链接层数过多: {}",
+                        p.display()
+                    )));
+                }
+                if target.is_absolute() {
+                    cur = PathBuf::from("/");
+                }
+                for t in components(&target).into_iter().rev() {
+                    queue.push_front(t);
+                }
+            }
+            Some(Node::File { .. } | Node::Other) if !last => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("{} 不是目录", next.display()),
+                ));
+            }
+            Some(_) => cur = next,
+        }
+    }
+    Ok(cur)
+}
+
+fn components(p: &Path) -> VecDeque<OsString> {
+    p.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_os_string()),
+            Component::ParentDir => Some("..".into()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn not_found(p: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} 不存在", p.display()))
+}
+
+fn node(t: &dyn Tree, at: &Path) -> io::Result<Node> {
+    t.get(at)?.ok_or_else(|| not_found(at))
+}
+
+fn meta_of(node: &Node) -> Metadata {
+    match node {
+        Node::File { mode } => Metadata::new(FileType::File, *mode),
+        Node::Dir { mode } => Metadata::new(FileType::Dir, *mode),
+        Node::Symlink(_) => Metadata::new(FileType::Symlink, 0o777),
+        Node::Other => Metadata::new(FileType::Other, 0),
+    }
+}
+
+fn tree_symlink_metadata(t: &dyn Tree, p: &Path) -> io::Result<Metadata> {
+    let at = resolve(t, p, false)?;
+    node(t, &at).map(|n| meta_of(&n))
+}
+
+fn tree_metadata(t: &dyn Tree, p: &Path) -> io::Result<Metadata> {
+    let at = resolve(t, p, true)?;
+    node(t, &at).map(|n| meta_of(&n))
+}
+
+fn tree_read_link(t: &dyn Tree, p: &Path) -> io::Result<PathBuf> {
+    let at = resolve(t, p, false)?;
+    match node(t, &at)? {
+        Node::Symlink(target) => Ok(target),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} 不是符号链接", p.display()),
+        )),
+    }
+}
+
+fn tree_canonicalize(t: &dyn Tree, p: &Path) -> io::Result<PathBuf> {
+    let at = resolve(t, p, true)?;
+    node(t, &at)?;
+    Ok(at)
+}
+
+fn tree_read_dir(t: &dyn Tree, p: &Path) -> io::Result<Vec<PathBuf>> {
+    let at = resolve(t, p, true)?;
+    if !matches!(node(t, &at)?, Node::Dir { .. }) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("{} 不是目录", p.display()),
+        ));
+    }
+    Ok(t.children(&at)?.iter().map(|n| p.join(n)).collect())
+}
+
+fn tree_create_dir_all(t: &dyn Tree, p: &Path) -> io::Result<()> {
+    match tree_metadata(t, p) {
+        Ok(m) if m.is_dir() => return Ok(()),
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} 已存在且不是目录", p.display()),
+            ))
+        }
+        Err(_) if tree_symlink_metadata(t, p).is_ok() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} 是损坏的符号链接", p.display()),
+            ))
+        }
+        Err(_) => {}
+    }
+    if let Some(parent) = p.parent() {
+        tree_create_dir_all(t, parent)?;
+    }
+    let at = resolve(t, p, false)?;
+    t.put(at, Some(Node::Dir { mode: 0o755 }));
+    t.record(Change::CreateDir(p.to_path_buf()));
+    Ok(())
+}
+
+fn tree_symlink(t: &dyn Tree, target: &Path, link: &Path) -> io::Result<()> {
+    let at = resolve(t, link, false)?;
+    if t.get(&at)?.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} 已存在", link.display()),
+        ));
+    }
+    if let Some(parent) = at.parent() {
+        if !matches!(node(t, parent)?, Node::Dir { .. }) {
+            return Err(not_found(parent));
+        }
+    }
+    t.put(at, Some(Node::Symlink(target.to_path_buf())));
+    t.record(Change::Symlink {
+        link: link.to_path_buf(),
+        target: target.to_path_buf(),
+    });
+    Ok(())
+}
+
+fn tree_remove_file(t: &dyn Tree, p: &Path) -> io::Result<()> {
+    let at = resolve(t, p, false)?;
+    if let Node::Dir { .. } = node(t, &at)? {
+        return Err(io::Error::new(
+            io::ErrorKind::IsADirectory,
+            format!("{} 是目录", p.display()),
+        ));
+    }
+    t.put(at, None);
+    t.record(Change::RemoveFile(p.to_path_buf()));
+    Ok(())
+}
+
+fn tree_remove_dir(t: &dyn Tree, p: &Path) -> io::Result<()> {
+    let at = resolve(t, p, false)?;
+    if !matches!(node(t, &at)?, Node::Dir { .. }) {
+        return Err(io::Error::new(
+            io::ErrorKind::NotADirectory,
+            format!("{} 不是目录", p.display()),
+        ));
+    }
+    if !t.children(&at)?.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::DirectoryNotEmpty,
+            format!("{} 不是空目录", p.display()),
+        ));
+    }
+    t.put(at, None);
+    t.record(Change::RemoveDir(p.to_path_buf()));
+    Ok(())
+}
+
+/// Key of entry `p` for a rename or exchange, whose parent must be a
+/// directory.
+fn move_key(t: &dyn Tree, p: &Path) -> io::Result<PathBuf> {
+    let at = resolve(t, p, false)?;
+    if let Some(parent) = at.parent() {
+        if !matches!(node(t, parent)?, Node::Dir { .. }) {
+            return Err(not_found(parent));
+        }
+    }
+    Ok(at)
+}
+
+/// Take the object at `at` and everything below it out of the tree, as
+/// paths relative to `at`.
+fn take_subtree(t: &dyn Tree, at: &Path) -> io::Result<Vec<(PathBuf, Node)>> {
+    let mut taken = Vec::new();
+    let mut queue = vec![PathBuf::new()];
+    while let Some(rel) = queue.pop() {
+        let key = at.join(&rel);
+        let n = node(t, &key)?;
+        if matches!(n, Node::Dir { .. }) {
+            queue.extend(t.children(&key)?.into_iter().map(|c| rel.join(c)));
+        }
+        taken.push((rel, n));
+    }
+    for (rel, _) in &taken {
+        t.put(at.join(rel), None);
+    }
+    Ok(taken)
+}
+
+fn put_subtree(t: &dyn Tree, at: &Path, nodes: Vec<(PathBuf, Node)>) {
+    for (rel, n) in nodes {
+        t.put(at.join(rel), Some(n));
+    }
+}
+
+fn tree_rename(t: &dyn Tree, from: &Path, to: &Path) -> io::Result<()> {
+    let from_at = move_key(t, from)?;
+    let to_at = move_key(t, to)?;
+    let moving = node(t, &from_at)?;
+    if from_at == to_at {
+        return Ok(());
+    }
+    if to_at.starts_with(&from_at) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("不能把 {} 移到其自身之下", from.display()),
+        ));
+    }
+    match (&moving, t.get(&to_at)?) {
+        (Node::Dir { .. }, Some(Node::Dir { .. })) if !t.children(&to_at)?.is_empty() => {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("{} 不是空目录", to.display()),
+            ));
+        }
+        (Node::Dir { .. }, Some(Node::Dir { .. })) => {}
+        (Node::Dir { .. }, Some(_)) => return Err(not_a_dir(to)),
+        (_, Some(Node::Dir { .. })) => {
+            return Err(io::Error::new(
+                io::ErrorKind::IsADirectory,
+                format!("{} 是目录", to.display()),
+            ))
+        }
+        _ => {}
+    }
+    let nodes = take_subtree(t, &from_at)?;
+    t.put(to_at.clone(), None);
+    put_subtree(t, &to_at, nodes);
+    t.record(Change::Rename {
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    });
+    Ok(())
+}
+
+fn tree_exchange(t: &dyn Tree, a: &Path, b: &Path) -> io::Result<()> {
+    let a_at = move_key(t, a)?;
+    let b_at = move_key(t, b)?;
+    if a_at.starts_with(&b_at) || b_at.starts_with(&a_at) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("不能交换 {} 和 {}", a.display(), b.display()),
+        ));
+    }
+    node(t, &a_at)?;
+    node(t, &b_at)?;
+    let a_nodes = take_subtree(t, &a_at)?;
+    let b_nodes = take_subtree(t, &b_at)?;
+    put_subtree(t, &a_at, b_nodes);
+    put_subtree(t, &b_at, a_nodes);
+    t.record(Change::Exchange {
+        from: a.to_path_buf(),
+        to: b.to_path_buf(),
+    });
+    Ok(())
+}
+
+fn tree_set_permissions(t: &dyn Tree, p: &Path, mode: u32) -> io::Result<()> {
+    let at = resolve(t, p, true)?;
+    let updated = match node(t, &at)? {
+        Node::File { .. } => Node::File {
+            mode: mode & 0o7777,
+        },
+        Node::Dir { .. } => Node::Dir {
+            mode: mode & 0o7777,
+        },
+        _ => return Err(not_found(p)),
+    };
+    t.put(at, Some(updated));
+    t.record(Change::SetPermissions {
+        path: p.to_path_buf(),
+        mode,
+    });
+    Ok(())
+}
+
+/// In-memory filesystem with Unix semantics for absolute paths.
+///
+/// The tree starts with only `/`. Objects are added with
+/// [`add_file`](MemFs::add_file), [`add_dir`](MemFs::add_dir) and
+/// [`add_symlink`](MemFs::add_symlink), which create missing parent
+/// directories. [`fail`](MemFs::fail) makes every later call of one
+/// operation on one (lexical) path return an error, and
+/// [`before`](MemFs::before) changes the tree right before an operation
+/// to simulate a concurrent writer.
+pub struct MemFs {
+    nodes: RefCell<BTreeMap<PathBuf, Node>>,
+    faults: RefCell<Vec<(Op, PathBuf, io::ErrorKind)>>,
+    hooks: RefCell<Vec<(Op, PathBuf, Hook)>>,
+}
+
+type Hook = Box<dyn FnOnce(&MemFs)>;
+
+impl Default for MemFs {
+    fn default() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), Node::Dir { mode: 0o755 });
+        Self {
+            nodes: RefCell::new(nodes),
+            faults: RefCell::new(Vec::new()),
+            hooks: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl MemFs {
+    /// A tree holding only the root directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a regular file with permission bits `mode`.
+    pub fn add_file(&self, p: impl AsRef<Path>, mode: u32) {
+        self.add(p.as_ref(), Node::File { mode });
+    }
+
+    /// Add a directory with mode `0o755`.
+    pub fn add_dir(&self, p: impl AsRef<Path>) {
+        self.add(p.as_ref(), Node::Dir { mode: 0o755 });
+    }
+
+    /// Add a symlink at `link` whose target is `target`, verbatim.
+    pub fn add_symlink(&self, link: impl AsRef<Path>, target: impl AsRef<Path>) {
+        self.add(link.as_ref(), Node::Symlink(target.as_ref().to_path_buf()));
+    }
+
+    /// Make every later `op` on `p` fail with `kind`. `p` is compared
+    /// lexically with the path the caller passes in.
+    pub fn fail(&self, op: Op, p: impl AsRef<Path>, kind: io::ErrorKind) {
+        self.faults
+            .borrow_mut()
+            .push((op, p.as_ref().to_path_buf(), kind));
+    }
+
+    /// Run `f` once, immediately before the next `op` on `p` (compared
+    /// lexically) is carried out.
+    pub fn before(&self, op: Op, p: impl AsRef<Path>, f: impl FnOnce(&MemFs) + 'static) {
+        self.hooks
+            .borrow_mut()
+            .push((op, p.as_ref().to_path_buf(), Box::new(f)));
+    }
+
+    /// Remove whatever `p` names, including everything below it. Symlinks
+    /// are removed, not followed. Missing paths are ignored.
+    pub fn remove_all(&self, p: impl AsRef<Path>) {
+        let Ok(at) = resolve(self, p.as_ref(), false) else {
+            return;
+        };
+        self.nodes.borrow_mut().retain(|k, _| !k.starts_with(&at));
+    }
+
+    fn add(&self, p: &Path, node: Node) {
+        if let Some(parent) = p.parent() {
+            tree_create_dir_all(self, parent)
+                .unwrap_or_else(|e| panic!("MemFs: cannot create {}: {}", parent.display(), e));
+        }
+        let at = resolve(self, p, false)
+            .unwrap_or_else(|e| panic!("MemFs: cannot add {}: {}", p.display(), e));
+        self.nodes.borrow_mut().insert(at, node);
+    }
+
+    fn check(&self, op: Op, p: &Path) -> io::Result<()> {
+        let hook = {
+            let mut hooks = self.hooks.borrow_mut();
+            hooks
+                .iter()
+                .position(|(o, hp, _)| *o == op && hp == p)
+                .map(|i| hooks.remove(i).2)
+        };
+        if let Some(f) = hook {
+            f(self);
+        }
+        match self
+            .faults
+            .borrow()
+            .iter()
+            .find(|(o, fp, _)| *o == op && fp == p)
+        {
+            Some((_, _, kind)) => Err(io::Error::new(
+                *kind,
+                format!("injected {:?} fault at {}", op, p.display()),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Tree for MemFs {
+    fn get(&self, at: &Path) -> io::Result<Option<Node>> {
+        Ok(self.nodes.borrow().get(at).cloned())
+    }
+
+    fn put(&self, at: PathBuf, node: Option<Node>) {
+        let mut nodes = self.nodes.borrow_mut();
+        match node {
+            Some(n) => nodes.insert(at, n),
+            None => nodes.remove(&at),
+        };
+    }
+
+    fn children(&self, at: &Path) -> io::Result<Vec<OsString>> {
+        Ok(self
+            .nodes
+            .borrow()
+            .range(at.to_path_buf()..)
+            .skip(1)
+            .take_while(|(k, _)| k.starts_with(at))
+            .filter(|(k, _)| k.parent() == Some(at))
+            .filter_map(|(k, _)| k.file_name().map(|n| n.to_os_string()))
+            .collect())
+    }
+}
+
+impl Fs for MemFs {
+    fn symlink_metadata(&self, p: &Path) -> io::Result<Metadata> {
+        self.check(Op::SymlinkMetadata, p)?;
+        tree_symlink_metadata(self, p)
+    }
+
+    fn metadata(&self, p: &Path) -> io::Result<Metadata> {
+        self.check(Op::Metadata, p)?;
+        tree_metadata(self, p)
+    }
+
+    fn read_link(&self, p: &Path) -> io::Result<PathBuf> {
+        self.check(Op::ReadLink, p)?;
+        tree_read_link(self, p)
+    }
+
+    fn canonicalize(&self, p: &Path) -> io::Result<PathBuf> {
+        self.check(Op::Canonicalize, p)?;
+        tree_canonicalize(self, p)
+    }
+
+    fn read_dir(&self, p: &Path) -> io::Result<Vec<PathBuf>> {
+        self.check(Op::ReadDir, p)?;
+        tree_read_dir(self, p)
+    }
+
+    fn create_dir_all(&self, p: &Path) -> io::Result<()> {
+        self.check(Op::CreateDirAll, p)?;
+        tree_create_dir_all(self, p)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        self.check(Op::Symlink, link)?;
+        tree_symlink(self, target, link)
+    }
+
+    fn remove_file(&self, p: &Path) -> io::Result<()> {
+        self.check(Op::RemoveFile, p)?;
+        tree_remove_file(self, p)
+    }
+
+    fn remove_dir(&self, p: &Path) -> io::Result<()> {
+        self.check(Op::RemoveDir, p)?;
+        tree_remove_dir(self, p)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(Op::Rename, to)?;
+        tree_rename(self, from, to)
+    }
+
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()> {
+        self.check(Op::Exchange, b)?;
+        tree_exchange(self, a, b)
+    }
+
+    fn set_permissions(&self, p: &Path, mode: u32) -> io::Result<()> {
+        self.check(Op::SetPermissions, p)?;
+        tree_set_permissions(self, p, mode)
+    }
+}
+
+/// Copy-on-write view of another [`Fs`]: reads fall through to the base
+/// until a path is modified, and modifications stay in memory. Used to
+/// predict what applying a plan would do without touching the base.
+///
+/// Base answers are memoized, so the view reflects the base as first
+/// observed. Every modification is logged and can be collected with
+/// [`take_changes`](OverlayFs::take_changes).
+pub struct OverlayFs<'a> {
+    base: &'a dyn Fs,
+    base_nodes: RefCell<HashMap<PathBuf, Option<Node>>>,
+    /// Modified paths; `None` marks a removal.
+    upper: RefCell<HashMap<PathBuf, Option<Node>>>,
+    changes: RefCell<Vec<Change>>,
+}
+
+impl<'a> OverlayFs<'a> {
+    /// An unmodified view of `base`.
+    pub fn new(base: &'a dyn Fs) -> Self {
+        Self {
+            base,
+            base_nodes: Default::default(),
+            upper: Default::default(),
+            changes: Default::default(),
+        }
+    }
+
+    /// Modifications made since the last call, in order.
+    pub fn take_changes(&self) -> Vec<Change> {
+        std::mem::take(&mut self.changes.borrow_mut())
+    }
+
+    fn base_node(&self, at: &Path) -> io::Result<Option<Node>> {
+        if let Some(n) = self.base_nodes.borrow().get(at) {
+            return Ok(n.clone());
+        }
+        let n = match self.base.symlink_metadata(at) {
+            Ok(m) => Some(match m.file_type() {
+                FileType::File => Node::File { mode: m.mode() },
+                FileType::Dir => Node::Dir { mode: m.mode() },
+                FileType::Symlink => Node::Symlink(self.base.read_link(at)?),
+                FileType::Other => Node::Other,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.base_nodes
+            .borrow_mut()
+            .insert(at.to_path_buf(), n.clone());
+        Ok(n)
+    }
+}
+
+impl Tree for OverlayFs<'_> {
+    fn get(&self, at: &Path) -> io::Result<Option<Node>> {
+        match self.upper.borrow().get(at) {
+            Some(n) => Ok(n.clone()),
+            None => self.base_node(at),
+        }
+    }
+
+    fn put(&self, at: PathBuf, node: Option<Node>) {
+        self.upper.borrow_mut().insert(at, node);
+    }
+
+    fn children(&self, at: &Path) -> io::Result<Vec<OsString>> {
+        let mut names: Vec<OsString> = match self.base_node(at)? {
+            Some(Node::Dir { .. }) => self
+                .base
+                .read_dir(at)?
+                .into_iter()
+                .filter_map(|p| p.file_name().map(|n| n.to_os_string()))
+                .collect(),
+            // Created in the overlay: nothing below it in the base.
+            _ => Vec::new(),
+        };
+        for (p, n) in self.upper.borrow().iter() {
+            if p.parent() != Some(at) {
+                continue;
+            }
+            let Some(name) = p.file_name() else { continue };
+            names.retain(|x| x != name);
+            if n.is_some() {
+                names.push(name.to_os_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn record(&self, change: Change) {
+        self.changes.borrow_mut().push(change);
+    }
+}
+
+impl Fs for OverlayFs<'_> {
+    fn symlink_metadata(&self, p: &Path) -> io::Result<Metadata> {
+        tree_symlink_metadata(self, p)
+    }
+
+    fn metadata(&self, p: &Path) -> io::Result<Metadata> {
+        tree_metadata(self, p)
+    }
+
+    fn read_link(&self, p: &Path) -> io::Result<PathBuf> {
+        tree_read_link(self, p)
+    }
+
+    fn canonicalize(&self, p: &Path) -> io::Result<PathBuf> {
+        tree_canonicalize(self, p)
+    }
+
+    fn read_dir(&self, p: &Path) -> io::Result<Vec<PathBuf>> {
+        tree_read_dir(self, p)
+    }
+
+    fn create_dir_all(&self, p: &Path) -> io::Result<()> {
+        tree_create_dir_all(self, p)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        tree_symlink(self, target, link)
+    }
+
+    fn remove_file(&self, p: &Path) -> io::Result<()> {
+        tree_remove_file(self, p)
+    }
+
+    fn remove_dir(&self, p: &Path) -> io::Result<()> {
+        tree_remove_dir(self, p)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        tree_rename(self, from, to)
+    }
+
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()> {
+        tree_exchange(self, a, b)
+    }
+
+    fn set_permissions(&self, p: &Path, mode: u32) -> io::Result<()> {
+        tree_set_permissions(self, p, mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symlinks_resolve_like_unix() {
+        let fs = MemFs::new();
+        fs.add_file("/repo/a", 0o644);
+        fs.add_symlink("/home/abs", "/repo");
+        fs.add_symlink("/home/rel", "../repo/a");
+        fs.add_symlink("/home/dangling", "/nowhere");
+
+        assert_eq!(
+            fs.canonicalize(Path::new("/home/abs/a")).unwrap(),
+            Path::new("/repo/a")
+        );
+        assert_eq!(
+            fs.canonicalize(Path::new("/home/rel")).unwrap(),
+            Path::new("/repo/a")
+        );
+        assert!(fs.is_symlink(Path::new("/home/dangling")));
+        assert!(!fs.exists(Path::new("/home/dangling")));
+        assert!(fs.metadata(Path::new("/home/abs")).unwrap().is_dir());
+    }
+
+    #[test]
+    fn symlink_loops_are_reported() {
+        let fs = MemFs::new();
+        fs.add_symlink("/a", "/b");
+        fs.add_symlink("/b", "/a");
+        assert!(fs.canonicalize(Path::new("/a")).is_err());
+        assert!(fs.is_symlink(Path::new("/a")));
+    }
+
+    #[test]
+    fn modifications_follow_std_rules() {
+        let fs = MemFs::new();
+        fs.add_dir("/d/sub");
+        assert_eq!(
+            fs.remove_dir(Path::new("/d")).unwrap_err().kind(),
+            io::ErrorKind::DirectoryNotEmpty
+        );
+        assert_eq!(
+            fs.symlink(Path::new("/t"), Path::new("/missing/l"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+        fs.symlink(Path::new("/t"), Path::new("/d/l")).unwrap();
+        assert_eq!(
+            fs.symlink(Path::new("/t"), Path::new("/d/l"))
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        fs.remove_file(Path::new("/d/l")).unwrap();
+        fs.remove_dir(Path::new("/d/sub")).unwrap();
+        assert!(fs.read_dir(Path::new("/d")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn injected_faults_hit_only_their_op_and_path() {
+        let fs = MemFs::new();
+        fs.add_file("/f", 0o644);
+        fs.fail(Op::SetPermissions, "/f", io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            fs.set_permissions(Path::new("/f"), 0o600)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert!(fs.metadata(Path::new("/f")).is_ok());
+    }
+
+    #[test]
+    fn overlay_keeps_changes_in_memory() {
+        let base = MemFs::new();
+        base.add_file("/repo/a", 0o644);
+        base.add_file("/home/.a", 0o644);
+        let o = OverlayFs::new(&base);
+
+        o.remove_file(Path::new("/home/.a")).unwrap();
+        o.create_dir_all(Path::new("/home/.config/x")).unwrap();
+        o.symlink(Path::new("/repo/a"), Path::new("/home/.config/x/a"))
+            .unwrap();
+        o.set_permissions(Path::new("/home/.config/x/a"), 0o600)
+            .unwrap();
+
+        assert_eq!(
+            o.canonicalize(Path::new("/home/.config/x/a")).unwrap(),
+            Path::new("/repo/a")
+        );
+        assert_eq!(o.metadata(Path::new("/repo/a")).unwrap().mode(), 0o600);
+        assert_eq!(
+            o.read_dir(Path::new("/home")).unwrap(),
+            vec![PathBuf::from("/home/.config")]
+        );
+        // The base is untouched.
+        assert!(base.exists(Path::new("/home/.a")));
+        assert!(!base.exists(Path::new("/home/.config")));
+        assert_eq!(base.metadata(Path::new("/repo/a")).unwrap().mode(), 0o644);
+
+        assert_eq!(
+            o.take_changes(),
+            vec![
+                Change::RemoveFile(PathBuf::from("/home/.a")),
+                Change::CreateDir(PathBuf::from("/home/.config")),
+                Change::CreateDir(PathBuf::from("/home/.config/x")),
+                Change::Symlink {
+                    link: PathBuf::from("/home/.config/x/a"),
+                    target: PathBuf::from("/repo/a"),
+                },
+                Change::SetPermissions {
+                    path: PathBuf::from("/home/.config/x/a"),
+                    mode: 0o600,
+                },
+            ]
+        );
+        assert!(o.take_changes().is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+//! Per-run memoization of filesystem probes used by planning.
+//!
+//! Planning asks the same questions about the same paths many times:
+//! every source under a config dir re-stats the same ancestors, and
+//! every link under `~/.config` re-canonicalizes the same parent. An
+//! [`FsCache`] lives for one planning run and answers repeated queries
+//! from memory. Planning never modifies the filesystem, so the cached
+//! view cannot go stale because of our own actions.
+//!
+//! The apply stage must observe fresh state for its re-checks, so it
+//! never shares a cache with planning.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fs::{Fs, Metadata};
+
+type IoResult<T> = Result<T, (io::ErrorKind, String)>;
+
+pub struct FsCache<'a> {
+    fs: &'a dyn Fs,
+    symlink_meta: RefCell<HashMap<PathBuf, Option<Metadata>>>,
+    meta: RefCell<HashMap<PathBuf, Option<Metadata>>>,
+    read_link: RefCell<HashMap<PathBuf, Option<PathBuf>>>,
+    canon: RefCell<HashMap<PathBuf, IoResult<PathBuf>>>,
+}
+
+impl<'a> FsCache<'a> {
+    pub fn new(fs: &'a dyn Fs) -> Self {
+        Self {
+            fs,
+            symlink_meta: Default::default(),
+            meta: Default::default(),
+            read_link: Default::default(),
+            canon: Default::default(),
+        }
+    }
+
+    /// The filesystem this cache reads from.
+    pub fn fs(&self) -> &'a dyn Fs {
+        self.fs
+    }
+
+    /// `Fs::symlink_metadata`, with errors collapsed to `None`.
+    pub fn symlink_metadata(&self, p: &Path) -> Option<Metadata> {
+        memo(&self.symlink_meta, p, |p| self.fs.symlink_metadata(p).ok())
+    }
+
+    /// `Fs::metadata` (follows symlinks), with errors collapsed to `None`.
+    pub fn metadata(&self, p: &Path) -> Option<Metadata> {
+        memo(&self.meta, p, |p| self.fs.metadata(p).ok())
+    }
+
+    pub fn read_link(&self, p: &Path) -> Option<PathBuf> {
+        memo(&self.read_link, p, |p| self.fs.read_link(p).ok())
+    }
+
+    pub fn canonicalize(&self, p: &Path) -> io::Result<PathBuf> {
+        memo(&self.canon, p, |p| {
+            self.fs
+                .canonicalize(p)
+                .map_err(|e| (e.kind(), e.to_string()))
+        })
+        .map_err(|(kind, msg)| io::Error::new(kind, msg))
+    }
+
+    /// `Path::exists`: the path resolves (following symlinks).
+    pub fn exists(&self, p: &Path) -> bool {
+        self.metadata(p).is_some()
+    }
+
+    /// `Path::is_symlink`: the final component is a symlink.
+    pub fn is_symlink(&self, p: &Path) -> bool {
+        self.symlink_metadata(p)
+            .is_some_and(|m| m.file_type().is_symlink())
+    }
+}
+
+fn memo<T: Clone>(
+    map: &RefCell<HashMap<PathBuf, T>>,
+    p: &Path,
+    probe: impl FnOnce(&Path) -> T,
+) -> T {
+    if let Some(v) = map.borrow().get(p) {
+        return v.clone();
+    }
+    let v = probe(p);
+    map.borrow_mut().insert(p.to_path_buf(), v.clone());
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::MemFs;
+
+    #[test]
+    fn answers_are_stable_for_one_run() {
+        let fs = MemFs::new();
+        let f = Path::new("/d/f");
+
+        let cache = FsCache::new(&fs);
+        assert!(!cache.exists(f));
+        assert_eq!(
+            cache.canonicalize(f).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        fs.add_file(f, 0o644);
+        // The first observation is memoized for the rest of the run.
+        assert!(!cache.exists(f));
+        assert!(FsCache::new(&fs).exists(f));
+    }
+}
@@ -0,0 +1,748 @@
+//! Dependencies checked out from other local git repositories.
+//!
+//! A `[dependencies]` entry may name a git repository instead of a
+//! subdirectory:
+//!
+//! ```toml
+//! [dependencies]
+//! "team" = { git = "file:///srv/team-dotfiles.git", rev = "main" }
+//! ```
+//!
+//! The commit `rev` resolved to is pinned in an `xdotter.lock` next to
+//! the declaring `xdotter.toml`, and the repository is checked out at
+//! that commit under `~/.cache/xdotter/git/checkouts`. Discovery only
+//! reads the lock and the checkout — it never runs git — and treats the
+//! checkout as a configuration directory tree of its own. [`update`]
+//! (`xd update-deps`) is the only code that fetches, checks out and
+//! writes locks.
+//!
+//! Only local repositories are supported: `file://` URLs and paths,
+//! relative paths being resolved against the declaring config directory.
+//! A config inside a checkout is never written to; its own git
+//! dependencies must be pinned by the `xdotter.lock` committed in that
+//! repository.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::codes;
+use crate::config::{Config, GitDependency};
+use crate::error::{decorate, ErrorBag, XdError};
+use crate::logging::{self, Level};
+use crate::path as p;
+
+/// Name of the lock file kept next to an `xdotter.toml`.
+pub const LOCK_FILE: &str = "xdotter.lock";
+
+const LOCK_HEADER: &str = "\
+# Pinned git dependencies, written by `xd update-deps`.
+# Commit this file so every machine deploys the same revisions.
+";
+
+/// Pin of one git dependency.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LockedGit {
+    /// Repository location, as written in `xdotter.toml`.
+    pub url: String,
+    /// Requested revision, as written in `xdotter.toml`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Full commit id `rev` resolved to.
+    pub commit: String,
+}
+
+/// Contents of an `xdotter.lock`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lock {
+    /// Pins by dependency name.
+    #[serde(default)]
+    pub git: BTreeMap<String, LockedGit>,
+}
+
+impl Lock {
+    /// Read the lock next to `config_dir`'s `xdotter.toml`; a missing
+    /// file is an empty lock.
+    pub fn load(config_dir: &Path) -> Result<Self, XdError> {
+        let path = config_dir.join(LOCK_FILE);
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::from_toml(&content, &path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(
+                XdError::config(format!("无法读取 {}: {}", path.display(), e))
+                    .with_code(codes::LOCK_INVALID),
+            ),
+        }
+    }
+
+    /// Parse lock text. Commit ids must be full hexadecimal ids.
+    pub fn from_toml(content: &str, source: &Path) -> Result<Self, XdError> {
+        let lock: Lock = basic_toml::from_str(content).map_err(|e| {
+            XdError::config(format!("{}: 锁文件解析失败: {}", source.display(), e))
+                .with_code(codes::LOCK_INVALID)
+        })?;
+        for (name, l) in &lock.git {
+            if let Err(e) = check_argument("仓库位置", &l.url).and_then(|()| match &l.rev {
+                Some(rev) => check_argument("修订", rev),
+                None => Ok(()),
+            }) {
+                return Err(XdError::config(format!(
+                    "{}: git 依赖 \"{}\": {}",
+                    source.display(),
+                    name,
+                    e.body()
+                ))
+                .with_code(codes::GIT_OPTION_LIKE));
+            }
+            if !is_commit_id(&l.commit) {
+                return Err(XdError::config(format!(
+                    "{}: git 依赖 \"{}\" 的提交 {} 不是完整的提交 ID",
+                    source.display(),
+                    name,
+                    l.commit
+                ))
+                .with_code(codes::LOCK_INVALID));
+            }
+        }
+        Ok(lock)
+    }
+
+    /// Write the lock next to `config_dir`'s `xdotter.toml`.
+    pub fn save(&self, config_dir: &Path) -> Result<(), XdError> {
+        let path = config_dir.join(LOCK_FILE);
+        let body = basic_toml::to_string(self).map_err(|e| {
+            XdError::apply(format!("序列化 {} 失败: {}", path.display(), e))
+                .with_code(codes::LOCK_UNWRITABLE)
+        })?;
+        fs::write(&path, format!("{}\n{}", LOCK_HEADER, body)).map_err(|e| {
+            XdError::apply(format!("写入 {} 失败: {}", path.display(), e))
+                .with_code(codes::LOCK_UNWRITABLE)
+        })
+    }
+
+    /// Pin for `name`, if it was made for the same `url` and `rev`.
+    pub fn get(&self, name: &str, dep: &GitDependency) -> Option<&LockedGit> {
+        self.git
+            .get(name)
+            .filter(|l| l.url == dep.url && l.rev == dep.rev)
+    }
+}
+
+/// Locations and revisions are passed to git as arguments; one starting
+/// with `-` would be read as an option.
+fn check_argument(what: &str, value: &str) -> Result<(), XdError> {
+    if value.starts_with('-') {
+        return Err(XdError::config(format!(
+            "{}不能以 - 开头，否则会被 git 当作选项: {}",
+            what, value
+        ))
+        .with_code(codes::GIT_OPTION_LIKE));
+    }
+    Ok(())
+}
+
+/// Revision `rev` of a git dependency, checked like [`location`] checks
+/// the URL.
+pub fn check_rev(rev: &str) -> Result<(), XdError> {
+    check_argument("修订", rev)
+}
+
+fn is_commit_id(s: &str) -> bool {
+    matches!(s.len(), 40 | 64) && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// `~/.cache/xdotter/git`, or `None` without a home directory.
+pub fn cache_dir() -> Option<PathBuf> {
+    p::home_dir().map(|h| h.join(".cache").join("xdotter").join("git"))
+}
+
+/// Local path of the repository `url` declared in `config_dir`.
+/// Network URLs, and locations git would take for an option, are
+/// configuration errors.
+pub fn location(url: &str, config_dir: &Path) -> Result<PathBuf, XdError> {
+    check_argument("仓库位置", url)?;
+    let path = if let Some(rest) = url.strip_prefix("file://") {
+        check_argument("仓库位置", rest)?;
+        PathBuf::from(rest)
+    } else if url.contains("://") || is_scp_like(url) {
+        return Err(XdError::config(format!(
+            "git 依赖只支持本地仓库 (file:// URL 或路径): {}",
+            url
+        ))
+        .with_code(codes::GIT_NOT_LOCAL));
+    } else {
+        config_dir.join(url)
+    };
+    let path = p::normalize(&path);
+    Ok(path.canonicalize().unwrap_or(path))
+}
+
+/// `host:path`, as opposed to a Windows drive letter.
+fn is_scp_like(url: &str) -> bool {
+    url.split_once(':')
+        .is_some_and(|(host, _)| host.len() > 1 && !host.contains(['/', '\\']))
+}
+
+/// Directory name for `s`: a readable prefix made of its last path
+/// component, then a hash of all of `s`, so that names differing only in
+/// punctuation (`a-b`, `a_b`) never share a directory.
+fn cache_key(s: &str) -> String {
+    let tail = s.rsplit(['/', '\\']).find(|c| !c.is_empty()).unwrap_or("");
+    let readable: String = tail
+        .chars()
+        .take(32)
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!(
+        "{}-{:016x}",
+        readable.trim_matches('_'),
+        fnv1a(s.as_bytes())
+    )
+}
+
+/// 64-bit FNV-1a. Cache paths must not change between builds, which
+/// rules out `std`'s hasher.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+fn db_dir(cache: &Path, location: &Path) -> PathBuf {
+    cache
+        .join("db")
+        .join(cache_key(&location.to_string_lossy()))
+}
+
+/// Where `location` is checked out for `rev`. One checkout per
+/// repository and revision spec, moved in place when the pin moves, so
+/// deployed links keep pointing at the same paths across updates.
+pub fn checkout_dir(cache: &Path, location: &Path, rev: Option<&str>) -> PathBuf {
+    cache
+        .join("checkouts")
+        .join(cache_key(&location.to_string_lossy()))
+        .join(cache_key(rev.unwrap_or("HEAD")))
+}
+
+/// Commit a checkout's detached `HEAD` is at.
+fn checked_out_commit(dir: &Path) -> Option<String> {
+    let head = fs::read_to_string(dir.join(".git").join("HEAD")).ok()?;
+    let head = head.trim();
+    is_commit_id(head).then(|| head.to_string())
+}
+
+/// The checkout of git dependency `name`, declared in `config_dir`, for
+/// discovery: it must be pinned in `lock` and checked out at the pinned
+/// commit. Errors are about the dependency entry; the caller says where
+/// it is.
+pub(crate) fn locked_checkout(
+    config_dir: &Path,
+    lock: &Lock,
+    name: &str,
+    dep: &GitDependency,
+) -> Result<PathBuf, XdError> {
+    let Some(locked) = lock.get(name, dep) else {
+        return Err(XdError::planning(format!(
+            "未在 {} 中锁定或锁定与配置不一致，请运行 xd update-deps",
+            LOCK_FILE
+        ))
+        .with_code(codes::GIT_NOT_LOCKED));
+    };
+    let cache = cache_dir().ok_or_else(|| {
+        XdError::planning("无法确定主目录，找不到 git 依赖缓存".to_string())
+            .with_code(codes::HOME_UNKNOWN)
+    })?;
+    let loc = checked_location(dep, config_dir)?;
+    let dir = checkout_dir(&cache, &loc, dep.rev.as_deref());
+    if checked_out_commit(&dir).as_deref() != Some(locked.commit.as_str()) {
+        return Err(XdError::planning(format!(
+            "没有检出锁定的提交 {} ({})，请运行 xd update-deps --locked",
+            &locked.commit[..12],
+            dir.display()
+        ))
+        .with_code(codes::GIT_NOT_CHECKED_OUT));
+    }
+    dir.canonicalize().map_err(|e| {
+        XdError::planning(format!("无法访问检出 {}: {}", dir.display(), e))
+            .with_code(codes::GIT_NOT_CHECKED_OUT)
+    })
+}
+
+/// [`location`] of `dep`, once its revision is known to be safe to pass
+/// to git as well.
+fn checked_location(dep: &GitDependency, config_dir: &Path) -> Result<PathBuf, XdError> {
+    if let Some(rev) = &dep.rev {
+        check_rev(rev)?;
+    }
+    location(&dep.url, config_dir)
+}
+
+/// How [`update`] treats existing pins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// Re-resolve every revision and move the pins.
+    Bump,
+    /// Keep every pin; only fetch and check out the pinned commits.
+    /// Unpinned dependencies are errors.
+    Locked,
+}
+
+/// One pin written by [`update`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinChange {
+    /// `xdotter.toml` declaring the dependency.
+    pub config_file: PathBuf,
+    /// Dependency name.
+    pub name: String,
+    /// Previous commit, `None` for a new pin.
+    pub old: Option<String>,
+    /// Commit now pinned.
+    pub new: String,
+}
+
+/// Result of [`update`].
+#[derive(Debug, Default)]
+pub struct UpdateReport {
+    /// Pins that moved or were added.
+    pub changes: Vec<PinChange>,
+    /// Git dependencies now checked out at their pins.
+    pub checked_out: usize,
+}
+
+/// Pin and check out every git dependency reachable from `roots`,
+/// following path and git dependencies alike, and rewrite the locks
+/// that changed. Configuration problems other than git dependencies are
+/// left for discovery to report.
+pub fn update(roots: &[PathBuf], mode: UpdateMode) -> Result<UpdateReport, XdError> {
+    let cache = cache_dir()
+        .ok_or_else(|| XdError::cli("无法确定主目录，找不到 git 依赖缓存".to_string()))?;
+    let mut u = Updater {
+        cache,
+        mode,
+        seen: BTreeSet::new(),
+        fetched: BTreeSet::new(),
+        report: UpdateReport::default(),
+        errors: ErrorBag::new(),
+    };
+    for root in roots {
+        match root.canonicalize() {
+            Ok(r) => u.visit(&r),
+            Err(e) => u.errors.push(
+                XdError::planning(format!("无法访问根配置目录 {}: {}", root.display(), e))
+                    .with_code(codes::ROOT_INACCESSIBLE),
+            ),
+        }
+    }
+    if !u.errors.is_empty() {
+        return Err(u.errors.into_error());
+    }
+    Ok(u.report)
+}
+
+struct Updater {
+    cache: PathBuf,
+    mode: UpdateMode,
+    seen: BTreeSet<PathBuf>,
+    /// Mirrors fetched during this run.
+    fetched: BTreeSet<PathBuf>,
+    report: UpdateReport,
+    errors: ErrorBag,
+}
+
+impl Updater {
+    fn visit(&mut self, dir: &Path) {
+        if !self.seen.insert(dir.to_path_buf()) {
+            return;
+        }
+        let toml = dir.join("xdotter.toml");
+        let Ok(content) = fs::read_to_string(&toml) else {
+            return;
+        };
+        let cfg = match Config::from_toml(&content, &toml) {
+            Ok(c) => c,
+            Err(e) => {
+                self.errors.push(e);
+                return;
+            }
+        };
+        for raw in cfg.dependencies.values() {
+            if let Ok(d) = dir.join(raw).canonicalize() {
+                if d.starts_with(dir) && d.join("xdotter.toml").is_file() {
+                    self.visit(&d);
+                }
+            }
+        }
+        if cfg.git_dependencies.is_empty() {
+            return;
+        }
+
+        // Checkouts are shared and moved by other configs' pins; never
+        // write a lock into one.
+        let writable = self.mode == UpdateMode::Bump && !dir.starts_with(&self.cache);
+        let lock = match Lock::load(dir) {
+            Ok(l) => l,
+            Err(e) => {
+                self.errors.push(e);
+                return;
+            }
+        };
+        let mut next = Lock::default();
+        for (name, dep) in &cfg.git_dependencies {
+            let pinned = if writable { None } else { lock.get(name, dep) };
+            match self.pin(dir, dep, pinned) {
+                Ok((commit, checkout)) => {
+                    let old = lock.git.get(name).map(|l| l.commit.clone());
+                    if old.as_deref() != Some(commit.as_str()) {
+                        self.report.changes.push(PinChange {
+                            config_file: toml.clone(),
+                            name: name.clone(),
+                            old,
+                            new: commit.clone(),
+                        });
+                    }
+                    next.git.insert(
+                        name.clone(),
+                        LockedGit {
+                            url: dep.url.clone(),
+                            rev: dep.rev.clone(),
+                            commit,
+                        },
+                    );
+                    self.report.checked_out += 1;
+                    self.visit(&checkout);
+                }
+                Err(e) => {
+                    // Keep the old pin rather than dropping it.
+                    if let Some(l) = lock.git.get(name) {
+                        next.git.insert(name.clone(), l.clone());
+                    }
+                    let at = cfg.dependency_spans.get(name).map(|s| &s.value);
+                    let ctx = format!("依赖 \"{name}\"");
+                    self.errors.push(decorate(&e, &toml, Some(&ctx), at));
+                }
+            }
+        }
+        if writable && next != lock {
+            if let Err(e) = next.save(dir) {
+                self.errors.push(e);
+            }
+        }
+    }
+
+    /// Commit for `dep` — `pinned`, or freshly resolved when `None` and
+    /// the lock may be written — and its checkout at that commit.
+    fn pin(
+        &mut self,
+        dir: &Path,
+        dep: &GitDependency,
+        pinned: Option<&LockedGit>,
+    ) -> Result<(String, PathBuf), XdError> {
+        let loc = checked_location(dep, dir)?;
+        let db = db_dir(&self.cache, &loc);
+        let commit = match pinned {
+            Some(l) => {
+                if !has_commit(&db, &l.commit) {
+                    self.fetch(&loc, &db)?;
+                }
+                if !has_commit(&db, &l.commit) {
+                    return Err(XdError::apply(format!(
+                        "锁定的提交 {} 在 {} 中不存在",
+                        l.commit, dep.url
+                    ))
+                    .with_code(codes::GIT_REVISION_MISSING));
+                }
+                l.commit.clone()
+            }
+            None if self.mode == UpdateMode::Locked || dir.starts_with(&self.cache) => {
+                return Err(XdError::planning(format!(
+                    "未在 {} 中锁定{}",
+                    LOCK_FILE,
+                    if dir.starts_with(&self.cache) {
+                        "；该仓库必须提交自己的锁文件"
+                    } else {
+                        "；去掉 --locked 以写入锁文件"
+                    }
+                ))
+                .with_code(codes::GIT_NOT_LOCKED));
+            }
+            None => {
+                self.fetch(&loc, &db)?;
+                let rev = dep.rev.as_deref().unwrap_or("HEAD");
+                let spec = format!("{}^{{commit}}", rev);
+                git([
+                    OsStr::new("--git-dir"),
+                    db.as_os_str(),
+                    OsStr::new("rev-parse"),
+                    OsStr::new("--verify"),
+                    OsStr::new("--quiet"),
+                    OsStr::new(&spec),
+                ])
+                .map(|out| out.trim().to_string())
+                .map_err(|_| {
+                    XdError::apply(format!("修订 {} 在 {} 中不存在", rev, dep.url))
+                        .with_code(codes::GIT_REVISION_MISSING)
+                })?
+            }
+        };
+        let checkout = checkout_dir(&self.cache, &loc, dep.rev.as_deref());
+        if checked_out_commit(&checkout).as_deref() != Some(commit.as_str()) {
+            check_out(&db, &checkout, &commit)?;
+        }
+        let checkout = checkout.canonicalize().map_err(|e| {
+            XdError::apply(format!("无法访问检出 {}: {}", checkout.display(), e))
+                .with_code(codes::FS_OPERATION_FAILED)
+        })?;
+        Ok((commit, checkout))
+    }
+
+    /// Clone or refresh the bare mirror `db` of `loc`, once per run.
+    fn fetch(&mut self, loc: &Path, db: &Path) -> Result<(), XdError> {
+        if !self.fetched.insert(db.to_path_buf()) {
+            return Ok(());
+        }
+        if db.exists() {
+            git([
+                OsStr::new("--git-dir"),
+                db.as_os_str(),
+                OsStr::new("fetch"),
+                OsStr::new("--quiet"),
+                OsStr::new("--force"),
+                OsStr::new("--prune"),
+                OsStr::new("--"),
+                loc.as_os_str(),
+                OsStr::new("+refs/heads/*:refs/heads/*"),
+                OsStr::new("+refs/tags/*:refs/tags/*"),
+            ])?;
+        } else {
+            create_parent(db)?;
+            git([
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--bare"),
+                OsStr::new("--"),
+                loc.as_os_str(),
+                db.as_os_str(),
+            ])?;
+        }
+        Ok(())
+    }
+}
+
+fn has_commit(db: &Path, commit: &str) -> bool {
+    let spec = format!("{}^{{commit}}", commit);
+    db.exists()
+        && git([
+            OsStr::new("--git-dir"),
+            db.as_os_str(),
+            OsStr::new("cat-file"),
+            OsStr::new("-e"),
+            OsStr::new(&spec),
+        ])
+        .is_ok()
+}
+
+/// Move `checkout` (cloning it from `db` if needed) to `commit`.
+fn check_out(db: &Path, checkout: &Path, commit: &str) -> Result<(), XdError> {
+    if checkout.join(".git").is_dir() {
+        git([
+            OsStr::new("-C"),
+            checkout.as_os_str(),
+            OsStr::new("fetch"),
+            OsStr::new("--quiet"),
+            OsStr::new("origin"),
+        ])?;
+    } else {
+        create_parent(checkout)?;
+        git([
+            OsStr::new("clone"),
+            OsStr::new("--quiet"),
+            OsStr::new("--no-checkout"),
+            OsStr::new("--"),
+            db.as_os_str(),
+            checkout.as_os_str(),
+        ])?;
+    }
+    git([
+        OsStr::new("-C"),
+        checkout.as_os_str(),
+        OsStr::new("checkout"),
+        OsStr::new("--quiet"),
+        OsStr::new("--force"),
+        OsStr::new("--detach"),
+        OsStr::new(commit),
+        OsStr::new("--"),
+    ])?;
+    Ok(())
+}
+
+fn create_parent(p: &Path) -> Result<(), XdError> {
+    match p.parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(|e| {
+            XdError::apply(format!("创建目录 {} 失败: {}", parent.display(), e))
+                .with_code(codes::FS_OPERATION_FAILED)
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Commit checked out in the git work tree containing `dir`; `None`
+/// outside a work tree, before the first commit, or without git.
+pub fn head(dir: &Path) -> Option<String> {
+    git([
+        OsStr::new("-C"),
+        dir.as_os_str(),
+        OsStr::new("rev-parse"),
+        OsStr::new("--verify"),
+        OsStr::new("--quiet"),
+        OsStr::new("HEAD"),
+    ])
+    .ok()
+    .map(|out| out.trim().to_string())
+    .filter(|c| !c.is_empty())
+}
+
+/// Run git non-interactively, returning its stdout.
+fn git<I, S>(args: I) -> Result<String, XdError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<_> = args.into_iter().map(|a| a.as_ref().to_owned()).collect();
+    let shown = args
+        .iter()
+        .map(|a| a.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    logging::log("gitdeps", Level::Debug, || format!("git {}", shown));
+    let out = Command::new("git")
+        .args(&args)
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| {
+            XdError::apply(format!("无法运行 git {}: {}", shown, e)).with_code(codes::GIT_FAILED)
+        })?;
+    if !out.status.success() {
+        return Err(XdError::apply(format!(
+            "git {} 失败: {}",
+            shown,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))
+        .with_code(codes::GIT_FAILED));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_round_trips_and_rejects_short_commits() {
+        let c = "0123456789abcdef0123456789abcdef01234567";
+        let mut lock = Lock::default();
+        lock.git.insert(
+            "team".to_string(),
+            LockedGit {
+                url: "../team".to_string(),
+                rev: Some("main".to_string()),
+                commit: c.to_string(),
+            },
+        );
+        let text = basic_toml::to_string(&lock).unwrap();
+        assert_eq!(Lock::from_toml(&text, Path::new("l")).unwrap(), lock);
+
+        let dep = GitDependency {
+            url: "../team".to_string(),
+            rev: Some("main".to_string()),
+        };
+        assert!(lock.get("team", &dep).is_some());
+        let moved = GitDependency { rev: None, ..dep };
+        assert!(lock.get("team", &moved).is_none());
+
+        let bad = "[git.team]\nurl = \"x\"\ncommit = \"../../etc\"\n";
+        assert!(Lock::from_toml(bad, Path::new("l"))
+            .unwrap_err()
+            .is_config());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn only_local_locations_are_accepted() {
+        let dir = Path::new("/repo");
+        assert_eq!(
+            location("file:///srv/team.git", dir).unwrap(),
+            Path::new("/srv/team.git")
+        );
+        assert_eq!(
+            location("vendor/team", dir).unwrap(),
+            Path::new("/repo/vendor/team")
+        );
+        for bad in [
+            "https://example.com/x.git",
+            "git@example.com:x.git",
+            "ssh://h/x",
+        ] {
+            assert!(location(bad, dir).unwrap_err().is_config(), "{bad}");
+        }
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn checkouts_are_keyed_by_location_and_rev() {
+        let cache = Path::new("/c");
+        let a = checkout_dir(cache, Path::new("/srv/team.git"), None);
+        let b = checkout_dir(cache, Path::new("/srv/team.git"), Some("release/2"));
+        assert_eq!(a.parent(), b.parent());
+        assert!(a.starts_with("/c/checkouts"), "{}", a.display());
+        let name = |p: &Path| p.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name(a.parent().unwrap()).starts_with("team_git-"));
+        assert!(name(&a).starts_with("HEAD-"));
+        assert!(name(&b).starts_with("2-"));
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn keys_never_merge_names_differing_in_punctuation() {
+        let cache = Path::new("/c");
+        assert_ne!(
+            db_dir(cache, Path::new("/srv/a-b")),
+            db_dir(cache, Path::new("/srv/a_b"))
+        );
+        let loc = Path::new("/srv/team.git");
+        assert_ne!(
+            checkout_dir(cache, loc, Some("v1.0")),
+            checkout_dir(cache, loc, Some("v1_0"))
+        );
+        // Stable across builds: checkouts are where deployed links point.
+        assert_eq!(cache_key("/srv/a-b"), "a_b-13f199b95460b7a4");
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn arguments_git_would_read_as_options_are_rejected() {
+        let dir = Path::new("/repo");
+        for bad in ["file://--upload-pack=touch /tmp/x;false", "--upload-pack=x"] {
+            assert!(location(bad, dir).unwrap_err().is_config(), "{bad}");
+        }
+        assert!(check_rev("--output=/tmp/x").unwrap_err().is_config());
+        assert!(check_rev("v1.0").is_ok());
+
+        let c = "0123456789abcdef0123456789abcdef01234567";
+        for entry in [
+            format!("url = \"--upload-pack=x\"\ncommit = \"{c}\""),
+            format!("url = \"../team\"\nrev = \"-x\"\ncommit = \"{c}\""),
+        ] {
+            let text = format!("[git.team]\n{entry}\n");
+            let e = Lock::from_toml(&text, Path::new("l")).unwrap_err();
+            assert!(
+                e.is_config() && e.to_string().contains("不能以 - 开头"),
+                "{e}"
+            );
+        }
+    }
+}
@@ -0,0 +1,644 @@
+//! Append-only audit log of apply-stage changes, per SPEC §"操作历史".
+//!
+//! Every live deploy, undeploy and watch run appends one [`Entry`] per
+//! filesystem change to `~/.local/state/xdotter/history.toml`. The
+//! entries of an action are written as soon as the action is done, so
+//! the log is complete up to the point where a run was interrupted.
+//! Dry-runs change nothing and record nothing. The file is TOML and
+//! xdotter never rewrites it:
+//!
+//! ```toml
+//! [[entry]]
+//! time = "2026-10-19T08:15:30.123Z"
+//! command = "deploy"
+//! mode = "force"
+//! op = "remove"
+//! path = "/home/me/.ssh/config"
+//! previous = "file 644"
+//! link = "/home/me/.ssh/config"
+//! source = "/home/me/dotfiles/ssh/config"
+//! config_file = "/home/me/dotfiles/xdotter.toml"
+//! revision = "3f2a9c1d2e4b5a6978695a4b3c2d1e0f9a8b7c6d"
+//! ```
+//!
+//! Apply is pointed at an [`AuditFs`], which notes each change and the
+//! state it replaced; a [`Recorder`] turns the changes of each action
+//! into entries.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self as stdfs, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes;
+use crate::error::XdError;
+use crate::fs::{Change, Dir, FileType, Fs, Metadata};
+use crate::gitdeps;
+use crate::logging;
+use crate::plan::{ConflictModeRecord, DeployAction, UndeployAction};
+
+/// One filesystem change made by an apply stage.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// UTC RFC 3339 time, with milliseconds.
+    pub time: String,
+    /// `deploy`, `undeploy` or `watch`.
+    pub command: String,
+    /// Conflict mode of the run: `default`, `force` or `interactive`.
+    pub mode: String,
+    /// `mkdir`, `symlink`, `rename`, `exchange`, `remove`, `rmdir` or
+    /// `chmod`.
+    pub op: String,
+    /// The path changed.
+    pub path: PathBuf,
+    /// For `rename`, the old path of what now is at `path`; for
+    /// `exchange`, the path `path` swapped with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<PathBuf>,
+    /// Target of the symlink created, verbatim.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+    /// Permission bits set, in octal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<String>,
+    /// What was at `path` before: `missing`, `file 644`, `dir 755`,
+    /// `symlink -> TARGET` or `other`.
+    pub previous: String,
+    /// Link path of the action that made the change.
+    pub link: PathBuf,
+    /// Source of the link, when it resolved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// Configuration declaring the link.
+    pub config_file: PathBuf,
+    /// Commit checked out in the configuration directory, when it is in
+    /// a git work tree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct RawHistory {
+    #[serde(default)]
+    entry: Vec<Entry>,
+}
+
+/// `~/.local/state/xdotter/history.toml`, or `None` without a home
+/// directory.
+pub fn default_path() -> Option<PathBuf> {
+    crate::path::home_dir().map(|h| {
+        h.join(".local")
+            .join("state")
+            .join("xdotter")
+            .join("history.toml")
+    })
+}
+
+/// Entries of the history file at `path`, oldest first; a missing file
+/// is an empty history.
+pub fn load(path: &Path) -> Result<Vec<Entry>, XdError> {
+    match stdfs::read_to_string(path) {
+        Ok(content) => parse(&content, path),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            Ok(Vec::new())
+        }
+        Err(e) => Err(XdError::config(format!(
+            "无法读取操作历史 {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+/// Parse history text.
+pub fn parse(content: &str, source: &Path) -> Result<Vec<Entry>, XdError> {
+    let raw: RawHistory = basic_toml::from_str(content)
+        .map_err(|e| XdError::config(format!("{}: 操作历史解析失败: {}", source.display(), e)))?;
+    Ok(raw.entry)
+}
+
+/// A change made through an [`AuditFs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mutation {
+    /// What changed.
+    pub change: Change,
+    /// What was at the changed path before, as in [`Entry::previous`].
+    pub previous: String,
+}
+
+/// A filesystem that forwards to `base` and notes every successful
+/// modification with the state it replaced.
+pub struct AuditFs<'a> {
+    base: &'a dyn Fs,
+    mutations: RefCell<Vec<Mutation>>,
+}
+
+impl<'a> AuditFs<'a> {
+    /// Audit changes made to `base`.
+    pub fn new(base: &'a dyn Fs) -> Self {
+        Self {
+            base,
+            mutations: RefCell::default(),
+        }
+    }
+
+    /// Modifications made since the last call, in order.
+    pub fn take(&self) -> Vec<Mutation> {
+        std::mem::take(&mut self.mutations.borrow_mut())
+    }
+
+    fn note(&self, change: Change, previous: String) {
+        self.mutations
+            .borrow_mut()
+            .push(Mutation { change, previous });
+    }
+
+    fn state(&self, p: &Path, m: io::Result<Metadata>) -> String {
+        state(m, || self.base.read_link(p))
+    }
+
+    fn before(&self, p: &Path) -> String {
+        self.state(p, self.base.symlink_metadata(p))
+    }
+}
+
+/// `missing`, `file 644`, `symlink -> T` and so on, for
+/// [`Entry::previous`].
+fn state(m: io::Result<Metadata>, read_link: impl FnOnce() -> io::Result<PathBuf>) -> String {
+    match m {
+        Ok(m) => match m.file_type() {
+            FileType::File => format!("file {:o}", m.mode()),
+            FileType::Dir => format!("dir {:o}", m.mode()),
+            FileType::Symlink => match read_link() {
+                Ok(t) => format!("symlink -> {}", t.display()),
+                Err(_) => "symlink".to_string(),
+            },
+            FileType::Other => "other".to_string(),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => "missing".to_string(),
+        Err(e) => format!("unreadable ({})", e),
+    }
+}
+
+impl Fs for AuditFs<'_> {
+    fn symlink_metadata(&self, p: &Path) -> io::Result<Metadata> {
+        self.base.symlink_metadata(p)
+    }
+    fn metadata(&self, p: &Path) -> io::Result<Metadata> {
+        self.base.metadata(p)
+    }
+    fn read_link(&self, p: &Path) -> io::Result<PathBuf> {
+        self.base.read_link(p)
+    }
+    fn canonicalize(&self, p: &Path) -> io::Result<PathBuf> {
+        self.base.canonicalize(p)
+    }
+    fn read_dir(&self, p: &Path) -> io::Result<Vec<PathBuf>> {
+        self.base.read_dir(p)
+    }
+    fn create_dir_all(&self, p: &Path) -> io::Result<()> {
+        let absent = |a: &Path| {
+            let m = self.base.symlink_metadata(a);
+            matches!(m, Err(e) if e.kind() == io::ErrorKind::NotFound)
+        };
+        let missing: Vec<PathBuf> = p
+            .ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .take_while(|a| absent(a))
+            .map(Path::to_path_buf)
+            .collect();
+        self.base.create_dir_all(p)?;
+        for dir in missing.into_iter().rev() {
+            self.note(Change::CreateDir(dir), "missing".to_string());
+        }
+        Ok(())
+    }
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let previous = self.before(link);
+        self.base.symlink(target, link)?;
+        self.note(
+            Change::Symlink {
+                link: link.to_path_buf(),
+                target: target.to_path_buf(),
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn remove_file(&self, p: &Path) -> io::Result<()> {
+        let previous = self.before(p);
+        self.base.remove_file(p)?;
+        self.note(Change::RemoveFile(p.to_path_buf()), previous);
+        Ok(())
+    }
+    fn remove_dir(&self, p: &Path) -> io::Result<()> {
+        let previous = self.before(p);
+        self.base.remove_dir(p)?;
+        self.note(Change::RemoveDir(p.to_path_buf()), previous);
+        Ok(())
+    }
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let previous = self.before(to);
+        self.base.rename(from, to)?;
+        self.note(
+            Change::Rename {
+                from: from.to_path_buf(),
+                to: to.to_path_buf(),
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()> {
+        let previous = self.before(b);
+        self.base.exchange(a, b)?;
+        self.note(
+            Change::Exchange {
+                from: a.to_path_buf(),
+                to: b.to_path_buf(),
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn set_permissions(&self, p: &Path, mode: u32) -> io::Result<()> {
+        // The change applies to what `p` resolves to.
+        let previous = self.state(p, self.base.metadata(p));
+        self.base.set_permissions(p, mode)?;
+        self.note(
+            Change::SetPermissions {
+                path: p.to_path_buf(),
+                mode,
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn open_dir(&self, p: &Path) -> io::Result<Box<dyn Dir + '_>> {
+        Ok(Box::new(AuditDir {
+            audit: self,
+            dir: self.base.open_dir(p)?,
+        }))
+    }
+}
+
+/// A [`Dir`] of an [`AuditFs`]: forwards to the base's handle and notes
+/// changes under the entry's path.
+struct AuditDir<'a> {
+    audit: &'a AuditFs<'a>,
+    dir: Box<dyn Dir + 'a>,
+}
+
+impl AuditDir<'_> {
+    fn entry(&self, name: &OsStr) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn before(&self, name: &OsStr) -> String {
+        state(self.dir.symlink_metadata(name), || self.dir.read_link(name))
+    }
+}
+
+impl Dir for AuditDir<'_> {
+    fn path(&self) -> &Path {
+        self.dir.path()
+    }
+    fn symlink_metadata(&self, name: &OsStr) -> io::Result<Metadata> {
+        self.dir.symlink_metadata(name)
+    }
+    fn read_link(&self, name: &OsStr) -> io::Result<PathBuf> {
+        self.dir.read_link(name)
+    }
+    fn open_dir(&self, name: &OsStr) -> io::Result<Box<dyn Dir + '_>> {
+        Ok(Box::new(AuditDir {
+            audit: self.audit,
+            dir: self.dir.open_dir(name)?,
+        }))
+    }
+    fn symlink(&self, target: &Path, name: &OsStr) -> io::Result<()> {
+        let previous = self.before(name);
+        self.dir.symlink(target, name)?;
+        self.audit.note(
+            Change::Symlink {
+                link: self.entry(name),
+                target: target.to_path_buf(),
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn remove_file(&self, name: &OsStr) -> io::Result<()> {
+        let previous = self.before(name);
+        self.dir.remove_file(name)?;
+        self.audit
+            .note(Change::RemoveFile(self.entry(name)), previous);
+        Ok(())
+    }
+    fn remove_dir(&self, name: &OsStr) -> io::Result<()> {
+        let previous = self.before(name);
+        self.dir.remove_dir(name)?;
+        self.audit
+            .note(Change::RemoveDir(self.entry(name)), previous);
+        Ok(())
+    }
+    fn rename(&self, from: &OsStr, to: &OsStr) -> io::Result<()> {
+        let previous = self.before(to);
+        self.dir.rename(from, to)?;
+        self.audit.note(
+            Change::Rename {
+                from: self.entry(from),
+                to: self.entry(to),
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn exchange(&self, a: &OsStr, b: &OsStr) -> io::Result<()> {
+        let previous = self.before(b);
+        self.dir.exchange(a, b)?;
+        self.audit.note(
+            Change::Exchange {
+                from: self.entry(a),
+                to: self.entry(b),
+            },
+            previous,
+        );
+        Ok(())
+    }
+    fn set_permissions(&self, name: &OsStr, mode: u32) -> io::Result<()> {
+        // Symlinks are refused, so the entry itself is what changes.
+        let previous = self.before(name);
+        self.dir.set_permissions(name, mode)?;
+        self.audit.note(
+            Change::SetPermissions {
+                path: self.entry(name),
+                mode,
+            },
+            previous,
+        );
+        Ok(())
+    }
+}
+
+/// Appends the entries of one run to a history file.
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+    command: &'static str,
+    mode: &'static str,
+    /// `HEAD` per configuration directory, looked up once per run.
+    revisions: HashMap<PathBuf, Option<String>>,
+}
+
+impl Recorder {
+    /// Open the history at `path` for appending, creating it and its
+    /// directory, for a run of `command` in `mode`. Fails before
+    /// anything is applied when the history cannot be written.
+    pub fn open(
+        path: &Path,
+        command: &'static str,
+        mode: ConflictModeRecord,
+    ) -> Result<Self, XdError> {
+        let unwritable = |e: io::Error| {
+            XdError::apply(format!("无法写入操作历史 {}: {}", path.display(), e))
+                .with_code(codes::HISTORY_UNWRITABLE)
+        };
+        if let Some(parent) = path.parent() {
+            stdfs::create_dir_all(parent).map_err(unwritable)?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(unwritable)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            command,
+            mode: if mode.force {
+                "force"
+            } else if mode.interactive {
+                "interactive"
+            } else {
+                "default"
+            },
+            revisions: HashMap::new(),
+        })
+    }
+
+    /// Record the changes `act` made.
+    pub fn deploy(&mut self, act: &DeployAction, mutations: Vec<Mutation>) -> Result<(), XdError> {
+        self.append(
+            &act.link_expanded,
+            Some(&act.source_canonical),
+            &act.config_file,
+            &act.config_dir,
+            mutations,
+        )
+    }
+
+    /// Record the changes `act` made.
+    pub fn undeploy(
+        &mut self,
+        act: &UndeployAction,
+        mutations: Vec<Mutation>,
+    ) -> Result<(), XdError> {
+        let config_dir = act.config_file.parent().unwrap_or(Path::new(""));
+        self.append(
+            &act.link_expanded,
+            act.source_canonical.as_deref(),
+            &act.config_file,
+            config_dir,
+            mutations,
+        )
+    }
+
+    fn append(
+        &mut self,
+        link: &Path,
+        source: Option<&Path>,
+        config_file: &Path,
+        config_dir: &Path,
+        mutations: Vec<Mutation>,
+    ) -> Result<(), XdError> {
+        if mutations.is_empty() {
+            return Ok(());
+        }
+        let revision = self
+            .revisions
+            .entry(config_dir.to_path_buf())
+            .or_insert_with(|| gitdeps::head(config_dir))
+            .clone();
+        let time = logging::timestamp(SystemTime::now());
+        let entry = mutations
+            .into_iter()
+            .map(|m| {
+                let (mut from, mut target, mut permissions) = (None, None, None);
+                let (op, path) = match m.change {
+                    Change::CreateDir(p) => ("mkdir", p),
+                    Change::Symlink { link, target: t } => {
+                        target = Some(t);
+                        ("symlink", link)
+                    }
+                    Change::Rename { from: f, to } => {
+                        from = Some(f);
+                        ("rename", to)
+                    }
+                    Change::Exchange { from: f, to } => {
+                        from = Some(f);
+                        ("exchange", to)
+                    }
+                    Change::RemoveFile(p) => ("remove", p),
+                    Change::RemoveDir(p) => ("rmdir", p),
+                    Change::SetPermissions { path, mode } => {
+                        permissions = Some(format!("{:o}", mode));
+                        ("chmod", path)
+                    }
+                };
+                Entry {
+                    time: time.clone(),
+                    command: self.command.to_string(),
+                    mode: self.mode.to_string(),
+                    op: op.to_string(),
+                    path,
+                    from,
+                    target,
+                    permissions,
+                    previous: m.previous,
+                    link: link.to_path_buf(),
+                    source: source.map(Path::to_path_buf),
+                    config_file: config_file.to_path_buf(),
+                    revision: revision.clone(),
+                }
+            })
+            .collect();
+        let unwritable = |e: String| {
+            XdError::apply(format!("无法写入操作历史 {}: {}", self.path.display(), e))
+                .with_code(codes::HISTORY_UNWRITABLE)
+        };
+        let text =
+            basic_toml::to_string(&RawHistory { entry }).map_err(|e| unwritable(e.to_string()))?;
+        // One write per action: concurrent runs append whole actions.
+        self.file
+            .write_all(format!("\n{}", text).as_bytes())
+            .map_err(|e| unwritable(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::MemFs;
+    use crate::plan::DeployActionKind;
+
+    #[test]
+    fn audited_changes_note_what_they_replaced() {
+        let fs = MemFs::new();
+        fs.add_file("/h/.vimrc", 0o644);
+        fs.add_file("/r/vimrc", 0o644);
+        let audit = AuditFs::new(&fs);
+        audit.remove_file(Path::new("/h/.vimrc")).unwrap();
+        audit
+            .symlink(Path::new("/r/vimrc"), Path::new("/h/.vimrc"))
+            .unwrap();
+        audit.create_dir_all(Path::new("/h/.config/nvim")).unwrap();
+        audit
+            .set_permissions(Path::new("/h/.vimrc"), 0o600)
+            .unwrap();
+        assert!(audit.remove_file(Path::new("/h/nothing")).is_err());
+
+        let previous: Vec<_> = audit
+            .take()
+            .into_iter()
+            .map(|m| (m.change, m.previous))
+            .collect();
+        assert_eq!(
+            previous,
+            [
+                (Change::RemoveFile("/h/.vimrc".into()), "file 644".into()),
+                (
+                    Change::Symlink {
+                        link: "/h/.vimrc".into(),
+                        target: "/r/vimrc".into()
+                    },
+                    "missing".into()
+                ),
+                (Change::CreateDir("/h/.config".into()), "missing".into()),
+                (
+                    Change::CreateDir("/h/.config/nvim".into()),
+                    "missing".into()
+                ),
+                (
+                    Change::SetPermissions {
+                        path: "/h/.vimrc".into(),
+                        mode: 0o600
+                    },
+                    "file 644".into()
+                ),
+            ]
+        );
+        assert!(audit.take().is_empty());
+    }
+
+    #[test]
+    fn recorded_entries_append_and_load_back() {
+        let dir = std::env::temp_dir().join(format!("xd-history-{}", std::process::id()));
+        let path = dir.join("state").join("history.toml");
+        let act = DeployAction {
+            config_file: dir.join("xdotter.toml"),
+            config_dir: dir.clone(),
+            source_raw: "ssh".into(),
+            source_canonical: dir.join("ssh"),
+            link_raw: "~/.ssh/config".into(),
+            link_expanded: "/h/.ssh/config".into(),
+            relative: false,
+            kind: DeployActionKind::Create,
+            permission_required: None,
+            permission_action: crate::plan::PermissionAction::None,
+        };
+        let created = Mutation {
+            change: Change::Symlink {
+                link: "/h/.ssh/config".into(),
+                target: dir.join("ssh"),
+            },
+            previous: "missing".into(),
+        };
+        for mode in [
+            ConflictModeRecord::default(),
+            ConflictModeRecord {
+                force: true,
+                interactive: false,
+            },
+        ] {
+            let mut rec = Recorder::open(&path, "deploy", mode).unwrap();
+            rec.deploy(&act, vec![]).unwrap();
+            rec.deploy(&act, vec![created.clone()]).unwrap();
+        }
+
+        let entries = load(&path).unwrap();
+        let _ = stdfs::remove_dir_all(&dir);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            (entries[0].mode.as_str(), entries[1].mode.as_str()),
+            ("default", "force")
+        );
+        let e = &entries[1];
+        assert_eq!((e.command.as_str(), e.op.as_str()), ("deploy", "symlink"));
+        assert_eq!(e.path, Path::new("/h/.ssh/config"));
+        assert_eq!(e.target.as_deref(), Some(dir.join("ssh").as_path()));
+        assert_eq!(e.previous, "missing");
+        assert_eq!(e.permissions, None);
+        assert!(e.time.ends_with('Z'), "{}", e.time);
+        assert!(load(&dir.join("absent.toml")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! Gitignore-style pattern lists, for lint rules that need to know what
+//! a repository does not track.
+//!
+//! Supported syntax: `#` comments, `!` negation, a trailing `/` for
+//! directories only, a leading or inner `/` anchoring the pattern to the
+//! file's directory, and the globs `*`, `?`, `[...]` and `**`. As in
+//! git, the last matching pattern wins and nothing below an ignored
+//! directory can be re-included.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+/// Patterns of one ignore file, relative to its directory.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct IgnoreList {
+    patterns: Vec<Pattern>,
+}
+
+impl IgnoreList {
+    pub(crate) fn parse(text: &str) -> Self {
+        let mut patterns = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.strip_prefix('/').unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            patterns.push(Pattern {
+                glob: line.chars().collect(),
+                negated,
+                dir_only,
+                anchored,
+            });
+        }
+        Self { patterns }
+    }
+
+    /// True when the last pattern matching `rel` ignores it.
+    pub(crate) fn is_match(&self, rel: &str, is_dir: bool) -> bool {
+        self.verdict(rel, is_dir) == Some(true)
+    }
+
+    /// Verdict of the last pattern matching `rel` (a `/`-separated path
+    /// relative to the list's directory): `Some(true)` ignored,
+    /// `Some(false)` re-included, `None` when no pattern matches.
+    fn verdict(&self, rel: &str, is_dir: bool) -> Option<bool> {
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        self.patterns
+            .iter()
+            .rev()
+            .find(|p| {
+                (!p.dir_only || is_dir)
+                    && if p.anchored {
+                        glob(&p.glob, &rel.chars().collect::<Vec<_>>())
+                    } else {
+                        glob(&p.glob, &name.chars().collect::<Vec<_>>())
+                    }
+            })
+            .map(|p| !p.negated)
+    }
+}
+
+/// Lazily loaded ignore files named `file_name` in every directory
+/// between a top directory and the paths asked about.
+pub(crate) struct Ignores {
+    file_name: &'static str,
+    lists: HashMap<PathBuf, IgnoreList>,
+}
+
+impl Ignores {
+    pub(crate) fn new(file_name: &'static str) -> Self {
+        Self {
+            file_name,
+            lists: HashMap::new(),
+        }
+    }
+
+    fn list(&mut self, dir: &Path) -> &IgnoreList {
+        let file_name = self.file_name;
+        self.lists.entry(dir.to_path_buf()).or_insert_with(|| {
+            fs::read_to_string(dir.join(file_name))
+                .map(|t| IgnoreList::parse(&t))
+                .unwrap_or_default()
+        })
+    }
+
+    /// True when `path` (below `top`) or one of its parent directories
+    /// is ignored by the files in `top` and the directories below it.
+    pub(crate) fn is_ignored(&mut self, top: &Path, path: &Path, is_dir: bool) -> bool {
+        let Ok(rel) = path.strip_prefix(top) else {
+            return false;
+        };
+        let parts: Vec<String> = rel
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        // Check every prefix, outermost first: an ignored directory hides
+        // everything below it.
+        for end in 1..=parts.len() {
+            let prefix_is_dir = end < parts.len() || is_dir;
+            let mut ignored = false;
+            for start in 0..end {
+                let dir = parts[..start]
+                    .iter()
+                    .fold(top.to_path_buf(), |d, p| d.join(p));
+                let rel = parts[start..end].join("/");
+                if let Some(v) = self.list(&dir).verdict(&rel, prefix_is_dir) {
+                    ignored = v;
+                }
+            }
+            if ignored {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Match `text` against a glob where `*` and `?` stop at `/` and `**`
+/// crosses it.
+fn glob(pat: &[char], text: &[char]) -> bool {
+    match pat.first() {
+        None => text.is_empty(),
+        Some('*') if pat.get(1) == Some(&'*') => {
+            let rest = &pat[2..];
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|i| glob(rest, &text[i..]))
+        }
+        Some('*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob(&pat[1..], &text[i..])),
+        Some('?') => text.first().is_some_and(|&c| c != '/') && glob(&pat[1..], &text[1..]),
+        Some('[') => {
+            let Some(close) = pat.iter().skip(2).position(|&c| c == ']').map(|i| i + 2) else {
+                return text.first() == Some(&'[') && glob(&pat[1..], &text[1..]);
+            };
+            let Some(&c) = text.first() else {
+                return false;
+            };
+            let class = &pat[1..close];
+            let (negate, class) = match class.first() {
+                Some('!') | Some('^') => (true, &class[1..]),
+                _ => (false, class),
+            };
+            let mut hit = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    hit |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    hit |= class[i] == c;
+                    i += 1;
+                }
+            }
+            hit != negate && c != '/' && glob(&pat[close + 1..], &text[1..])
+        }
+        Some(&p) => text.first() == Some(&p) && glob(&pat[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(list: &str, rel: &str, is_dir: bool) -> Option<bool> {
+        IgnoreList::parse(list).verdict(rel, is_dir)
+    }
+
+    #[test]
+    fn patterns_follow_gitignore_rules() {
+        assert_eq!(ignored("*.log\n", "a/b/x.log", false), Some(true));
+        assert_eq!(ignored("/build\n", "build", true), Some(true));
+        assert_eq!(ignored("/build\n", "sub/build", true), None);
+        assert_eq!(ignored("out/\n", "out", false), None);
+        assert_eq!(ignored("out/\n", "out", true), Some(true));
+        assert_eq!(
+            ignored("*.log\n!keep.log\n", "keep.log", false),
+            Some(false)
+        );
+        assert_eq!(
+            ignored("doc/**/*.tmp\n", "doc/a/b/x.tmp", false),
+            Some(true)
+        );
+        assert_eq!(ignored("doc/**/*.tmp\n", "doc/x.tmp", false), Some(true));
+        assert_eq!(ignored("a/*.c\n", "a/b/x.c", false), None);
+        assert_eq!(ignored("file[0-9].txt\n", "file7.txt", false), Some(true));
+        assert_eq!(ignored("file[!0-9].txt\n", "file7.txt", false), None);
+        assert_eq!(ignored("# c\n\n", "x", false), None);
+    }
+
+    #[test]
+    fn nested_files_and_ignored_parents() {
+        let top = std::env::temp_dir().join(format!("xd_ignore_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&top);
+        fs::create_dir_all(top.join("sub")).unwrap();
+        fs::write(top.join(".gitignore"), "target/\n*.bak\n").unwrap();
+        fs::write(top.join("sub/.gitignore"), "!x.bak\nlocal\n").unwrap();
+        let mut ig = Ignores::new(".gitignore");
+        assert!(ig.is_ignored(&top, &top.join("target/debug/xd"), false));
+        assert!(ig.is_ignored(&top, &top.join("a.bak"), false));
+        assert!(!ig.is_ignored(&top, &top.join("sub/x.bak"), false));
+        assert!(ig.is_ignored(&top, &top.join("sub/local"), false));
+        assert!(!ig.is_ignored(&top, &top.join("local"), false));
+        assert!(!ig.is_ignored(&top, Path::new("/elsewhere"), false));
+        let _ = fs::remove_dir_all(&top);
+    }
+}
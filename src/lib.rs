@@ -0,0 +1,97 @@
+//! xdotter as a library: discovery, planning and apply for symlink-based
+//! dotfile management, the same engine the `xd` binary drives.
+//!
+//! The lifecycle mirrors SPEC §"执行模型" and is split into three
+//! stages, each a module:
+//!
+//! 1. [`discover`](mod@discover) walks `xdotter.toml` and its
+//!    `[dependencies]` into a [`Discovered`](discover::Discovered) set of
+//!    configurations.
+//! 2. [`plan`] validates every link against the filesystem and produces
+//!    a [`DeployPlan`](plan::DeployPlan), an
+//!    [`UndeployPlan`](plan::UndeployPlan) or a status report. Planning
+//!    never modifies the filesystem.
+//! 3. [`apply`] executes a plan, re-checking each link before touching
+//!    it.
+//!
+//! Planning and apply reach the filesystem only through the [`fs::Fs`]
+//! trait; the `*_with` variants of their entry points accept any
+//! implementation, such as the in-memory [`fs::MemFs`].
+//!
+//! Every failure is an [`XdError`] carrying one of the four SPEC error
+//! classes; stages that can report several errors at once collect them
+//! in an [`ErrorBag`]. A plan must only be applied when its error bag is
+//! empty. Every stage also reports what it does as [`logging`] records,
+//! which go nowhere unless the program installs a sink. Conditions that
+//! do not stop a run are [`Warning`](warning::Warning)s, collected in a
+//! [`WarningBag`](warning::WarningBag) next to the errors.
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use xdotter::plan::{self, ConflictMode};
+//!
+//! let disc = xdotter::discover(Path::new("/path/to/dotfiles"));
+//! let res = plan::build_deploy_plan(disc, ConflictMode::Default);
+//! if !res.errors.is_empty() {
+//!     eprintln!("{}", res.errors.into_error());
+//!     return;
+//! }
+//! let outcome = xdotter::apply::apply_deploy(&res.plan);
+//! println!("{} links deployed", outcome.successes);
+//! ```
+//!
+//! # Stability
+//!
+//! Everything reachable from this crate root is public API and follows
+//! semantic versioning. The modules besides the three stages hold the
+//! types their signatures use; the helpers behind the `xd` commands
+//! (registry, lock files, history, lint, scanning) are not exported. While the crate is `0.x`, breaking changes bump
+//! the minor version and are listed in `CHANGELOG.md`. Message texts
+//! (the Chinese diagnostics) are not part of the API; match on
+//! [`XdError`] variants and [`codes`] instead. The `xd` command-line
+//! interface is versioned separately by SPEC.md.
+
+#![warn(missing_docs)]
+
+pub mod answers;
+pub mod apply;
+pub mod codes;
+pub mod config;
+pub mod discover;
+pub mod error;
+pub mod fs;
+pub mod logging;
+pub mod plan;
+pub mod span;
+pub mod warning;
+
+mod diff;
+#[cfg(target_os = "linux")]
+mod dirfd;
+mod edit;
+mod fscache;
+mod gitdeps;
+mod history;
+mod ignore;
+mod lint;
+mod lock;
+mod orphans;
+mod path;
+mod permissions;
+mod registry;
+mod scan;
+mod trie;
+
+// The `xd` command line.
+mod cli;
+mod color;
+mod commands;
+mod log;
+
+pub use config::Config;
+pub use discover::discover;
+pub use error::{ErrorBag, XdError};
+
+/// Entry point of the `xd` binary. Not part of the library API.
+#[doc(hidden)]
+pub use commands::main;
@@ -0,0 +1,386 @@
+//! Repository hygiene checks for `xd lint`.
+//!
+//! A configuration can be valid and still sloppy. [`lint`] runs a fixed
+//! set of named [`Rule`]s over the discovered configurations and
+//! reports each hit as a [`Finding`]. Findings are warnings: they never
+//! become [`XdError`]s, and hard errors met on the way (configuration
+//! errors, link collisions) are returned separately in
+//! [`LintReport::errors`].
+//!
+//! Every rule can be switched off by name, for the whole run
+//! (`xd lint --allow RULE`) or for one configuration:
+//!
+//! ```toml
+//! [lint]
+//! allow = ["unreferenced-file"]
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::discover::Discovered;
+use crate::error::{ErrorBag, XdError};
+use crate::ignore::{IgnoreList, Ignores};
+use crate::orphans::{self, walk_files};
+use crate::permissions;
+use crate::plan::collect_global_links;
+
+/// A lint rule.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Rule {
+    /// A file in a configuration tree that no `[links]` source covers.
+    UnreferencedFile,
+    /// A source the repository's `.gitignore` ignores, such as build
+    /// output, which a fresh clone will not have.
+    IgnoredSource,
+    /// A directory source holding a file that looks like a key or
+    /// credential.
+    SecretInDirectory,
+    /// A link path below a directory every user can write to.
+    WorldWritableParent,
+}
+
+impl Rule {
+    /// Every rule, in declaration order.
+    pub const ALL: [Rule; 4] = [
+        Rule::UnreferencedFile,
+        Rule::IgnoredSource,
+        Rule::SecretInDirectory,
+        Rule::WorldWritableParent,
+    ];
+
+    /// Name used by `--allow` and `[lint] allow`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::UnreferencedFile => "unreferenced-file",
+            Self::IgnoredSource => "ignored-source",
+            Self::SecretInDirectory => "secret-in-directory",
+            Self::WorldWritableParent => "world-writable-parent",
+        }
+    }
+
+    /// Rule named `name`, as returned by [`Rule::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.name() == name)
+    }
+
+    /// Rule named `name`, or a CLI error listing the known names.
+    pub fn parse(name: &str) -> Result<Self, XdError> {
+        Self::from_name(name).ok_or_else(|| XdError::cli(unknown_rule(name)))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn unknown_rule(name: &str) -> String {
+    let known: Vec<_> = Rule::ALL.iter().map(|r| r.name()).collect();
+    format!("未知的 lint 规则 {}，可用规则: {}", name, known.join(", "))
+}
+
+/// One warning.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Rule that fired.
+    pub rule: Rule,
+    /// Configuration the finding belongs to.
+    pub config_file: PathBuf,
+    /// What is wrong (Chinese, for display).
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {}: {}",
+            self.rule,
+            self.config_file.display(),
+            self.message
+        )
+    }
+}
+
+/// Result of [`lint`].
+#[derive(Debug, Default)]
+pub struct LintReport {
+    /// Warnings that were not allowed, in configuration order.
+    pub findings: Vec<Finding>,
+    /// Number of warnings an allow list switched off.
+    pub suppressed: usize,
+    /// Hard errors from discovery and link collection.
+    pub errors: ErrorBag,
+}
+
+/// Names of files that usually hold keys or credentials, in ignore-file
+/// syntax.
+const SECRET_PATTERNS: &str = "\
+id_rsa
+id_dsa
+id_ecdsa
+id_ed25519
+*.pem
+*.key
+*.p12
+*.pfx
+*.kdbx
+.env
+.env.*
+.netrc
+.pgpass
+credentials
+secring.*
+";
+
+/// Run every rule not in `allow` over `disc`.
+pub fn lint(disc: Discovered, allow: &[Rule]) -> LintReport {
+    let mut errors = disc.errors;
+    let configs = disc.configs;
+    let entries = collect_global_links(&configs, &mut errors);
+
+    let config_dirs: BTreeSet<&Path> = configs.iter().map(|c| c.config_dir.as_path()).collect();
+    let orphans = orphans::find(&configs);
+    let secrets = IgnoreList::parse(SECRET_PATTERNS);
+    let mut gitignores = Ignores::new(".gitignore");
+
+    let mut report = LintReport::default();
+    for c in &configs {
+        let mut allowed: BTreeSet<Rule> = allow.iter().copied().collect();
+        for name in &c.config.lint_allow {
+            match Rule::from_name(name) {
+                Some(r) => {
+                    allowed.insert(r);
+                }
+                None => errors.push(XdError::config(format!(
+                    "{}: [lint] allow 中{}",
+                    c.config_file.display(),
+                    unknown_rule(name)
+                ))),
+            }
+        }
+        let top = orphans::tree_top(&config_dirs, &c.config_dir);
+
+        let mut found = Vec::new();
+        let mut push = |rule: Rule, message: String| {
+            found.push(Finding {
+                rule,
+                config_file: c.config_file.clone(),
+                message,
+            })
+        };
+
+        for o in orphans.iter().filter(|o| o.config_file == c.config_file) {
+            push(
+                Rule::UnreferencedFile,
+                format!("{} 未被任何链接条目引用", o.relative().display()),
+            );
+        }
+
+        for e in entries.iter().filter(|e| e.config_file == c.config_file) {
+            let source_is_dir = e.source_resolved.is_dir();
+            if gitignores.is_ignored(top, &e.source_resolved, source_is_dir) {
+                push(
+                    Rule::IgnoredSource,
+                    format!(
+                        "源 \"{}\" 被 .gitignore 忽略（构建产物？），仓库的新克隆中不会有它",
+                        e.source_raw
+                    ),
+                );
+            }
+            if source_is_dir {
+                for file in walk_files(&e.source_resolved, &|_| false) {
+                    let rel = file.strip_prefix(&e.source_resolved).unwrap_or(&file);
+                    let rel = rel.to_string_lossy().replace('\\', "/");
+                    let name = rel.rsplit('/').next().unwrap_or(&rel);
+                    let sensitive = e.link_raw.starts_with("~/")
+                        && permissions::required_permission(&format!(
+                            "{}/{}",
+                            e.link_raw.trim_end_matches('/'),
+                            rel
+                        ))
+                        .is_some();
+                    if sensitive || secrets.is_match(name, false) {
+                        push(
+                            Rule::SecretInDirectory,
+                            format!(
+                                "目录源 \"{}\" 包含疑似密钥或凭据的文件 {}",
+                                e.source_raw, rel
+                            ),
+                        );
+                    }
+                }
+            }
+            if let Some(dir) = world_writable_ancestor(&e.link_expanded) {
+                push(
+                    Rule::WorldWritableParent,
+                    format!(
+                        "链接路径 {} 位于所有用户可写的目录 {} 下，其他用户可以抢先创建或替换它",
+                        e.link_expanded.display(),
+                        dir.display()
+                    ),
+                );
+            }
+        }
+
+        for f in found {
+            if allowed.contains(&f.rule) {
+                report.suppressed += 1;
+            } else {
+                report.findings.push(f);
+            }
+        }
+    }
+    report.errors = errors;
+    report
+}
+
+/// The nearest ancestor of `link` in which another user could create
+/// or replace the next component on the way to it: a world-writable
+/// directory, unless its sticky bit protects a component that already
+/// exists (as for a home directory below `/tmp`).
+#[cfg(unix)]
+fn world_writable_ancestor(link: &Path) -> Option<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+    let chain: Vec<&Path> = link.ancestors().collect();
+    chain.windows(2).find_map(|w| {
+        let (child, dir) = (w[0], w[1]);
+        let mode = fs::metadata(dir)
+            .ok()
+            .filter(|m| m.is_dir())?
+            .permissions()
+            .mode();
+        let sticky_protected = mode & 0o1000 != 0 && fs::symlink_metadata(child).is_ok();
+        (mode & 0o002 != 0 && !sticky_protected).then(|| dir.to_path_buf())
+    })
+}
+
+#[cfg(not(unix))]
+fn world_writable_ancestor(_link: &Path) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discover::discover;
+
+    fn tmp(tag: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!("xd_lint_{}_{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).unwrap();
+        p.canonicalize().unwrap()
+    }
+
+    fn rules(r: &LintReport) -> Vec<(Rule, String)> {
+        r.findings
+            .iter()
+            .map(|f| (f.rule, f.message.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn unreferenced_ignored_and_secret_files_are_reported() {
+        let d = tmp("rules");
+        fs::write(
+            d.join("xdotter.toml"),
+            "[links]\n\"zshrc\" = \"~/.zshrc\"\n\"ssh\" = \"~/.ssh\"\n\"build/app.conf\" = \"~/.app.conf\"\n[dependencies]\nsub = \"sub\"\n",
+        )
+        .unwrap();
+        fs::write(d.join(".gitignore"), "build/\n*.swp\n").unwrap();
+        fs::write(d.join("zshrc"), "").unwrap();
+        fs::write(d.join("old_vimrc"), "").unwrap();
+        fs::write(d.join(".zshrc.swp"), "").unwrap();
+        fs::create_dir_all(d.join("ssh")).unwrap();
+        fs::write(d.join("ssh/config"), "").unwrap();
+        fs::write(d.join("ssh/deploy.pem"), "").unwrap();
+        fs::create_dir_all(d.join("build")).unwrap();
+        fs::write(d.join("build/app.conf"), "").unwrap();
+        fs::create_dir_all(d.join("sub")).unwrap();
+        fs::write(d.join("sub/xdotter.toml"), "").unwrap();
+        fs::write(d.join("sub/stray"), "").unwrap();
+
+        let r = lint(discover(&d), &[]);
+        assert!(r.errors.is_empty(), "{:?}", r.errors);
+        let got = rules(&r);
+        assert_eq!(
+            got.iter().map(|(r, _)| *r).collect::<Vec<_>>(),
+            [
+                Rule::UnreferencedFile,
+                Rule::IgnoredSource,
+                Rule::SecretInDirectory,
+                Rule::SecretInDirectory,
+                Rule::UnreferencedFile,
+            ],
+            "{got:?}"
+        );
+        assert!(got[0].1.starts_with("old_vimrc "), "{got:?}");
+        assert!(got[1].1.contains("\"build/app.conf\""), "{got:?}");
+        assert!(got[2].1.ends_with(" config"), "{got:?}");
+        assert!(got[3].1.ends_with(" deploy.pem"), "{got:?}");
+        assert!(got[4].1.starts_with("stray "), "{got:?}");
+        assert!(r.findings[4].config_file.ends_with("sub/xdotter.toml"));
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[test]
+    fn rules_are_suppressed_globally_and_per_config() {
+        let d = tmp("allow");
+        fs::write(
+            d.join("xdotter.toml"),
+            "[lint]\nallow = [\"unreferenced-file\"]\n[dependencies]\nsub = \"sub\"\n",
+        )
+        .unwrap();
+        fs::write(d.join("stray"), "").unwrap();
+        fs::create_dir_all(d.join("sub")).unwrap();
+        fs::write(d.join("sub/xdotter.toml"), "[lint]\nallow = [\"nope\"]\n").unwrap();
+        fs::write(d.join("sub/stray"), "").unwrap();
+
+        let r = lint(discover(&d), &[]);
+        assert_eq!(r.suppressed, 1);
+        assert_eq!(r.findings.len(), 1);
+        assert!(r.findings[0].config_file.ends_with("sub/xdotter.toml"));
+        let e = r.errors.into_error();
+        assert!(e.is_config() && e.to_string().contains("nope"), "{e}");
+
+        let r = lint(discover(&d), &[Rule::UnreferencedFile]);
+        assert_eq!((r.findings.len(), r.suppressed), (0, 2));
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn links_below_world_writable_directories_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+        let d = tmp("ww");
+        let shared = d.join("shared");
+        fs::create_dir_all(&shared).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o777)).unwrap();
+        fs::write(
+            d.join("xdotter.toml"),
+            format!("[links]\n\"f\" = \"{}/x/f\"\n", shared.display()),
+        )
+        .unwrap();
+        fs::write(d.join("f"), "").unwrap();
+
+        let r = lint(discover(&d), &[]);
+        assert_eq!(rules(&r).len(), 1, "{:?}", r.findings);
+        assert_eq!(r.findings[0].rule, Rule::WorldWritableParent);
+        assert!(r.findings[0]
+            .message
+            .contains(&shared.display().to_string()));
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for r in Rule::ALL {
+            assert_eq!(Rule::from_name(r.name()), Some(r));
+        }
+        assert!(Rule::parse("x").unwrap_err().is_cli());
+    }
+}
@@ -0,0 +1,238 @@
+//! Advisory locks that keep concurrent runs apart, per SPEC §"并发运行".
+//!
+//! A run that modifies links holds its locks from the start of planning
+//! until apply is done:
+//!
+//! - one per home directory, `~/.local/state/xdotter/run.lock`, which
+//!   also records the PID of its holder;
+//! - one per root configuration directory, taken on the directory itself
+//!   so nothing is written into the repository. It stops a run under a
+//!   different home from working on the same repository at the same
+//!   time. Where directories cannot be locked (Windows) it is skipped.
+//!
+//! The locks are advisory (`flock` on Unix, `LockFileEx` on Windows) and
+//! are released when the [`RunLock`] is dropped or the process exits,
+//! however it exits.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::codes;
+use crate::error::XdError;
+
+/// Locks held by one run; released on drop.
+#[derive(Debug)]
+pub struct RunLock {
+    home: File,
+    _repos: Vec<File>,
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // A later holder rewrites the PID; clearing it keeps a stale one
+        // from being reported should that holder not have written yet.
+        let _ = self.home.set_len(0);
+    }
+}
+
+/// A lock another process holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Holder {
+    /// The lock file, or the locked configuration directory.
+    pub path: PathBuf,
+    /// PID of the holder, when it recorded one.
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "另一个 xd 进程 (PID {})", pid)?,
+            None => f.write_str("另一个 xd 进程")?,
+        }
+        write!(f, "持有运行锁 {}", self.path.display())
+    }
+}
+
+/// `~/.local/state/xdotter/run.lock`, or `None` without a home
+/// directory.
+pub fn home_lock_path() -> Option<PathBuf> {
+    crate::path::home_dir().map(|h| {
+        h.join(".local")
+            .join("state")
+            .join("xdotter")
+            .join("run.lock")
+    })
+}
+
+/// Take the home lock at `home_lock` and the lock of every directory in
+/// `roots`. When one is held elsewhere, wait for it after telling
+/// `on_wait` who holds it, or fail with `wait` false.
+pub fn acquire(
+    home_lock: &Path,
+    roots: &[PathBuf],
+    wait: bool,
+    mut on_wait: impl FnMut(&Holder),
+) -> Result<RunLock, XdError> {
+    if let Some(parent) = home_lock.parent() {
+        fs::create_dir_all(parent).map_err(|e| unavailable(parent, e))?;
+    }
+    let mut home = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(home_lock)
+        .map_err(|e| unavailable(home_lock, e))?;
+    take(&home, home_lock, wait, &mut on_wait, || recorded_pid(&home))?;
+    home.set_len(0)
+        .and_then(|_| home.rewind())
+        .and_then(|_| writeln!(home, "{}", std::process::id()))
+        .map_err(|e| unavailable(home_lock, e))?;
+
+    // A fixed order keeps two runs over overlapping repositories from
+    // each waiting for a lock the other holds.
+    let mut dirs: Vec<PathBuf> = roots
+        .iter()
+        .map(|r| fs::canonicalize(r).unwrap_or_else(|_| r.clone()))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    let mut repos = Vec::new();
+    for dir in dirs {
+        if let Some(file) = lock_dir(&dir, wait, &mut on_wait)? {
+            repos.push(file);
+        }
+    }
+    Ok(RunLock {
+        home,
+        _repos: repos,
+    })
+}
+
+#[cfg(unix)]
+fn lock_dir(
+    dir: &Path,
+    wait: bool,
+    on_wait: &mut dyn FnMut(&Holder),
+) -> Result<Option<File>, XdError> {
+    let file = File::open(dir).map_err(|e| unavailable(dir, e))?;
+    take(&file, dir, wait, on_wait, || None)?;
+    Ok(Some(file))
+}
+
+#[cfg(not(unix))]
+fn lock_dir(
+    _dir: &Path,
+    _wait: bool,
+    _on_wait: &mut dyn FnMut(&Holder),
+) -> Result<Option<File>, XdError> {
+    Ok(None)
+}
+
+fn take(
+    file: &File,
+    path: &Path,
+    wait: bool,
+    on_wait: &mut dyn FnMut(&Holder),
+    pid: impl Fn() -> Option<u32>,
+) -> Result<(), XdError> {
+    match file.try_lock() {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => {
+            let holder = Holder {
+                path: path.to_path_buf(),
+                pid: pid(),
+            };
+            if !wait {
+                return Err(XdError::planning(format!(
+                    "{}，本次运行未做任何修改；去掉 --no-wait 可等待其结束",
+                    holder
+                ))
+                .with_code(codes::LOCK_HELD));
+            }
+            on_wait(&holder);
+            file.lock().map_err(|e| unavailable(path, e))
+        }
+        Err(TryLockError::Error(e)) => Err(unavailable(path, e)),
+    }
+}
+
+fn recorded_pid(mut file: &File) -> Option<u32> {
+    let mut text = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut text).ok()?;
+    text.trim().parse().ok()
+}
+
+fn unavailable(path: &Path, e: io::Error) -> XdError {
+    XdError::planning(format!("无法获取运行锁 {}: {}", path.display(), e))
+        .with_code(codes::LOCK_UNAVAILABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(tag: &str) -> PathBuf {
+        let d = std::env::temp_dir().join(format!("xd-lock-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&d);
+        fs::create_dir_all(&d).unwrap();
+        d
+    }
+
+    #[test]
+    fn a_held_home_lock_names_its_holder() {
+        let d = scratch("home");
+        let path = d.join("state").join("run.lock");
+        let held = acquire(&path, &[], false, |_| {}).unwrap();
+
+        let err = acquire(&path, &[], false, |_| {}).unwrap_err();
+        assert!(err.is_planning());
+        assert_eq!(err.code(), Some(codes::LOCK_HELD));
+        assert!(
+            err.to_string()
+                .contains(&format!("(PID {})持有运行锁", std::process::id())),
+            "{err}"
+        );
+
+        drop(held);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        let again = acquire(&path, &[], false, |_| {}).unwrap();
+        drop(again);
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn repositories_are_locked_across_homes() {
+        let d = scratch("repo");
+        let repo = d.join("dotfiles");
+        fs::create_dir_all(&repo).unwrap();
+        let roots = vec![repo];
+        let held = acquire(&d.join("a.lock"), &roots, false, |_| {}).unwrap();
+
+        let err = acquire(&d.join("b.lock"), &roots, false, |_| {}).unwrap_err();
+        assert_eq!(err.code(), Some(codes::LOCK_HELD));
+        assert!(
+            err.to_string().contains("另一个 xd 进程持有运行锁"),
+            "{err}"
+        );
+
+        // A waiting run reports the holder, then proceeds once released.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let waiter = std::thread::spawn(move || {
+            acquire(&d.join("b.lock"), &roots, true, |h| {
+                tx.send(h.clone()).unwrap()
+            })
+            .map(|_| d)
+        });
+        let holder = rx.recv().unwrap();
+        assert_eq!(holder.pid, None);
+        drop(held);
+        let d = waiter.join().unwrap().unwrap();
+        let _ = fs::remove_dir_all(&d);
+    }
+}
@@ -1,9 +1,25 @@
-//! Tiny verbosity-aware diagnostic helper per SPEC §"输出语义".
+//! Tiny verbosity-aware diagnostic helper per SPEC §"输出语义", and the
+//! structured log behind `--log-file` and `XD_LOG`.
 //!
 //! `-v` (level 1) prints normal operation info; `-vv` (2) prints debug
 //! info; `-vvv` (3) prints trace info. Any value > 3 is treated as 3.
 //! All diagnostics go to stderr; warnings/errors are printed
 //! unconditionally elsewhere.
+//!
+//! [`init`] installs a sink for the library's [`crate::logging`]
+//! records: `--log-file` appends them to a file (every target at trace
+//! unless `XD_LOG` says otherwise), `XD_LOG` alone sends them to stderr.
+//! Messages of this module are recorded under the `xd` target as well,
+//! except where that would print them to stderr a second time.
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::logging::{self, Filter};
+use crate::XdError;
 
 use crate::cli::Cli;
 
@@ -14,15 +30,87 @@ pub enum Level {
     Trace = 3,
 }
 
+impl From<Level> for logging::Level {
+    fn from(l: Level) -> Self {
+        match l {
+            Level::Info => logging::Level::Info,
+            Level::Debug => logging::Level::Debug,
+            Level::Trace => logging::Level::Trace,
+        }
+    }
+}
+
+/// Records go to a `--log-file` rather than stderr.
+static TO_FILE: AtomicBool = AtomicBool::new(false);
+
+/// Install the record sink `--log-file` and `XD_LOG` ask for. Both are
+/// checked here, before any configuration is read, so a bad value is a
+/// CLI error.
+pub fn init(cli: &Cli) -> Result<(), XdError> {
+    let filter = match env::var("XD_LOG") {
+        Ok(spec) if !spec.trim().is_empty() => Some(Filter::parse(&spec)?),
+        _ => None,
+    };
+    match (&cli.log_file, filter) {
+        (Some(path), filter) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| XdError::cli(format!("无法打开日志文件 {}: {}", path.display(), e)))?;
+            let file = Mutex::new(file);
+            logging::install(
+                filter.unwrap_or(Filter::all(logging::Level::Trace)),
+                move |r| {
+                    if let Ok(mut f) = file.lock() {
+                        let _ = writeln!(f, "{}", r);
+                    }
+                },
+            );
+            TO_FILE.store(true, Ordering::Relaxed);
+        }
+        (None, Some(filter)) => {
+            logging::install(filter, |r| eprintln!("{}", r));
+        }
+        (None, None) => {}
+    }
+    trace(
+        cli,
+        format!(
+            "xd {}: 参数 {:?}, 当前目录 {}",
+            env!("CARGO_PKG_VERSION"),
+            env::args().skip(1).collect::<Vec<_>>(),
+            env::current_dir().unwrap_or_default().display()
+        ),
+    );
+    Ok(())
+}
+
+/// Record how the command ended. Only a log file gets this: on stderr
+/// the error has just been printed.
+pub fn finish(result: &Result<(), XdError>) {
+    if !TO_FILE.load(Ordering::Relaxed) {
+        return;
+    }
+    match result {
+        Ok(()) => logging::log("xd", logging::Level::Info, || "退出码 0".to_string()),
+        Err(e) => logging::log("xd", logging::Level::Error, || format!("退出码 1: {}", e)),
+    }
+}
+
 fn current_level(cli: &Cli) -> u8 {
     cli.verbose.min(3)
 }
 
 /// Emit a diagnostic line to stderr if `cli`'s verbosity is at least `lvl`.
 pub fn log(cli: &Cli, lvl: Level, msg: impl AsRef<str>) {
-    if current_level(cli) >= lvl as u8 {
+    let printed = current_level(cli) >= lvl as u8;
+    if printed {
         eprintln!("{}", msg.as_ref());
     }
+    if !printed || TO_FILE.load(Ordering::Relaxed) {
+        logging::log("xd", lvl.into(), || msg.as_ref().to_string());
+    }
 }
 
 pub fn info(cli: &Cli, msg: impl AsRef<str>) {
@@ -33,7 +121,6 @@ pub fn debug(cli: &Cli, msg: impl AsRef<str>) {
     log(cli, Level::Debug, msg);
 }
 
-#[allow(dead_code)]
 pub fn trace(cli: &Cli, msg: impl AsRef<str>) {
     log(cli, Level::Trace, msg);
 }
@@ -0,0 +1,271 @@
+//! Leveled diagnostic records from every stage, for `xd --log-file` and
+//! the `XD_LOG` filter.
+//!
+//! The library never prints diagnostics itself. Each stage reports what
+//! it does through [`log`] under a fixed target name ([`TARGETS`]), and
+//! the records reach whatever sink the embedding program [`install`]ed,
+//! filtered per target:
+//!
+//! ```text
+//! XD_LOG=plan=trace,apply=debug      plan at trace, apply at debug, rest off
+//! XD_LOG=debug,fs=off                everything at debug except syscalls
+//! ```
+//!
+//! Without an installed sink, [`log`] returns before formatting anything,
+//! so an unlogged run pays one atomic load per call site.
+
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::XdError;
+
+/// Targets records are reported under.
+///
+/// - `discover`: configuration files read, dependencies followed.
+/// - `plan`: per-entry planning inputs and decisions.
+/// - `apply`: each step and its result.
+/// - `fs`: every filesystem call on the live filesystem.
+/// - `gitdeps`: git commands run for git dependencies.
+/// - `xd`: the command-line front end.
+pub const TARGETS: &[&str] = &["discover", "plan", "apply", "fs", "gitdeps", "xd"];
+
+/// Severity of a record, most severe first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    /// A stage failed.
+    Error = 1,
+    /// Something worth attention that does not stop the run.
+    Warn,
+    /// What a run does, at the granularity of configurations and plans.
+    Info,
+    /// One record per entry or step.
+    Debug,
+    /// Inputs of each decision, and every filesystem call.
+    Trace,
+}
+
+impl Level {
+    /// Every level, most severe first.
+    pub const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    /// Name used by `XD_LOG` and in records, lowercase.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+/// Which records to keep: a most verbose level per target, and one for
+/// every other target. `None` keeps nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    default: Option<Level>,
+    targets: Vec<(&'static str, Option<Level>)>,
+}
+
+impl Filter {
+    /// Keep records of every target up to `level`.
+    pub fn all(level: Level) -> Self {
+        Self {
+            default: Some(level),
+            targets: Vec::new(),
+        }
+    }
+
+    /// Parse an `XD_LOG` value: comma-separated directives, each a level
+    /// (`debug`) for every target or `target=level` for one. `off` keeps
+    /// nothing. A later directive overrides an earlier one.
+    pub fn parse(spec: &str) -> Result<Self, XdError> {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                None => filter.default = parse_level(directive)?,
+                Some((target, level)) => {
+                    let target = TARGETS
+                        .iter()
+                        .find(|t| t.eq_ignore_ascii_case(target.trim()))
+                        .ok_or_else(|| {
+                            XdError::cli(format!(
+                                "XD_LOG 中未知的日志目标 {}，可用目标: {}",
+                                target.trim(),
+                                TARGETS.join(", ")
+                            ))
+                        })?;
+                    let level = parse_level(level.trim())?;
+                    filter.targets.retain(|(t, _)| t != target);
+                    filter.targets.push((target, level));
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// True when records of `target` at `level` are kept.
+    pub fn enabled(&self, target: &str, level: Level) -> bool {
+        let max = self
+            .targets
+            .iter()
+            .find(|(t, _)| *t == target)
+            .map_or(self.default, |(_, l)| *l);
+        max.is_some_and(|max| level <= max)
+    }
+}
+
+fn parse_level(name: &str) -> Result<Option<Level>, XdError> {
+    if name.eq_ignore_ascii_case("off") {
+        return Ok(None);
+    }
+    Level::ALL
+        .into_iter()
+        .find(|l| l.name().eq_ignore_ascii_case(name))
+        .map(Some)
+        .ok_or_else(|| {
+            XdError::cli(format!(
+                "XD_LOG 中未知的日志级别 {}，可用级别: off, error, warn, info, debug, trace",
+                name
+            ))
+        })
+}
+
+/// One diagnostic record.
+#[derive(Clone, Debug)]
+pub struct Record {
+    /// When the record was made.
+    pub time: SystemTime,
+    /// Severity.
+    pub level: Level,
+    /// One of [`TARGETS`].
+    pub target: &'static str,
+    /// What happened.
+    pub message: String,
+}
+
+impl fmt::Display for Record {
+    /// `2026-10-19T08:15:30.123Z TRACE plan: message`, continuation lines
+    /// of the message indented.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:<5} {}: {}",
+            timestamp(self.time),
+            self.level.name().to_uppercase(),
+            self.target,
+            self.message.replace('\n', "\n    ")
+        )
+    }
+}
+
+/// A UTC RFC 3339 timestamp with milliseconds.
+pub(crate) fn timestamp(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = d.as_secs();
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Howard Hinnant's days-to-civil algorithm.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        d.subsec_millis()
+    )
+}
+
+type Sink = Box<dyn Fn(&Record) + Send + Sync>;
+
+static LOGGER: OnceLock<(Filter, Sink)> = OnceLock::new();
+
+/// Send records `filter` keeps to `sink` for the rest of the process.
+/// Only the first call takes effect; it returns false for later ones.
+pub fn install(filter: Filter, sink: impl Fn(&Record) + Send + Sync + 'static) -> bool {
+    LOGGER.set((filter, Box::new(sink))).is_ok()
+}
+
+/// True when a record of `target` at `level` would reach a sink.
+pub fn enabled(target: &str, level: Level) -> bool {
+    LOGGER
+        .get()
+        .is_some_and(|(filter, _)| filter.enabled(target, level))
+}
+
+/// Report `message()` under `target` at `level`. `message` is only
+/// called when the record is kept.
+pub fn log(target: &'static str, level: Level, message: impl FnOnce() -> String) {
+    let Some((filter, sink)) = LOGGER.get() else {
+        return;
+    };
+    if filter.enabled(target, level) {
+        sink(&Record {
+            time: SystemTime::now(),
+            level,
+            target,
+            message: message(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn directives_set_levels_per_target() {
+        let f = Filter::parse("plan=trace, apply=debug").unwrap();
+        assert!(f.enabled("plan", Level::Trace));
+        assert!(f.enabled("apply", Level::Debug));
+        assert!(!f.enabled("apply", Level::Trace));
+        assert!(!f.enabled("fs", Level::Error));
+
+        let f = Filter::parse("debug,fs=off,FS=error").unwrap();
+        assert!(f.enabled("discover", Level::Debug));
+        assert!(f.enabled("fs", Level::Error));
+        assert!(!f.enabled("fs", Level::Warn));
+
+        assert!(Filter::parse("plan=loud").unwrap_err().is_cli());
+        assert!(Filter::parse("planner=trace").unwrap_err().is_cli());
+        assert_eq!(Filter::parse("").unwrap(), Filter::default());
+    }
+
+    #[test]
+    fn records_carry_utc_timestamps() {
+        let r = Record {
+            time: UNIX_EPOCH + Duration::from_millis(1_792_397_730_123),
+            level: Level::Info,
+            target: "plan",
+            message: "a\nb".to_string(),
+        };
+        assert_eq!(
+            r.to_string(),
+            "2026-10-19T08:15:30.123Z INFO  plan: a\n    b"
+        );
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000Z"
+        );
+    }
+}
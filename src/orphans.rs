@@ -0,0 +1,272 @@
+//! Files in configuration trees that no `[links]` entry references, for
+//! `xd orphans` and the `unreferenced-file` lint rule.
+//!
+//! [`find`] walks every discovered configuration directory tree and
+//! subtracts the sources claimed by `[links]`, a directory source
+//! claiming everything below it. Nested configuration trees are walked
+//! as their own configuration, `.git` is skipped, and files ignored by
+//! `.gitignore` or by an [`IGNORE_FILE`] (same syntax) are not orphans.
+//! [`suggest`] turns the result into `[links]` entries to start from.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::discover::DiscoveredConfig;
+use crate::ignore::Ignores;
+use crate::scan::quote;
+
+/// Ignore file read in every directory of a configuration tree, on top
+/// of `.gitignore`.
+pub const IGNORE_FILE: &str = ".xdignore";
+
+/// Files the repository itself needs, never orphans.
+const METADATA_FILES: &[&str] = &["xdotter.toml", "xdotter.lock", ".gitignore", IGNORE_FILE];
+
+/// Home directories other programs write into too; a link replacing one
+/// of them whole is never suggested.
+const SHARED_DIRS: &[&str] = &[".config", ".local", ".local/share", ".local/bin"];
+
+/// A file no `[links]` source covers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Orphan {
+    /// Configuration whose tree holds the file.
+    pub config_file: PathBuf,
+    /// Directory of that configuration.
+    pub config_dir: PathBuf,
+    /// Absolute path of the file.
+    pub path: PathBuf,
+}
+
+impl Orphan {
+    /// The file's path relative to its configuration directory.
+    pub fn relative(&self) -> &Path {
+        self.path
+            .strip_prefix(&self.config_dir)
+            .unwrap_or(&self.path)
+    }
+}
+
+/// Orphans of every configuration, in configuration order and then path
+/// order.
+pub fn find(configs: &[DiscoveredConfig]) -> Vec<Orphan> {
+    let config_dirs: BTreeSet<&Path> = configs.iter().map(|c| c.config_dir.as_path()).collect();
+    let sources: Vec<PathBuf> = configs
+        .iter()
+        .flat_map(|c| {
+            c.config
+                .links
+                .keys()
+                .map(|s| crate::path::normalize(&c.config_dir.join(s)))
+        })
+        .collect();
+    let mut gitignores = Ignores::new(".gitignore");
+    let mut xdignores = Ignores::new(IGNORE_FILE);
+
+    let mut out = Vec::new();
+    for c in configs {
+        let top = tree_top(&config_dirs, &c.config_dir);
+        let nested = |p: &Path| p != c.config_dir && config_dirs.contains(p);
+        for file in walk_files(&c.config_dir, &nested) {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            if METADATA_FILES.contains(&name.as_ref())
+                || sources.iter().any(|s| file.starts_with(s))
+                || gitignores.is_ignored(top, &file, false)
+                || xdignores.is_ignored(top, &file, false)
+            {
+                continue;
+            }
+            out.push(Orphan {
+                config_file: c.config_file.clone(),
+                config_dir: c.config_dir.clone(),
+                path: file,
+            });
+        }
+    }
+    out
+}
+
+/// Suggested `[links]` entries for `orphans`, one snippet per
+/// configuration. A directory whose files are all orphans is suggested
+/// as one entry, unless it would link a shared directory such as
+/// `~/.config`. Link paths are a guess: the file's place under the
+/// home directory, with a dot added to the first component when it has
+/// none (`config/fish` becomes `~/.config/fish`).
+pub fn suggest(orphans: &[Orphan]) -> String {
+    let all: BTreeSet<&Path> = orphans.iter().map(|o| o.path.as_path()).collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < orphans.len() {
+        let config_file = &orphans[i].config_file;
+        let group_end = orphans[i..]
+            .iter()
+            .position(|o| &o.config_file != config_file)
+            .map_or(orphans.len(), |n| i + n);
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("# {}\n[links]\n", config_file.display()));
+
+        let mut emitted: Vec<PathBuf> = Vec::new();
+        for o in &orphans[i..group_end] {
+            if emitted.iter().any(|e| o.path.starts_with(e)) {
+                continue;
+            }
+            let entry = collapse(o, &all);
+            let rel = entry
+                .strip_prefix(&o.config_dir)
+                .unwrap_or(&entry)
+                .to_string_lossy()
+                .replace('\\', "/");
+            out.push_str(&format!("{} = {}\n", quote(&rel), quote(&guess_link(&rel))));
+            emitted.push(entry);
+        }
+        i = group_end;
+    }
+    out
+}
+
+/// The outermost directory above `o` (inside its configuration
+/// directory) whose files are all orphans, or the file itself.
+fn collapse(o: &Orphan, all: &BTreeSet<&Path>) -> PathBuf {
+    let rel: Vec<_> = o.relative().components().collect();
+    let mut dir = o.config_dir.clone();
+    for c in &rel[..rel.len().saturating_sub(1)] {
+        dir.push(c);
+        let rel = dir.strip_prefix(&o.config_dir).unwrap_or(&dir);
+        let link = guess_link(&rel.to_string_lossy().replace('\\', "/"));
+        if SHARED_DIRS.contains(&&link[2..]) {
+            continue;
+        }
+        // `xdotter.toml` of a nested tree is never an orphan, so nested
+        // trees are never collapsed into.
+        if walk_files(&dir, &|_| false)
+            .iter()
+            .all(|f| all.contains(f.as_path()))
+        {
+            return dir;
+        }
+    }
+    o.path.clone()
+}
+
+fn guess_link(rel: &str) -> String {
+    if rel.starts_with('.') {
+        format!("~/{}", rel)
+    } else {
+        format!("~/.{}", rel)
+    }
+}
+
+/// The outermost configuration directory containing `dir`; its
+/// `.gitignore` and those below it apply to the files of `dir`.
+pub(crate) fn tree_top<'a>(config_dirs: &BTreeSet<&'a Path>, dir: &'a Path) -> &'a Path {
+    config_dirs
+        .iter()
+        .find(|d| dir.starts_with(d))
+        .copied()
+        .unwrap_or(dir)
+}
+
+/// Every regular file below `dir`, in path order. Symlinks are not
+/// followed, `.git` is skipped, and so is every directory `stop`
+/// accepts.
+pub(crate) fn walk_files(dir: &Path, stop: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&d) else {
+            continue;
+        };
+        for e in entries.flatten() {
+            let path = e.path();
+            let Ok(ft) = e.file_type() else {
+                continue;
+            };
+            if ft.is_dir() {
+                if e.file_name() != ".git" && !stop(&path) {
+                    stack.push(path);
+                }
+            } else if ft.is_file() {
+                out.push(path);
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discover::discover;
+
+    fn tmp(tag: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!("xd_orphans_{}_{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).unwrap();
+        p.canonicalize().unwrap()
+    }
+
+    fn write(path: PathBuf, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn sources_nested_trees_and_ignored_files_are_subtracted() {
+        let d = tmp("find");
+        write(
+            d.join("xdotter.toml"),
+            "[links]\n\"zsh\" = \"~/.zsh\"\n\"vimrc\" = \"~/.vimrc\"\n[dependencies]\nsub = \"sub\"\n",
+        );
+        write(d.join("zsh/a.zsh"), "");
+        write(d.join("zsh/deep/b.zsh"), "");
+        write(d.join("vimrc"), "");
+        write(d.join("old/gitconfig"), "");
+        write(d.join("notes.md"), "");
+        write(d.join("scratch/tmp.txt"), "");
+        write(d.join(".xdignore"), "*.md\n");
+        write(d.join("scratch/.gitignore"), "*\n");
+        write(d.join(".git/config"), "");
+        write(d.join("sub/xdotter.toml"), "");
+        write(d.join("sub/stray"), "");
+
+        let disc = discover(&d);
+        assert!(disc.errors.is_empty());
+        let found = find(&disc.configs);
+        let rel: Vec<_> = found.iter().map(|o| o.relative().to_path_buf()).collect();
+        assert_eq!(
+            rel,
+            [PathBuf::from("old/gitconfig"), PathBuf::from("stray")]
+        );
+        assert!(found[1].config_file.ends_with("sub/xdotter.toml"));
+        let _ = fs::remove_dir_all(&d);
+    }
+
+    #[test]
+    fn suggestions_collapse_fully_orphaned_directories() {
+        let d = tmp("suggest");
+        write(
+            d.join("xdotter.toml"),
+            "[links]\n\"config/kept\" = \"~/.config/kept\"\n",
+        );
+        write(d.join("config/kept"), "");
+        write(d.join("config/fish/config.fish"), "");
+        write(d.join("config/fish/functions/f.fish"), "");
+        write(d.join(".tmux.conf"), "");
+
+        let disc = discover(&d);
+        let text = suggest(&find(&disc.configs));
+        assert_eq!(
+            text,
+            format!(
+                "# {}\n[links]\n\".tmux.conf\" = \"~/.tmux.conf\"\n\"config/fish\" = \"~/.config/fish\"\n",
+                d.join("xdotter.toml").display()
+            )
+        );
+        let parsed = crate::config::Config::from_toml(&text, Path::new("x")).unwrap();
+        assert_eq!(parsed.links.len(), 2);
+        let _ = fs::remove_dir_all(&d);
+    }
+}
@@ -10,6 +10,7 @@
 
 use std::path::{Component, Path, PathBuf};
 
+use crate::codes;
 use crate::error::XdError;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -80,6 +81,7 @@ pub fn expand_tilde(raw: &str) -> Result<PathBuf, XdError> {
                 "无法确定当前用户的 HOME 目录，无法展开 `~/`：{}",
                 raw
             ))
+            .with_code(codes::HOME_UNKNOWN)
         })?;
         // Preserve the slash so that `~/` becomes `<home>/`.
         let mut p = home;
@@ -128,6 +130,30 @@ pub fn has_parent_traversal(p: &Path) -> bool {
     p.components().any(|c| c == Component::ParentDir)
 }
 
+/// Lexical path from directory `base` to `target`, both absolute and
+/// normalized: `..` for each component of `base` past the common
+/// prefix, then the rest of `target`. `None` when the two do not share a
+/// root (different Windows drives).
+pub fn relative_to(target: &Path, base: &Path) -> Option<PathBuf> {
+    let t: Vec<_> = target.components().collect();
+    let b: Vec<_> = base.components().collect();
+    let common = t.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    if common == 0 && (target.has_root() || base.has_root()) {
+        return None;
+    }
+    let mut out = PathBuf::new();
+    for _ in common..b.len() {
+        out.push("..");
+    }
+    for c in &t[common..] {
+        out.push(c.as_os_str());
+    }
+    if out.as_os_str().is_empty() {
+        out.push(".");
+    }
+    Some(out)
+}
+
 // -----------------------------------------------------------------------------
 // Static validators (config-error category)
 // -----------------------------------------------------------------------------
@@ -141,19 +167,22 @@ pub fn has_parent_traversal(p: &Path) -> bool {
 /// Filesystem-existence and symlink-component checks belong to the
 /// planning stage, not this function.
 pub fn validate_source_path(raw: &str) -> Result<(), XdError> {
-    let form =
-        classify(raw).map_err(|m| XdError::config(format!("源路径 \"{}\" 非法: {}", raw, m)))?;
+    let form = classify(raw).map_err(|m| {
+        XdError::config(format!("源路径 \"{}\" 非法: {}", raw, m))
+            .with_code(codes::SOURCE_MALFORMED)
+    })?;
     if form != PathForm::NormalRelative {
         return Err(XdError::config(format!(
             "源路径必须是普通相对路径，不得是绝对或 home 相对路径: \"{}\"",
             raw
-        )));
+        ))
+        .with_code(codes::SOURCE_NOT_RELATIVE));
     }
     if has_parent_traversal(Path::new(raw)) {
-        return Err(XdError::config(format!(
-            "源路径不得包含父目录跳转 `..`: \"{}\"",
-            raw
-        )));
+        return Err(
+            XdError::config(format!("源路径不得包含父目录跳转 `..`: \"{}\"", raw))
+                .with_code(codes::SOURCE_PARENT_TRAVERSAL),
+        );
     }
     Ok(())
 }
@@ -168,32 +197,37 @@ pub fn validate_source_path(raw: &str) -> Result<(), XdError> {
 ///   separators (e.g. `//`)
 pub fn validate_link_path(raw: &str) -> Result<(), XdError> {
     if raw.is_empty() {
-        return Err(XdError::config("链接路径不得为空".to_string()));
+        return Err(
+            XdError::config("链接路径不得为空".to_string()).with_code(codes::LINK_MALFORMED)
+        );
     }
     // Reject pure-separator strings statically (SPEC: "~", "~/", "/",
     // "//" are rejected without home expansion).
     if raw == "~" || raw == "~/" {
-        return Err(XdError::config(format!(
-            "链接路径不得解析为 home 目录本身: \"{}\"",
-            raw
-        )));
+        return Err(
+            XdError::config(format!("链接路径不得解析为 home 目录本身: \"{}\"", raw))
+                .with_code(codes::LINK_IS_HOME),
+        );
     }
     if is_pure_separators(raw) {
-        return Err(XdError::config(format!(
-            "链接路径不得解析为文件系统根目录: \"{}\"",
-            raw
-        )));
+        return Err(
+            XdError::config(format!("链接路径不得解析为文件系统根目录: \"{}\"", raw))
+                .with_code(codes::LINK_IS_ROOT),
+        );
     }
 
-    let form =
-        classify(raw).map_err(|m| XdError::config(format!("链接路径 \"{}\" 非法: {}", raw, m)))?;
+    let form = classify(raw).map_err(|m| {
+        XdError::config(format!("链接路径 \"{}\" 非法: {}", raw, m))
+            .with_code(codes::LINK_MALFORMED)
+    })?;
 
     match form {
         PathForm::NormalRelative => {
             return Err(XdError::config(format!(
                 "链接路径必须是绝对路径或 home 相对路径，不得是普通相对路径: \"{}\"",
                 raw
-            )));
+            ))
+            .with_code(codes::LINK_RELATIVE));
         }
         PathForm::Absolute | PathForm::HomeRelative => {}
     }
@@ -205,20 +239,20 @@ pub fn validate_link_path(raw: &str) -> Result<(), XdError> {
     // prefix; checking the raw string for any `..` component covers it
     // because `~/` itself contains no `..`.
     if has_parent_traversal(Path::new(raw)) {
-        return Err(XdError::config(format!(
-            "链接路径不得包含父目录跳转 `..`: \"{}\"",
-            raw
-        )));
+        return Err(
+            XdError::config(format!("链接路径不得包含父目录跳转 `..`: \"{}\"", raw))
+                .with_code(codes::LINK_PARENT_TRAVERSAL),
+        );
     }
 
     // Reject `~/` followed only by separators / dots that normalize to
     // the home directory itself, e.g. "~/", "~/.", "~/./", "~/.//".
     if let Some(rest) = raw.strip_prefix("~/") {
         if rest_is_empty_or_curdir_only(rest) {
-            return Err(XdError::config(format!(
-                "链接路径不得解析为 home 目录本身: \"{}\"",
-                raw
-            )));
+            return Err(
+                XdError::config(format!("链接路径不得解析为 home 目录本身: \"{}\"", raw))
+                    .with_code(codes::LINK_IS_HOME),
+            );
         }
     }
 
@@ -232,7 +266,8 @@ pub fn validate_link_path(raw: &str) -> Result<(), XdError> {
                 return Err(XdError::config(format!(
                     "链接路径不得解析为文件系统根目录: \"{}\"",
                     raw
-                )));
+                ))
+                .with_code(codes::LINK_IS_ROOT));
             }
         }
     }
@@ -256,19 +291,22 @@ fn rest_is_empty_or_curdir_only(rest: &str) -> bool {
 
 /// Validate a dependency path string per SPEC §"依赖路径".
 pub fn validate_dependency_path(raw: &str) -> Result<(), XdError> {
-    let form =
-        classify(raw).map_err(|m| XdError::config(format!("依赖路径 \"{}\" 非法: {}", raw, m)))?;
+    let form = classify(raw).map_err(|m| {
+        XdError::config(format!("依赖路径 \"{}\" 非法: {}", raw, m))
+            .with_code(codes::DEPENDENCY_MALFORMED)
+    })?;
     if form != PathForm::NormalRelative {
         return Err(XdError::config(format!(
             "依赖路径必须是相对路径，不得是绝对或 home 相对路径: \"{}\"",
             raw
-        )));
+        ))
+        .with_code(codes::DEPENDENCY_NOT_RELATIVE));
     }
     if has_parent_traversal(Path::new(raw)) {
-        return Err(XdError::config(format!(
-            "依赖路径不得包含父目录跳转 `..`: \"{}\"",
-            raw
-        )));
+        return Err(
+            XdError::config(format!("依赖路径不得包含父目录跳转 `..`: \"{}\"", raw))
+                .with_code(codes::DEPENDENCY_PARENT_TRAVERSAL),
+        );
     }
     Ok(())
 }
@@ -278,6 +316,22 @@ mod tests {
     use super::*;
 
     // ---------- classify ----------
+    #[test]
+    #[cfg(not(windows))]
+    fn relative_to_walks_up_from_the_base() {
+        let r = |t: &str, b: &str| relative_to(Path::new(t), Path::new(b)).unwrap();
+        assert_eq!(
+            r("/repo/zsh/zshrc", "/home/u"),
+            Path::new("../../repo/zsh/zshrc")
+        );
+        assert_eq!(r("/home/u/dots/vimrc", "/home/u"), Path::new("dots/vimrc"));
+        assert_eq!(
+            r("/home/u/dots/a", "/home/u/.config/x"),
+            Path::new("../../dots/a")
+        );
+        assert_eq!(r("/home/u", "/home/u"), Path::new("."));
+    }
+
     #[test]
     fn classify_basic() {
         assert_eq!(classify("foo/bar").unwrap(), PathForm::NormalRelative);
@@ -6,6 +6,8 @@
 
 use std::path::Path;
 
+use crate::fs::Fs;
+
 /// SPEC permission table:
 ///
 /// | link path                                  | required mode |
@@ -58,41 +60,17 @@ pub fn required_permission(home_relative_link: &str) -> Option<(u32, &'static st
 /// On Unix, returns true iff the file's mode bits are no more permissive
 /// than `required_mode` (i.e. `(mode & !required_mode) == 0`). On Windows
 /// SPEC says no Unix-mode check is performed; we return true.
-pub fn check_permission(path: &Path, required_mode: u32) -> bool {
+pub fn check_permission(fs: &dyn Fs, path: &Path, required_mode: u32) -> bool {
     #[cfg(unix)]
     {
-        use std::os::unix::fs::PermissionsExt;
-        match std::fs::metadata(path) {
-            Ok(m) => {
-                let cur = m.permissions().mode() & 0o7777;
-                (cur & !required_mode) == 0
-            }
+        match fs.metadata(path) {
+            Ok(m) => (m.mode() & !required_mode) == 0,
             Err(_) => false,
         }
     }
     #[cfg(not(unix))]
     {
-        let _ = (path, required_mode);
-        true
-    }
-}
-
-/// Set the file's mode bits exactly to `required_mode` (Unix only).
-/// Returns true on success.
-pub fn fix_permission(path: &Path, required_mode: u32) -> bool {
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = match std::fs::metadata(path) {
-            Ok(m) => m.permissions(),
-            Err(_) => return false,
-        };
-        perms.set_mode(required_mode);
-        std::fs::set_permissions(path, perms).is_ok()
-    }
-    #[cfg(not(unix))]
-    {
-        let _ = (path, required_mode);
+        let _ = (fs, path, required_mode);
         true
     }
 }
@@ -3,26 +3,141 @@
 //! Consumes the result of `discover` and produces a [`DeployPlan`] /
 //! [`UndeployPlan`]. All configuration errors and planning-block errors
 //! across the root and all reachable dependencies are collected before
-//! returning so the user sees them in one shot.
+//! returning so the user sees them in one shot. Sensitive targets and
+//! non-symlinks in the way of an undeploy are reported as warnings,
+//! minus the kinds the declaring config allows.
 //!
 //! No filesystem modification happens here.
 
-use std::collections::BTreeMap;
-use std::fs;
+use std::collections::BTreeSet;
 use std::path::{Path, PathBuf};
 
-use crate::cli::ConflictMode;
+use crate::codes;
 use crate::discover::{is_inside, Discovered, DiscoveredConfig};
 use crate::error::{decorate, ErrorBag, XdError};
+use crate::fs::{Fs, RealFs};
+use crate::fscache::FsCache;
+use crate::logging::{self, Level};
 use crate::path as p;
 use crate::permissions;
+use crate::span::EntrySpan;
+use crate::trie::PathTrie;
+use crate::warning::{Warning, WarningBag, WarningKind};
 
 // -----------------------------------------------------------------------------
 // Plan data types
 // -----------------------------------------------------------------------------
 
+/// Conflict-handling mode per SPEC §"冲突处理模式". Exactly one mode is
+/// active for deploy and undeploy.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConflictMode {
+    /// Conservative: create missing links, skip every recoverable conflict.
+    Default,
+    /// `--force`: resolve the recoverable conflicts in the set
+    /// automatically; the rest are skipped as in default mode.
+    Force(ForceSet),
+    /// `--interactive`: ask before each recoverable destructive operation.
+    Interactive,
+}
+
+impl ConflictMode {
+    /// Mode selected by the mutually exclusive `--force[=KINDS]` /
+    /// `--interactive` flags.
+    pub fn from_flags(force: Option<ForceSet>, interactive: bool) -> Self {
+        if let Some(set) = force {
+            ConflictMode::Force(set)
+        } else if interactive {
+            ConflictMode::Interactive
+        } else {
+            ConflictMode::Default
+        }
+    }
+}
+
+/// Recoverable-conflict categories `--force` may resolve, per SPEC
+/// §"强制模式". Bare `--force` allows [`ForceSet::ALL`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ForceSet(u8);
+
+impl ForceSet {
+    /// Replace a regular file (`file`).
+    pub const FILE: ForceSet = ForceSet(1);
+    /// Replace an empty real directory (`empty-dir`).
+    pub const EMPTY_DIR: ForceSet = ForceSet(1 << 1);
+    /// Replace a wrong symlink on deploy, delete one on undeploy
+    /// (`wrong-symlink`).
+    pub const WRONG_SYMLINK: ForceSet = ForceSet(1 << 2);
+    /// Replace a broken symlink (`broken`).
+    pub const BROKEN_SYMLINK: ForceSet = ForceSet(1 << 3);
+    /// Fix the permissions of a sensitive source (`perms`).
+    pub const PERMS: ForceSet = ForceSet(1 << 4);
+    /// Every category.
+    pub const ALL: ForceSet = ForceSet(0b1_1111);
+
+    const NAMES: [(&'static str, ForceSet); 5] = [
+        ("file", ForceSet::FILE),
+        ("empty-dir", ForceSet::EMPTY_DIR),
+        ("wrong-symlink", ForceSet::WRONG_SYMLINK),
+        ("broken", ForceSet::BROKEN_SYMLINK),
+        ("perms", ForceSet::PERMS),
+    ];
+
+    /// True when every category of `other` is allowed.
+    pub fn contains(self, other: ForceSet) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Category of replacing `existing`.
+    pub fn replacing(existing: &ExistingKind) -> ForceSet {
+        match existing {
+            ExistingKind::RegularFile => ForceSet::FILE,
+            ExistingKind::EmptyRealDir => ForceSet::EMPTY_DIR,
+            ExistingKind::WrongSymlink => ForceSet::WRONG_SYMLINK,
+            ExistingKind::BrokenSymlink => ForceSet::BROKEN_SYMLINK,
+        }
+    }
+
+    /// Name of a single category, as accepted by [`ForceSet::parse`].
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, s)| *s == self)
+            .map_or("all", |(n, _)| n)
+    }
+
+    /// Names of the single categories, in declaration order; `all` is
+    /// not included.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::NAMES.iter().map(|(n, _)| *n)
+    }
+
+    /// Parse a comma-separated `--force` value such as
+    /// `broken,wrong-symlink,perms`; `all` selects every category.
+    pub fn parse(value: &str) -> Result<ForceSet, XdError> {
+        let mut set = ForceSet(0);
+        for name in value.split(',').map(str::trim) {
+            let part = match name {
+                "all" => ForceSet::ALL,
+                _ => match Self::NAMES.iter().find(|(n, _)| *n == name) {
+                    Some((_, s)) => *s,
+                    None => {
+                        let known: Vec<_> = Self::names().collect();
+                        return Err(XdError::cli(format!(
+                            "--force 的类别 {:?} 无效，可用类别: {}, all",
+                            name,
+                            known.join(", ")
+                        )));
+                    }
+                },
+            };
+            set.0 |= part.0;
+        }
+        Ok(set)
+    }
+}
+
 /// One planned action against a single link path.
-#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct DeployAction {
     /// Origin config file (for diagnostics).
@@ -37,15 +152,20 @@ pub struct DeployAction {
     pub link_raw: String,
     /// ``~/``-expanded link path (lexical, not canonical).
     pub link_expanded: PathBuf,
+    /// Create the symlink with a target relative to the link's parent
+    /// directory instead of ``source_canonical`` itself.
+    pub relative: bool,
     /// What we plan to do at the link path.
     pub kind: DeployActionKind,
     /// True iff this link path matches a SPEC permission target.
     /// In that case ``permission_required`` is ``Some((mode, label))`` and
     /// ``permission_action`` describes what we'll do about it.
     pub permission_required: Option<(u32, &'static str)>,
+    /// What to do about the source's permission bits.
     pub permission_action: PermissionAction,
 }
 
+/// What a deploy action does at its link path.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DeployActionKind {
     /// Link path does not exist; create the symlink.
@@ -61,33 +181,50 @@ pub enum DeployActionKind {
     SkipFailure(String),
 }
 
+/// Object found at a link path that deploy may replace.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExistingKind {
+    /// A regular file.
     RegularFile,
+    /// An empty real (non-symlink) directory.
     EmptyRealDir,
+    /// A symlink whose target exists but is not the source.
     WrongSymlink,
+    /// A symlink whose target does not exist.
     BrokenSymlink,
 }
 
+/// Planned handling of a SPEC permission target.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PermissionAction {
+    /// The link path is not a permission target.
     None,
+    /// The source already satisfies the required mode.
     AlreadyOk,
+    /// Tighten the source's mode to the required mode.
     Fix,
+    /// Permission problem the current mode does not fix; skip the link.
     SkipFailure(String),
 }
 
-#[allow(dead_code)]
+/// One planned action against a single link path during undeploy.
 #[derive(Debug, Clone)]
 pub struct UndeployAction {
+    /// Origin config file (for diagnostics).
     pub config_file: PathBuf,
+    /// Raw source-path string from ``[links]`` key.
     pub source_raw: String,
+    /// Canonicalized source path, if the source currently resolves.
     pub source_canonical: Option<PathBuf>,
+    /// Raw link-path string from ``[links]`` value.
     pub link_raw: String,
+    /// ``~/``-expanded link path (lexical, not canonical).
     pub link_expanded: PathBuf,
+    /// What we plan to do at the link path.
     pub kind: UndeployActionKind,
 }
 
+/// What an undeploy action does at its link path.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UndeployActionKind {
     /// Link path doesn't exist — silent success.
@@ -105,29 +242,73 @@ pub enum UndeployActionKind {
     NotASymlinkWarning,
 }
 
+/// Validated deploy plan: one action per global link entry.
 #[derive(Debug, Default)]
 pub struct DeployPlan {
+    /// Actions in stable planning order.
     pub actions: Vec<DeployAction>,
+    /// Conflict mode the plan was built for.
     pub mode: ConflictModeRecord,
 }
 
+impl DeployPlan {
+    /// True when a `--dry-run` of this plan would skip at least one
+    /// link and count it as a failure. Interactive dry-runs answer "no"
+    /// to every prompt per SPEC §"执行模式".
+    pub fn dry_run_has_failures(&self) -> bool {
+        let declined = self.mode.interactive;
+        self.actions.iter().any(|a| {
+            matches!(a.kind, DeployActionKind::SkipFailure(_))
+                || (declined && matches!(a.kind, DeployActionKind::Replace(_)))
+                || (declined && matches!(a.permission_action, PermissionAction::Fix))
+                || matches!(a.permission_action, PermissionAction::SkipFailure(_))
+        })
+    }
+}
+
+/// Validated undeploy plan: one action per global link entry.
 #[derive(Debug, Default)]
 pub struct UndeployPlan {
+    /// Actions in stable planning order.
     pub actions: Vec<UndeployAction>,
+    /// Conflict mode the plan was built for.
     pub mode: ConflictModeRecord,
 }
 
-#[allow(dead_code)]
+impl UndeployPlan {
+    /// True when a `--dry-run` of this plan would skip at least one
+    /// link and count it as a failure.
+    pub fn dry_run_has_failures(&self) -> bool {
+        let declined = self.mode.interactive;
+        self.actions.iter().any(|a| {
+            matches!(
+                a.kind,
+                UndeployActionKind::SkipFailure(_) | UndeployActionKind::NotASymlinkWarning
+            ) || (declined
+                && matches!(
+                    a.kind,
+                    UndeployActionKind::DeleteCorrect
+                        | UndeployActionKind::DeleteBroken
+                        | UndeployActionKind::DeleteWrong
+                ))
+        })
+    }
+}
+
+/// Flattened [`ConflictMode`] stored on a plan.
 #[derive(Debug, Default, Clone, Copy)]
 pub struct ConflictModeRecord {
+    /// Plan was built with `--force`.
     pub force: bool,
+    /// Plan was built with `--interactive`.
     pub interactive: bool,
 }
 
 impl ConflictModeRecord {
+    /// Record for `m`.
     pub fn from(m: ConflictMode) -> Self {
         Self {
-            force: matches!(m, ConflictMode::Force),
+            force: matches!(m, ConflictMode::Force(_)),
             interactive: matches!(m, ConflictMode::Interactive),
         }
     }
@@ -137,103 +318,275 @@ impl ConflictModeRecord {
 // Status data type for `xd status`
 // -----------------------------------------------------------------------------
 
+/// Deployment state of one link per SPEC §"xd status".
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LinkStatus {
+    /// Correct symlink to a valid source.
     Deployed,
+    /// Link path does not exist.
     NotDeployed,
+    /// Symlink to an existing object other than the source.
     WrongLink,
+    /// Symlink whose target does not exist.
     BrokenLink,
+    /// Symlink to the configured source, which does not exist.
     SourceMissing,
+    /// Symlink to the configured source, which is not a regular file or
+    /// directory or has a symlink component.
     SourceTypeInvalid,
+    /// Link path exists but is not a symlink.
     NonSymlink,
 }
 
-#[allow(dead_code)]
+/// Status of one global link entry.
 #[derive(Debug, Clone)]
 pub struct LinkStatusRecord {
+    /// Origin config file.
     pub config_file: PathBuf,
+    /// Registered repository the config belongs to, if any.
+    pub repo: Option<String>,
+    /// Raw source-path string from ``[links]`` key.
     pub source_raw: String,
+    /// Raw link-path string from ``[links]`` value.
     pub link_raw: String,
+    /// ``~/``-expanded link path.
     pub link_expanded: PathBuf,
+    /// Deployment state.
     pub status: LinkStatus,
+    /// `Some((required_mode, label))` when the link hits a SPEC
+    /// permission target whose source is too permissive.
     pub permission_issue: Option<(u32, &'static str)>,
 }
 
+/// Per-status counters for the `xd status` summary.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StatusSummary {
+    /// Number of records (`M` in `Status: N/M deployed`).
+    pub total: usize,
+    /// [`LinkStatus::Deployed`] count.
+    pub deployed: usize,
+    /// [`LinkStatus::NotDeployed`] count.
+    pub not_deployed: usize,
+    /// [`LinkStatus::WrongLink`] count.
+    pub wrong: usize,
+    /// [`LinkStatus::BrokenLink`] count.
+    pub broken: usize,
+    /// [`LinkStatus::SourceMissing`] count.
+    pub source_missing: usize,
+    /// [`LinkStatus::SourceTypeInvalid`] count.
+    pub source_type_invalid: usize,
+    /// [`LinkStatus::NonSymlink`] count.
+    pub non_symlink: usize,
+    /// Records with a permission issue, independent of their status.
+    pub permission_issues: usize,
+}
+
+impl StatusSummary {
+    /// True when anything other than a clean, fully deployed state was
+    /// observed; `xd status` exits non-zero in that case.
+    pub fn has_problems(&self) -> bool {
+        self.deployed != self.total || self.permission_issues > 0
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Build plans
 // -----------------------------------------------------------------------------
 
+/// Output of [`build_deploy_plan`]. The plan must not be applied unless
+/// `errors` is empty.
 pub struct DeployPlanResult {
+    /// Planned actions for every entry that planned cleanly.
     pub plan: DeployPlan,
+    /// Discovery plus planning errors.
     pub errors: ErrorBag,
+    /// Sensitive-target warnings.
+    pub warnings: WarningBag,
 }
 
+/// Output of [`build_undeploy_plan`]. The plan must not be applied
+/// unless `errors` is empty.
 pub struct UndeployPlanResult {
+    /// Planned actions for every entry that planned cleanly.
     pub plan: UndeployPlan,
+    /// Discovery plus planning errors.
     pub errors: ErrorBag,
+    /// Not-a-symlink warnings.
+    pub warnings: WarningBag,
 }
 
+/// Output of [`build_status`].
 pub struct StatusResult {
+    /// One record per global link entry.
     pub records: Vec<LinkStatusRecord>,
+    /// Discovery plus configuration errors.
     pub errors: ErrorBag,
 }
 
+impl StatusResult {
+    /// Count records per status.
+    pub fn summary(&self) -> StatusSummary {
+        let mut s = StatusSummary {
+            total: self.records.len(),
+            ..Default::default()
+        };
+        for r in &self.records {
+            match r.status {
+                LinkStatus::Deployed => s.deployed += 1,
+                LinkStatus::NotDeployed => s.not_deployed += 1,
+                LinkStatus::WrongLink => s.wrong += 1,
+                LinkStatus::BrokenLink => s.broken += 1,
+                LinkStatus::SourceMissing => s.source_missing += 1,
+                LinkStatus::SourceTypeInvalid => s.source_type_invalid += 1,
+                LinkStatus::NonSymlink => s.non_symlink += 1,
+            }
+            if r.permission_issue.is_some() {
+                s.permission_issues += 1;
+            }
+        }
+        s
+    }
+}
+
 /// Construct a deploy plan from a successful discovery. Errors from
 /// `disc.errors` are forwarded verbatim; new errors from this stage are
 /// appended.
 pub fn build_deploy_plan(disc: Discovered, mode: ConflictMode) -> DeployPlanResult {
+    build_deploy_plan_with(&RealFs, disc, mode)
+}
+
+/// [`build_deploy_plan`] probing `fs` instead of the live filesystem.
+pub fn build_deploy_plan_with(
+    fs: &dyn Fs,
+    disc: Discovered,
+    mode: ConflictMode,
+) -> DeployPlanResult {
     let mut errors = disc.errors;
     let entries = collect_global_links(&disc.configs, &mut errors);
+    let cache = FsCache::new(fs);
 
     let mut actions = Vec::new();
+    let mut warnings = WarningBag::new();
     for ge in entries {
-        match plan_one_deploy(&ge, mode) {
-            Ok(Some(act)) => actions.push(act),
+        match plan_one_deploy(&ge, mode, &cache) {
+            Ok(Some(act)) => {
+                // SPEC §"权限和敏感文件语义": warn, but do not block.
+                if let Some((mode, label)) = act.permission_required {
+                    warnings.push_unless_allowed(
+                        &ge.warnings_allow,
+                        Warning {
+                            kind: WarningKind::SensitiveTarget,
+                            config_file: Some(ge.config_file.clone()),
+                            path: act.link_expanded.clone(),
+                            message: format!(
+                                "链接 {} 命中敏感目标 ({}, 期望权限 {:o})；请确认该路径由 xdotter 管理",
+                                act.link_expanded.display(),
+                                label,
+                                mode
+                            ),
+                        },
+                    );
+                }
+                actions.push(act);
+            }
             Ok(None) => {}
             Err(e) => errors.push(e),
         }
     }
 
+    logging::log("plan", Level::Info, || {
+        format!(
+            "部署计划: {} 条动作, {} 个错误, {} 条警告",
+            actions.len(),
+            errors.len(),
+            warnings.len()
+        )
+    });
     DeployPlanResult {
         plan: DeployPlan {
             actions,
             mode: ConflictModeRecord::from(mode),
         },
         errors,
+        warnings,
     }
 }
 
+/// Construct an undeploy plan; same error aggregation as
+/// [`build_deploy_plan`].
 pub fn build_undeploy_plan(disc: Discovered, mode: ConflictMode) -> UndeployPlanResult {
+    build_undeploy_plan_with(&RealFs, disc, mode)
+}
+
+/// [`build_undeploy_plan`] probing `fs` instead of the live filesystem.
+pub fn build_undeploy_plan_with(
+    fs: &dyn Fs,
+    disc: Discovered,
+    mode: ConflictMode,
+) -> UndeployPlanResult {
     let mut errors = disc.errors;
     let entries = collect_global_links(&disc.configs, &mut errors);
+    let cache = FsCache::new(fs);
 
     let mut actions = Vec::new();
+    let mut warnings = WarningBag::new();
     for ge in entries {
-        match plan_one_undeploy(&ge, mode) {
-            Ok(Some(act)) => actions.push(act),
+        match plan_one_undeploy(&ge, mode, &cache) {
+            Ok(Some(act)) => {
+                if act.kind == UndeployActionKind::NotASymlinkWarning {
+                    warnings.push_unless_allowed(
+                        &ge.warnings_allow,
+                        Warning {
+                            kind: WarningKind::NotASymlink,
+                            config_file: Some(ge.config_file.clone()),
+                            path: act.link_expanded.clone(),
+                            message: format!(
+                                "链接路径 {} 是非符号链接对象，未删除",
+                                act.link_expanded.display()
+                            ),
+                        },
+                    );
+                }
+                actions.push(act);
+            }
             Ok(None) => {}
             Err(e) => errors.push(e),
         }
     }
 
+    logging::log("plan", Level::Info, || {
+        format!(
+            "卸载计划: {} 条动作, {} 个错误, {} 条警告",
+            actions.len(),
+            errors.len(),
+            warnings.len()
+        )
+    });
     UndeployPlanResult {
         plan: UndeployPlan {
             actions,
             mode: ConflictModeRecord::from(mode),
         },
         errors,
+        warnings,
     }
 }
 
 /// Build a status report. Status does not need conflict modes; it just
 /// classifies each link.
 pub fn build_status(disc: Discovered) -> StatusResult {
+    build_status_with(&RealFs, disc)
+}
+
+/// [`build_status`] probing `fs` instead of the live filesystem.
+pub fn build_status_with(fs: &dyn Fs, disc: Discovered) -> StatusResult {
     let mut errors = disc.errors;
     let entries = collect_global_links(&disc.configs, &mut errors);
+    let cache = FsCache::new(fs);
 
     let mut records = Vec::new();
     for ge in entries {
-        records.push(classify_link_for_status(&ge));
+        records.push(classify_link_for_status(&ge, &cache));
     }
     StatusResult { records, errors }
 }
@@ -242,47 +595,60 @@ pub fn build_status(disc: Discovered) -> StatusResult {
 // Internal: global link collection + per-entry resolution
 // -----------------------------------------------------------------------------
 
-#[allow(dead_code)]
 #[derive(Debug, Clone)]
-struct GlobalEntry {
-    config_file: PathBuf,
-    config_dir: PathBuf,
-    source_raw: String,
+pub(crate) struct GlobalEntry {
+    pub(crate) config_file: PathBuf,
+    pub(crate) config_dir: PathBuf,
+    pub(crate) source_raw: String,
     /// Source resolved against config_dir, lexically (no symlink resolution).
-    source_resolved: PathBuf,
-    link_raw: String,
+    pub(crate) source_resolved: PathBuf,
+    pub(crate) link_raw: String,
     /// `~/`-expanded, lexically normalized link path (used for global
     /// uniqueness comparison).
-    link_expanded: PathBuf,
+    pub(crate) link_expanded: PathBuf,
     /// Same as `link_expanded` but canonical-normalized for stable
     /// dedup keys (no filesystem canonicalize).
-    link_key: PathBuf,
+    pub(crate) link_key: PathBuf,
+    /// Config asks for a relative symlink target for this entry.
+    pub(crate) relative: bool,
+    /// Registered repository the declaring config belongs to.
+    pub(crate) repo: Option<String>,
+    /// Where the entry is written, for diagnostics.
+    pub(crate) span: Option<EntrySpan>,
+    /// Warnings the declaring config switches off.
+    pub(crate) warnings_allow: Vec<WarningKind>,
 }
 
-fn collect_global_links(configs: &[DiscoveredConfig], errors: &mut ErrorBag) -> Vec<GlobalEntry> {
+pub(crate) fn collect_global_links(
+    configs: &[DiscoveredConfig],
+    errors: &mut ErrorBag,
+) -> Vec<GlobalEntry> {
     // Per-config: validate static rules on raw source/link strings.
     // Global: detect link-path collisions and report ALL conflicting
     // declarations.
     let mut entries: Vec<GlobalEntry> = Vec::new();
-    let mut by_link: BTreeMap<PathBuf, Vec<usize>> = BTreeMap::new();
+    let mut by_link: PathTrie<usize> = PathTrie::new();
 
     for c in configs {
         for (src_raw, link_raw) in &c.config.links {
+            let span = c.config.link_spans.get(src_raw);
+            let key_at = span.map(|s| &s.key);
+            let value_at = span.map(|s| &s.value);
             // Static source-path rules.
             if let Err(e) = p::validate_source_path(src_raw) {
-                errors.push(decorate(&e, &c.config_file, None));
+                errors.push(decorate(&e, &c.config_file, None, key_at));
                 continue;
             }
             // Static link-path rules.
             if let Err(e) = p::validate_link_path(link_raw) {
-                errors.push(decorate(&e, &c.config_file, None));
+                errors.push(decorate(&e, &c.config_file, None, value_at));
                 continue;
             }
             let source_resolved = p::normalize(&c.config_dir.join(src_raw));
             let expanded = match p::expand_tilde(link_raw) {
                 Ok(p) => p::normalize(&p),
                 Err(e) => {
-                    errors.push(decorate(&e, &c.config_file, None));
+                    errors.push(decorate(&e, &c.config_file, None, value_at));
                     continue;
                 }
             };
@@ -296,48 +662,69 @@ fn collect_global_links(configs: &[DiscoveredConfig], errors: &mut ErrorBag) ->
                 link_raw: link_raw.clone(),
                 link_expanded: expanded,
                 link_key: key.clone(),
+                relative: c.config.is_relative(src_raw),
+                repo: c.repo.clone(),
+                span: span.cloned(),
+                warnings_allow: c.config.warnings_allow.clone(),
             });
-            by_link.entry(key).or_default().push(idx);
+            by_link.insert(&key, idx);
         }
     }
 
     // Detect collisions and emit one config error per collision group,
     // listing all (config_file, source) pairs.
-    let mut bad_indices: std::collections::BTreeSet<usize> = Default::default();
-    for (link_key, idxs) in &by_link {
-        if idxs.len() < 2 {
-            continue;
-        }
+    let mut bad_indices: BTreeSet<usize> = Default::default();
+    for idxs in by_link.collisions() {
+        let link_key = entries[idxs[0]].link_key.clone();
         let mut listing = String::new();
-        for &i in idxs {
+        for &i in &idxs {
             let e = &entries[i];
             listing.push_str(&format!(
-                "\n  - {} (源 \"{}\")",
-                e.config_file.display(),
+                "\n  - {}{} (源 \"{}\")",
+                repo_label(e.repo.as_deref()),
+                e.location(),
                 e.source_raw
             ));
+            if let Some(span) = &e.span {
+                for line in span.value.snippet().lines() {
+                    listing.push_str("\n    ");
+                    listing.push_str(line);
+                }
+            }
             bad_indices.insert(i);
         }
-        errors.push(XdError::config(format!(
-            "多个链接条目展开后指向同一链接路径 {}：{}",
-            link_key.display(),
-            listing
-        )));
+        errors.push(
+            XdError::config(format!(
+                "多个链接条目展开后指向同一链接路径 {}：{}",
+                link_key.display(),
+                listing
+            ))
+            .with_code(codes::LINK_COLLISION),
+        );
     }
 
     // Detect topological nesting: no link path may be inside another link
     // path, because the parent link would be a symlink and creating a
-    // child inside it is unsafe (SPEC §"符号链接安全语义").
-    let nesting_pairs = detect_link_nesting(&entries);
+    // child inside it is unsafe (SPEC §"符号链接安全语义"). Pairs are
+    // reported in (outer, inner) declaration order.
+    let mut nesting_pairs = by_link.nested_pairs();
+    nesting_pairs.sort_unstable();
     for (outer_idx, inner_idx) in nesting_pairs {
         let outer = &entries[outer_idx];
         let inner = &entries[inner_idx];
-        errors.push(XdError::planning(format!(
-            "链接路径 {} 位于另一链接路径 {} 内部（源 \"{}\"），不允许在符号链接内部创建子链接",
+        let mut message = format!(
+            "链接路径 {} 位于另一链接路径 {} 内部（{}{} 源 \"{}\"），不允许在符号链接内部创建子链接",
             inner.link_expanded.display(),
             outer.link_expanded.display(),
+            repo_label(inner.repo.as_deref()),
+            inner.location(),
             inner.source_raw
-        )));
+        );
+        if let Some(span) = &inner.span {
+            message.push('\n');
+            message.push_str(&span.value.snippet());
+        }
+        errors.push(XdError::planning(message).with_code(codes::LINK_NESTED));
         bad_indices.insert(outer_idx);
         bad_indices.insert(inner_idx);
     }
@@ -356,66 +743,84 @@ fn collect_global_links(configs: &[DiscoveredConfig], errors: &mut ErrorBag) ->
         .collect()
 }
 
-/// Detect pairs of link entries where one expanded link path is inside
-fn detect_link_nesting(entries: &[GlobalEntry]) -> Vec<(usize, usize)> {
-    let mut pairs = Vec::new();
-    for i in 0..entries.len() {
-        for j in 0..entries.len() {
-            if i == j {
-                continue;
-            }
-            let outer = &entries[i].link_expanded;
-            let inner = &entries[j].link_expanded;
-            if is_proper_path_prefix(outer, inner) {
-                pairs.push((i, j));
-            }
+impl GlobalEntry {
+    /// `path:line:column` of the entry's link value, or the config path
+    /// when the entry was not located.
+    fn location(&self) -> String {
+        match &self.span {
+            Some(s) => format!(
+                "{}:{}:{}",
+                self.config_file.display(),
+                s.value.line,
+                s.value.column
+            ),
+            None => self.config_file.display().to_string(),
         }
     }
-    pairs
-}
 
-/// Check if `outer` is a proper path prefix of `inner` — i.e.,
-/// `inner` starts with all components of `outer` and has additional
-/// components beyond that. This avoids `starts_with` false positives
-/// like `/home/user/.a` matching `/home/user/.abc`.
-fn is_proper_path_prefix(outer: &Path, inner: &Path) -> bool {
-    let mut outer_comps = outer.components();
-    let mut inner_comps = inner.components();
-    loop {
-        match (outer_comps.next(), inner_comps.next()) {
-            (Some(o), Some(i)) if o == i => continue,
-            (None, Some(_)) => return true,
-            _ => return false,
-        }
+    /// Attribute a planning error about this entry to its config file,
+    /// pointing at the entry's key.
+    fn decorate(&self, e: &XdError) -> XdError {
+        decorate(
+            e,
+            &self.config_file,
+            None,
+            self.span.as_ref().map(|s| &s.key),
+        )
     }
 }
 
-
+/// `[name] ` prefix naming a registered repository in diagnostics.
+fn repo_label(repo: Option<&str>) -> String {
+    repo.map(|r| format!("[{}] ", r)).unwrap_or_default()
+}
 
 // -----------------------------------------------------------------------------
 // Per-entry deploy planning
 // -----------------------------------------------------------------------------
-fn plan_one_deploy(ge: &GlobalEntry, mode: ConflictMode) -> Result<Option<DeployAction>, XdError> {
+fn plan_one_deploy(
+    ge: &GlobalEntry,
+    mode: ConflictMode,
+    cache: &FsCache,
+) -> Result<Option<DeployAction>, XdError> {
+    trace(ge, || {
+        format!(
+            "规划部署 \"{}\" = \"{}\" ({}): 源={}, 模式={:?}, relative={}",
+            ge.source_raw,
+            ge.link_raw,
+            ge.config_file.display(),
+            ge.source_resolved.display(),
+            mode,
+            ge.relative
+        )
+    });
     // 1. Source must exist and be a regular file or directory; no
     //    component (final or intermediate) may be a symlink; must stay
     //    inside config dir tree.
-    let source_canonical = match validate_source_filesystem(&ge.source_resolved, &ge.config_dir) {
-        Ok(p) => p,
-        Err(e) => return Err(decorate(&e, &ge.config_file, None)),
-    };
+    let source_canonical =
+        match validate_source_filesystem(&ge.source_resolved, &ge.config_dir, cache) {
+            Ok(p) => p,
+            Err(e) => {
+                trace(ge, || format!("源路径不可用: {}", e.body()));
+                return Err(ge.decorate(&e));
+            }
+        };
+    trace(ge, || format!("源规范路径 {}", source_canonical.display()));
 
     // 2. Inspect existing object at link_expanded before topology checks.
     //    A correct existing symlink is already deployed and must not be
     //    rejected merely because following the symlink reaches the source.
-    let kind = classify_link_target(&ge.link_expanded, &source_canonical);
+    let kind = classify_link_target(&ge.link_expanded, &source_canonical, cache);
+    trace(ge, || format!("链接路径现状 {:?}", kind));
 
     // 3. Topological safety with the link path. Already-correct symlinks
     //    do not require creating/replacing the link itself, so parent/link
     //    creation topology is irrelevant for that action (permission checks
     //    are handled separately below).
     if !matches!(kind, LinkSlot::CorrectSymlink) {
-        if let Err(e) = check_topology(&ge.link_expanded, &source_canonical) {
-            return Err(decorate(&e, &ge.config_file, None));
+        if let Err(e) = check_topology(&ge.link_expanded, &source_canonical, cache) {
+            trace(ge, || format!("拓扑检查失败: {}", e.body()));
+            return Err(ge.decorate(&e));
         }
     }
 
@@ -428,15 +833,14 @@ fn plan_one_deploy(ge: &GlobalEntry, mode: ConflictMode) -> Result<Option<Deploy
         LinkSlot::RegularFile => act_for_replace(ExistingKind::RegularFile, mode),
         LinkSlot::EmptyRealDir => {
             // Replacing this directory must not delete or contain the source.
-            if dir_contains_source(&ge.link_expanded, &source_canonical) {
-                return Err(decorate(
+            if dir_contains_source(&ge.link_expanded, &source_canonical, cache) {
+                return Err(ge.decorate(
                     &XdError::planning(format!(
                         "目标目录 {} 包含源路径 {}，替换会删除或包含源",
                         ge.link_expanded.display(),
                         source_canonical.display()
-                    )),
-                    &ge.config_file,
-                    None,
+                    ))
+                    .with_code(codes::TARGET_CONTAINS_SOURCE),
                 ));
             }
             act_for_replace(ExistingKind::EmptyRealDir, mode)
@@ -449,10 +853,19 @@ fn plan_one_deploy(ge: &GlobalEntry, mode: ConflictMode) -> Result<Option<Deploy
     // 4. Permissions (only if link path matches SPEC table). A default-mode
     //    permission issue is a recoverable conflict that skips the entire
     //    link before any create/replace filesystem modification happens.
-    let (perm_required, perm_action) = plan_permission(&ge.link_expanded, &source_canonical, mode);
+    let (perm_required, perm_action) =
+        plan_permission(&ge.link_expanded, &source_canonical, mode, cache.fs());
+    if perm_required.is_some() {
+        trace(ge, || {
+            format!("权限目标 {:?}: {:?}", perm_required, perm_action)
+        });
+    }
     if let PermissionAction::SkipFailure(reason) = &perm_action {
         action_kind = DeployActionKind::SkipFailure(reason.clone());
     }
+    logging::log("plan", Level::Debug, || {
+        format!("{}: {:?}", ge.link_expanded.display(), action_kind)
+    });
 
     Ok(Some(DeployAction {
         config_file: ge.config_file.clone(),
@@ -461,23 +874,40 @@ fn plan_one_deploy(ge: &GlobalEntry, mode: ConflictMode) -> Result<Option<Deploy
         source_canonical,
         link_raw: ge.link_raw.clone(),
         link_expanded: ge.link_expanded.clone(),
+        relative: ge.relative,
         kind: action_kind,
         permission_required: perm_required,
         permission_action: perm_action,
     }))
 }
 
+/// A planning decision about `ge`, at trace level.
+fn trace(ge: &GlobalEntry, message: impl FnOnce() -> String) {
+    logging::log("plan", Level::Trace, || {
+        format!("{}: {}", ge.link_expanded.display(), message())
+    });
+}
+
 fn act_for_replace(existing: ExistingKind, mode: ConflictMode) -> DeployActionKind {
     match mode {
         ConflictMode::Default => DeployActionKind::SkipFailure(format!(
             "默认模式不替换已有对象 ({})",
             describe_existing(&existing)
         )),
-        ConflictMode::Force | ConflictMode::Interactive => DeployActionKind::Replace(existing),
+        ConflictMode::Force(set) if !set.contains(ForceSet::replacing(&existing)) => {
+            DeployActionKind::SkipFailure(format!(
+                "--force 未包含 {}，不替换已有对象 ({})",
+                ForceSet::replacing(&existing).name(),
+                describe_existing(&existing)
+            ))
+        }
+        ConflictMode::Force(_) | ConflictMode::Interactive => DeployActionKind::Replace(existing),
     }
 }
 
-pub(crate) fn describe_existing(k: &ExistingKind) -> &'static str {
+/// Short Chinese description of an existing object, as used in
+/// diagnostics.
+pub fn describe_existing(k: &ExistingKind) -> &'static str {
     match k {
         ExistingKind::RegularFile => "普通文件",
         ExistingKind::EmptyRealDir => "空真实目录",
@@ -490,6 +920,7 @@ fn plan_permission(
     link_expanded: &Path,
     source_canonical: &Path,
     mode: ConflictMode,
+    fs: &dyn Fs,
 ) -> (Option<(u32, &'static str)>, PermissionAction) {
     let key = match link_path_to_tilde_key(link_expanded) {
         Some(k) => k,
@@ -499,7 +930,7 @@ fn plan_permission(
         Some(v) => v,
         None => return (None, PermissionAction::None),
     };
-    if permissions::check_permission(source_canonical, mode_required) {
+    if permissions::check_permission(fs, source_canonical, mode_required) {
         return (Some((mode_required, label)), PermissionAction::AlreadyOk);
     }
     let action = match mode {
@@ -507,7 +938,13 @@ fn plan_permission(
             "{} 权限过宽（要求不宽于 {:o}）",
             label, mode_required
         )),
-        ConflictMode::Force | ConflictMode::Interactive => PermissionAction::Fix,
+        ConflictMode::Force(set) if !set.contains(ForceSet::PERMS) => {
+            PermissionAction::SkipFailure(format!(
+                "{} 权限过宽（要求不宽于 {:o}），--force 未包含 perms",
+                label, mode_required
+            ))
+        }
+        ConflictMode::Force(_) | ConflictMode::Interactive => PermissionAction::Fix,
     };
     (Some((mode_required, label)), action)
 }
@@ -524,8 +961,6 @@ pub fn link_path_to_tilde_key(link: &Path) -> Option<String> {
     Some(format!("~/{}", stripped.display()))
 }
 
-
-
 // -----------------------------------------------------------------------------
 // Per-entry undeploy planning
 // -----------------------------------------------------------------------------
@@ -533,15 +968,16 @@ pub fn link_path_to_tilde_key(link: &Path) -> Option<String> {
 fn plan_one_undeploy(
     ge: &GlobalEntry,
     mode: ConflictMode,
+    cache: &FsCache,
 ) -> Result<Option<UndeployAction>, XdError> {
     // SPEC: undeploy applies the same dependency-path rules as deploy
     // (already covered by discover) and the same link-path rules
     // (already covered by collect_global_links). Source need not exist
     // for undeploy to function, but we still resolve it to compare
     // symlink targets.
-    let source_canonical = ge.source_resolved.canonicalize().ok();
+    let source_canonical = cache.canonicalize(&ge.source_resolved).ok();
 
-    let kind = match read_link_target(&ge.link_expanded) {
+    let kind = match read_link_target(&ge.link_expanded, cache) {
         LinkProbe::DoesNotExist => UndeployActionKind::NotPresent,
         LinkProbe::NotASymlink => UndeployActionKind::NotASymlinkWarning,
         LinkProbe::Symlink {
@@ -572,7 +1008,12 @@ fn plan_one_undeploy(
                     ConflictMode::Default => {
                         UndeployActionKind::SkipFailure("默认模式不删除错误符号链接".to_string())
                     }
-                    ConflictMode::Force | ConflictMode::Interactive => {
+                    ConflictMode::Force(set) if !set.contains(ForceSet::WRONG_SYMLINK) => {
+                        UndeployActionKind::SkipFailure(
+                            "--force 未包含 wrong-symlink，不删除错误符号链接".to_string(),
+                        )
+                    }
+                    ConflictMode::Force(_) | ConflictMode::Interactive => {
                         UndeployActionKind::DeleteWrong
                     }
                 }
@@ -580,6 +1021,9 @@ fn plan_one_undeploy(
         }
     };
 
+    logging::log("plan", Level::Debug, || {
+        format!("{}: {:?}", ge.link_expanded.display(), kind)
+    });
     Ok(Some(UndeployAction {
         config_file: ge.config_file.clone(),
         source_raw: ge.source_raw.clone(),
@@ -594,32 +1038,31 @@ fn plan_one_undeploy(
 // Per-entry status classification
 // -----------------------------------------------------------------------------
 
-fn classify_link_for_status(ge: &GlobalEntry) -> LinkStatusRecord {
+fn classify_link_for_status(ge: &GlobalEntry, cache: &FsCache) -> LinkStatusRecord {
     let lp = &ge.link_expanded;
     let mut status = LinkStatus::NotDeployed;
     let mut permission_issue = None;
+    let source_canon = cache.canonicalize(&ge.source_resolved).ok();
 
-    let exists_or_link = lp.exists() || lp.is_symlink();
+    let exists_or_link = cache.exists(lp) || cache.is_symlink(lp);
     if !exists_or_link {
         // not deployed
-    } else if !lp.is_symlink() {
+    } else if !cache.is_symlink(lp) {
         status = LinkStatus::NonSymlink;
     } else {
         // It is a symlink — read its target.
-        match fs::read_link(lp) {
-            Ok(t) => {
+        match cache.read_link(lp) {
+            Some(t) => {
                 let target_abs = if t.is_absolute() {
                     t.clone()
                 } else {
                     lp.parent().map(|pp| pp.join(&t)).unwrap_or(t.clone())
                 };
-                let target_meta = fs::metadata(&target_abs);
-                if target_meta.is_err() {
+                if !cache.exists(&target_abs) {
                     status = LinkStatus::BrokenLink;
                 } else {
                     // Compare canonical of target vs canonical of configured source.
-                    let target_canon = target_abs.canonicalize().ok();
-                    let source_canon = ge.source_resolved.canonicalize().ok();
+                    let target_canon = cache.canonicalize(&target_abs).ok();
                     let points_to_us = match (&target_canon, &source_canon) {
                         (Some(a), Some(b)) => a == b,
                         _ => false,
@@ -628,10 +1071,14 @@ fn classify_link_for_status(ge: &GlobalEntry) -> LinkStatusRecord {
                         status = LinkStatus::WrongLink;
                     } else {
                         // Now check source health for SPEC §"源路径".
-                        if !ge.source_resolved.exists() {
+                        if !cache.exists(&ge.source_resolved) {
                             status = LinkStatus::SourceMissing;
-                        } else if !is_regular_file_or_dir(&ge.source_resolved)
-                            || any_symlink_component(&ge.source_resolved, &ge.config_dir)
+                        } else if !is_regular_file_or_dir(&ge.source_resolved, cache)
+                            || any_symlink_component_cached(
+                                &ge.source_resolved,
+                                &ge.config_dir,
+                                cache,
+                            )
                         {
                             status = LinkStatus::SourceTypeInvalid;
                         } else {
@@ -640,7 +1087,7 @@ fn classify_link_for_status(ge: &GlobalEntry) -> LinkStatusRecord {
                     }
                 }
             }
-            Err(_) => status = LinkStatus::BrokenLink,
+            None => status = LinkStatus::BrokenLink,
         }
     }
 
@@ -648,9 +1095,8 @@ fn classify_link_for_status(ge: &GlobalEntry) -> LinkStatusRecord {
     if let Some(key) = link_path_to_tilde_key(lp) {
         if let Some((mode, label)) = permissions::required_permission(&key) {
             // Only meaningful when source object exists.
-            let src = ge.source_resolved.canonicalize().ok();
-            if let Some(src) = src {
-                if !permissions::check_permission(&src, mode) {
+            if let Some(src) = &source_canon {
+                if !permissions::check_permission(cache.fs(), src, mode) {
                     permission_issue = Some((mode, label));
                 }
             }
@@ -659,6 +1105,7 @@ fn classify_link_for_status(ge: &GlobalEntry) -> LinkStatusRecord {
 
     LinkStatusRecord {
         config_file: ge.config_file.clone(),
+        repo: ge.repo.clone(),
         source_raw: ge.source_raw.clone(),
         link_raw: ge.link_raw.clone(),
         link_expanded: ge.link_expanded.clone(),
@@ -672,36 +1119,42 @@ fn classify_link_for_status(ge: &GlobalEntry) -> LinkStatusRecord {
 // -----------------------------------------------------------------------------
 
 /// Validate the source path against the filesystem per SPEC.
-fn validate_source_filesystem(source: &Path, config_dir: &Path) -> Result<PathBuf, XdError> {
-    if !source.exists() && !source.is_symlink() {
-        return Err(XdError::planning(format!(
-            "源路径不存在: {}",
-            source.display()
-        )));
+fn validate_source_filesystem(
+    source: &Path,
+    config_dir: &Path,
+    cache: &FsCache,
+) -> Result<PathBuf, XdError> {
+    if !cache.exists(source) && !cache.is_symlink(source) {
+        return Err(
+            XdError::planning(format!("源路径不存在: {}", source.display()))
+                .with_code(codes::SOURCE_MISSING),
+        );
     }
-    if any_symlink_component(source, config_dir) {
-        return Err(XdError::planning(format!(
-            "源路径任一组件是符号链接: {}",
-            source.display()
-        )));
+    if any_symlink_component_cached(source, config_dir, cache) {
+        return Err(
+            XdError::planning(format!("源路径任一组件是符号链接: {}", source.display()))
+                .with_code(codes::SOURCE_SYMLINK_COMPONENT),
+        );
     }
-    let canon = source
-        .canonicalize()
-        .map_err(|e| XdError::planning(format!("无法访问源路径 {}: {}", source.display(), e)))?;
-    if !is_regular_file_or_dir(&canon) {
-        return Err(XdError::planning(format!(
-            "源路径不是普通文件或目录: {}",
-            source.display()
-        )));
+    let canon = cache.canonicalize(source).map_err(|e| {
+        XdError::planning(format!("无法访问源路径 {}: {}", source.display(), e))
+            .with_code(codes::SOURCE_INACCESSIBLE)
+    })?;
+    if !is_regular_file_or_dir(&canon, cache) {
+        return Err(
+            XdError::planning(format!("源路径不是普通文件或目录: {}", source.display()))
+                .with_code(codes::SOURCE_NOT_FILE_OR_DIR),
+        );
     }
-    let canon_dir = config_dir
-        .canonicalize()
+    let canon_dir = cache
+        .canonicalize(config_dir)
         .unwrap_or_else(|_| config_dir.to_path_buf());
     if !is_inside(&canon, &canon_dir) {
         return Err(XdError::planning(format!(
             "源路径解析后逃出当前配置目录树: {}",
             source.display()
-        )));
+        ))
+        .with_code(codes::SOURCE_ESCAPES));
     }
     Ok(canon)
 }
@@ -709,42 +1162,41 @@ fn validate_source_filesystem(source: &Path, config_dir: &Path) -> Result<PathBu
 /// True iff any component of `p`, walked from `boundary` downward, is
 /// a symlink. `boundary` is the config dir; everything at or above it
 /// is not the source's responsibility.
-pub(crate) fn any_symlink_component(p: &Path, boundary: &Path) -> bool {
-    let boundary = boundary
-        .canonicalize()
+///
+/// Always probes `fs` afresh; the apply stage relies on this for its
+/// re-checks.
+pub(crate) fn any_symlink_component(fs: &dyn Fs, p: &Path, boundary: &Path) -> bool {
+    any_symlink_component_cached(p, boundary, &FsCache::new(fs))
+}
+
+fn any_symlink_component_cached(p: &Path, boundary: &Path, cache: &FsCache) -> bool {
+    let boundary = cache
+        .canonicalize(boundary)
         .unwrap_or_else(|_| boundary.to_path_buf());
     let mut cur = PathBuf::new();
     for comp in p.components() {
         cur.push(comp.as_os_str());
-        // Skip components up to and including the boundary.
-        let Ok(cmeta) = fs::symlink_metadata(&cur) else {
-            // Component does not yet exist — we won't classify as symlink.
+        // The boundary itself or its ancestors being symlinks is outside
+        // of source-path scope; only components strictly inside boundary
+        // are checked.
+        let inside = cur
+            .strip_prefix(&boundary)
+            .is_ok_and(|rel| !rel.as_os_str().is_empty());
+        if !inside {
             continue;
-        };
-        if cmeta.file_type().is_symlink() {
-            // If `cur` is inside or equal to boundary, ignore.
-            if let Ok(canon_cur) = cur.canonicalize() {
-                if !canon_cur.starts_with(&boundary) {
-                    // doesn't matter for this check — what matters is "is symlink".
-                }
-            }
-            // The boundary itself or its ancestors being symlinks is
-            // outside of source-path scope; only report symlinks for
-            // components strictly inside boundary.
-            if let Ok(rel) = cur.strip_prefix(&boundary) {
-                if !rel.as_os_str().is_empty() {
-                    return true;
-                }
-            }
+        }
+        // A component that does not exist yet is not classified as symlink.
+        if cache.is_symlink(&cur) {
+            return true;
         }
     }
     false
 }
 
-fn is_regular_file_or_dir(canon: &Path) -> bool {
-    match fs::metadata(canon) {
-        Ok(m) => m.is_file() || m.is_dir(),
-        Err(_) => false,
+fn is_regular_file_or_dir(canon: &Path, cache: &FsCache) -> bool {
+    match cache.metadata(canon) {
+        Some(m) => m.is_file() || m.is_dir(),
+        None => false,
     }
 }
 
@@ -759,32 +1211,31 @@ enum LinkSlot {
     NonEmptyRealDir,
 }
 
-fn classify_link_target(link: &Path, source_canon: &Path) -> LinkSlot {
-    let symlink_meta = fs::symlink_metadata(link);
-    match symlink_meta {
-        Err(_) => LinkSlot::Missing,
-        Ok(m) => {
+fn classify_link_target(link: &Path, source_canon: &Path, cache: &FsCache) -> LinkSlot {
+    match cache.symlink_metadata(link) {
+        None => LinkSlot::Missing,
+        Some(m) => {
             let ft = m.file_type();
             if ft.is_symlink() {
-                match fs::read_link(link) {
-                    Ok(t) => {
+                match cache.read_link(link) {
+                    Some(t) => {
                         let abs = if t.is_absolute() {
                             t
                         } else {
                             link.parent().map(|p| p.join(&t)).unwrap_or(t)
                         };
-                        match abs.canonicalize() {
+                        match cache.canonicalize(&abs) {
                             Ok(c) if c == source_canon => LinkSlot::CorrectSymlink,
                             Ok(_) => LinkSlot::WrongSymlink,
                             Err(_) => LinkSlot::BrokenSymlink,
                         }
                     }
-                    Err(_) => LinkSlot::BrokenSymlink,
+                    None => LinkSlot::BrokenSymlink,
                 }
             } else if ft.is_file() {
                 LinkSlot::RegularFile
             } else if ft.is_dir() {
-                if dir_is_empty(link) {
+                if dir_is_empty(cache.fs(), link) {
                     LinkSlot::EmptyRealDir
                 } else {
                     LinkSlot::NonEmptyRealDir
@@ -797,14 +1248,14 @@ fn classify_link_target(link: &Path, source_canon: &Path) -> LinkSlot {
     }
 }
 
-fn dir_is_empty(p: &Path) -> bool {
-    fs::read_dir(p)
-        .map(|mut it| it.next().is_none())
-        .unwrap_or(false)
+fn dir_is_empty(fs: &dyn Fs, p: &Path) -> bool {
+    fs.read_dir(p).map(|es| es.is_empty()).unwrap_or(false)
 }
 
-fn dir_contains_source(link: &Path, source_canon: &Path) -> bool {
-    let link_canon = link.canonicalize().unwrap_or_else(|_| link.to_path_buf());
+fn dir_contains_source(link: &Path, source_canon: &Path, cache: &FsCache) -> bool {
+    let link_canon = cache
+        .canonicalize(link)
+        .unwrap_or_else(|_| link.to_path_buf());
     is_inside(source_canon, &link_canon)
 }
 
@@ -819,21 +1270,21 @@ enum LinkProbe {
     },
 }
 
-fn read_link_target(link: &Path) -> LinkProbe {
-    match fs::symlink_metadata(link) {
-        Err(_) => LinkProbe::DoesNotExist,
-        Ok(m) => {
+fn read_link_target(link: &Path, cache: &FsCache) -> LinkProbe {
+    match cache.symlink_metadata(link) {
+        None => LinkProbe::DoesNotExist,
+        Some(m) => {
             if !m.file_type().is_symlink() {
                 return LinkProbe::NotASymlink;
             }
-            match fs::read_link(link) {
-                Ok(t) => {
+            match cache.read_link(link) {
+                Some(t) => {
                     let abs = if t.is_absolute() {
                         t
                     } else {
                         link.parent().map(|p| p.join(&t)).unwrap_or(t)
                     };
-                    let canon = abs.canonicalize().ok();
+                    let canon = cache.canonicalize(&abs).ok();
                     let exists = canon.is_some();
                     LinkProbe::Symlink {
                         target_exists: exists,
@@ -841,7 +1292,7 @@ fn read_link_target(link: &Path) -> LinkProbe {
                         target_canonical: canon,
                     }
                 }
-                Err(_) => LinkProbe::Symlink {
+                None => LinkProbe::Symlink {
                     target_exists: false,
                     target_abs: PathBuf::new(),
                     target_canonical: None,
@@ -855,22 +1306,27 @@ fn read_link_target(link: &Path) -> LinkProbe {
 // Topology safety per SPEC §"符号链接安全语义"
 // -----------------------------------------------------------------------------
 
-fn check_topology(link: &Path, source_canon: &Path) -> Result<(), XdError> {
-    check_same_object_or_nesting(link, source_canon)?;
-    check_unsafe_ancestors(link, source_canon)?;
-    check_for_loop(link, source_canon)?;
+fn check_topology(link: &Path, source_canon: &Path, cache: &FsCache) -> Result<(), XdError> {
+    check_same_object_or_nesting(link, source_canon, cache)?;
+    check_unsafe_ancestors(link, source_canon, cache)?;
+    check_for_loop(link, source_canon, cache)?;
     Ok(())
 }
 
 /// Check that link and source are not the same object and that the link
 /// does not end up inside the source (either canonically or lexically).
-fn check_same_object_or_nesting(link: &Path, source_canon: &Path) -> Result<(), XdError> {
-    if let Ok(link_canon) = link.canonicalize() {
+fn check_same_object_or_nesting(
+    link: &Path,
+    source_canon: &Path,
+    cache: &FsCache,
+) -> Result<(), XdError> {
+    if let Ok(link_canon) = cache.canonicalize(link) {
         if link_canon == source_canon {
             return Err(XdError::planning(format!(
                 "链接路径与源路径解析为同一对象: {}",
                 source_canon.display()
-            )));
+            ))
+            .with_code(codes::LINK_IS_SOURCE));
         }
         // Link is inside source.
         if is_inside(&link_canon, source_canon) && link_canon != *source_canon {
@@ -878,7 +1334,8 @@ fn check_same_object_or_nesting(link: &Path, source_canon: &Path) -> Result<(),
                 "链接路径位于源路径内部: {} 在 {} 之内",
                 link_canon.display(),
                 source_canon.display()
-            )));
+            ))
+            .with_code(codes::LINK_INSIDE_SOURCE));
         }
     } else {
         // Link doesn't exist yet: first check lexical path prefix
@@ -892,12 +1349,13 @@ fn check_same_object_or_nesting(link: &Path, source_canon: &Path) -> Result<(),
                 "链接路径位于源路径内部: {} 在 {} 之内",
                 link.display(),
                 source_canon.display()
-            )));
+            ))
+            .with_code(codes::LINK_INSIDE_SOURCE));
         }
         // Then check the lexical relationship after resolving any
         // parent directory that does exist.
         if let Some(parent) = link.parent() {
-            if let Ok(parent_canon) = parent.canonicalize() {
+            if let Ok(parent_canon) = cache.canonicalize(parent) {
                 let final_pos = link
                     .file_name()
                     .map(|n| parent_canon.join(n))
@@ -906,7 +1364,8 @@ fn check_same_object_or_nesting(link: &Path, source_canon: &Path) -> Result<(),
                     return Err(XdError::planning(format!(
                         "链接路径会落在源路径内部或等于源路径: {}",
                         source_canon.display()
-                    )));
+                    ))
+                    .with_code(codes::LINK_INSIDE_SOURCE));
                 }
             }
         }
@@ -917,10 +1376,14 @@ fn check_same_object_or_nesting(link: &Path, source_canon: &Path) -> Result<(),
 /// Walk up from link's parent; reject if any existing ancestor is a
 /// non-directory, or a symlink whose rebased creation position would
 /// land inside or equal to the source.
-fn check_unsafe_ancestors(link: &Path, source_canon: &Path) -> Result<(), XdError> {
+fn check_unsafe_ancestors(
+    link: &Path,
+    source_canon: &Path,
+    cache: &FsCache,
+) -> Result<(), XdError> {
     let mut cur = link.parent();
     while let Some(c) = cur {
-        let Ok(meta) = fs::symlink_metadata(c) else {
+        let Some(meta) = cache.symlink_metadata(c) else {
             cur = c.parent();
             if cur == Some(c) {
                 break;
@@ -930,29 +1393,30 @@ fn check_unsafe_ancestors(link: &Path, source_canon: &Path) -> Result<(), XdErro
         let ft = meta.file_type();
         if ft.is_symlink() {
             if let Ok(rest) = link.strip_prefix(c) {
-                if let Ok(target) = fs::read_link(c) {
+                if let Some(target) = cache.read_link(c) {
                     let target_abs = if target.is_absolute() {
                         target
                     } else {
                         c.parent().map(|p| p.join(&target)).unwrap_or(target)
                     };
-                    if let Ok(canon) = target_abs.canonicalize() {
+                    if let Ok(canon) = cache.canonicalize(&target_abs) {
                         let final_pos = canon.join(rest);
                         if final_pos == *source_canon || is_inside(&final_pos, source_canon) {
                             return Err(XdError::planning(format!(
                                 "链接路径祖先 {} 是不安全的符号链接 (-> {})",
                                 c.display(),
                                 canon.display()
-                            )));
+                            ))
+                            .with_code(codes::ANCESTOR_UNSAFE_SYMLINK));
                         }
                     }
                 }
             }
         } else if !ft.is_dir() {
-            return Err(XdError::planning(format!(
-                "链接路径祖先 {} 不是目录",
-                c.display()
-            )));
+            return Err(
+                XdError::planning(format!("链接路径祖先 {} 不是目录", c.display()))
+                    .with_code(codes::ANCESTOR_NOT_DIR),
+            );
         }
         let next = c.parent();
         if next == Some(c) {
@@ -965,42 +1429,35 @@ fn check_unsafe_ancestors(link: &Path, source_canon: &Path) -> Result<(), XdErro
 
 /// Reject if an ancestor symlink's target would create a cycle with
 /// the new link.
-fn check_for_loop(link: &Path, source_canon: &Path) -> Result<(), XdError> {
-    if would_create_loop(link, source_canon) {
+fn check_for_loop(link: &Path, source_canon: &Path, cache: &FsCache) -> Result<(), XdError> {
+    if would_create_loop(link, cache) {
         return Err(XdError::planning(format!(
             "创建符号链接 {} -> {} 会产生符号链接循环",
             link.display(),
             source_canon.display()
-        )));
+        ))
+        .with_code(codes::SYMLINK_LOOP));
     }
     Ok(())
 }
 
-fn would_create_loop(link: &Path, source_canon: &Path) -> bool {
+fn would_create_loop(link: &Path, cache: &FsCache) -> bool {
     // Walk from link's parent upwards; if any ancestor is a symlink
     // whose canonical target equals or contains the link-creation site,
-    // we'd loop.
+    // we'd loop. A source below a symlinked ancestor is not a loop.
     let mut cur = link.parent();
     while let Some(c) = cur {
-        if let Ok(meta) = fs::symlink_metadata(c) {
-            if meta.file_type().is_symlink() {
-                if let Ok(t) = fs::read_link(c) {
-                    let abs = if t.is_absolute() {
-                        t
-                    } else {
-                        c.parent().map(|p| p.join(&t)).unwrap_or(t)
-                    };
-                    if let Ok(canon) = abs.canonicalize() {
-                        if let Ok(link_canon) = link.canonicalize() {
-                            if is_inside(&canon, &link_canon) {
-                                return true;
-                            }
-                        }
-                        // also consider source loop
-                        if let Ok(rel) = source_canon.strip_prefix(&canon) {
-                            let _ = rel;
-                            // Source under symlinked ancestor isn't itself a loop;
-                            // skip false positive.
+        if cache.is_symlink(c) {
+            if let Some(t) = cache.read_link(c) {
+                let abs = if t.is_absolute() {
+                    t
+                } else {
+                    c.parent().map(|p| p.join(&t)).unwrap_or(t)
+                };
+                if let Ok(canon) = cache.canonicalize(&abs) {
+                    if let Ok(link_canon) = cache.canonicalize(link) {
+                        if is_inside(&canon, &link_canon) {
+                            return true;
                         }
                     }
                 }
@@ -1021,6 +1478,10 @@ mod tests {
     #[cfg(not(windows))]
     use crate::config::Config;
     #[cfg(not(windows))]
+    use crate::fs::MemFs;
+    #[cfg(not(windows))]
+    use std::fs;
+    #[cfg(not(windows))]
     use std::sync::atomic::{AtomicU64, Ordering};
 
     #[cfg(not(windows))]
@@ -1037,7 +1498,7 @@ mod tests {
 
     #[cfg(not(windows))]
     fn dc(dir: &Path, links: Vec<(&str, &str)>) -> DiscoveredConfig {
-        let mut m = BTreeMap::new();
+        let mut m = std::collections::BTreeMap::new();
         for (k, v) in links {
             m.insert(k.to_string(), v.to_string());
         }
@@ -1046,8 +1507,9 @@ mod tests {
             config_dir: dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()),
             config: Config {
                 links: m,
-                dependencies: BTreeMap::new(),
+                ..Default::default()
             },
+            repo: None,
         }
     }
 
@@ -1075,6 +1537,31 @@ mod tests {
         assert!(v[0].is_config());
     }
 
+    #[test]
+    #[cfg(not(windows))]
+    fn cross_repo_collision_names_each_repo() {
+        std::env::set_var("HOME", "/tmp");
+        let d1 = tmpdir("xrepo1");
+        let d2 = tmpdir("xrepo2");
+        fs::write(d1.join("a"), "a").unwrap();
+        fs::write(d2.join("b"), "b").unwrap();
+        let confs = vec![
+            DiscoveredConfig {
+                repo: Some("personal".to_string()),
+                ..dc(&d1, vec![("a", "/tmp/xd_xrepo")])
+            },
+            DiscoveredConfig {
+                repo: Some("team".to_string()),
+                ..dc(&d2, vec![("b", "/tmp/xd_xrepo")])
+            },
+        ];
+        let mut errs = ErrorBag::new();
+        assert!(collect_global_links(&confs, &mut errs).is_empty());
+        let msg = errs.into_vec()[0].body().to_string();
+        assert!(msg.contains("- [personal] "), "{msg}");
+        assert!(msg.contains("- [team] "), "{msg}");
+    }
+
     #[test]
     #[cfg(not(windows))]
     fn three_way_collision_lists_all_three() {
@@ -1111,10 +1598,121 @@ mod tests {
         assert!(errs.is_empty());
         let mut errs2 = ErrorBag::new();
         for e in entries {
-            if let Err(err) = plan_one_deploy(&e, ConflictMode::Default) {
+            if let Err(err) = plan_one_deploy(&e, ConflictMode::Default, &FsCache::new(&RealFs)) {
                 errs2.push(err);
             }
         }
         assert!(errs2.iter().any(|e| e.is_planning()));
     }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn planning_reads_through_the_fs_trait() {
+        let fs = MemFs::new();
+        fs.add_file("/repo/f", 0o644);
+        fs.add_file("/repo/g", 0o644);
+        fs.add_symlink("/home/a", "/repo/g");
+        fs.add_symlink("/home/b", "../repo/f");
+        let disc = Discovered {
+            configs: vec![DiscoveredConfig {
+                config_file: PathBuf::from("/repo/xdotter.toml"),
+                config_dir: PathBuf::from("/repo"),
+                config: Config {
+                    links: [("f", "/home/a"), ("g", "/home/b")]
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                    ..Default::default()
+                },
+                repo: None,
+            }],
+            errors: ErrorBag::new(),
+        };
+        let res = build_deploy_plan_with(&fs, disc, ConflictMode::Force(ForceSet::ALL));
+        assert!(res.errors.is_empty());
+        let kinds: Vec<_> = res.plan.actions.iter().map(|a| a.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DeployActionKind::Replace(ExistingKind::WrongSymlink),
+                DeployActionKind::Replace(ExistingKind::WrongSymlink),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn relative_links_to_the_source_are_correct() {
+        let fs = MemFs::new();
+        fs.add_file("/repo/f", 0o644);
+        fs.add_dir("/home/u");
+        fs.add_symlink("/home/u/a", "../../repo/f");
+        let disc = || Discovered {
+            configs: vec![DiscoveredConfig {
+                config_file: PathBuf::from("/repo/xdotter.toml"),
+                config_dir: PathBuf::from("/repo"),
+                config: Config {
+                    links: [("f".to_string(), "/home/u/a".to_string())].into(),
+                    relative: [("f".to_string(), true)].into(),
+                    ..Default::default()
+                },
+                repo: None,
+            }],
+            errors: ErrorBag::new(),
+        };
+        let res = build_deploy_plan_with(&fs, disc(), ConflictMode::Default);
+        assert!(res.errors.is_empty());
+        let a = &res.plan.actions[0];
+        assert_eq!(a.kind, DeployActionKind::AlreadyCorrect);
+        assert!(a.relative);
+        let status = build_status_with(&fs, disc());
+        assert_eq!(status.summary().deployed, 1);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn force_set_downgrades_disallowed_categories() {
+        let fs = MemFs::new();
+        fs.add_file("/repo/f", 0o644);
+        fs.add_file("/repo/g", 0o644);
+        fs.add_file("/home/a", 0o644);
+        fs.add_symlink("/home/b", "/repo/missing");
+        let disc = Discovered {
+            configs: vec![DiscoveredConfig {
+                config_file: PathBuf::from("/repo/xdotter.toml"),
+                config_dir: PathBuf::from("/repo"),
+                config: Config {
+                    links: [("f", "/home/a"), ("g", "/home/b")]
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                    ..Default::default()
+                },
+                repo: None,
+            }],
+            errors: ErrorBag::new(),
+        };
+        let set = ForceSet::parse("broken,wrong-symlink").unwrap();
+        let res = build_deploy_plan_with(&fs, disc, ConflictMode::Force(set));
+        let kinds: Vec<_> = res.plan.actions.iter().map(|a| a.kind.clone()).collect();
+        assert!(
+            matches!(&kinds[0], DeployActionKind::SkipFailure(r) if r.contains("未包含 file")),
+            "{kinds:?}"
+        );
+        assert_eq!(
+            kinds[1],
+            DeployActionKind::Replace(ExistingKind::BrokenSymlink)
+        );
+    }
+
+    #[test]
+    fn force_set_parses_category_lists() {
+        let set = ForceSet::parse("broken, wrong-symlink,perms").unwrap();
+        assert!(set.contains(ForceSet::BROKEN_SYMLINK));
+        assert!(set.contains(ForceSet::PERMS));
+        assert!(!set.contains(ForceSet::FILE));
+        assert_eq!(ForceSet::parse("all").unwrap(), ForceSet::ALL);
+        assert!(ForceSet::parse("broken,files").unwrap_err().is_cli());
+        assert_eq!(ForceSet::EMPTY_DIR.name(), "empty-dir");
+    }
 }
@@ -0,0 +1,206 @@
+//! User-level registry of dotfile repositories, for `xd repo`.
+//!
+//! The registry lives in `~/.config/xdotter/repos.toml` and maps a name
+//! to the absolute root directory of each repository:
+//!
+//! ```toml
+//! [repos]
+//! personal = "/home/u/dotfiles"
+//! team = "/home/u/work/team-dotfiles"
+//! ```
+//!
+//! When it lists at least one repository, deploy, undeploy and status
+//! discover every registered root together (see
+//! [`discover_repos`](crate::discover::discover_repos)), so link
+//! collisions between repositories are caught like collisions inside
+//! one.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::XdError;
+
+/// Registered repositories by name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Registry {
+    repos: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RawRegistry {
+    #[serde(default)]
+    repos: BTreeMap<String, PathBuf>,
+}
+
+impl Registry {
+    /// `~/.config/xdotter/repos.toml`, or `None` without a home
+    /// directory.
+    pub fn default_path() -> Option<PathBuf> {
+        crate::path::home_dir().map(|h| h.join(".config").join("xdotter").join("repos.toml"))
+    }
+
+    /// Read the registry at `path`; a missing file (or a missing
+    /// directory on the way to it) is an empty registry.
+    pub fn load(path: &Path) -> Result<Self, XdError> {
+        use std::io::ErrorKind;
+        match fs::read_to_string(path) {
+            Ok(content) => Self::from_toml(&content, path),
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+                Ok(Self::default())
+            }
+            Err(e) => Err(XdError::config(format!(
+                "无法读取仓库注册表 {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    /// Parse registry text. Every path must be absolute.
+    pub fn from_toml(content: &str, source: &Path) -> Result<Self, XdError> {
+        let raw: RawRegistry = basic_toml::from_str(content).map_err(|e| {
+            XdError::config(format!("{}: 仓库注册表解析失败: {}", source.display(), e))
+        })?;
+        for (name, path) in &raw.repos {
+            validate_name(name)
+                .map_err(|e| XdError::config(format!("{}: {}", source.display(), e.body())))?;
+            if !path.is_absolute() {
+                return Err(XdError::config(format!(
+                    "{}: 仓库 \"{}\" 的路径必须是绝对路径: {}",
+                    source.display(),
+                    name,
+                    path.display()
+                )));
+            }
+        }
+        Ok(Self { repos: raw.repos })
+    }
+
+    /// Write the registry to `path`, creating its parent directory.
+    pub fn save(&self, path: &Path) -> Result<(), XdError> {
+        let raw = RawRegistry {
+            repos: self.repos.clone(),
+        };
+        let text = basic_toml::to_string(&raw)
+            .map_err(|e| XdError::apply(format!("序列化仓库注册表失败: {}", e)))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| {
+                XdError::apply(format!("创建目录 {} 失败: {}", parent.display(), e))
+            })?;
+        }
+        fs::write(path, text)
+            .map_err(|e| XdError::apply(format!("写入 {} 失败: {}", path.display(), e)))
+    }
+
+    /// True when no repository is registered.
+    pub fn is_empty(&self) -> bool {
+        self.repos.is_empty()
+    }
+
+    /// Registered `(name, root)` pairs in name order.
+    pub fn repos(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.repos.iter().map(|(n, p)| (n.as_str(), p.as_path()))
+    }
+
+    /// Register `root` under `name`. The root must be absolute; a name
+    /// or root that is already registered is a CLI error.
+    pub fn add(&mut self, name: &str, root: &Path) -> Result<(), XdError> {
+        validate_name(name)?;
+        if !root.is_absolute() {
+            return Err(XdError::cli(format!(
+                "仓库路径必须是绝对路径: {}",
+                root.display()
+            )));
+        }
+        if let Some(p) = self.repos.get(name) {
+            return Err(XdError::cli(format!(
+                "仓库名 \"{}\" 已注册为 {}",
+                name,
+                p.display()
+            )));
+        }
+        if let Some((other, _)) = self.repos().find(|(_, p)| *p == root) {
+            return Err(XdError::cli(format!(
+                "{} 已注册为仓库 \"{}\"",
+                root.display(),
+                other
+            )));
+        }
+        self.repos.insert(name.to_string(), root.to_path_buf());
+        Ok(())
+    }
+
+    /// Unregister `name`, returning its root.
+    pub fn remove(&mut self, name: &str) -> Option<PathBuf> {
+        self.repos.remove(name)
+    }
+}
+
+/// Names label diagnostics as `[name]`, so keep them to one plain word.
+fn validate_name(name: &str) -> Result<(), XdError> {
+    let ok = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if ok {
+        Ok(())
+    } else {
+        Err(XdError::cli(format!(
+            "仓库名 \"{}\" 无效：只能包含字母、数字、'-'、'_' 和 '.'",
+            name
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(windows))]
+    fn round_trips_through_toml() {
+        let mut r = Registry::default();
+        r.add("team", Path::new("/srv/team")).unwrap();
+        r.add("personal", Path::new("/home/u/dots")).unwrap();
+        let dir = std::env::temp_dir().join(format!("xd_registry_{}", std::process::id()));
+        let file = dir.join("nested/repos.toml");
+        r.save(&file).unwrap();
+        let back = Registry::load(&file).unwrap();
+        assert_eq!(back, r);
+        let names: Vec<_> = back.repos().map(|(n, _)| n).collect();
+        assert_eq!(names, ["personal", "team"]);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(Registry::load(&dir.join("missing.toml"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn duplicates_and_bad_entries_are_rejected() {
+        let mut r = Registry::default();
+        r.add("a", Path::new("/x")).unwrap();
+        assert!(r.add("a", Path::new("/y")).unwrap_err().is_cli());
+        assert!(r.add("b", Path::new("/x")).unwrap_err().is_cli());
+        assert!(r.add("c d", Path::new("/z")).unwrap_err().is_cli());
+        assert!(r.add("c", Path::new("rel")).unwrap_err().is_cli());
+        assert_eq!(r.remove("a"), Some(PathBuf::from("/x")));
+        assert_eq!(r.remove("a"), None);
+
+        let p = Path::new("repos.toml");
+        for bad in [
+            "[repos]\na = \"rel\"\n",
+            "[repos]\n\"a b\" = \"/x\"\n",
+            "x = 1\n",
+        ] {
+            assert!(
+                Registry::from_toml(bad, p).unwrap_err().is_config(),
+                "{bad}"
+            );
+        }
+    }
+}
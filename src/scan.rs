@@ -0,0 +1,460 @@
+//! Home-directory scan behind `xd new --scan`.
+//!
+//! [`scan`] looks for well-known dotfiles at the top of a directory
+//! (normally `$HOME`) and for every entry of its `.config` directory,
+//! the XDG configuration home. Each hit becomes a [`ScanEntry`] whose
+//! source path inside the repository mirrors its place under the
+//! scanned directory (`~/.config/nvim` is kept as `.config/nvim`).
+//!
+//! Entries that should not be adopted are reported as [`Skipped`]
+//! instead: symlinks (already managed by something), caches (a
+//! `CACHEDIR.TAG` or a name containing `cache`), directories larger than
+//! [`MAX_DIR_BYTES`] or holding more than [`MAX_DIR_FILES`] files, and
+//! anything containing the repository itself. Entries the permission
+//! table of SPEC §"权限安全" applies to are kept but carry the required
+//! mode so the caller can flag them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::XdError;
+use crate::permissions;
+
+/// Directories whose files add up to more than this are skipped.
+pub const MAX_DIR_BYTES: u64 = 50 * 1024 * 1024;
+/// Directories holding more files than this are skipped.
+pub const MAX_DIR_FILES: u64 = 5_000;
+
+/// Well-known dotfiles and dot-directories looked for at the top of the
+/// scanned directory, besides the entries of `.config`.
+const WELL_KNOWN: &[&str] = &[
+    ".bashrc",
+    ".bash_profile",
+    ".bash_aliases",
+    ".bash_logout",
+    ".profile",
+    ".zshrc",
+    ".zshenv",
+    ".zprofile",
+    ".zlogin",
+    ".inputrc",
+    ".vimrc",
+    ".gvimrc",
+    ".vim",
+    ".emacs",
+    ".emacs.d",
+    ".tmux.conf",
+    ".screenrc",
+    ".gitconfig",
+    ".gitignore_global",
+    ".hgrc",
+    ".editorconfig",
+    ".curlrc",
+    ".wgetrc",
+    ".npmrc",
+    ".Xresources",
+    ".xinitrc",
+    ".xprofile",
+    ".ssh/config",
+    ".netrc",
+    ".pgpass",
+];
+
+/// A file or directory that can be moved into the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanEntry {
+    /// Absolute path of the entry today.
+    pub path: PathBuf,
+    /// Source path inside the repository, relative to its root.
+    pub source: String,
+    /// Link path for the configuration: `~/…` under the home
+    /// directory, absolute otherwise.
+    pub link: String,
+    /// True for a directory.
+    pub is_dir: bool,
+    /// Total size of the file or of the files below the directory.
+    pub bytes: u64,
+    /// Mode and description from the permission table, for sensitive
+    /// entries.
+    pub permission: Option<(u32, &'static str)>,
+}
+
+/// An entry found but not offered, with the reason.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Skipped {
+    /// Absolute path of the entry.
+    pub path: PathBuf,
+    /// Why it is not offered (Chinese, for display).
+    pub reason: String,
+}
+
+/// Result of [`scan`], both lists in path order.
+#[derive(Debug, Default, Clone)]
+pub struct Scan {
+    /// Entries that can be adopted.
+    pub entries: Vec<ScanEntry>,
+    /// Entries found but left alone.
+    pub skipped: Vec<Skipped>,
+}
+
+/// Scan `root` for dotfiles. `home` decides whether link paths are
+/// written `~/…`; entries containing `repo` are never offered.
+pub fn scan(root: &Path, home: Option<&Path>, repo: &Path) -> Scan {
+    let mut candidates: Vec<PathBuf> = WELL_KNOWN.iter().map(|n| root.join(n)).collect();
+    if let Ok(dir) = fs::read_dir(root.join(".config")) {
+        candidates.extend(dir.flatten().map(|e| e.path()));
+    }
+    candidates.sort();
+
+    let mut out = Scan::default();
+    for path in candidates {
+        let Ok(meta) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let Ok(rel) = path.strip_prefix(root) else {
+            continue;
+        };
+        let source = rel.to_string_lossy().replace('\\', "/");
+        let mut skip = |reason: String| {
+            out.skipped.push(Skipped {
+                path: path.clone(),
+                reason,
+            })
+        };
+        if meta.file_type().is_symlink() {
+            skip("已是符号链接".to_string());
+            continue;
+        }
+        if repo.starts_with(&path) {
+            skip("包含仓库目录".to_string());
+            continue;
+        }
+        let name = rel
+            .file_name()
+            .map(|n| n.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        if meta.is_dir() && name == "xdotter" {
+            skip("xdotter 自身的配置".to_string());
+            continue;
+        }
+        if name.contains("cache") {
+            skip("缓存目录".to_string());
+            continue;
+        }
+        let bytes = if meta.is_dir() {
+            if path.join("CACHEDIR.TAG").exists() {
+                skip("缓存目录 (CACHEDIR.TAG)".to_string());
+                continue;
+            }
+            match measure(&path) {
+                Some(bytes) => bytes,
+                None => {
+                    skip(format!(
+                        "目录过大 (超过 {} MiB 或 {} 个文件)",
+                        MAX_DIR_BYTES / (1024 * 1024),
+                        MAX_DIR_FILES
+                    ));
+                    continue;
+                }
+            }
+        } else {
+            meta.len()
+        };
+        let link = match home.and_then(|h| path.strip_prefix(h).ok()) {
+            Some(under_home) => format!("~/{}", under_home.to_string_lossy().replace('\\', "/")),
+            None => path.to_string_lossy().into_owned(),
+        };
+        out.entries.push(ScanEntry {
+            permission: permissions::required_permission(&link),
+            path,
+            source,
+            link,
+            is_dir: meta.is_dir(),
+            bytes,
+        });
+    }
+    out
+}
+
+/// Total file size below `dir`, or `None` once it passes
+/// [`MAX_DIR_BYTES`] or [`MAX_DIR_FILES`]. Symlinks are not followed.
+fn measure(dir: &Path) -> Option<u64> {
+    let (mut bytes, mut files) = (0u64, 0u64);
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&d) else {
+            continue;
+        };
+        for e in entries.flatten() {
+            let Ok(meta) = e.metadata() else {
+                continue;
+            };
+            // `DirEntry::metadata` does not follow symlinks.
+            if meta.is_dir() {
+                stack.push(e.path());
+                continue;
+            }
+            files += 1;
+            bytes += meta.len();
+            if bytes > MAX_DIR_BYTES || files > MAX_DIR_FILES {
+                return None;
+            }
+        }
+    }
+    Some(bytes)
+}
+
+/// Text of an `xdotter.toml` linking every entry, sensitive entries
+/// annotated with the mode deploy will require.
+pub fn render_config(entries: &[&ScanEntry]) -> String {
+    let mut out =
+        String::from("# xdotter configuration file, generated by `xd new --scan`\n\n[links]\n");
+    for e in entries {
+        if let Some((mode, what)) = e.permission {
+            out.push_str(&format!("# {}: 部署时要求权限 {:o}\n", what, mode));
+        }
+        out.push_str(&format!("{} = {}\n", quote(&e.source), quote(&e.link)));
+    }
+    out
+}
+
+/// `s` as a TOML basic string.
+pub(crate) fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parse a selection such as `1,3-5` or `all` over `count` numbered
+/// entries into 0-based indices, sorted and without duplicates. Empty
+/// text selects nothing.
+pub fn select(text: &str, count: usize) -> Result<Vec<usize>, XdError> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("all") {
+        return Ok((0..count).collect());
+    }
+    let mut picked = std::collections::BTreeSet::new();
+    for part in text.split([',', ' ']).filter(|p| !p.is_empty()) {
+        let bad = || {
+            XdError::cli(format!(
+                "无效的选择 \"{}\"：应为 1 到 {} 之间的编号或范围",
+                part, count
+            ))
+        };
+        let (a, b) = part.split_once('-').unwrap_or((part, part));
+        let a: usize = a.trim().parse().map_err(|_| bad())?;
+        let b: usize = b.trim().parse().map_err(|_| bad())?;
+        if a == 0 || a > b || b > count {
+            return Err(bad());
+        }
+        picked.extend(a - 1..b);
+    }
+    Ok(picked.into_iter().collect())
+}
+
+/// Move `entry` to its source path under `repo`, creating parent
+/// directories. Across filesystems, where a rename cannot move it, the
+/// entry is copied and then removed.
+pub fn adopt(entry: &ScanEntry, repo: &Path) -> Result<(), XdError> {
+    let dest = repo.join(&entry.source);
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| XdError::apply(format!("创建目录 {} 失败: {}", parent.display(), e)))?;
+    }
+    let moved = match fs::rename(&entry.path, &dest) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => copy_then_remove(&entry.path, &dest),
+        r => r,
+    };
+    moved.map_err(|e| {
+        XdError::apply(format!(
+            "移动 {} 到 {} 失败: {}",
+            entry.path.display(),
+            dest.display(),
+            e
+        ))
+    })
+}
+
+/// Move `src` to `dest` on another filesystem. A failed copy is removed
+/// again and leaves `src` untouched.
+fn copy_then_remove(src: &Path, dest: &Path) -> io::Result<()> {
+    if let Err(e) = copy_tree(src, dest) {
+        let _ = match fs::symlink_metadata(dest) {
+            Ok(m) if m.is_dir() => fs::remove_dir_all(dest),
+            _ => fs::remove_file(dest),
+        };
+        return Err(e);
+    }
+    if fs::symlink_metadata(src)?.is_dir() {
+        fs::remove_dir_all(src)
+    } else {
+        fs::remove_file(src)
+    }
+}
+
+/// Copy `src` to `dest` (which must not exist), keeping permission bits
+/// and copying symlinks as symlinks.
+fn copy_tree(src: &Path, dest: &Path) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+    let ft = meta.file_type();
+    if ft.is_symlink() {
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(fs::read_link(src)?, dest);
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("无法跨文件系统复制符号链接 {}", src.display()),
+        ));
+    }
+    if ft.is_file() {
+        return fs::copy(src, dest).map(drop);
+    }
+    if !ft.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("无法复制特殊文件 {}", src.display()),
+        ));
+    }
+    fs::create_dir(dest)?;
+    for child in fs::read_dir(src)? {
+        let child = child?;
+        copy_tree(&child.path(), &dest.join(child.file_name()))?;
+    }
+    fs::set_permissions(dest, meta.permissions())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmp(tag: &str) -> PathBuf {
+        let p = std::env::temp_dir().join(format!("xd_scan_{}_{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&p);
+        fs::create_dir_all(&p).unwrap();
+        p
+    }
+
+    #[test]
+    fn finds_dotfiles_and_config_dirs_and_skips_caches() {
+        let home = tmp("find");
+        fs::write(home.join(".zshrc"), "x").unwrap();
+        fs::write(home.join(".unknownrc"), "x").unwrap();
+        fs::create_dir_all(home.join(".ssh")).unwrap();
+        fs::write(home.join(".ssh/config"), "Host *").unwrap();
+        fs::create_dir_all(home.join(".config/nvim")).unwrap();
+        fs::write(home.join(".config/nvim/init.lua"), "--").unwrap();
+        fs::create_dir_all(home.join(".config/fontconfig-cache")).unwrap();
+        fs::create_dir_all(home.join(".config/tagged")).unwrap();
+        fs::write(home.join(".config/tagged/CACHEDIR.TAG"), "").unwrap();
+        let repo = home.join(".config/dotfiles");
+        fs::create_dir_all(&repo).unwrap();
+
+        let s = scan(&home, Some(&home), &repo);
+        let links: Vec<_> = s.entries.iter().map(|e| e.link.as_str()).collect();
+        assert_eq!(links, ["~/.config/nvim", "~/.ssh/config", "~/.zshrc"]);
+        let nvim = &s.entries[0];
+        assert_eq!(
+            (nvim.source.as_str(), nvim.is_dir, nvim.bytes),
+            (".config/nvim", true, 2)
+        );
+        assert_eq!(s.entries[1].permission.map(|p| p.0), Some(0o600));
+
+        let skipped: Vec<_> = s
+            .skipped
+            .iter()
+            .map(|k| k.path.strip_prefix(&home).unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ".config/dotfiles",
+                ".config/fontconfig-cache",
+                ".config/tagged"
+            ]
+        );
+        let _ = fs::remove_dir_all(&home);
+    }
+
+    #[test]
+    fn large_directories_are_skipped() {
+        let home = tmp("large");
+        fs::create_dir_all(home.join(".config/big")).unwrap();
+        let f = fs::File::create(home.join(".config/big/blob")).unwrap();
+        f.set_len(MAX_DIR_BYTES + 1).unwrap();
+        let s = scan(&home, None, Path::new("/nonexistent"));
+        assert!(s.entries.is_empty());
+        assert!(s.skipped[0].reason.contains("目录过大"), "{:?}", s.skipped);
+        let _ = fs::remove_dir_all(&home);
+    }
+
+    #[test]
+    fn selections_parse_numbers_ranges_and_all() {
+        assert_eq!(select("3, 1-2 2", 4).unwrap(), [0, 1, 2]);
+        assert_eq!(select(" ALL\n", 3).unwrap(), [0, 1, 2]);
+        assert!(select("\n", 3).unwrap().is_empty());
+        for bad in ["0", "4", "2-1", "x", "1-"] {
+            assert!(select(bad, 3).unwrap_err().is_cli(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn rendered_config_parses_back() {
+        let entry = |source: &str, link: &str, permission| ScanEntry {
+            path: PathBuf::new(),
+            source: source.to_string(),
+            link: link.to_string(),
+            is_dir: false,
+            bytes: 0,
+            permission,
+        };
+        let a = entry(".zshrc", "~/.zshrc", None);
+        let b = entry(".ssh/config", "~/.ssh/config", Some((0o600, "SSH config")));
+        let c = entry("we\"ird", "~/we\"ird", None);
+        let text = render_config(&[&a, &b, &c]);
+        assert!(
+            text.contains("# SSH config: 部署时要求权限 600\n"),
+            "{text}"
+        );
+        let cfg = crate::config::Config::from_toml(&text, Path::new("xdotter.toml")).unwrap();
+        assert_eq!(cfg.links.get(".ssh/config").unwrap(), "~/.ssh/config");
+        assert_eq!(cfg.links.get("we\"ird").unwrap(), "~/we\"ird");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copying_across_filesystems_keeps_the_tree() {
+        use std::os::unix::fs::PermissionsExt;
+        let d = tmp("copy");
+        let src = d.join(".config/app");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/key"), "k").unwrap();
+        fs::set_permissions(src.join("sub/key"), fs::Permissions::from_mode(0o600)).unwrap();
+        std::os::unix::fs::symlink("sub/key", src.join("link")).unwrap();
+        let dest = d.join("repo/app");
+        fs::create_dir_all(d.join("repo")).unwrap();
+
+        copy_then_remove(&src, &dest).unwrap();
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dest.join("sub/key")).unwrap(), "k");
+        let mode = fs::metadata(dest.join("sub/key"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            fs::read_link(dest.join("link")).unwrap(),
+            Path::new("sub/key")
+        );
+        let _ = fs::remove_dir_all(&d);
+    }
+}
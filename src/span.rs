@@ -0,0 +1,378 @@
+//! Source locations of `[links]` and `[dependencies]` entries, and the
+//! rustc-style snippets diagnostics point at them with.
+//!
+//! basic-toml keeps no spans, so `locate` re-reads the configuration
+//! text with a small line-oriented scanner. It understands the shapes an
+//! entry can take — `key = value` under the table header, an inline
+//! table value, a `[links.KEY]` sub-table and a dotted `links.KEY` key —
+//! and leaves anything else unlocated; a diagnostic without a span
+//! simply has no snippet.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// A stretch of one line of a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column, counted in characters.
+    pub column: usize,
+    /// Length in characters, at least 1.
+    pub len: usize,
+    /// The whole line, without its line break.
+    pub text: String,
+}
+
+impl Span {
+    /// The line with a caret underline, as rustc prints it:
+    ///
+    /// ```text
+    ///   |
+    /// 3 | "a" = "rel/path"
+    ///   |       ^^^^^^^^^^
+    /// ```
+    pub fn snippet(&self) -> String {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Keep tabs in the padding so the caret lines up with the text.
+        let pad: String = self
+            .text
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let mut out = String::new();
+        let _ = writeln!(out, "{} |", gutter);
+        let _ = writeln!(out, "{} | {}", number, self.text);
+        let _ = write!(out, "{} | {}{}", gutter, pad, "^".repeat(self.len.max(1)));
+        out
+    }
+}
+
+/// Where one entry's key and value are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntrySpan {
+    /// The entry's key: the source path of a link, the name of a
+    /// dependency.
+    pub key: Span,
+    /// The entry's value. For a link written as a table this is the
+    /// `link` value, for a git dependency the `git` value.
+    pub value: Span,
+}
+
+/// Entry spans of one configuration text, keyed like the parsed tables.
+#[derive(Debug, Default)]
+pub(crate) struct Located {
+    pub(crate) links: BTreeMap<String, EntrySpan>,
+    pub(crate) dependencies: BTreeMap<String, EntrySpan>,
+}
+
+/// A key of a key path with its position on the line.
+struct Key {
+    name: String,
+    span: Span,
+}
+
+/// Locate every `[links]` and `[dependencies]` entry of `text`.
+pub(crate) fn locate(text: &str) -> Located {
+    let mut out = Located::default();
+    // Keys of the current `[table]` header.
+    let mut header: Vec<Key> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let chars: Vec<char> = line.chars().collect();
+        let pos = skip_ws(&chars, 0);
+        match chars.get(pos) {
+            None | Some('#') => continue,
+            Some('[') => {
+                header = if chars.get(pos + 1) == Some(&'[') {
+                    Vec::new()
+                } else {
+                    key_path(&chars, pos + 1, i, line)
+                        .map(|(keys, _)| keys)
+                        .unwrap_or_default()
+                };
+                continue;
+            }
+            _ => {}
+        }
+        let Some((keys, end)) = key_path(&chars, pos, i, line) else {
+            continue;
+        };
+        let eq = skip_ws(&chars, end);
+        if chars.get(eq) != Some(&'=') {
+            continue;
+        }
+        let start = skip_ws(&chars, eq + 1);
+        let value_end = value_extent(&chars, start);
+
+        let path: Vec<&Key> = header.iter().chain(keys.iter()).collect();
+        let (table, field) = match path.first().map(|k| k.name.as_str()) {
+            Some("links") => (&mut out.links, "link"),
+            Some("dependencies") => (&mut out.dependencies, "git"),
+            _ => continue,
+        };
+        match path.len() {
+            2 => {
+                let (s, e) = if chars.get(start) == Some(&'{') {
+                    inline_field(&chars, start, field).unwrap_or((start, value_end))
+                } else {
+                    (start, value_end)
+                };
+                table.insert(
+                    path[1].name.clone(),
+                    EntrySpan {
+                        key: path[1].span.clone(),
+                        value: span(i, line, s, e),
+                    },
+                );
+            }
+            3 if path[2].name == field => {
+                table.insert(
+                    path[1].name.clone(),
+                    EntrySpan {
+                        key: path[1].span.clone(),
+                        value: span(i, line, start, value_end),
+                    },
+                );
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Split a basic-toml error message into its text and the location it
+/// ends with (`… at line L column C`).
+pub(crate) fn parse_error(message: &str, text: &str) -> Option<(String, Span)> {
+    let (head, at) = message.rsplit_once(" at line ")?;
+    let (line, column) = at.split_once(" column ")?;
+    let line: usize = line.trim().parse().ok()?;
+    let column: usize = column.trim().parse().ok()?;
+    let text = text.lines().nth(line.checked_sub(1)?).unwrap_or("");
+    Some((
+        head.to_string(),
+        Span {
+            line,
+            column: column.max(1),
+            len: 1,
+            text: text.to_string(),
+        },
+    ))
+}
+
+fn span(index: usize, line: &str, start: usize, end: usize) -> Span {
+    Span {
+        line: index + 1,
+        column: start + 1,
+        len: end.saturating_sub(start).max(1),
+        text: line.to_string(),
+    }
+}
+
+fn skip_ws(chars: &[char], mut pos: usize) -> usize {
+    while matches!(chars.get(pos), Some(' ') | Some('\t')) {
+        pos += 1;
+    }
+    pos
+}
+
+/// A dotted key path starting at `pos`, and the position after it.
+fn key_path(chars: &[char], mut pos: usize, index: usize, line: &str) -> Option<(Vec<Key>, usize)> {
+    let mut keys = Vec::new();
+    loop {
+        pos = skip_ws(chars, pos);
+        let (name, end) = key(chars, pos)?;
+        keys.push(Key {
+            name,
+            span: span(index, line, pos, end),
+        });
+        pos = skip_ws(chars, end);
+        if chars.get(pos) != Some(&'.') {
+            return Some((keys, pos));
+        }
+        pos += 1;
+    }
+}
+
+/// One bare, basic-string or literal-string key starting at `pos`.
+fn key(chars: &[char], pos: usize) -> Option<(String, usize)> {
+    match chars.get(pos)? {
+        '"' => basic_string(chars, pos),
+        '\'' => {
+            let close = chars[pos + 1..].iter().position(|&c| c == '\'')? + pos + 1;
+            Some((chars[pos + 1..close].iter().collect(), close + 1))
+        }
+        _ => {
+            let end = chars[pos..]
+                .iter()
+                .position(|&c| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+                .map_or(chars.len(), |n| pos + n);
+            (end > pos).then(|| (chars[pos..end].iter().collect(), end))
+        }
+    }
+}
+
+/// A `"…"` string starting at `pos`, unescaped, and the position after
+/// its closing quote.
+fn basic_string(chars: &[char], pos: usize) -> Option<(String, usize)> {
+    let mut out = String::new();
+    let mut i = pos + 1;
+    loop {
+        match *chars.get(i)? {
+            '"' => return Some((out, i + 1)),
+            '\\' => {
+                let c = *chars.get(i + 1)?;
+                i += 2;
+                match c {
+                    'b' => out.push('\u{8}'),
+                    't' => out.push('\t'),
+                    'n' => out.push('\n'),
+                    'f' => out.push('\u{c}'),
+                    'r' => out.push('\r'),
+                    'u' | 'U' => {
+                        let n = if c == 'u' { 4 } else { 8 };
+                        let hex: String = chars.get(i..i + n)?.iter().collect();
+                        out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                        i += n;
+                    }
+                    other => out.push(other),
+                }
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// End of the value starting at `pos`. Values spanning several lines
+/// end with their first line.
+fn value_extent(chars: &[char], pos: usize) -> usize {
+    match chars.get(pos) {
+        Some('"') if chars.get(pos + 1..pos + 3) == Some(&['"', '"']) => chars.len(),
+        Some('\'') if chars.get(pos + 1..pos + 3) == Some(&['\'', '\'']) => chars.len(),
+        Some('"') => basic_string(chars, pos).map_or(chars.len(), |(_, end)| end),
+        Some('\'') => key(chars, pos).map_or(chars.len(), |(_, end)| end),
+        Some('{') | Some('[') => {
+            let mut depth = 0usize;
+            let mut i = pos;
+            while i < chars.len() {
+                match chars[i] {
+                    '{' | '[' => depth += 1,
+                    '}' | ']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return i + 1;
+                        }
+                    }
+                    '"' | '\'' => {
+                        i = value_extent(chars, i);
+                        continue;
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            chars.len()
+        }
+        _ => chars[pos..]
+            .iter()
+            .position(|&c| c == ' ' || c == '\t' || c == '#' || c == ',' || c == '}')
+            .map_or(chars.len(), |n| pos + n),
+    }
+}
+
+/// Extent of the value of `field` in the inline table starting at `pos`.
+fn inline_field(chars: &[char], pos: usize, field: &str) -> Option<(usize, usize)> {
+    let mut i = pos + 1;
+    loop {
+        i = skip_ws(chars, i);
+        if chars.get(i) == Some(&'}') {
+            return None;
+        }
+        let (name, end) = key(chars, i)?;
+        let eq = skip_ws(chars, end);
+        if chars.get(eq) != Some(&'=') {
+            return None;
+        }
+        let start = skip_ws(chars, eq + 1);
+        let end = value_extent(chars, start);
+        if name == field {
+            return Some((start, end));
+        }
+        i = skip_ws(chars, end);
+        if chars.get(i) != Some(&',') {
+            return None;
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &Span) -> (usize, usize, usize) {
+        (s.line, s.column, s.len)
+    }
+
+    #[test]
+    fn every_entry_shape_is_located() {
+        let text = r#"
+# comment
+[links]
+".zshrc" = "~/.zshrc"   # trailing
+'lit' = { link = "~/lit", relative = true }
+bare = "~/bare"
+"esc\u0041" = "~/e"
+
+[dependencies]
+nvim = "config/nvim"
+team = { rev = "v1", git = "file:///t.git" }
+
+[links."sub table"]
+relative = false
+link = "~/sub"
+"#;
+        let l = locate(text);
+        let zshrc = &l.links[".zshrc"];
+        assert_eq!(at(&zshrc.key), (4, 1, 8));
+        assert_eq!(at(&zshrc.value), (4, 12, 10));
+        assert_eq!(at(&l.links["lit"].value), (5, 18, 7));
+        assert_eq!(at(&l.links["bare"].key), (6, 1, 4));
+        assert_eq!(at(&l.links["escA"].key), (7, 1, 11));
+        assert_eq!(at(&l.dependencies["nvim"].value), (10, 8, 13));
+        assert_eq!(at(&l.dependencies["team"].value), (11, 28, 15));
+        let sub = &l.links["sub table"];
+        assert_eq!(at(&sub.key), (13, 8, 11));
+        assert_eq!(at(&sub.value), (15, 8, 7));
+
+        let dotted = locate("links.a = \"~/a\"\n");
+        assert_eq!(at(&dotted.links["a"].key), (1, 7, 1));
+    }
+
+    #[test]
+    fn snippets_underline_the_span() {
+        let s = Span {
+            line: 12,
+            column: 7,
+            len: 5,
+            text: "\"a\" = \"rel\"".to_string(),
+        };
+        assert_eq!(s.snippet(), "   |\n12 | \"a\" = \"rel\"\n   |       ^^^^^");
+    }
+
+    #[test]
+    fn parse_error_locations_are_split_off() {
+        let (msg, s) = parse_error(
+            "expected a value, found a newline at line 2 column 7",
+            "[links]\n\"a\" =\n",
+        )
+        .unwrap();
+        assert_eq!(msg, "expected a value, found a newline");
+        assert_eq!((s.line, s.column, s.text.as_str()), (2, 7, "\"a\" ="));
+        assert!(parse_error("no location", "").is_none());
+    }
+}
@@ -0,0 +1,134 @@
+//! Component-wise path trie used by planning for global link-path
+//! collision and nesting detection.
+//!
+//! Every inserted path is split into components; entries that end at the
+//! same node share a link path (collision), and entries below a node
+//! that itself holds entries are nested inside that link path. Both
+//! queries are answered in one walk whose cost is proportional to the
+//! total number of components plus the number of reported pairs,
+//! instead of comparing every pair of entries.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct PathTrie<T> {
+    root: Node<T>,
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    /// Children keyed by component; `BTreeMap` keeps walks ordered the
+    /// same way `PathBuf`'s `Ord` orders paths.
+    children: BTreeMap<OsString, Node<T>>,
+    values: Vec<T>,
+}
+
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Self {
+            children: BTreeMap::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T> Default for PathTrie<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::default(),
+        }
+    }
+}
+
+impl<T: Copy> PathTrie<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &Path, value: T) {
+        let mut node = &mut self.root;
+        for comp in path.components() {
+            node = node
+                .children
+                .entry(comp.as_os_str().to_os_string())
+                .or_default();
+        }
+        node.values.push(value);
+    }
+
+    /// Groups of two or more values inserted at the same path, in path
+    /// order; values within a group keep insertion order.
+    pub fn collisions(&self) -> Vec<Vec<T>> {
+        let mut out = Vec::new();
+        collect_collisions(&self.root, &mut out);
+        out
+    }
+
+    /// `(outer, inner)` pairs where `inner` was inserted strictly below
+    /// `outer`'s path.
+    pub fn nested_pairs(&self) -> Vec<(T, T)> {
+        let mut out = Vec::new();
+        let mut ancestors = Vec::new();
+        collect_nested(&self.root, &mut ancestors, &mut out);
+        out
+    }
+}
+
+fn collect_collisions<T: Copy>(node: &Node<T>, out: &mut Vec<Vec<T>>) {
+    if node.values.len() > 1 {
+        out.push(node.values.clone());
+    }
+    for child in node.children.values() {
+        collect_collisions(child, out);
+    }
+}
+
+fn collect_nested<T: Copy>(node: &Node<T>, ancestors: &mut Vec<T>, out: &mut Vec<(T, T)>) {
+    for &inner in &node.values {
+        for &outer in ancestors.iter() {
+            out.push((outer, inner));
+        }
+    }
+    let pushed = node.values.len();
+    ancestors.extend(node.values.iter().copied());
+    for child in node.children.values() {
+        collect_nested(child, ancestors, out);
+    }
+    ancestors.truncate(ancestors.len() - pushed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trie(paths: &[&str]) -> PathTrie<usize> {
+        let mut t = PathTrie::new();
+        for (i, p) in paths.iter().enumerate() {
+            t.insert(Path::new(p), i);
+        }
+        t
+    }
+
+    #[test]
+    fn collisions_group_equal_paths() {
+        let t = trie(&["/h/.a", "/h/.b", "/h/.a", "/h/.b", "/h/.c", "/h/.a"]);
+        assert_eq!(t.collisions(), vec![vec![0, 2, 5], vec![1, 3]]);
+    }
+
+    #[test]
+    fn nesting_is_component_wise() {
+        // `/h/.a` must not be treated as a prefix of `/h/.abc`.
+        let t = trie(&["/h/.a", "/h/.abc", "/h/.a/x", "/h/.a/x/y"]);
+        let mut pairs = t.nested_pairs();
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 2), (0, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn equal_paths_are_not_nested() {
+        let t = trie(&["/h/.a", "/h/.a"]);
+        assert!(t.nested_pairs().is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! Warnings per SPEC §"输出语义": conditions worth telling the user
+//! about that do not stop a run.
+//!
+//! Stages collect [`Warning`]s in a [`WarningBag`] next to their
+//! [`ErrorBag`], and the caller decides what to do with them: print them
+//! to stderr, or, under `--deny-warnings`, turn them into errors with
+//! [`WarningBag::deny`]. A configuration switches off warnings about its
+//! own entries by kind:
+//!
+//! ```toml
+//! [warnings]
+//! allow = ["sensitive-target"]
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::codes;
+use crate::error::{ErrorBag, XdError};
+
+/// Label printed before every warning.
+pub const LABEL: &str = "[警告]";
+
+/// What a warning is about. Serializes as its [`name`](WarningKind::name).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WarningKind {
+    /// A link path matches the built-in permission table (SPEC
+    /// §"权限和敏感文件语义").
+    SensitiveTarget,
+    /// Undeploy found a real file or directory where a link should be,
+    /// and left it alone.
+    NotASymlink,
+    /// `xd new --scan` adopts a file the permission table covers into
+    /// the repository.
+    SensitiveFile,
+}
+
+impl WarningKind {
+    /// Every kind, in declaration order.
+    pub const ALL: [WarningKind; 3] = [
+        WarningKind::SensitiveTarget,
+        WarningKind::NotASymlink,
+        WarningKind::SensitiveFile,
+    ];
+
+    /// Name used by `[warnings] allow` and printed after the message.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SensitiveTarget => "sensitive-target",
+            Self::NotASymlink => "not-a-symlink",
+            Self::SensitiveFile => "sensitive-file",
+        }
+    }
+
+    /// Kind named `name`, as returned by [`WarningKind::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.name() == name)
+    }
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One warning. Serializable so that machine-readable output can carry
+/// it unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    /// What the warning is about.
+    pub kind: WarningKind,
+    /// Configuration whose entry the warning is about, if any.
+    pub config_file: Option<PathBuf>,
+    /// The link path or file the warning is about.
+    pub path: PathBuf,
+    /// What to look at (Chinese, for display).
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} [{}]", LABEL, self.message, self.kind)
+    }
+}
+
+/// Warnings of one run, and how many an allow list switched off.
+#[derive(Debug, Default)]
+pub struct WarningBag {
+    items: Vec<Warning>,
+    suppressed: usize,
+}
+
+impl WarningBag {
+    /// Empty bag.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add one warning.
+    pub fn push(&mut self, w: Warning) {
+        self.items.push(w);
+    }
+    /// Add `w` unless its kind is in `allow`, in which case it is only
+    /// counted as suppressed.
+    pub fn push_unless_allowed(&mut self, allow: &[WarningKind], w: Warning) {
+        if allow.contains(&w.kind) {
+            self.suppressed += 1;
+        } else {
+            self.items.push(w);
+        }
+    }
+    /// True when no warning was collected.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    /// Number of collected warnings.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    /// Number of warnings an allow list switched off.
+    pub fn suppressed(&self) -> usize {
+        self.suppressed
+    }
+    /// Iterate over collected warnings in insertion order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Warning> {
+        self.items.iter()
+    }
+    /// Keep only the warnings `keep` accepts. Dropped warnings are not
+    /// counted as suppressed.
+    pub fn retain(&mut self, keep: impl FnMut(&Warning) -> bool) {
+        self.items.retain(keep);
+    }
+    /// `--deny-warnings`: every warning as a planning error, so the run
+    /// stops before anything is modified.
+    pub fn deny(self) -> ErrorBag {
+        let mut errors = ErrorBag::new();
+        for w in self.items {
+            errors.push(
+                XdError::planning(format!(
+                    "警告被 --deny-warnings 视为错误: {} [{}]",
+                    w.message, w.kind
+                ))
+                .with_code(codes::WARNING_DENIED),
+            );
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(kind: WarningKind) -> Warning {
+        Warning {
+            kind,
+            config_file: None,
+            path: PathBuf::from("/h/.ssh/config"),
+            message: "m".to_string(),
+        }
+    }
+
+    #[test]
+    fn allowed_kinds_are_counted_not_kept() {
+        let mut bag = WarningBag::new();
+        let allow = [WarningKind::NotASymlink];
+        bag.push_unless_allowed(&allow, warning(WarningKind::SensitiveTarget));
+        bag.push_unless_allowed(&allow, warning(WarningKind::NotASymlink));
+        assert_eq!((bag.len(), bag.suppressed()), (1, 1));
+        assert_eq!(
+            bag.iter().next().unwrap().to_string(),
+            "[警告] m [sensitive-target]"
+        );
+    }
+
+    #[test]
+    fn denied_warnings_become_coded_planning_errors() {
+        let mut bag = WarningBag::new();
+        bag.push(warning(WarningKind::SensitiveFile));
+        let errors = bag.deny().into_vec();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_planning());
+        assert_eq!(errors[0].code(), Some(codes::WARNING_DENIED));
+        assert_eq!(
+            errors[0].to_string(),
+            "[规划阻塞错误][XD0414] 警告被 --deny-warnings 视为错误: m [sensitive-file]"
+        );
+        for k in WarningKind::ALL {
+            assert_eq!(WarningKind::from_name(k.name()), Some(k));
+        }
+    }
+
+    #[test]
+    fn warnings_serialize_with_kind_names() {
+        let mut w = warning(WarningKind::SensitiveTarget);
+        w.config_file = Some(PathBuf::from("/r/xdotter.toml"));
+        assert_eq!(
+            basic_toml::to_string(&w).unwrap(),
+            "kind = \"sensitive-target\"\nconfig_file = \"/r/xdotter.toml\"\npath = \"/h/.ssh/config\"\nmessage = \"m\"\n"
+        );
+        for k in WarningKind::ALL {
+            #[derive(Serialize)]
+            struct Kind {
+                kind: WarningKind,
+            }
+            assert_eq!(
+                basic_toml::to_string(&Kind { kind: k }).unwrap(),
+                format!("kind = \"{}\"\n", k.name())
+            );
+        }
+    }
+}
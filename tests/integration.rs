@@ -39,10 +39,15 @@ struct Output {
 }
 
 fn run_in(dir: &Path, args: &[&str], home: &Path) -> Output {
+    run_in_env(dir, args, home, &[])
+}
+
+fn run_in_env(dir: &Path, args: &[&str], home: &Path, envs: &[(&str, &str)]) -> Output {
     let out = Command::new(xd_bin())
         .args(args)
         .current_dir(dir)
         .env("HOME", home)
+        .envs(envs.iter().copied())
         // Keep PATH so the linker / clang etc. work; everything else
         // is unset to avoid the host user's HOME leaking in.
         .output()
@@ -145,6 +150,57 @@ fn new_refuses_overwrite() {
     assert!(o.stderr.contains("[配置错误]"));
 }
 
+#[test]
+#[cfg(unix)]
+fn new_scan_adopts_home_dotfiles_and_deploys_them_back() {
+    let d = tmpdir("scan");
+    let h = unique_home("scan");
+    fs::write(h.join(".zshrc"), "export A=1\n").unwrap();
+    fs::create_dir_all(h.join(".ssh")).unwrap();
+    fs::write(h.join(".ssh/config"), "Host *\n").unwrap();
+    fs::create_dir_all(h.join(".config/nvim")).unwrap();
+    fs::write(h.join(".config/nvim/init.lua"), "--\n").unwrap();
+    fs::create_dir_all(h.join(".config/some-cache")).unwrap();
+
+    // Piped stdin cannot pick entries.
+    let o = run_in(&d, &["new", "--scan"], &h);
+    assert!(o.stderr.contains("--all"), "{}", o.stderr);
+
+    let o = run_in(&d, &["new", "--scan", "--all", "--dry-run"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(o.stdout.contains("跳过"), "{}", o.stdout);
+    assert!(o.stdout.contains("[敏感: SSH config"), "{}", o.stdout);
+    assert!(o.stderr.contains("[警告] ~/.ssh/config"), "{}", o.stderr);
+    assert!(o.stdout.contains("Would create xdotter.toml with 3 links"));
+    assert!(h.join(".zshrc").is_file() && !d.join("xdotter.toml").exists());
+
+    let o = run_in(&d, &["new", "--scan", "--all"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(
+        o.stdout.contains("Moved ~/.zshrc -> .zshrc"),
+        "{}",
+        o.stdout
+    );
+    assert_eq!(
+        fs::read_to_string(d.join(".zshrc")).unwrap(),
+        "export A=1\n"
+    );
+    assert!(d.join(".config/nvim/init.lua").is_file());
+    assert!(h.join(".config/some-cache").is_dir());
+
+    let o = run_in(&d, &["deploy", "--force=perms"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert_eq!(fs::read_link(h.join(".zshrc")).unwrap(), d.join(".zshrc"));
+    assert_eq!(
+        fs::read_link(h.join(".config/nvim")).unwrap(),
+        d.join(".config/nvim")
+    );
+    assert!(fs::symlink_metadata(h.join(".ssh/config"))
+        .unwrap()
+        .file_type()
+        .is_symlink());
+}
+
 // ============================================================
 // Deploy / Status / Undeploy happy path
 // ============================================================
@@ -208,6 +264,36 @@ fn dry_run_does_not_modify_filesystem() {
     assert!(!h.join(".zshrc").is_symlink());
 }
 
+#[test]
+fn dry_run_predicts_sequential_effects() {
+    let d = tmpdir("drysim");
+    let h = unique_home("drysim");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("b"), "B").unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        r#"
+[links]
+"a" = "~/.config/app/a"
+"b" = "~/.config/app/b"
+"#,
+    )
+    .unwrap();
+
+    let o = run_in(&d, &["deploy", "--dry-run"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    // The first link creates the shared parent; the second reuses it.
+    let mkdir = format!("fs: mkdir {}", h.join(".config/app").display());
+    assert_eq!(o.stdout.matches(&mkdir).count(), 1, "stdout: {}", o.stdout);
+    assert!(
+        o.stdout
+            .contains("# Predicted deploy: 2 succeeded, 0 skipped, 0 failed (planned 2)"),
+        "stdout: {}",
+        o.stdout
+    );
+    assert!(!h.join(".config").exists());
+}
+
 // ============================================================
 // SPEC error classes — all four labels are reachable
 // ============================================================
@@ -248,6 +334,79 @@ fn missing_source_is_planning_error() {
     assert!(o.stderr.contains("[规划阻塞错误]"), "stderr: {}", o.stderr);
 }
 
+#[test]
+fn entry_errors_show_the_offending_line() {
+    let d = tmpdir("spans");
+    let h = unique_home("spans");
+    fs::write(
+        d.join("xdotter.toml"),
+        "[links]\n\"ghost\" = \"~/.ghost\"\n\"a\" = { link = \"rel/a\" }\n",
+    )
+    .unwrap();
+    let o = run_in(&d, &["deploy", "--dry-run"], &h);
+    assert_ne!(o.code, 0);
+    let toml = d.join("xdotter.toml").canonicalize().unwrap();
+    assert!(
+        o.stderr.contains(&format!(
+            "[配置错误][XD0207] {}:3:16: 链接路径必须是绝对路径或 home 相对路径",
+            toml.display()
+        )) && o
+            .stderr
+            .contains("  |\n3 | \"a\" = { link = \"rel/a\" }\n  |                ^^^^^^^\n"),
+        "{}",
+        o.stderr
+    );
+    assert!(
+        o.stderr.contains(&format!(
+            "[规划阻塞错误][XD0403] {}:2:1: 源路径不存在",
+            toml.display()
+        )) && o
+            .stderr
+            .contains("2 | \"ghost\" = \"~/.ghost\"\n  | ^^^^^^^\n"),
+        "{}",
+        o.stderr
+    );
+
+    fs::write(d.join("xdotter.toml"), "[links]\n\"a\" = \n").unwrap();
+    let o = run_in(&d, &["deploy"], &h);
+    assert!(
+        o.stderr
+            .contains(":2:7: TOML 解析失败: expected a value, found a newline\n"),
+        "{}",
+        o.stderr
+    );
+}
+
+#[test]
+fn explain_describes_the_code_an_error_carries() {
+    let d = tmpdir("explain");
+    let h = unique_home("explain");
+    fs::write(d.join("xdotter.toml"), "[links]\n\"../a\" = \"~/a\"\n").unwrap();
+    let o = run_in(&d, &["deploy"], &h);
+    assert!(o.stderr.contains("[配置错误][XD0203] "), "{}", o.stderr);
+
+    let o = run_in(&d, &["explain", "xd0203"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(
+        o.stdout
+            .starts_with("XD0203 [配置错误] 源路径包含 `..`\nSPEC: §\"源路径\"\n"),
+        "{}",
+        o.stdout
+    );
+    assert!(o.stdout.contains("修复："), "{}", o.stdout);
+
+    let o = run_in(&d, &["explain"], &h);
+    assert!(
+        o.stdout.lines().any(|l| l.starts_with("XD0506  ")),
+        "{}",
+        o.stdout
+    );
+
+    let o = run_in(&d, &["explain", "XD9999"], &h);
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "{}", o.stderr);
+}
+
 #[test]
 fn link_root_or_home_rejected() {
     for raw in ["~", "~/", "/", "//"] {
@@ -476,6 +635,49 @@ fn deploy_emits_sensitive_target_warning_even_when_correct() {
     );
 }
 
+#[test]
+#[cfg(unix)]
+fn warnings_can_be_denied_or_allowed_per_config() {
+    let d = tmpdir("warn_deny");
+    let h = unique_home("warn_deny");
+    fs::create_dir_all(h.join(".ssh")).unwrap();
+    let src = d.join("id_test");
+    fs::write(&src, "key").unwrap();
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o600)).unwrap();
+    }
+    let toml = "[links]\n\"id_test\" = \"~/.ssh/id_test\"\n";
+    fs::write(d.join("xdotter.toml"), toml).unwrap();
+
+    // Reported once, with its kind, even though dry-run also simulates.
+    let o = run_in(&d, &["deploy", "--dry-run"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert_eq!(o.stderr.matches("[警告]").count(), 1, "{}", o.stderr);
+    assert!(o.stderr.contains("[sensitive-target]"), "{}", o.stderr);
+
+    let o = run_in(&d, &["--deny-warnings", "deploy"], &h);
+    assert_ne!(o.code, 0);
+    assert!(
+        o.stderr
+            .contains("[规划阻塞错误][XD0414] 警告被 --deny-warnings 视为错误"),
+        "{}",
+        o.stderr
+    );
+    assert!(!o.stderr.contains("[警告]"), "{}", o.stderr);
+    assert!(fs::symlink_metadata(h.join(".ssh/id_test")).is_err());
+
+    fs::write(
+        d.join("xdotter.toml"),
+        format!("{}[warnings]\nallow = [\"sensitive-target\"]\n", toml),
+    )
+    .unwrap();
+    let o = run_in(&d, &["deploy", "--deny-warnings"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(!o.stderr.contains("[警告]"), "{}", o.stderr);
+    assert!(h.join(".ssh/id_test").is_symlink());
+}
+
 #[test]
 #[cfg(unix)]
 fn verbose_emits_per_link_diagnostics_to_stderr() {
@@ -501,6 +703,171 @@ fn verbose_emits_per_link_diagnostics_to_stderr() {
     );
 }
 
+#[test]
+#[cfg(unix)]
+fn log_file_records_planning_decisions_filtered_by_xd_log() {
+    let d = tmpdir("logfile");
+    let h = unique_home("logfile");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("xdotter.toml"), "[links]\n\"a\" = \"~/.a\"\n").unwrap();
+    let log = d.join("xd.log");
+    let log_arg = log.to_str().unwrap();
+
+    let o = run_in(&d, &["deploy", "--log-file", log_arg], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert_eq!(
+        o.stderr,
+        "Deploy: 1 succeeded, 0 skipped, 0 failed (planned 1)\n"
+    );
+    let text = fs::read_to_string(&log).unwrap();
+    let link = h.join(".a");
+    for needle in [
+        "Z DEBUG discover: 读取配置 ".to_string(),
+        format!(
+            "Z TRACE plan: {}: 规划部署 \"a\" = \"~/.a\"",
+            link.display()
+        ),
+        format!("Z DEBUG plan: {}: Create", link.display()),
+        format!(
+            "Z TRACE fs: symlinkat {} -> {}",
+            d.join("a").canonicalize().unwrap().display(),
+            link.display()
+        ),
+        format!("Z DEBUG apply: deploy {}: 成功", link.display()),
+        "Z INFO  xd: 退出码 0".to_string(),
+    ] {
+        assert!(text.contains(&needle), "missing {needle:?} in:\n{text}");
+    }
+
+    // XD_LOG narrows the file; without a file it goes to stderr.
+    fs::remove_file(&log).unwrap();
+    let o = run_in_env(
+        &d,
+        &["undeploy", "--log-file", log_arg],
+        &h,
+        &[("XD_LOG", "apply=debug")],
+    );
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    let text = fs::read_to_string(&log).unwrap();
+    assert_eq!(text.lines().count(), 1, "{text}");
+    assert!(text.contains(" DEBUG apply: undeploy "), "{text}");
+
+    // A failing status still ends its log with the exit code.
+    fs::remove_file(&log).unwrap();
+    let o = run_in(&d, &["status", "--log-file", log_arg], &h);
+    assert_eq!(o.code, 1, "{}", o.stderr);
+    let text = fs::read_to_string(&log).unwrap();
+    assert!(text.contains("Z ERROR xd: 退出码 1: "), "{text}");
+
+    let o = run_in_env(
+        &d,
+        &["deploy", "-n"],
+        &h,
+        &[("XD_LOG", "plan=debug,fs=off")],
+    );
+    assert!(o.stderr.contains(" DEBUG plan: "), "{}", o.stderr);
+    assert!(!o.stderr.contains(" TRACE "), "{}", o.stderr);
+
+    let o = run_in_env(&d, &["status"], &h, &[("XD_LOG", "planner=trace")]);
+    assert_ne!(o.code, 0);
+    assert!(
+        o.stderr
+            .contains("[CLI 参数错误] XD_LOG 中未知的日志目标 planner"),
+        "{}",
+        o.stderr
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn history_records_each_change_with_the_config_revision() {
+    let d = tmpdir("history");
+    let h = unique_home("history");
+    fs::create_dir_all(h.join(".ssh")).unwrap();
+    fs::write(h.join(".ssh/config"), "old").unwrap();
+    fs::write(d.join("ssh_config"), "Host *").unwrap();
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        "[links]\n\"ssh_config\" = \"~/.ssh/config\"\n\"a\" = \"~/.a\"\n",
+    )
+    .unwrap();
+    git_in(&d, &["init", "--quiet"]);
+    git_in(&d, &["add", "."]);
+    git_in(&d, &["commit", "--quiet", "-m", "dotfiles"]);
+    let head = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(&d)
+        .output()
+        .unwrap();
+    let head = String::from_utf8(head.stdout).unwrap().trim().to_string();
+    let log = h.join(".local/state/xdotter/history.toml");
+
+    // Dry-runs change nothing and record nothing.
+    let o = run_in(&d, &["deploy", "--force", "-n"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(!log.exists());
+
+    let o = run_in(&d, &["deploy", "--force"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    let o = run_in(&d, &["undeploy"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    let text = fs::read_to_string(&log).unwrap();
+    assert!(text.contains(&format!("revision = \"{}\"", head)), "{text}");
+
+    let source = d.join("ssh_config").canonicalize().unwrap();
+    let o = run_in(&d, &["history", "--link", "~/.ssh/config"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    let lines: Vec<_> = o
+        .stdout
+        .lines()
+        .map(|l| l.split_once("Z  ").expect(l).1)
+        .collect();
+    let link = h.join(".ssh/config");
+    let short = &head[..12];
+    // The replacing link is made under a temporary name carrying the PID
+    // of the run, then renamed over the file.
+    let temp = lines[0]
+        .strip_prefix("deploy --force  symlink ")
+        .and_then(|l| l.split_once(" -> "))
+        .expect(lines[0])
+        .0;
+    let temp_name = Path::new(temp).file_name().unwrap().to_str().unwrap();
+    assert!(
+        temp_name.starts_with(".config.xd-") && temp_name.ends_with(".new"),
+        "{temp}"
+    );
+    assert_eq!(
+        lines,
+        [
+            format!(
+                "deploy --force  symlink {} -> {}  (was missing)  [link {}]  @{short}",
+                temp,
+                source.display(),
+                link.display()
+            ),
+            format!(
+                "deploy --force  rename {} -> {}  (was file 644)  @{short}",
+                temp,
+                link.display()
+            ),
+            format!(
+                "deploy --force  chmod 600 {}  (was file 644)  [link {}]  @{short}",
+                source.display(),
+                link.display()
+            ),
+            format!(
+                "undeploy  remove {}  (was symlink -> {})  @{short}",
+                link.display(),
+                source.display()
+            ),
+        ]
+    );
+
+    let o = run_in(&d, &["history"], &h);
+    assert_eq!(o.stdout.lines().count(), 6, "{}", o.stdout);
+}
+
 #[test]
 #[cfg(unix)]
 fn unsafe_ancestor_regular_file_is_planning_error() {
@@ -540,3 +907,817 @@ fn status_summary_lines_go_to_stdout() {
     assert!(o.stdout.contains("Status: 0/0 deployed"));
     assert!(o.stderr.is_empty());
 }
+
+// ============================================================
+// Output styling
+// ============================================================
+
+#[test]
+#[cfg(unix)]
+fn piped_output_is_not_colored_by_default() {
+    let d = tmpdir("color_auto");
+    let h = unique_home("color_auto");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("xdotter.toml"), "[links]\n\"a\" = \"~/.a\"\n").unwrap();
+    let o = run_in(&d, &["deploy", "--dry-run"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert!(!o.stdout.contains('\x1b'), "stdout: {:?}", o.stdout);
+}
+
+#[test]
+#[cfg(unix)]
+fn color_always_styles_markers_and_error_labels() {
+    let d = tmpdir("color_always");
+    let h = unique_home("color_always");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(h.join(".a"), "stale").unwrap();
+    fs::write(d.join("xdotter.toml"), "[links]\n\"a\" = \"~/.a\"\n").unwrap();
+    // NO_COLOR only affects auto-detection; an explicit choice wins.
+    let o = run_in_env(
+        &d,
+        &["--color=always", "deploy", "--dry-run"],
+        &h,
+        &[("NO_COLOR", "1")],
+    );
+    assert_ne!(o.code, 0);
+    assert!(
+        o.stdout.contains("\x1b[31m!\x1b[0m"),
+        "stdout: {:?}",
+        o.stdout
+    );
+    assert!(
+        o.stderr.contains("\x1b[1;31m[规划阻塞错误]\x1b[0m"),
+        "stderr: {:?}",
+        o.stderr
+    );
+}
+
+#[test]
+fn color_never_is_accepted_by_every_command() {
+    let d = tmpdir("color_never");
+    let h = unique_home("color_never");
+    let o = run_in(&d, &["version", "--color", "never"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert!(!o.stdout.contains('\x1b'));
+}
+
+// ============================================================
+// `xd watch`
+// ============================================================
+
+#[test]
+#[cfg(unix)]
+fn watch_creates_new_links_and_leaves_conflicts_alone() {
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    let d = tmpdir("watch");
+    let h = unique_home("watch");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("c"), "C").unwrap();
+    fs::write(h.join(".c"), "user data").unwrap();
+    fs::write(d.join("xdotter.toml"), "[links]\n\"a\" = \"~/.a\"\n").unwrap();
+
+    let mut child = Command::new(xd_bin())
+        .args(["watch", "--interval", "20", "--debounce", "50"])
+        .current_dir(&d)
+        .env("HOME", &h)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let wait_for = |p: &Path| {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !p.is_symlink() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        p.is_symlink()
+    };
+
+    let initial = wait_for(&h.join(".a"));
+    fs::write(d.join("b"), "B").unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        "[links]\n\"a\" = \"~/.a\"\n\"b\" = \"~/.b\"\n\"c\" = \"~/.c\"\n",
+    )
+    .unwrap();
+    let added = wait_for(&h.join(".b"));
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(initial, "initial cycle should deploy ~/.a");
+    assert!(added, "watch should deploy the newly added ~/.b");
+    assert!(!h.join(".c").is_symlink(), "conflicts must not be replaced");
+    assert_eq!(fs::read_to_string(h.join(".c")).unwrap(), "user data");
+}
+
+// ============================================================
+// --edit-plan
+// ============================================================
+
+/// Write an `$EDITOR` stand-in that runs `body` with the plan file as $1.
+#[cfg(unix)]
+fn editor_script(dir: &Path, body: &str) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let p = dir.join("editor.sh");
+    fs::write(&p, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    fs::set_permissions(&p, fs::Permissions::from_mode(0o755)).unwrap();
+    p.display().to_string()
+}
+
+#[cfg(unix)]
+fn edit_plan_fixture(tag: &str) -> (PathBuf, PathBuf, PathBuf) {
+    let d = tmpdir(tag);
+    let h = unique_home(tag);
+    let tools = tmpdir(&format!("{}_tools", tag));
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("b"), "B").unwrap();
+    fs::write(h.join(".a"), "old a").unwrap();
+    fs::write(h.join(".b"), "old b").unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        r#"
+[links]
+"a" = "~/.a"
+"b" = "~/.b"
+"#,
+    )
+    .unwrap();
+    (d, h, tools)
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_plan_applies_only_kept_actions() {
+    let (d, h, tools) = edit_plan_fixture("editplan");
+    let editor = editor_script(&tools, r#"sed -i 's/^replace \(.*\/\.b \)/skip \1/' "$1""#);
+    let o = run_in_env(
+        &d,
+        &["deploy", "--edit-plan"],
+        &h,
+        &[("VISUAL", &editor), ("EDITOR", &editor)],
+    );
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert!(h.join(".a").is_symlink());
+    assert_eq!(fs::read_to_string(h.join(".b")).unwrap(), "old b");
+    assert!(
+        o.stderr
+            .contains("1 succeeded, 1 skipped, 0 failed (planned 2)"),
+        "stderr: {}",
+        o.stderr
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_plan_dry_run_predicts_the_live_summary() {
+    let (d, h, tools) = edit_plan_fixture("editpredict");
+    // Delete the .b line instead of marking it skip: both leave it out.
+    let editor = editor_script(&tools, r#"sed -i '/\/\.b /d' "$1""#);
+    let env = [("VISUAL", editor.as_str()), ("EDITOR", editor.as_str())];
+    let dry = run_in_env(&d, &["deploy", "--edit-plan", "--dry-run"], &h, &env);
+    assert_eq!(dry.code, 0, "stderr: {}", dry.stderr);
+    let predicted = dry
+        .stdout
+        .lines()
+        .find_map(|l| l.strip_prefix("# Predicted deploy: "))
+        .unwrap_or_else(|| panic!("stdout: {}", dry.stdout))
+        .to_string();
+    assert_eq!(predicted, "1 succeeded, 1 skipped, 0 failed (planned 2)");
+
+    let live = run_in_env(&d, &["deploy", "--edit-plan"], &h, &env);
+    assert_eq!(live.code, 0, "stderr: {}", live.stderr);
+    assert!(
+        live.stderr.contains(&format!("Deploy: {}", predicted)),
+        "stderr: {}",
+        live.stderr
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_plan_rejects_actions_not_in_the_plan() {
+    let (d, h, tools) = edit_plan_fixture("editbad");
+    let editor = editor_script(&tools, r#"echo "create /etc/passwd -> /tmp/x" >> "$1""#);
+    let o = run_in_env(
+        &d,
+        &["deploy", "--edit-plan"],
+        &h,
+        &[("VISUAL", &editor), ("EDITOR", &editor)],
+    );
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "stderr: {}", o.stderr);
+    assert!(
+        o.stderr.contains("不是原计划中的操作"),
+        "stderr: {}",
+        o.stderr
+    );
+    assert!(!h.join(".a").is_symlink() && !h.join(".b").is_symlink());
+}
+
+// ============================================================
+// Run lock
+// ============================================================
+
+#[cfg(unix)]
+#[test]
+fn a_second_run_waits_for_the_lock_or_fails_with_no_wait() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    // The first run holds the lock while its plan is open in the editor.
+    let (d, h, tools) = edit_plan_fixture("runlock");
+    let editing = tools.join("editing");
+    let release = tools.join("release");
+    let editor = editor_script(
+        &tools,
+        &format!(
+            "touch '{}'; while [ ! -e '{}' ]; do sleep 0.02; done",
+            editing.display(),
+            release.display()
+        ),
+    );
+    let xd = |args: &[&str]| {
+        let mut cmd = Command::new(xd_bin());
+        cmd.args(args)
+            .current_dir(&d)
+            .env("HOME", &h)
+            .env("VISUAL", &editor)
+            .env("EDITOR", &editor)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        cmd
+    };
+    let first = xd(&["deploy", "--edit-plan"]).spawn().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !editing.exists() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert!(editing.exists(), "the first run never opened the editor");
+
+    let o = run_in(&d, &["deploy", "--no-wait"], &h);
+    assert_ne!(o.code, 0);
+    assert!(
+        o.stderr.contains(&format!(
+            "[规划阻塞错误][XD0415] 另一个 xd 进程 (PID {})持有运行锁 ",
+            first.id()
+        )),
+        "stderr: {}",
+        o.stderr
+    );
+    // Dry-runs change nothing and do not wait.
+    let o = run_in(&d, &["deploy", "--dry-run"], &h);
+    assert!(!o.stderr.contains("XD0415"), "stderr: {}", o.stderr);
+
+    // The last of --no-wait / --wait wins.
+    let mut second = xd(&["undeploy", "--no-wait", "--wait"]).spawn().unwrap();
+    let mut waiting = String::new();
+    BufReader::new(second.stderr.as_mut().unwrap())
+        .read_line(&mut waiting)
+        .unwrap();
+    assert!(
+        waiting.starts_with(&format!("另一个 xd 进程 (PID {})持有运行锁 ", first.id()))
+            && waiting.ends_with("，等待其结束…\n"),
+        "{waiting}"
+    );
+    fs::write(&release, "").unwrap();
+
+    let first = first.wait_with_output().unwrap();
+    assert!(first.status.success(), "{:?}", first);
+    let second = second.wait_with_output().unwrap();
+    assert!(second.status.success(), "{:?}", second);
+    // The waiting undeploy ran after the deploy it waited for.
+    assert!(!h.join(".a").exists() && !h.join(".b").exists());
+}
+
+// ============================================================
+// Scripted answers
+// ============================================================
+
+#[cfg(unix)]
+#[test]
+fn scripted_answers_work_without_a_tty() {
+    let d = tmpdir("answers");
+    let h = unique_home("answers");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("b"), "B").unwrap();
+    fs::write(h.join(".a"), "user data").unwrap();
+    std::os::unix::fs::symlink(d.join("gone"), h.join(".b")).unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        r#"
+[links]
+"a" = "~/.a"
+"b" = "~/.b"
+"#,
+    )
+    .unwrap();
+    fs::write(d.join("answers.toml"), "replace-file = \"no\"\n").unwrap();
+    let args = [
+        "deploy",
+        "--interactive",
+        "--answers",
+        "answers.toml",
+        "--answer",
+        "replace-broken-symlink=yes",
+    ];
+
+    let mut dry = args.to_vec();
+    dry.push("--dry-run");
+    let o = run_in(&d, &dry, &h);
+    assert!(
+        o.stdout
+            .contains("# Predicted deploy: 1 succeeded, 0 skipped, 1 failed (planned 2)"),
+        "stdout: {}",
+        o.stdout
+    );
+
+    let o = run_in(&d, &args, &h);
+    assert_ne!(o.code, 0, "the declined file replace counts as a failure");
+    assert_eq!(fs::read_to_string(h.join(".a")).unwrap(), "user data");
+    assert_eq!(fs::read_link(h.join(".b")).unwrap(), d.join("b"));
+    assert!(
+        o.stderr.contains("2 answered by script"),
+        "stderr: {}",
+        o.stderr
+    );
+}
+
+#[test]
+fn answer_requires_interactive_and_a_known_kind() {
+    let d = tmpdir("answerbad");
+    let h = unique_home("answerbad");
+    fs::write(d.join("xdotter.toml"), "[links]\n").unwrap();
+    let o = run_in(&d, &["deploy", "--answer", "replace-file=yes"], &h);
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "stderr: {}", o.stderr);
+
+    let o = run_in(&d, &["deploy", "-i", "--answer", "replace-files=yes"], &h);
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("未知的回答类型"), "stderr: {}", o.stderr);
+}
+
+// ============================================================
+// --force=KINDS
+// ============================================================
+
+#[cfg(unix)]
+#[test]
+fn force_with_categories_only_resolves_those_conflicts() {
+    let d = tmpdir("forceset");
+    let h = unique_home("forceset");
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("b"), "B").unwrap();
+    fs::write(h.join(".a"), "user data").unwrap();
+    std::os::unix::fs::symlink(d.join("gone"), h.join(".b")).unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        r#"
+[links]
+"a" = "~/.a"
+"b" = "~/.b"
+"#,
+    )
+    .unwrap();
+
+    let o = run_in(&d, &["deploy", "--force=broken,wrong-symlink"], &h);
+    assert_ne!(o.code, 0, "the regular file is a skipped failure");
+    assert!(
+        o.stderr.contains("--force 未包含 file"),
+        "stderr: {}",
+        o.stderr
+    );
+    assert_eq!(fs::read_to_string(h.join(".a")).unwrap(), "user data");
+    assert_eq!(fs::read_link(h.join(".b")).unwrap(), d.join("b"));
+
+    let o = run_in(&d, &["deploy", "--force=bogus"], &h);
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "stderr: {}", o.stderr);
+}
+
+// ============================================================
+// Relative symlink targets
+// ============================================================
+
+#[cfg(unix)]
+#[test]
+fn relative_links_survive_moving_home_and_repo_together() {
+    let root = tmpdir("relative");
+    let (d, h) = (root.join("repo"), root.join("home"));
+    fs::create_dir_all(&d).unwrap();
+    fs::create_dir_all(&h).unwrap();
+    fs::write(d.join("a"), "A").unwrap();
+    fs::write(d.join("b"), "B").unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        r#"
+[options]
+relative_links = true
+
+[links]
+"a" = "~/.a"
+"b" = { link = "~/.b", relative = false }
+"#,
+    )
+    .unwrap();
+
+    let o = run_in(&d, &["deploy"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert_eq!(fs::read_link(h.join(".a")).unwrap(), Path::new("../repo/a"));
+    assert!(fs::read_link(h.join(".b")).unwrap().is_absolute());
+
+    let moved = root.with_file_name(format!(
+        "{}_moved",
+        root.file_name().unwrap().to_string_lossy()
+    ));
+    fs::rename(&root, &moved).unwrap();
+    let o = run_in(&moved.join("repo"), &["status"], &moved.join("home"));
+    assert!(
+        o.stdout.contains("Status: 1/2 deployed"),
+        "stdout: {}",
+        o.stdout
+    );
+    assert!(o.stdout.contains("Broken links: 1"), "stdout: {}", o.stdout);
+}
+
+// ============================================================
+// Repository registry
+// ============================================================
+
+#[cfg(unix)]
+#[test]
+fn registered_repos_deploy_together_and_collide_across_repos() {
+    let h = unique_home("registry");
+    let personal = tmpdir("reg_personal");
+    let team = tmpdir("reg_team");
+    let elsewhere = tmpdir("reg_elsewhere");
+    fs::write(personal.join("zshrc"), "mine").unwrap();
+    fs::write(
+        personal.join("xdotter.toml"),
+        "[links]\n\"zshrc\" = \"~/.zshrc\"\n",
+    )
+    .unwrap();
+    fs::write(team.join("gitconfig"), "team").unwrap();
+    fs::write(
+        team.join("xdotter.toml"),
+        "[links]\n\"gitconfig\" = \"~/.gitconfig\"\n",
+    )
+    .unwrap();
+
+    let o = run_in(&personal, &["repo", "add"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    let team_path = team.to_str().unwrap();
+    let o = run_in(
+        &elsewhere,
+        &["repo", "add", team_path, "--name", "team"],
+        &h,
+    );
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    let o = run_in(
+        &elsewhere,
+        &["repo", "add", team_path, "--name", "again"],
+        &h,
+    );
+    assert!(
+        o.stderr.contains("已注册为仓库 \"team\""),
+        "stderr: {}",
+        o.stderr
+    );
+
+    let o = run_in(&elsewhere, &["repo", "list"], &h);
+    assert!(o.stdout.contains("team\t"), "stdout: {}", o.stdout);
+    assert_eq!(o.stdout.lines().count(), 2, "stdout: {}", o.stdout);
+
+    // Deploy from a directory without xdotter.toml covers both repos.
+    let o = run_in(&elsewhere, &["deploy"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert_eq!(fs::read_to_string(h.join(".zshrc")).unwrap(), "mine");
+    assert_eq!(fs::read_to_string(h.join(".gitconfig")).unwrap(), "team");
+    let o = run_in(&elsewhere, &["status", "-v"], &h);
+    assert!(
+        o.stdout.contains("Status: 2/2 deployed"),
+        "stdout: {}",
+        o.stdout
+    );
+    assert!(o.stdout.contains("[team] "), "stdout: {}", o.stdout);
+
+    // A link claimed by both repos is a collision naming each repo.
+    fs::write(team.join("zshrc"), "team").unwrap();
+    fs::write(
+        team.join("xdotter.toml"),
+        "[links]\n\"gitconfig\" = \"~/.gitconfig\"\n\"zshrc\" = \"~/.zshrc\"\n",
+    )
+    .unwrap();
+    let o = run_in(&elsewhere, &["status"], &h);
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("[配置错误]"), "stderr: {}", o.stderr);
+    assert!(o.stderr.contains("- [team] "), "stderr: {}", o.stderr);
+    let personal_name = personal.file_name().unwrap().to_str().unwrap();
+    assert!(
+        o.stderr.contains(&format!("- [{}] ", personal_name)),
+        "stderr: {}",
+        o.stderr
+    );
+
+    let o = run_in(&elsewhere, &["repo", "remove", "team"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    let o = run_in(&elsewhere, &["repo", "remove", "team"], &h);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "stderr: {}", o.stderr);
+}
+
+// ============================================================
+// Git dependencies
+// ============================================================
+
+#[cfg(unix)]
+fn git_in(dir: &Path, args: &[&str]) {
+    let out = Command::new("git")
+        .args(["-c", "user.name=xd", "-c", "user.email=xd@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("failed to spawn git");
+    assert!(out.status.success(), "git {:?}: {:?}", args, out);
+}
+
+#[cfg(unix)]
+#[test]
+fn git_dependencies_are_pinned_and_bumped_by_update_deps() {
+    let h = unique_home("gitdep");
+    let team = tmpdir("gitdep_team");
+    let d = tmpdir("gitdep_main");
+    git_in(&team, &["init", "--quiet"]);
+    fs::write(team.join("gitconfig"), "v1").unwrap();
+    fs::write(
+        team.join("xdotter.toml"),
+        "[links]\n\"gitconfig\" = \"~/.gitconfig\"\n",
+    )
+    .unwrap();
+    git_in(&team, &["add", "-A"]);
+    git_in(&team, &["commit", "--quiet", "-m", "v1"]);
+    fs::write(
+        d.join("xdotter.toml"),
+        format!(
+            "[dependencies]\n\"team\" = {{ git = \"file://{}\" }}\n",
+            team.display()
+        ),
+    )
+    .unwrap();
+
+    let o = run_in(&d, &["deploy"], &h);
+    assert_ne!(o.code, 0);
+    assert!(o.stderr.contains("xd update-deps"), "stderr: {}", o.stderr);
+    assert!(o.stderr.contains("[XD0311]"), "stderr: {}", o.stderr);
+    // The error points at the entry, like any other dependency error.
+    assert!(
+        o.stderr
+            .contains("xdotter.toml:2:18: 依赖 \"team\" 未在 xdotter.lock 中锁定"),
+        "stderr: {}",
+        o.stderr
+    );
+    assert!(
+        o.stderr.contains("2 | \"team\" = { git"),
+        "stderr: {}",
+        o.stderr
+    );
+
+    let o = run_in(&d, &["update-deps"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert!(o.stdout.contains("Locked team at "), "stdout: {}", o.stdout);
+    let lock = fs::read_to_string(d.join("xdotter.lock")).unwrap();
+    assert!(lock.contains("[git.team]"), "{lock}");
+
+    let o = run_in(&d, &["deploy"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert_eq!(fs::read_to_string(h.join(".gitconfig")).unwrap(), "v1");
+
+    // New upstream commits are ignored until the pin is bumped.
+    fs::write(team.join("gitconfig"), "v2").unwrap();
+    git_in(&team, &["commit", "--quiet", "-am", "v2"]);
+    let o = run_in(&d, &["status"], &h);
+    assert_eq!(o.code, 0, "stdout: {}", o.stdout);
+    assert_eq!(fs::read_to_string(h.join(".gitconfig")).unwrap(), "v1");
+
+    let o = run_in(&d, &["update-deps"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert!(o.stdout.contains("Updated team "), "stdout: {}", o.stdout);
+    // The checkout moves in place, so the deployed link follows the pin.
+    assert_eq!(fs::read_to_string(h.join(".gitconfig")).unwrap(), "v2");
+
+    // A fresh machine restores the pinned checkout without moving pins.
+    let lock = fs::read_to_string(d.join("xdotter.lock")).unwrap();
+    fs::remove_dir_all(h.join(".cache")).unwrap();
+    let o = run_in(&d, &["status"], &h);
+    assert!(
+        o.stderr.contains("xd update-deps --locked"),
+        "stderr: {}",
+        o.stderr
+    );
+    let o = run_in(&d, &["update-deps", "--locked"], &h);
+    assert_eq!(o.code, 0, "stderr: {}", o.stderr);
+    assert!(o.stdout.contains("0 pins changed"), "stdout: {}", o.stdout);
+    assert_eq!(fs::read_to_string(d.join("xdotter.lock")).unwrap(), lock);
+    let o = run_in(&d, &["status"], &h);
+    assert_eq!(o.code, 0, "stdout: {}", o.stdout);
+}
+
+// ============================================================
+// Shell completion
+// ============================================================
+
+#[test]
+fn every_completion_script_calls_the_dynamic_endpoint() {
+    let d = tmpdir("cplscripts");
+    let h = unique_home("cplscripts");
+    for shell in ["bash", "zsh", "fish", "powershell", "elvish", "nushell"] {
+        let o = run_in(&d, &["completion", shell], &h);
+        assert_eq!(o.code, 0, "{shell}: {}", o.stderr);
+        assert!(o.stdout.contains("xd __complete"), "{shell}: {}", o.stdout);
+    }
+    let o = run_in(&d, &["completion", "tcsh"], &h);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "{}", o.stderr);
+    assert!(o.stderr.contains("nushell"), "{}", o.stderr);
+}
+
+#[test]
+fn complete_lists_repo_values_and_command_line_candidates() {
+    let d = tmpdir("cpl");
+    let h = unique_home("cpl");
+    fs::write(
+        d.join("xdotter.toml"),
+        "[links]\n\"zshrc\" = \"~/.zshrc\"\n[dependencies]\nnvim = \"nvim\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(d.join("nvim")).unwrap();
+    fs::write(
+        d.join("nvim/xdotter.toml"),
+        "[links]\n\"init.lua\" = \"~/.config/nvim/init.lua\"\n",
+    )
+    .unwrap();
+    let lines = |args: &[&str]| -> Vec<String> {
+        let o = run_in(&d, args, &h);
+        assert_eq!(o.code, 0, "{args:?}: {}", o.stderr);
+        o.stdout
+            .lines()
+            .map(|l| l.split('\t').next().unwrap().to_string())
+            .collect()
+    };
+
+    assert_eq!(lines(&["__complete", "--values", "deps"]), ["nvim"]);
+    assert_eq!(
+        lines(&["__complete", "--values", "links"]),
+        ["~/.zshrc", "~/.config/nvim/init.lua"]
+    );
+    assert_eq!(
+        lines(&["__complete", "--values", "sources"]),
+        ["zshrc", "nvim/init.lua"]
+    );
+
+    assert_eq!(lines(&["__complete", "--cur=up"]), ["update-deps"]);
+    assert!(!lines(&["__complete", "--cur="]).contains(&"__complete".to_string()));
+    assert_eq!(
+        lines(&["__complete", "--cur=--force=broken,w", "--", "deploy"]),
+        ["--force=broken,wrong-symlink"]
+    );
+    assert_eq!(
+        lines(&["__complete", "--cur=a", "--", "-v", "--color"]),
+        ["auto", "always"]
+    );
+    assert_eq!(
+        lines(&["__complete", "--cur=fix", "--", "deploy", "-i", "--answer"]),
+        ["fix-permission=yes", "fix-permission=no"]
+    );
+
+    let o = run_in(&d, &["repo", "add", "--name", "dots"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert_eq!(
+        lines(&["__complete", "--cur=", "--", "repo", "remove"]),
+        ["dots"]
+    );
+    // Outside any repository there is nothing to look up, but no error.
+    let elsewhere = tmpdir("cplnone");
+    let o = run_in(
+        &elsewhere,
+        &["__complete", "--values", "links"],
+        &unique_home("cplnone"),
+    );
+    assert_eq!((o.code, o.stdout.as_str()), (0, ""), "{}", o.stderr);
+}
+
+// ============================================================
+// Lint
+// ============================================================
+
+#[test]
+fn lint_reports_named_warnings_without_failing() {
+    let d = tmpdir("lint");
+    let h = unique_home("lint");
+    fs::write(
+        d.join("xdotter.toml"),
+        "[links]\n\"ssh\" = \"~/.ssh\"\n[dependencies]\nbad = \"bad\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(d.join("ssh")).unwrap();
+    fs::write(d.join("ssh/id_ed25519"), "").unwrap();
+    fs::write(d.join("notes.txt"), "").unwrap();
+    fs::create_dir_all(d.join("bad")).unwrap();
+    fs::write(
+        d.join("bad/xdotter.toml"),
+        "[lint]\nallow = [\"unreferenced-file\"]\n",
+    )
+    .unwrap();
+    fs::write(d.join("bad/leftover"), "").unwrap();
+
+    let o = run_in(&d, &["lint"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(
+        o.stdout.contains("[unreferenced-file] ")
+            && o.stdout.contains("notes.txt 未被任何链接条目引用"),
+        "{}",
+        o.stdout
+    );
+    assert!(o.stdout.contains("[secret-in-directory] "), "{}", o.stdout);
+    assert!(!o.stdout.contains("leftover"), "{}", o.stdout);
+    assert!(
+        o.stdout.ends_with("Lint: 2 warnings, 1 suppressed\n"),
+        "{}",
+        o.stdout
+    );
+    assert!(o.stderr.is_empty(), "{}", o.stderr);
+
+    let o = run_in(
+        &d,
+        &[
+            "lint",
+            "--allow",
+            "secret-in-directory",
+            "--allow",
+            "unreferenced-file",
+        ],
+        &h,
+    );
+    assert!(
+        o.stdout.ends_with("Lint: 0 warnings, 3 suppressed\n"),
+        "{}",
+        o.stdout
+    );
+
+    let o = run_in(&d, &["lint", "--allow", "typo"], &h);
+    assert!(o.stderr.contains("[CLI 参数错误]"), "{}", o.stderr);
+
+    // Hard errors are still errors.
+    fs::write(
+        d.join("bad/xdotter.toml"),
+        "[links]\n\"leftover\" = \"~/.ssh\"\n",
+    )
+    .unwrap();
+    let o = run_in(&d, &["lint"], &h);
+    assert_ne!(o.code, 0);
+    assert_eq!(o.stderr.matches("[配置错误]").count(), 1, "{}", o.stderr);
+}
+
+#[test]
+fn orphans_lists_unreferenced_files_and_suggests_links() {
+    let d = tmpdir("orphans");
+    let h = unique_home("orphans");
+    fs::write(d.join("xdotter.toml"), "[links]\n\"zsh\" = \"~/.zsh\"\n").unwrap();
+    fs::create_dir_all(d.join("zsh")).unwrap();
+    fs::write(d.join("zsh/zshrc"), "").unwrap();
+    fs::create_dir_all(d.join("config/nvim")).unwrap();
+    fs::write(d.join("config/nvim/init.lua"), "").unwrap();
+    fs::write(d.join("README.md"), "").unwrap();
+    fs::write(d.join(".xdignore"), "README.md\n").unwrap();
+
+    let o = run_in(&d, &["orphans"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(
+        o.stdout.contains(": config/nvim/init.lua\n"),
+        "{}",
+        o.stdout
+    );
+    assert!(!o.stdout.contains("zshrc"), "{}", o.stdout);
+    assert!(!o.stdout.contains("README"), "{}", o.stdout);
+    assert!(o.stdout.ends_with("Orphans: 1 files\n"), "{}", o.stdout);
+
+    let o = run_in(&d, &["orphans", "--suggest"], &h);
+    assert_eq!(o.code, 0, "{}", o.stderr);
+    assert!(
+        o.stdout
+            .contains("[links]\n\"config/nvim\" = \"~/.config/nvim\"\n# Orphans: 1 files\n"),
+        "{}",
+        o.stdout
+    );
+
+    // A configuration that fails to load is reported, once.
+    fs::write(d.join("config/xdotter.toml"), "[links\n").unwrap();
+    fs::write(
+        d.join("xdotter.toml"),
+        "[links]\n\"zsh\" = \"~/.zsh\"\n[dependencies]\nconfig = \"config\"\n",
+    )
+    .unwrap();
+    let o = run_in(&d, &["orphans"], &h);
+    assert_ne!(o.code, 0);
+    assert_eq!(o.stderr.matches("[XD0101]").count(), 1, "{}", o.stderr);
+}